@@ -0,0 +1,67 @@
+#version 330 core
+
+// Split sum BRDF integration: scale and bias of F0 by n.v (x) and roughness (y)
+
+uniform int sample_count;
+
+in VS_OUTPUT {
+  vec2 Uv;
+  vec3 Direction;
+} IN;
+
+out vec2 Color;
+
+const float PI = 3.14159265359;
+
+vec2 hammersley(uint i, uint count)
+{
+  uint bits = i;
+  bits = (bits << 16u) | (bits >> 16u);
+  bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+  bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+  bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+  bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+  return vec2(float(i) / float(count), float(bits) * 2.3283064365386963e-10);
+}
+
+vec3 importance_sample_ggx(vec2 xi, float alpha)
+{
+  float phi = 2.0 * PI * xi.x;
+  float cos_theta = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+  float sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+  return vec3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+}
+
+float geometry_schlick(float n_dot_x, float roughness)
+{
+  float k = roughness * roughness / 2.0;  // image based lighting remaps k differently
+  return n_dot_x / (n_dot_x * (1.0 - k) + k);
+}
+
+void main()
+{
+  float n_dot_v = max(IN.Uv.x, 1e-4);
+  float roughness = IN.Uv.y;
+  vec3 view = vec3(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+
+  float scale = 0.0;
+  float bias = 0.0;
+  uint count = uint(sample_count);
+  for (uint i = 0u; i < count; i++) {
+    vec3 halfway = importance_sample_ggx(hammersley(i, count), roughness * roughness);
+    vec3 light = normalize(2.0 * dot(view, halfway) * halfway - view);
+
+    float n_dot_l = max(light.z, 0.0);
+    float n_dot_h = max(halfway.z, 0.0);
+    float v_dot_h = max(dot(view, halfway), 0.0);
+    if (n_dot_l > 0.0) {
+      float geometry = geometry_schlick(n_dot_v, roughness) * geometry_schlick(n_dot_l, roughness);
+      float visibility = geometry * v_dot_h / (n_dot_h * n_dot_v);
+      float fresnel = pow(1.0 - v_dot_h, 5.0);
+      scale += (1.0 - fresnel) * visibility;
+      bias += fresnel * visibility;
+    }
+  }
+
+  Color = vec2(scale, bias) / float(sample_count);
+}
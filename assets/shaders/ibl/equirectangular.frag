@@ -0,0 +1,22 @@
+#version 330 core
+
+// Samples a latitude longitude panorama in the direction of the texel
+
+uniform sampler2D panorama;     // top row first, so v = 0 is straight up
+
+in VS_OUTPUT {
+  vec2 Uv;
+  vec3 Direction;
+} IN;
+
+out vec4 Color;
+
+const float PI = 3.14159265359;
+
+void main()
+{
+  vec3 direction = normalize(IN.Direction);
+  vec2 uv = vec2(atan(direction.z, direction.x) / (2.0 * PI) + 0.5, 0.5 - asin(clamp(direction.y, -1.0, 1.0)) / PI);
+  // the top level, derivatives jump where u wraps around
+  Color = vec4(textureLod(panorama, uv, 0.0).rgb, 1.0);
+}
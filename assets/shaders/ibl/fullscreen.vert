@@ -0,0 +1,20 @@
+#version 330 core
+
+// A triangle covering the screen, made from gl_VertexID without vertex buffers
+
+uniform mat4 ClipToWorld;  // inverse view projection of the cube face being rendered
+
+out VS_OUTPUT {
+  vec2 Uv;
+  vec3 Direction;
+} OUT;
+
+void main()
+{
+  vec2 position = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2) * 2.0 - 1.0;
+  gl_Position = vec4(position, 0.0, 1.0);
+
+  vec4 world = ClipToWorld * vec4(position, 1.0, 1.0);
+  OUT.Uv = position * 0.5 + 0.5;
+  OUT.Direction = world.xyz / world.w;
+}
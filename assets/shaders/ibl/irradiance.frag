@@ -0,0 +1,36 @@
+#version 330 core
+
+// Cosine weighted average of the environment over the hemisphere around the normal
+
+uniform samplerCube environment;
+
+in VS_OUTPUT {
+  vec2 Uv;
+  vec3 Direction;
+} IN;
+
+out vec4 Color;
+
+const float PI = 3.14159265359;
+const float STEP = 0.025;
+
+void main()
+{
+  vec3 normal = normalize(IN.Direction);
+  vec3 up = abs(normal.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0);
+  vec3 right = normalize(cross(up, normal));
+  up = cross(normal, right);
+
+  vec3 irradiance = vec3(0.0);
+  float samples = 0.0;
+  for (float phi = 0.0; phi < 2.0 * PI; phi += STEP) {
+    for (float theta = 0.0; theta < 0.5 * PI; theta += STEP) {
+      vec3 tangent = vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+      vec3 direction = tangent.x * right + tangent.y * up + tangent.z * normal;
+      irradiance += texture(environment, direction).rgb * cos(theta) * sin(theta);
+      samples += 1.0;
+    }
+  }
+
+  Color = vec4(PI * irradiance / samples, 1.0);
+}
@@ -0,0 +1,76 @@
+#version 330 core
+
+// The environment convolved with the GGX lobe of `roughness`, assuming n = v = r
+
+uniform samplerCube environment;
+uniform float roughness;
+uniform int sample_count;
+uniform float source_size;  // of a face of the environment, in texels
+
+in VS_OUTPUT {
+  vec2 Uv;
+  vec3 Direction;
+} IN;
+
+out vec4 Color;
+
+const float PI = 3.14159265359;
+
+vec2 hammersley(uint i, uint count)
+{
+  uint bits = i;
+  bits = (bits << 16u) | (bits >> 16u);
+  bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+  bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+  bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+  bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+  return vec2(float(i) / float(count), float(bits) * 2.3283064365386963e-10);
+}
+
+vec3 importance_sample_ggx(vec2 xi, vec3 normal, float alpha)
+{
+  float phi = 2.0 * PI * xi.x;
+  float cos_theta = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+  float sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+  vec3 halfway = vec3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+
+  vec3 up = abs(normal.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+  vec3 tangent = normalize(cross(up, normal));
+  vec3 bitangent = cross(normal, tangent);
+  return normalize(tangent * halfway.x + bitangent * halfway.y + normal * halfway.z);
+}
+
+float distribution_ggx(float n_dot_h, float alpha)
+{
+  float alpha2 = alpha * alpha;
+  float denominator = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+  return alpha2 / (PI * denominator * denominator);
+}
+
+void main()
+{
+  vec3 normal = normalize(IN.Direction);
+  float alpha = roughness * roughness;
+
+  vec3 color = vec3(0.0);
+  float weight = 0.0;
+  uint count = uint(sample_count);
+  for (uint i = 0u; i < count; i++) {
+    vec3 halfway = importance_sample_ggx(hammersley(i, count), normal, alpha);
+    vec3 light = normalize(2.0 * dot(normal, halfway) * halfway - normal);
+    float n_dot_l = dot(normal, light);
+    if (n_dot_l > 0.0) {
+      // sample a mip whose texels cover the solid angle of the sample
+      float n_dot_h = max(dot(normal, halfway), 0.0);
+      float pdf = distribution_ggx(n_dot_h, alpha) / 4.0 + 0.0001;
+      float sample_angle = 1.0 / (float(sample_count) * pdf);
+      float texel_angle = 4.0 * PI / (6.0 * source_size * source_size);
+      float level = roughness == 0.0 ? 0.0 : 0.5 * log2(sample_angle / texel_angle);
+
+      color += textureLod(environment, light, level).rgb * n_dot_l;
+      weight += n_dot_l;
+    }
+  }
+
+  Color = vec4(color / weight, 1.0);
+}
@@ -0,0 +1,29 @@
+#version 330 core
+
+// A gradient sky with a sun, in linear hdr
+
+in VS_OUTPUT {
+  vec2 Uv;
+  vec3 Direction;
+} IN;
+
+out vec4 Color;
+
+const vec3 ZENITH = vec3(0.15, 0.3, 0.65);
+const vec3 HORIZON = vec3(0.7, 0.8, 0.9);
+const vec3 GROUND = vec3(0.2, 0.18, 0.15);
+const vec3 SUN_DIRECTION = normalize(vec3(1.0, 2.0, 1.0));
+const vec3 SUN_COLOR = vec3(20.0, 18.0, 15.0);
+
+void main()
+{
+  vec3 direction = normalize(IN.Direction);
+  float height = direction.y;
+
+  vec3 sky = mix(HORIZON, ZENITH, pow(clamp(height, 0.0, 1.0), 0.5));
+  vec3 ground = mix(HORIZON, GROUND, clamp(-height * 4.0, 0.0, 1.0));
+  vec3 color = height >= 0.0 ? sky : ground;
+
+  float sun = smoothstep(0.9995, 0.9999, dot(direction, SUN_DIRECTION));
+  Color = vec4(color + SUN_COLOR * sun, 1.0);
+}
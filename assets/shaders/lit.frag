@@ -0,0 +1,127 @@
+#version 330 core
+
+// Blinn-Phong with shadows, render_gl::shade does the same on the cpu without them
+
+#define MAX_LIGHTS 16
+#define DIRECTIONAL 0
+#define SPOT 2
+
+struct Light {
+  vec4 position;      // xyz, kind in w
+  vec4 direction;     // xyz the light travels in, cos of the inner cone in w
+  vec4 color;         // rgb, intensity in w
+  vec4 attenuation;   // constant, linear, quadratic, cos of the outer cone in w
+};
+
+layout (std140) uniform Lights {
+  vec4 ambient;       // rgb, number of lights in w
+  vec4 eye;
+  Light lights[MAX_LIGHTS];
+};
+
+#define MAX_SHADOWS 8       // render_gl::MAX_SHADOWS
+
+struct Shadow {
+  mat4 matrix;        // world to atlas uv and depth
+  vec4 rect;          // the tile in the atlas, min uv in xy and max uv in zw
+};
+
+layout (std140) uniform Shadows {
+  vec4 shadow_params;   // number of views, pcf radius in texels, depth bias, normal bias
+  vec4 view_forward;    // camera forward in xyz, number of cascades in w
+  vec4 cascade_splits;  // view distance each cascade ends at
+  vec4 first_shadow[MAX_LIGHTS / 4];  // first view of light i at [i / 4][i % 4], -1 for none
+  Shadow shadows[MAX_SHADOWS];
+};
+
+uniform sampler2DShadow shadow_map;
+
+uniform vec3 diffuse;
+uniform vec3 specular;
+uniform float shininess;
+
+in VS_OUTPUT {
+  vec3 WorldPosition;
+  vec3 Normal;
+} IN;
+
+out vec4 Color;
+
+// How much of light `index` reaches `position`, averaged over the texels within the pcf radius
+float shadowing(int index, vec3 position, vec3 normal)
+{
+  int view = int(first_shadow[index / 4][index % 4]);
+  if (view < 0) {
+    return 1.0;
+  }
+  if (int(lights[index].position.w) == DIRECTIONAL) {
+    float depth = dot(position - eye.xyz, view_forward.xyz);
+    int cascade = 0;
+    while (cascade < int(view_forward.w) && depth > cascade_splits[cascade]) {
+      cascade++;
+    }
+    if (cascade == int(view_forward.w)) {
+      return 1.0;
+    }
+    view += cascade;
+  }
+
+  vec4 projected = shadows[view].matrix * vec4(position + normal * shadow_params.w, 1.0);
+  vec3 coords = projected.xyz / projected.w;
+  vec4 rect = shadows[view].rect;
+  if (coords.z >= 1.0 || any(lessThan(coords.xy, rect.xy)) || any(greaterThan(coords.xy, rect.zw))) {
+    return 1.0;
+  }
+
+  vec2 texel = 1.0 / vec2(textureSize(shadow_map, 0));
+  int radius = int(shadow_params.y);
+  float lit = 0.0;
+  for (int x = -radius; x <= radius; x++) {
+    for (int y = -radius; y <= radius; y++) {
+      vec2 uv = clamp(coords.xy + vec2(x, y) * texel, rect.xy + texel * 0.5, rect.zw - texel * 0.5);
+      lit += texture(shadow_map, vec3(uv, coords.z - shadow_params.z));
+    }
+  }
+  return lit / float((2 * radius + 1) * (2 * radius + 1));
+}
+
+void main()
+{
+  vec3 normal = normalize(IN.Normal);
+  vec3 to_eye = normalize(eye.xyz - IN.WorldPosition);
+  vec3 color = ambient.rgb * diffuse;
+
+  for (int i = 0; i < int(ambient.w); i++) {
+    Light light = lights[i];
+    int kind = int(light.position.w);
+
+    vec3 to_light;
+    float amount = 1.0;
+    if (kind == DIRECTIONAL) {
+      to_light = -normalize(light.direction.xyz);
+    } else {
+      vec3 offset = light.position.xyz - IN.WorldPosition;
+      float distance = length(offset);
+      to_light = offset / max(distance, 1e-6);
+      amount = 1.0 / (light.attenuation.x + light.attenuation.y * distance + light.attenuation.z * distance * distance);
+      if (kind == SPOT) {
+        float cos_angle = dot(-to_light, normalize(light.direction.xyz));
+        float cone = max(light.direction.w - light.attenuation.w, 1e-6);
+        amount *= clamp((cos_angle - light.attenuation.w) / cone, 0.0, 1.0);
+      }
+    }
+    amount *= shadowing(i, IN.WorldPosition, normal);
+
+    float lambert = max(dot(normal, to_light), 0.0);
+    if (lambert <= 0.0) {
+      continue;
+    }
+    vec3 halfway = normalize(to_light + to_eye);
+    float highlight = pow(max(dot(normal, halfway), 0.0), shininess);
+
+    vec3 radiance = light.color.rgb * light.color.w * amount;
+    color += radiance * (diffuse * lambert + specular * highlight);
+  }
+
+  Color = vec4(color, 1.0);
+}
@@ -0,0 +1,20 @@
+#version 330 core
+
+layout (location = 0) in vec3 Position;
+layout (location = 1) in vec3 Normal;
+
+uniform mat4 MVP;
+uniform mat4 Model;
+uniform mat3 NormalMatrix;
+
+out VS_OUTPUT {
+  vec3 WorldPosition;
+  vec3 Normal;
+} OUT;
+
+void main()
+{
+  gl_Position = MVP * vec4(Position, 1.0);
+  OUT.WorldPosition = (Model * vec4(Position, 1.0)).xyz;
+  OUT.Normal = NormalMatrix * Normal;
+}
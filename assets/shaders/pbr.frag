@@ -0,0 +1,220 @@
+#version 330 core
+
+// Metallic-roughness shading with image based light and shadows, render_gl::shade_pbr does the
+// unshadowed direct light on the cpu
+
+#define MAX_LIGHTS 16
+#define DIRECTIONAL 0
+#define SPOT 2
+#define PREFILTERED_LEVELS 5    // render_gl::PREFILTERED_LEVELS
+#define PI 3.14159265359
+
+struct Light {
+  vec4 position;      // xyz, kind in w
+  vec4 direction;     // xyz the light travels in, cos of the inner cone in w
+  vec4 color;         // rgb, intensity in w
+  vec4 attenuation;   // constant, linear, quadratic, cos of the outer cone in w
+};
+
+layout (std140) uniform Lights {
+  vec4 ambient;       // rgb, number of lights in w
+  vec4 eye;
+  Light lights[MAX_LIGHTS];
+};
+
+#define MAX_SHADOWS 8       // render_gl::MAX_SHADOWS
+
+struct Shadow {
+  mat4 matrix;        // world to atlas uv and depth
+  vec4 rect;          // the tile in the atlas, min uv in xy and max uv in zw
+};
+
+layout (std140) uniform Shadows {
+  vec4 shadow_params;   // number of views, pcf radius in texels, depth bias, normal bias
+  vec4 view_forward;    // camera forward in xyz, number of cascades in w
+  vec4 cascade_splits;  // view distance each cascade ends at
+  vec4 first_shadow[MAX_LIGHTS / 4];  // first view of light i at [i / 4][i % 4], -1 for none
+  Shadow shadows[MAX_SHADOWS];
+};
+
+uniform sampler2DShadow shadow_map;
+
+uniform sampler2D base_color_map;
+uniform sampler2D metallic_roughness_map;   // roughness in g, metallic in b, as in glTF
+uniform sampler2D normal_map;
+uniform sampler2D occlusion_map;
+uniform sampler2D emissive_map;
+
+uniform vec4 base_color_factor;
+uniform float metallic_factor;
+uniform float roughness_factor;
+uniform float normal_scale;
+uniform float occlusion_strength;
+uniform vec3 emissive_factor;
+
+uniform samplerCube irradiance_map;
+uniform samplerCube prefiltered_map;
+uniform sampler2D brdf_lut;
+
+uniform float exposure;
+uniform int tone_mapping;   // render_gl::ToneMapping::index
+uniform float gamma;
+
+in VS_OUTPUT {
+  vec3 WorldPosition;
+  vec3 Normal;
+  vec2 Uv;
+  vec4 Tangent;
+} IN;
+
+out vec4 Color;
+
+// How much of light `index` reaches `position`, averaged over the texels within the pcf radius
+float shadowing(int index, vec3 position, vec3 normal)
+{
+  int view = int(first_shadow[index / 4][index % 4]);
+  if (view < 0) {
+    return 1.0;
+  }
+  if (int(lights[index].position.w) == DIRECTIONAL) {
+    float depth = dot(position - eye.xyz, view_forward.xyz);
+    int cascade = 0;
+    while (cascade < int(view_forward.w) && depth > cascade_splits[cascade]) {
+      cascade++;
+    }
+    if (cascade == int(view_forward.w)) {
+      return 1.0;
+    }
+    view += cascade;
+  }
+
+  vec4 projected = shadows[view].matrix * vec4(position + normal * shadow_params.w, 1.0);
+  vec3 coords = projected.xyz / projected.w;
+  vec4 rect = shadows[view].rect;
+  if (coords.z >= 1.0 || any(lessThan(coords.xy, rect.xy)) || any(greaterThan(coords.xy, rect.zw))) {
+    return 1.0;
+  }
+
+  vec2 texel = 1.0 / vec2(textureSize(shadow_map, 0));
+  int radius = int(shadow_params.y);
+  float lit = 0.0;
+  for (int x = -radius; x <= radius; x++) {
+    for (int y = -radius; y <= radius; y++) {
+      vec2 uv = clamp(coords.xy + vec2(x, y) * texel, rect.xy + texel * 0.5, rect.zw - texel * 0.5);
+      lit += texture(shadow_map, vec3(uv, coords.z - shadow_params.z));
+    }
+  }
+  return lit / float((2 * radius + 1) * (2 * radius + 1));
+}
+
+vec3 fresnel_schlick(float cos_theta, vec3 f0)
+{
+  return f0 + (1.0 - f0) * pow(1.0 - clamp(cos_theta, 0.0, 1.0), 5.0);
+}
+
+vec3 fresnel_schlick_roughness(float cos_theta, vec3 f0, float roughness)
+{
+  return f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(1.0 - clamp(cos_theta, 0.0, 1.0), 5.0);
+}
+
+float distribution_ggx(float n_dot_h, float roughness)
+{
+  float alpha = roughness * roughness;
+  float alpha2 = alpha * alpha;
+  float denominator = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+  return alpha2 / max(PI * denominator * denominator, 1e-7);
+}
+
+float geometry_smith(float n_dot_v, float n_dot_l, float roughness)
+{
+  float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+  float view = n_dot_v / (n_dot_v * (1.0 - k) + k);
+  float light = n_dot_l / (n_dot_l * (1.0 - k) + k);
+  return view * light;
+}
+
+vec3 surface_normal()
+{
+  vec3 normal = normalize(IN.Normal);
+  vec3 tangent = normalize(IN.Tangent.xyz - normal * dot(normal, IN.Tangent.xyz));
+  vec3 bitangent = cross(normal, tangent) * IN.Tangent.w;
+
+  vec3 sampled = texture(normal_map, IN.Uv).xyz * 2.0 - 1.0;
+  sampled.xy *= normal_scale;
+  return normalize(mat3(tangent, bitangent, normal) * sampled);
+}
+
+vec3 tone_map(vec3 color)
+{
+  color *= exposure;
+  if (tone_mapping == 1) {
+    return color / (1.0 + color);
+  }
+  if (tone_mapping == 2) {
+    return clamp((color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14), 0.0, 1.0);
+  }
+  return color;
+}
+
+void main()
+{
+  vec4 base_color = texture(base_color_map, IN.Uv) * base_color_factor;
+  vec4 metallic_roughness = texture(metallic_roughness_map, IN.Uv);
+  float metallic = clamp(metallic_roughness.b * metallic_factor, 0.0, 1.0);
+  float roughness = clamp(metallic_roughness.g * roughness_factor, 0.04, 1.0);
+  float occlusion = mix(1.0, texture(occlusion_map, IN.Uv).r, occlusion_strength);
+  vec3 emissive = texture(emissive_map, IN.Uv).rgb * emissive_factor;
+
+  vec3 normal = surface_normal();
+  vec3 to_eye = normalize(eye.xyz - IN.WorldPosition);
+  float n_dot_v = max(dot(normal, to_eye), 1e-4);
+  vec3 f0 = mix(vec3(0.04), base_color.rgb, metallic);
+
+  vec3 color = vec3(0.0);
+  for (int i = 0; i < int(ambient.w); i++) {
+    Light light = lights[i];
+    int kind = int(light.position.w);
+
+    vec3 to_light;
+    float amount = 1.0;
+    if (kind == DIRECTIONAL) {
+      to_light = -normalize(light.direction.xyz);
+    } else {
+      vec3 offset = light.position.xyz - IN.WorldPosition;
+      float distance = length(offset);
+      to_light = offset / max(distance, 1e-6);
+      amount = 1.0 / (light.attenuation.x + light.attenuation.y * distance + light.attenuation.z * distance * distance);
+      if (kind == SPOT) {
+        float cos_angle = dot(-to_light, normalize(light.direction.xyz));
+        float cone = max(light.direction.w - light.attenuation.w, 1e-6);
+        amount *= clamp((cos_angle - light.attenuation.w) / cone, 0.0, 1.0);
+      }
+    }
+    amount *= shadowing(i, IN.WorldPosition, normalize(IN.Normal));
+
+    float n_dot_l = dot(normal, to_light);
+    if (n_dot_l <= 0.0) {
+      continue;
+    }
+    vec3 halfway = normalize(to_light + to_eye);
+    vec3 fresnel = fresnel_schlick(max(dot(halfway, to_eye), 0.0), f0);
+    vec3 specular = fresnel * distribution_ggx(max(dot(normal, halfway), 0.0), roughness)
+      * geometry_smith(n_dot_v, n_dot_l, roughness) / (4.0 * n_dot_v * n_dot_l);
+    // what is not reflected is refracted and scattered, except by metals which absorb it
+    vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic);
+
+    vec3 radiance = light.color.rgb * light.color.w * amount;
+    color += (diffuse * base_color.rgb / PI + specular) * radiance * n_dot_l;
+  }
+
+  // split sum image based light, the environment replaces the flat ambient term
+  vec3 fresnel = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+  vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * texture(irradiance_map, normal).rgb * base_color.rgb;
+  vec3 reflected = reflect(-to_eye, normal);
+  vec3 prefiltered = textureLod(prefiltered_map, reflected, roughness * float(PREFILTERED_LEVELS - 1)).rgb;
+  vec2 brdf = texture(brdf_lut, vec2(n_dot_v, roughness)).rg;
+  vec3 specular = prefiltered * (fresnel * brdf.x + brdf.y);
+  color += (diffuse + specular) * occlusion + emissive;
+
+  Color = vec4(pow(tone_map(color), vec3(1.0 / gamma)), base_color.a);
+}
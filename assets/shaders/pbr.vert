@@ -0,0 +1,26 @@
+#version 330 core
+
+layout (location = 0) in vec3 Position;
+layout (location = 1) in vec3 Normal;
+layout (location = 2) in vec2 Uv;
+layout (location = 3) in vec4 Tangent;    // xyz, w is the handedness of the bitangent
+
+uniform mat4 MVP;
+uniform mat4 Model;
+uniform mat3 NormalMatrix;
+
+out VS_OUTPUT {
+  vec3 WorldPosition;
+  vec3 Normal;
+  vec2 Uv;
+  vec4 Tangent;
+} OUT;
+
+void main()
+{
+  gl_Position = MVP * vec4(Position, 1.0);
+  OUT.WorldPosition = (Model * vec4(Position, 1.0)).xyz;
+  OUT.Normal = NormalMatrix * Normal;
+  OUT.Uv = Uv;
+  OUT.Tangent = vec4(mat3(Model) * Tangent.xyz, Tangent.w);
+}
@@ -0,0 +1,19 @@
+#version 330 core
+
+// Adds the blurred bright parts back onto the input
+
+uniform sampler2D source;
+uniform sampler2D bloom;
+uniform float intensity;
+
+in VS_OUTPUT {
+  vec2 Uv;
+} IN;
+
+out vec4 Color;
+
+void main()
+{
+  vec4 color = texture(source, IN.Uv);
+  Color = vec4(color.rgb + texture(bloom, IN.Uv).rgb * intensity, color.a);
+}
@@ -0,0 +1,31 @@
+#version 330 core
+
+// The parts of the input brighter than the threshold, with a soft knee
+
+uniform sampler2D source;
+uniform vec2 texel_size;
+uniform float threshold;
+uniform float knee;
+
+in VS_OUTPUT {
+  vec2 Uv;
+} IN;
+
+out vec4 Color;
+
+void main()
+{
+  // four taps averages the full resolution input down to half
+  vec2 offset = texel_size * 0.5;
+  vec3 color = (texture(source, IN.Uv + vec2(-offset.x, -offset.y)).rgb
+              + texture(source, IN.Uv + vec2( offset.x, -offset.y)).rgb
+              + texture(source, IN.Uv + vec2(-offset.x,  offset.y)).rgb
+              + texture(source, IN.Uv + vec2( offset.x,  offset.y)).rgb) * 0.25;
+
+  float brightness = max(color.r, max(color.g, color.b));
+  float soft = clamp(brightness - threshold + knee, 0.0, 2.0 * knee);
+  soft = soft * soft / (4.0 * knee + 0.0001);
+  float contribution = max(soft, brightness - threshold) / max(brightness, 0.0001);
+
+  Color = vec4(color * contribution, 1.0);
+}
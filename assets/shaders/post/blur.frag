@@ -0,0 +1,27 @@
+#version 330 core
+
+// Separable gaussian blur along `direction`, nine taps read with five linear samples
+
+uniform sampler2D source;
+uniform vec2 texel_size;
+uniform vec2 direction;
+
+in VS_OUTPUT {
+  vec2 Uv;
+} IN;
+
+out vec4 Color;
+
+const float OFFSETS[3] = float[](0.0, 1.3846153846, 3.2307692308);
+const float WEIGHTS[3] = float[](0.2270270270, 0.3162162162, 0.0702702703);
+
+void main()
+{
+  vec2 step = direction * texel_size;
+  vec3 color = texture(source, IN.Uv).rgb * WEIGHTS[0];
+  for (int i = 1; i < 3; i++) {
+    color += texture(source, IN.Uv + step * OFFSETS[i]).rgb * WEIGHTS[i];
+    color += texture(source, IN.Uv - step * OFFSETS[i]).rgb * WEIGHTS[i];
+  }
+  Color = vec4(color, 1.0);
+}
@@ -0,0 +1,23 @@
+#version 330 core
+
+// Looks the color up in a 3D table, expects display space colors in [0, 1]
+
+uniform sampler2D source;
+uniform sampler3D lut;
+uniform float lut_size;
+uniform float strength;
+
+in VS_OUTPUT {
+  vec2 Uv;
+} IN;
+
+out vec4 Color;
+
+void main()
+{
+  vec4 color = texture(source, IN.Uv);
+  // sample the centers of the first and last texels at 0 and 1
+  vec3 coordinate = clamp(color.rgb, 0.0, 1.0) * ((lut_size - 1.0) / lut_size) + 0.5 / lut_size;
+  vec3 graded = texture(lut, coordinate).rgb;
+  Color = vec4(mix(color.rgb, graded, strength), color.a);
+}
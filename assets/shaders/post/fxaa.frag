@@ -0,0 +1,47 @@
+#version 330 core
+
+// Fast approximate anti-aliasing, after Lottes' FXAA 3.11 console version.
+// Expects display space colors, so it goes after tone mapping.
+
+uniform sampler2D source;
+uniform vec2 texel_size;
+uniform float span_max;
+uniform float reduce_min;
+uniform float reduce_mul;
+
+in VS_OUTPUT {
+  vec2 Uv;
+} IN;
+
+out vec4 Color;
+
+const vec3 LUMA = vec3(0.299, 0.587, 0.114);
+
+void main()
+{
+  float luma_nw = dot(texture(source, IN.Uv + vec2(-1.0, -1.0) * texel_size).rgb, LUMA);
+  float luma_ne = dot(texture(source, IN.Uv + vec2( 1.0, -1.0) * texel_size).rgb, LUMA);
+  float luma_sw = dot(texture(source, IN.Uv + vec2(-1.0,  1.0) * texel_size).rgb, LUMA);
+  float luma_se = dot(texture(source, IN.Uv + vec2( 1.0,  1.0) * texel_size).rgb, LUMA);
+  vec4 center = texture(source, IN.Uv);
+  float luma_m = dot(center.rgb, LUMA);
+
+  float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+  float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+  // blur along the edge, perpendicular to the luma gradient
+  vec2 direction = vec2(-((luma_nw + luma_ne) - (luma_sw + luma_se)),
+                          (luma_nw + luma_sw) - (luma_ne + luma_se));
+  float reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * reduce_mul, reduce_min);
+  float scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+  direction = clamp(direction * scale, vec2(-span_max), vec2(span_max)) * texel_size;
+
+  vec3 a = 0.5 * (texture(source, IN.Uv + direction * (1.0 / 3.0 - 0.5)).rgb
+                + texture(source, IN.Uv + direction * (2.0 / 3.0 - 0.5)).rgb);
+  vec3 b = a * 0.5 + 0.25 * (texture(source, IN.Uv + direction * -0.5).rgb
+                           + texture(source, IN.Uv + direction * 0.5).rgb);
+
+  // the wider sample crossed another edge, keep the narrow one
+  float luma_b = dot(b, LUMA);
+  Color = vec4((luma_b < luma_min || luma_b > luma_max) ? a : b, center.a);
+}
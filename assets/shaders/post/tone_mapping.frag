@@ -0,0 +1,38 @@
+#version 330 core
+
+// Maps hdr color into [0, 1] and gamma corrects it. After this pass colors are in display space.
+
+uniform sampler2D source;
+uniform int operator;      // 0 none, 1 Reinhard, 2 ACES
+uniform float exposure;
+uniform float gamma;
+
+in VS_OUTPUT {
+  vec2 Uv;
+} IN;
+
+out vec4 Color;
+
+vec3 reinhard(vec3 color)
+{
+  return color / (color + vec3(1.0));
+}
+
+// Narkowicz's fit of the ACES filmic curve
+vec3 aces(vec3 color)
+{
+  const float a = 2.51;
+  const float b = 0.03;
+  const float c = 2.43;
+  const float d = 0.59;
+  const float e = 0.14;
+  return clamp((color * (a * color + b)) / (color * (c * color + d) + e), 0.0, 1.0);
+}
+
+void main()
+{
+  vec4 color = texture(source, IN.Uv);
+  vec3 exposed = color.rgb * exposure;
+  vec3 mapped = operator == 2 ? aces(exposed) : operator == 1 ? reinhard(exposed) : clamp(exposed, 0.0, 1.0);
+  Color = vec4(pow(mapped, vec3(1.0 / gamma)), color.a);
+}
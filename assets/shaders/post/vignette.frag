@@ -0,0 +1,22 @@
+#version 330 core
+
+// Darkens the corners of the screen
+
+uniform sampler2D source;
+uniform float strength;
+uniform float radius;      // distance from the center, in half screens, where darkening starts
+uniform float softness;
+
+in VS_OUTPUT {
+  vec2 Uv;
+} IN;
+
+out vec4 Color;
+
+void main()
+{
+  vec4 color = texture(source, IN.Uv);
+  float distance = length(IN.Uv - 0.5) * 2.0;
+  float vignette = smoothstep(radius, radius + softness, distance);
+  Color = vec4(color.rgb * (1.0 - vignette * strength), color.a);
+}
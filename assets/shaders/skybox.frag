@@ -0,0 +1,16 @@
+#version 330 core
+
+// The sky in the direction of the pixel, in linear hdr like the lit shaders
+
+uniform samplerCube sky;
+
+in VS_OUTPUT {
+  vec3 Direction;
+} IN;
+
+out vec4 Color;
+
+void main()
+{
+  Color = vec4(textureLod(sky, normalize(IN.Direction), 0.0).rgb, 1.0);
+}
@@ -0,0 +1,19 @@
+#version 330 core
+
+// A triangle covering the screen at the far plane, made from gl_VertexID
+
+uniform mat4 ClipToWorld;  // inverse of the projection * view without translation
+
+out VS_OUTPUT {
+  vec3 Direction;
+} OUT;
+
+void main()
+{
+  vec2 position = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2) * 2.0 - 1.0;
+  // z = w puts it at depth 1 after the perspective divide
+  gl_Position = vec4(position, 1.0, 1.0);
+
+  vec4 world = ClipToWorld * vec4(position, 1.0, 1.0);
+  OUT.Direction = world.xyz / world.w;
+}
@@ -3,17 +3,26 @@ extern crate gl_generator;
 use gl_generator::{Registry, Fallbacks, StructGenerator, Api, Profile, DebugStructGenerator};
 use std::env;
 use std::fs::File;
+use std::io::{self, Write};
 use std::path::Path;
 
 fn main() {
     let out_dir = env::var("OUT_DIR").unwrap();
     let mut file_gl = File::create(&Path::new(&out_dir).join("bindings.rs")).unwrap();
 
-    let registry = Registry::new(Api::Gl, (4, 5), Profile::Core, Fallbacks::All, [
+    let mut registry = Registry::new(Api::Gl, (4, 5), Profile::Core, Fallbacks::All, [
         "GL_NV_command_list", // additional extension we want to use
+        "GL_NV_shader_buffer_load", // buffer gpu addresses for command list tokens
+        "GL_NV_vertex_buffer_unified_memory",
     ]);
 
-    if env::var("CARGO_FEATURE_DEBUG").is_ok() {
+    // NV_vertex_buffer_unified_memory toggles its client states through these, which the
+    // core profile otherwise removed
+    let compatibility = Registry::new(Api::Gl, (4, 5), Profile::Compatibility, Fallbacks::All, []);
+    registry.cmds.extend(compatibility.cmds.into_iter()
+        .filter(|cmd| cmd.proto.ident == "EnableClientState" || cmd.proto.ident == "DisableClientState"));
+
+    if env::var("CARGO_FEATURE_TRACE_CALLS").is_ok() {
         registry.write_bindings(
             DebugStructGenerator,
             &mut file_gl
@@ -25,5 +34,140 @@ fn main() {
         ).unwrap();
     }
 
+    if env::var("CARGO_FEATURE_RECORDING").is_ok() {
+        write_recording_functions(&registry, &mut file_gl).unwrap();
+    }
+
+    if env::var("CARGO_FEATURE_ERROR_CHECK").is_ok() {
+        let mut file_checked = File::create(Path::new(&out_dir).join("error_checked.rs")).unwrap();
+        write_error_checked_methods(&registry, &mut file_checked).unwrap();
+    }
+}
+
+/**
+ * Writes an `impl Gl` with a wrapper per gl command that calls through to the bindings and
+ * then `check_call`, which drains `glGetError`. The wrappers are `#[track_caller]`, so a
+ * failure is reported at the line in our code that made the call. Arguments are only
+ * formatted when the call failed.
+ */
+fn write_error_checked_methods<W: Write>(registry: &Registry, dest: &mut W)
+-> io::Result<()>
+{
+    writeln!(dest, "#[allow(non_snake_case, clippy::let_unit_value, clippy::unused_unit, clippy::missing_safety_doc, clippy::too_many_arguments)]")?;
+    writeln!(dest, "impl Gl {{")?;
+
+    for cmd in registry.cmds.iter().filter(|cmd| cmd.proto.ident != "GetError") {
+        // the bindings refer to c_void through a private module
+        let ty = |ty: &str| ty.replace("__gl_imports::", "std::os::");
+
+        let params: Vec<String> = cmd.params.iter()
+            .map(|p| format!("{}: {}", p.ident, ty(&p.ty)))
+            .collect();
+        let idents: Vec<&str> = cmd.params.iter().map(|p| p.ident.as_str()).collect();
+        let formats: Vec<String> = idents.iter().map(|ident| format!("{}: {{:?}}", ident)).collect();
+
+        writeln!(dest, "    #[track_caller]")?;
+        writeln!(dest, "    #[inline]")?;
+        writeln!(dest, "    pub unsafe fn {}(&self, {}) -> {} {{", cmd.proto.ident, params.join(", "), ty(&cmd.proto.ty))?;
+        writeln!(dest, "        let result = self.inner.{}({});", cmd.proto.ident, idents.join(", "))?;
+        if idents.is_empty() {
+            writeln!(dest, "        self.check_call(\"{}\", String::new);", cmd.proto.ident)?;
+        } else {
+            writeln!(
+                dest,
+                "        self.check_call(\"{}\", || format!(\"{}\", {}));",
+                cmd.proto.ident,
+                formats.join(", "),
+                idents.join(", ")
+            )?;
+        }
+        writeln!(dest, "        result")?;
+        writeln!(dest, "    }}")?;
+    }
+
+    writeln!(dest, "}}")
 }
 
+/**
+ * Writes a `recording_functions` module with one fake `extern "system"` function per gl
+ * command, plus a `lookup` loader to hand to `Gl::load_with`. Every fake logs its call
+ * through `crate::recording`; a few naming conventions of the gl api get fake behaviour:
+ *
+ * - `Gen*` / `Create*` with `n` and a `*mut GLuint` output fill it with fresh names
+ * - `Create*` returning a `GLuint` returns a fresh name
+ * - `*Location` commands return a stable location per name string
+ * - `Get*` with a `pname` and a `*mut GLint` output write the configured integer
+ * - everything else returns the configured value for that command, or zero / null
+ */
+fn write_recording_functions<W: Write>(registry: &Registry, dest: &mut W)
+-> io::Result<()>
+{
+    writeln!(dest, "pub(crate) mod recording_functions {{")?;
+    writeln!(dest, "    #![allow(non_snake_case, unused_variables, unused_unsafe)]")?;
+    writeln!(dest, "    use super::{{types, __gl_imports}};")?;
+    writeln!(dest, "    use crate::recording::{{self, Arg}};")?;
+
+    for cmd in &registry.cmds {
+        let name = &cmd.proto.ident;
+        let has_param = |ident: &str| cmd.params.iter().any(|p| p.ident == ident);
+        let last_param = cmd.params.last();
+
+        let params: Vec<String> = cmd.params.iter()
+            .map(|p| format!("{}: {}", p.ident, p.ty))
+            .collect();
+
+        let generates_names = (name.starts_with("Gen") || name.starts_with("Create"))
+            && has_param("n")
+            && last_param.is_some_and(|p| p.ty == "*mut types::GLuint");
+
+        let args: Vec<String> = cmd.params.iter()
+            .enumerate()
+            .map(|(i, p)| {
+                if generates_names && i == cmd.params.len() - 1 {
+                    format!("unsafe {{ recording::generate_names(n, {}) }}", p.ident)
+                } else if p.ty == "*const types::GLuint" && has_param("n") {
+                    format!("unsafe {{ recording::names(n, {}) }}", p.ident)
+                } else if p.ty == "*const types::GLchar" && p.ident == "name" {
+                    format!("unsafe {{ recording::string({}) }}", p.ident)
+                } else {
+                    format!("Arg::from({})", p.ident)
+                }
+            })
+            .collect();
+
+        writeln!(dest, "    pub extern \"system\" fn {}({}) -> {} {{", name, params.join(", "), cmd.proto.ty)?;
+        writeln!(dest, "        recording::record(\"{}\", vec![{}]);", name, args.join(", "))?;
+
+        if name.starts_with("Get") && has_param("pname") {
+            if let Some(p) = last_param.filter(|p| p.ty == "*mut types::GLint") {
+                writeln!(dest, "        unsafe {{ recording::write_integer(pname, {}) }}", p.ident)?;
+            }
+        }
+
+        if cmd.proto.ty == "()" {
+            // nothing to return
+        } else if name.starts_with("Create") && cmd.proto.ty == "types::GLuint" {
+            writeln!(dest, "        recording::generate_name()")?;
+        } else if name.ends_with("Location") && has_param("name") {
+            writeln!(dest, "        unsafe {{ recording::location(name) }}")?;
+        } else {
+            writeln!(dest, "        recording::fake_return(\"{}\")", name)?;
+        }
+
+        writeln!(dest, "    }}")?;
+    }
+
+    writeln!(dest, "    pub fn lookup(symbol: &str) -> *const __gl_imports::raw::c_void {{")?;
+    writeln!(dest, "        match symbol {{")?;
+    for cmd in &registry.cmds {
+        writeln!(
+            dest,
+            "            \"gl{0}\" => {0} as *const __gl_imports::raw::c_void,",
+            cmd.proto.ident
+        )?;
+    }
+    writeln!(dest, "            _ => ::std::ptr::null(),")?;
+    writeln!(dest, "        }}")?;
+    writeln!(dest, "    }}")?;
+    writeln!(dest, "}}")
+}
@@ -0,0 +1,130 @@
+#[cfg(feature = "error_check")]
+use std::cell::RefCell;
+use std::fmt;
+use std::panic::Location;
+
+use super::types::GLenum;
+
+/**
+ * A `glGetError` code
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Fail)]
+pub struct ErrorCode(pub GLenum);
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.0 {
+            super::INVALID_ENUM => "GL_INVALID_ENUM",
+            super::INVALID_VALUE => "GL_INVALID_VALUE",
+            super::INVALID_OPERATION => "GL_INVALID_OPERATION",
+            super::INVALID_FRAMEBUFFER_OPERATION => "GL_INVALID_FRAMEBUFFER_OPERATION",
+            super::OUT_OF_MEMORY => "GL_OUT_OF_MEMORY",
+            super::STACK_UNDERFLOW => "GL_STACK_UNDERFLOW",
+            super::STACK_OVERFLOW => "GL_STACK_OVERFLOW",
+            super::CONTEXT_LOST => "GL_CONTEXT_LOST",
+            _ => return write!(f, "gl error 0x{:04X}", self.0),
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/**
+ * The first gl call that raised an error since the last `Gl::check_errors`,
+ * with the error code as its cause
+ */
+#[derive(Debug, Fail)]
+#[fail(display = "gl{}({}) failed at {}{}", function, arguments, location, later)]
+pub struct CallError {
+    pub function: &'static str,
+    pub arguments: String,
+    pub location: &'static Location<'static>,
+    #[cause] pub code: ErrorCode,
+    later: LaterErrors,
+}
+
+impl CallError {
+    /**
+     * How many more gl errors were raised after this one before they were checked
+     */
+    pub fn later_errors(&self) -> usize {
+        self.later.0
+    }
+}
+
+#[derive(Debug, Default)]
+struct LaterErrors(usize);
+
+impl fmt::Display for LaterErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            0 => Ok(()),
+            n => write!(f, " (and {} later errors)", n),
+        }
+    }
+}
+
+/**
+ * Errors seen by the checked wrappers, shared by every clone of a `Gl`
+ */
+#[cfg(feature = "error_check")]
+#[derive(Default)]
+pub(crate) struct ErrorLog {
+    first: RefCell<Option<CallError>>,
+}
+
+#[cfg(feature = "error_check")]
+impl ErrorLog {
+    pub(crate) fn record(
+        &self,
+        function: &'static str,
+        arguments: String,
+        location: &'static Location<'static>,
+        code: GLenum
+    )
+    {
+        let mut first = self.first.borrow_mut();
+        match first.as_mut() {
+            Some(error) => error.later.0 += 1,
+            None => *first = Some(CallError {
+                function,
+                arguments,
+                location,
+                code: ErrorCode(code),
+                later: LaterErrors(0),
+            }),
+        }
+    }
+
+    pub(crate) fn take(&self) -> Option<CallError> {
+        self.first.borrow_mut().take()
+    }
+}
+
+#[cfg(all(test, feature = "recording", feature = "error_check"))]
+mod tests {
+    use crate::recording::Recorder;
+
+    #[test]
+    fn failed_call_is_reported_with_arguments_and_call_site() {
+        let (gl, recorder) = Recorder::new();
+
+        unsafe { gl.BindBuffer(0x1234, 7); }
+        assert!(gl.check_errors().is_ok());
+
+        recorder.set_return("GetError", crate::INVALID_ENUM as i64);
+        unsafe { gl.BindBuffer(0x1234, 7); }
+        let failed_line = line!() - 1;
+        recorder.set_return("GetError", crate::NO_ERROR as i64);
+
+        let error = gl.check_errors().unwrap_err();
+        assert_eq!(error.function, "BindBuffer");
+        assert_eq!(error.arguments, "target: 4660, buffer: 7");
+        assert_eq!(error.location.file(), file!());
+        assert_eq!(error.location.line(), failed_line);
+        assert_eq!(error.code.to_string(), "GL_INVALID_ENUM");
+        assert_eq!(error.later_errors(), super::super::MAX_ERRORS_PER_CALL - 1);
+
+        // taken by the check
+        assert!(gl.check_errors().is_ok());
+    }
+}
@@ -5,6 +5,16 @@ mod bindings {
 
 pub use bindings::*;
 pub use bindings::Gl as InnerGl; // This is to allow us to view docs for Gl since we are shadowing it with our custom Gl implementation
+#[macro_use] extern crate failure;
+
+#[cfg(feature = "recording")]
+pub mod recording;
+mod error_check;
+mod state_cache;
+
+pub use error_check::{CallError, ErrorCode};
+pub use state_cache::StateCounters;
+
 use std::rc::Rc;
 use std::ops::Deref;
 
@@ -17,6 +27,9 @@ use std::ops::Deref;
 #[derive(Clone)]
 pub struct Gl {
     inner: Rc<bindings::Gl>,
+    state: Rc<state_cache::StateCache>,
+    #[cfg(feature = "error_check")]
+    errors: Rc<error_check::ErrorLog>,
 }
 
 impl Gl {
@@ -24,11 +37,158 @@ impl Gl {
         where F: FnMut(&'static str) -> *const types::GLvoid
     {
         Gl {
-            inner: Rc::new(bindings::Gl::load_with(loadfn))
+            inner: Rc::new(bindings::Gl::load_with(loadfn)),
+            state: Rc::new(state_cache::StateCache::default()),
+            #[cfg(feature = "error_check")]
+            errors: Rc::new(error_check::ErrorLog::default()),
+        }
+    }
+
+    /**
+     * Returns the first gl call that failed since the last check, with its arguments and
+     * call site. Calls are only checked with the `error_check` feature, without it this
+     * always succeeds, so it is free to call once per frame.
+     */
+    pub fn check_errors(&self) -> Result<(), CallError> {
+        #[cfg(feature = "error_check")]
+        {
+            if let Some(error) = self.errors.take() {
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    // -----------------------------------------------------------------
+    // Cached state changes. These skip the gl call when the cache says it would not change
+    // anything, so everything that binds programs, vertex arrays, buffers or textures or
+    // toggles capabilities should go through them (or call `invalidate_state_cache` after).
+
+    #[track_caller]
+    pub fn use_program(&self, program: types::GLuint) {
+        if self.state.use_program(program) {
+            unsafe { self.UseProgram(program); }
+        }
+    }
+
+    #[track_caller]
+    pub fn bind_vertex_array(&self, vertex_array: types::GLuint) {
+        if self.state.bind_vertex_array(vertex_array) {
+            unsafe { self.BindVertexArray(vertex_array); }
+        }
+    }
+
+    #[track_caller]
+    pub fn bind_buffer(&self, target: types::GLenum, buffer: types::GLuint) {
+        if self.state.bind_buffer(target, buffer) {
+            unsafe { self.BindBuffer(target, buffer); }
+        }
+    }
+
+    #[track_caller]
+    pub fn active_texture(&self, unit: u32) {
+        if self.state.active_texture(unit) {
+            unsafe { self.ActiveTexture(TEXTURE0 + unit); }
+        }
+    }
+
+    /**
+     * Binds `texture` on the active texture unit
+     */
+    #[track_caller]
+    pub fn bind_texture(&self, target: types::GLenum, texture: types::GLuint) {
+        match self.state.active_texture_unit() {
+            Some(unit) => self.bind_texture_to_unit(unit, target, texture),
+            None => unsafe { self.BindTexture(target, texture); },  // unknown unit, can't cache
+        }
+    }
+
+    #[track_caller]
+    pub fn bind_texture_to_unit(&self, unit: u32, target: types::GLenum, texture: types::GLuint) {
+        self.active_texture(unit);
+        if self.state.bind_texture(unit, target, texture) {
+            unsafe { self.BindTexture(target, texture); }
+        }
+    }
+
+    #[track_caller]
+    pub fn set_capability(&self, capability: types::GLenum, enabled: bool) {
+        if self.state.set_capability(capability, enabled) {
+            unsafe {
+                if enabled {
+                    self.Enable(capability);
+                } else {
+                    self.Disable(capability);
+                }
+            }
+        }
+    }
+
+    /**
+     * Call from `Drop` before deleting an object, so a reused name is not mistaken for bound
+     */
+    pub fn forget_program(&self, program: types::GLuint) {
+        self.state.forget_program(program);
+    }
+
+    pub fn forget_vertex_array(&self, vertex_array: types::GLuint) {
+        self.state.forget_vertex_array(vertex_array);
+    }
+
+    pub fn forget_buffer(&self, buffer: types::GLuint) {
+        self.state.forget_buffer(buffer);
+    }
+
+    pub fn forget_texture(&self, texture: types::GLuint) {
+        self.state.forget_texture(texture);
+    }
+
+    /**
+     * Forget all cached state, eg. after code that calls gl directly
+     */
+    pub fn invalidate_state_cache(&self) {
+        self.state.invalidate();
+    }
+
+    pub fn state_counters(&self) -> StateCounters {
+        self.state.counters()
+    }
+
+    pub fn reset_state_counters(&self) {
+        self.state.reset_counters();
+    }
+
+    /**
+     * Called by the generated wrappers after every gl call
+     */
+    #[cfg(feature = "error_check")]
+    #[track_caller]
+    fn check_call<F>(&self, function: &'static str, arguments: F)
+        where F: Fn() -> String
+    {
+        let location = std::panic::Location::caller();
+        #[cfg(feature = "recording")]
+        let _unrecorded = recording::Unrecorded::begin();
+
+        // each call clears one error flag, bounded since a lost context may keep reporting
+        for _ in 0..MAX_ERRORS_PER_CALL {
+            let code = unsafe { self.inner.GetError() };
+            if code == NO_ERROR {
+                break;
+            }
+            self.errors.record(function, arguments(), location, code);
         }
     }
 }
 
+#[cfg(feature = "error_check")]
+const MAX_ERRORS_PER_CALL: usize = 8;
+
+// With `error_check` every gl function gets an inherent wrapper on `Gl`, which takes
+// precedence over the unchecked function reached through `Deref`
+#[cfg(feature = "error_check")]
+include!(concat!(env!("OUT_DIR"), "/error_checked.rs"));
+
 impl Deref for Gl {
     type Target = bindings::Gl;
     fn deref(&self) -> &bindings::Gl {
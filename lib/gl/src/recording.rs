@@ -0,0 +1,282 @@
+/*!
+ * A fake gl backend for unit tests, enabled with the `recording` feature.
+ *
+ * `Recorder::new` hands out a `Gl` whose function pointers are generated fakes (see
+ * `write_recording_functions` in build.rs) instead of driver entry points, so code that
+ * takes a `&gl::Gl` runs unchanged without a context. Every call is logged with its
+ * arguments, and objects get fake names 1, 2, 3, ... in creation order.
+ *
+ * The log lives in a thread local, so tests running in parallel don't see each other's calls,
+ * but every `Gl` created on one thread shares it.
+ */
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::marker::PhantomData;
+
+use super::bindings::recording_functions;
+use super::types::*;
+use super::Gl;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Arg {
+    Int(i64),
+    Float(f64),
+    Ptr(usize),
+    Names(Vec<GLuint>),     // contents of a `n`, `*const GLuint` pair, or the names written by a Gen* call
+    Str(String),            // a null terminated `name` argument
+}
+
+/**
+ * One logged gl call, `name` is the command without the `gl` prefix
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Call {
+    pub name: &'static str,
+    pub args: Vec<Arg>,
+}
+
+impl Call {
+    /**
+     * Integer argument `index`, panics if the argument is not an integer
+     */
+    pub fn int(&self, index: usize) -> i64 {
+        match self.args.get(index) {
+            Some(Arg::Int(value)) => *value,
+            other => panic!("argument {} of {} is {:?}, not an integer", index, self.name, other),
+        }
+    }
+
+    /**
+     * Names argument `index`, panics if the argument is not a list of names
+     */
+    pub fn names(&self, index: usize) -> &[GLuint] {
+        match self.args.get(index) {
+            Some(Arg::Names(names)) => names,
+            other => panic!("argument {} of {} is {:?}, not names", index, self.name, other),
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    calls: Vec<Call>,
+    last_name: GLuint,
+    locations: HashMap<String, GLint>,
+    integers: HashMap<GLenum, GLint>,
+    returns: HashMap<&'static str, i64>,
+    unrecorded: bool,
+}
+
+impl State {
+    fn new() -> State {
+        let mut state = State::default();
+
+        // compiling and linking succeed unless a test says otherwise
+        state.integers.insert(super::COMPILE_STATUS, super::TRUE as GLint);
+        state.integers.insert(super::LINK_STATUS, super::TRUE as GLint);
+        state.integers.insert(super::VALIDATE_STATUS, super::TRUE as GLint);
+
+        state.returns.insert("CheckFramebufferStatus", super::FRAMEBUFFER_COMPLETE as i64);
+        state.returns.insert("CheckNamedFramebufferStatus", super::FRAMEBUFFER_COMPLETE as i64);
+
+        state
+    }
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State::new());
+}
+
+/**
+ * Handle to the call log of the current thread. Not `Send`, the log is per thread.
+ */
+pub struct Recorder {
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Recorder {
+    /**
+     * Resets the log of this thread and returns a fake `Gl` recording into it
+     */
+    pub fn new() -> (Gl, Recorder) {
+        STATE.with(|state| *state.borrow_mut() = State::new());
+
+        let gl = Gl::load_with(recording_functions::lookup);
+        (gl, Recorder { _not_send: PhantomData })
+    }
+
+    pub fn calls(&self) -> Vec<Call> {
+        STATE.with(|state| state.borrow().calls.clone())
+    }
+
+    /**
+     * Returns the calls logged so far and clears the log
+     */
+    pub fn take_calls(&self) -> Vec<Call> {
+        STATE.with(|state| ::std::mem::take(&mut state.borrow_mut().calls))
+    }
+
+    pub fn calls_to(&self, name: &str) -> Vec<Call> {
+        STATE.with(|state| {
+            state.borrow().calls.iter().filter(|call| call.name == name).cloned().collect()
+        })
+    }
+
+    pub fn count(&self, name: &str) -> usize {
+        STATE.with(|state| state.borrow().calls.iter().filter(|call| call.name == name).count())
+    }
+
+    pub fn last_call_to(&self, name: &str) -> Option<Call> {
+        STATE.with(|state| state.borrow().calls.iter().rev().find(|call| call.name == name).cloned())
+    }
+
+    /**
+     * Value written by `Get*iv` calls querying `pname` (default 0, statuses default to TRUE)
+     */
+    pub fn set_integer(&self, pname: GLenum, value: GLint) {
+        STATE.with(|state| state.borrow_mut().integers.insert(pname, value));
+    }
+
+    /**
+     * Value returned by every call to `command` (default 0 / null, or FRAMEBUFFER_COMPLETE)
+     */
+    pub fn set_return(&self, command: &'static str, value: i64) {
+        STATE.with(|state| state.borrow_mut().returns.insert(command, value));
+    }
+}
+
+// -----------------------------------------------------------------
+// called from the generated fake functions
+
+pub(crate) fn record(name: &'static str, args: Vec<Arg>) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if !state.unrecorded {
+            state.calls.push(Call { name, args });
+        }
+    });
+}
+
+/**
+ * Keeps calls out of the log while alive, so the `GetError`s of the `error_check`
+ * wrappers don't show up between the calls a test looks at
+ */
+#[cfg(feature = "error_check")]
+pub(crate) struct Unrecorded(());
+
+#[cfg(feature = "error_check")]
+impl Unrecorded {
+    pub(crate) fn begin() -> Unrecorded {
+        STATE.with(|state| state.borrow_mut().unrecorded = true);
+        Unrecorded(())
+    }
+}
+
+#[cfg(feature = "error_check")]
+impl Drop for Unrecorded {
+    fn drop(&mut self) {
+        STATE.with(|state| state.borrow_mut().unrecorded = false);
+    }
+}
+
+pub(crate) fn generate_name() -> GLuint {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.last_name += 1;
+        state.last_name
+    })
+}
+
+pub(crate) unsafe fn generate_names(n: GLsizei, names: *mut GLuint) -> Arg {
+    let generated: Vec<GLuint> = (0..n).map(|_| generate_name()).collect();
+    if !names.is_null() {
+        ::std::ptr::copy_nonoverlapping(generated.as_ptr(), names, generated.len());
+    }
+    Arg::Names(generated)
+}
+
+pub(crate) unsafe fn names(n: GLsizei, names: *const GLuint) -> Arg {
+    if names.is_null() {
+        return Arg::Ptr(0);
+    }
+    Arg::Names(::std::slice::from_raw_parts(names, n.max(0) as usize).to_vec())
+}
+
+pub(crate) unsafe fn string(name: *const GLchar) -> Arg {
+    if name.is_null() {
+        return Arg::Ptr(0);
+    }
+    Arg::Str(CStr::from_ptr(name).to_string_lossy().into_owned())
+}
+
+pub(crate) unsafe fn location(name: *const GLchar) -> GLint {
+    let name = match string(name) {
+        Arg::Str(name) => name,
+        _ => return -1,
+    };
+
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let next = state.locations.len() as GLint;
+        *state.locations.entry(name).or_insert(next)
+    })
+}
+
+pub(crate) unsafe fn write_integer(pname: GLenum, params: *mut GLint) {
+    if !params.is_null() {
+        *params = STATE.with(|state| state.borrow().integers.get(&pname).cloned().unwrap_or(0));
+    }
+}
+
+pub(crate) fn fake_return<T: FakeReturn>(command: &'static str) -> T {
+    T::from_i64(STATE.with(|state| state.borrow().returns.get(command).cloned().unwrap_or(0)))
+}
+
+pub(crate) trait FakeReturn {
+    fn from_i64(value: i64) -> Self;
+}
+
+macro_rules! impl_fake_return_for_int {
+    ($($t:ty),*) => { $(
+        impl FakeReturn for $t {
+            fn from_i64(value: i64) -> $t { value as $t }
+        }
+    )* }
+}
+
+impl_fake_return_for_int!(u8, u16, u32, i32, u64);
+
+impl<T> FakeReturn for *const T {
+    fn from_i64(value: i64) -> *const T { value as usize as *const T }
+}
+
+impl<T> FakeReturn for *mut T {
+    fn from_i64(value: i64) -> *mut T { value as usize as *mut T }
+}
+
+// -----------------------------------------------------------------
+// argument conversions for every parameter type in the bindings
+
+macro_rules! impl_arg_from {
+    ($variant:ident as $as:ty: $($t:ty),*) => { $(
+        impl From<$t> for Arg {
+            fn from(value: $t) -> Arg { Arg::$variant(value as $as) }
+        }
+    )* }
+}
+
+impl_arg_from!(Int as i64: u8, i8, u16, i16, u32, i32, u64, i64, isize);
+impl_arg_from!(Float as f64: f32, f64);
+
+impl<T> From<*const T> for Arg {
+    fn from(value: *const T) -> Arg { Arg::Ptr(value as usize) }
+}
+
+impl<T> From<*mut T> for Arg {
+    fn from(value: *mut T) -> Arg { Arg::Ptr(value as usize) }
+}
+
+impl From<GLDEBUGPROC> for Arg {
+    fn from(value: GLDEBUGPROC) -> Arg { Arg::Ptr(value as usize) }
+}
@@ -0,0 +1,132 @@
+use std::cell::{ Cell, RefCell };
+use std::collections::HashMap;
+
+use super::types::{ GLenum, GLuint };
+
+/**
+ * How many state changes went to the driver and how many were dropped as redundant
+ */
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateCounters {
+    pub issued: u64,
+    pub skipped: u64,
+}
+
+/**
+ * The bindings and capabilities last set through `Gl`. `None` / missing entries mean unknown,
+ * in which case the call is always issued.
+ */
+#[derive(Default)]
+pub(crate) struct StateCache {
+    program: Cell<Option<GLuint>>,
+    vertex_array: Cell<Option<GLuint>>,
+    buffers: RefCell<HashMap<GLenum, GLuint>>,              // target -> buffer
+    active_texture_unit: Cell<Option<u32>>,
+    textures: RefCell<HashMap<(u32, GLenum), GLuint>>,      // (unit, target) -> texture
+    capabilities: RefCell<HashMap<GLenum, bool>>,
+    counters: Cell<StateCounters>,
+}
+
+impl StateCache {
+    /**
+     * Updates `slot` and returns true when the driver has to be told
+     */
+    fn change<T: PartialEq + Copy>(&self, slot: &Cell<Option<T>>, value: T) -> bool {
+        let changed = slot.get() != Some(value);
+        slot.set(Some(value));
+        self.count(changed)
+    }
+
+    fn change_entry<K, T>(&self, map: &RefCell<HashMap<K, T>>, key: K, value: T) -> bool
+        where K: ::std::hash::Hash + Eq, T: PartialEq + Copy
+    {
+        let changed = map.borrow_mut().insert(key, value) != Some(value);
+        self.count(changed)
+    }
+
+    fn count(&self, changed: bool) -> bool {
+        let mut counters = self.counters.get();
+        if changed {
+            counters.issued += 1;
+        } else {
+            counters.skipped += 1;
+        }
+        self.counters.set(counters);
+        changed
+    }
+
+    pub(crate) fn use_program(&self, program: GLuint) -> bool {
+        self.change(&self.program, program)
+    }
+
+    pub(crate) fn bind_vertex_array(&self, vertex_array: GLuint) -> bool {
+        let changed = self.change(&self.vertex_array, vertex_array);
+        if changed {
+            // the element array binding is part of the vertex array
+            self.buffers.borrow_mut().remove(&super::ELEMENT_ARRAY_BUFFER);
+        }
+        changed
+    }
+
+    pub(crate) fn bind_buffer(&self, target: GLenum, buffer: GLuint) -> bool {
+        self.change_entry(&self.buffers, target, buffer)
+    }
+
+    pub(crate) fn active_texture(&self, unit: u32) -> bool {
+        self.change(&self.active_texture_unit, unit)
+    }
+
+    pub(crate) fn active_texture_unit(&self) -> Option<u32> {
+        self.active_texture_unit.get()
+    }
+
+    pub(crate) fn bind_texture(&self, unit: u32, target: GLenum, texture: GLuint) -> bool {
+        self.change_entry(&self.textures, (unit, target), texture)
+    }
+
+    pub(crate) fn set_capability(&self, capability: GLenum, enabled: bool) -> bool {
+        self.change_entry(&self.capabilities, capability, enabled)
+    }
+
+    /**
+     * Deleting a bound object resets the binding to 0 (programs stay in use until replaced),
+     * and its name may be handed out again, so every binding of it becomes unknown
+     */
+    pub(crate) fn forget_program(&self, program: GLuint) {
+        if self.program.get() == Some(program) {
+            self.program.set(None);
+        }
+    }
+
+    pub(crate) fn forget_vertex_array(&self, vertex_array: GLuint) {
+        if self.vertex_array.get() == Some(vertex_array) {
+            self.vertex_array.set(None);
+            self.buffers.borrow_mut().remove(&super::ELEMENT_ARRAY_BUFFER);
+        }
+    }
+
+    pub(crate) fn forget_buffer(&self, buffer: GLuint) {
+        self.buffers.borrow_mut().retain(|_, bound| *bound != buffer);
+    }
+
+    pub(crate) fn forget_texture(&self, texture: GLuint) {
+        self.textures.borrow_mut().retain(|_, bound| *bound != texture);
+    }
+
+    pub(crate) fn invalidate(&self) {
+        self.program.set(None);
+        self.vertex_array.set(None);
+        self.buffers.borrow_mut().clear();
+        self.active_texture_unit.set(None);
+        self.textures.borrow_mut().clear();
+        self.capabilities.borrow_mut().clear();
+    }
+
+    pub(crate) fn counters(&self) -> StateCounters {
+        self.counters.get()
+    }
+
+    pub(crate) fn reset_counters(&self) {
+        self.counters.set(StateCounters::default());
+    }
+}
@@ -1,129 +1,352 @@
 // #[recursion_limit="128"] // tutorial used this line but the compiler says that it is not necessary. Leaving it here in case future issues arise
 
 extern crate proc_macro;
+extern crate proc_macro2;
 extern crate syn;
 #[macro_use] extern crate quote;
 
+use proc_macro2::{ Span, TokenStream };
+use syn::spanned::Spanned;
+
+/**
+ * Derives `vertex_attrib_pointers(gl)` and `vertex_layout()` for a `#[repr(C, packed)]`
+ * struct whose fields are all `render_gl::data` types tagged with `#[location = N]`.
+ *
+ * Every problem that can be found from the struct definition alone is reported
+ * as a spanned `compile_error!` instead of a panic. Locations that overlap only
+ * once the slot count of each field type is known (eg. a dvec4 taking two
+ * locations) are caught by a const check when the impl is instantiated.
+ */
 #[proc_macro_derive(VertexAttribPointers, attributes(location))]
 pub fn vertex_attrib_pointers_derive(input: proc_macro::TokenStream)
 -> proc_macro::TokenStream
 {
-    // construct a string representation of the type definition
-    let s = input.to_string();
-
-    // parse the string representation
-    let ast = syn::parse_derive_input(&s).unwrap();
-
-    // Build the impl
-    let gen = generate_impl(&ast);
+    let ast = syn::parse_macro_input!(input as syn::DeriveInput);
 
-    // Return the generated impl
+    // Build the impl, or the errors explaining why it could not be built
+    generate_impl(&ast)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
 
-    gen.parse().unwrap()
+struct LocatedField<'a> {
+    name: String,
+    ty: &'a syn::Type,
+    location: usize,
+    location_span: Span,
 }
 
 fn generate_impl(ast: &syn::DeriveInput)
--> quote::Tokens
+-> syn::Result<TokenStream>
 {
     let ident = &ast.ident;
-    let generics = &ast.generics;
-    let where_clause = &ast.generics.where_clause;
 
-    let fields_vertex_attrib_pointers = generate_vertex_attrib_pointer_calls(&ast.body);
+    let named_fields = struct_fields(ast)?;
+
+    let mut errors = Vec::new();
+
+    if let Err(e) = check_repr_c_packed(ast) {
+        errors.push(e);
+    }
+
+    let fields = match located_fields(named_fields) {
+        Ok(fields) => fields,
+        Err(e) => {
+            errors.push(e);
+            Vec::new()
+        }
+    };
+
+    if let Err(e) = check_duplicate_locations(&fields) {
+        errors.push(e);
+    }
+
+    if let Some(e) = combine_errors(errors) {
+        return Err(e);
+    }
+
+    // every field type has to be usable as a vertex attribute, this also covers generic fields
+    let mut generics = ast.generics.clone();
+    {
+        let where_clause = generics.make_where_clause();
+        for field in &fields {
+            let ty = field.ty;
+            where_clause.predicates.push(syn::parse_quote! {
+                #ty: crate::render_gl::data::VertexAttribPointer
+            });
+        }
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let fields_vertex_attrib_pointers = fields.iter()
+        .map(generate_struct_field_vertex_attrib_pointer_call);
+    let fields_vertex_attributes = fields.iter()
+        .map(generate_struct_field_vertex_attribute);
+    let overlap_checks = generate_overlap_checks(&fields);
+
+    // without generics the check can run right away, so `cargo check` reports it too
+    let eager_overlap_check = if ast.generics.params.is_empty() {
+        quote! { const _: () = #ident::__VERTEX_ATTRIB_LOCATIONS_DO_NOT_OVERLAP; }
+    } else {
+        quote! {}
+    };
+
+    Ok(quote!{
+        #eager_overlap_check
+
+        impl #impl_generics #ident #ty_generics #where_clause {
+            #[doc(hidden)]
+            const __VERTEX_ATTRIB_LOCATIONS_DO_NOT_OVERLAP: () = {
+                #(#overlap_checks)*
+            };
 
-    quote!{
-        impl #ident #generics #where_clause {
-            #[allow(unused_variables)]
+            #[allow(unused_variables, clippy::let_unit_value)]
             pub fn vertex_attrib_pointers(gl: &::gl::Gl) {
+                let () = Self::__VERTEX_ATTRIB_LOCATIONS_DO_NOT_OVERLAP;
+
                 let stride = ::std::mem::size_of::<Self>(); // byte offset between consecutive attributes
                 let offset = 0;
 
                 #(#fields_vertex_attrib_pointers)*
             }
+
+            /// Runtime description of this vertex, for checking it against a shader with `Program::validate_vertex_layout`
+            #[allow(unused_variables, clippy::let_unit_value)]
+            pub fn vertex_layout() -> crate::render_gl::VertexLayout {
+                let () = Self::__VERTEX_ATTRIB_LOCATIONS_DO_NOT_OVERLAP;
+
+                let stride = ::std::mem::size_of::<Self>();
+                let offset = 0;
+                let mut attributes = ::std::vec::Vec::new();
+
+                #(#fields_vertex_attributes)*
+
+                crate::render_gl::VertexLayout { stride, attributes }
+            }
+        }
+    })
+}
+
+fn combine_errors(errors: Vec<syn::Error>)
+-> Option<syn::Error>
+{
+    errors.into_iter().fold(None, |combined, e| match combined {
+        None => Some(e),
+        Some(mut combined) => {
+            combined.combine(e);
+            Some(combined)
+        }
+    })
+}
+
+/**
+ * The derive computes offsets by summing field sizes, which is only correct
+ * when the compiler is not allowed to reorder or pad the fields.
+ */
+fn check_repr_c_packed(ast: &syn::DeriveInput)
+-> syn::Result<()>
+{
+    let mut has_c = false;
+    let mut has_packed = false;
+
+    for attr in ast.attrs.iter().filter(|a| a.path.is_ident("repr")) {
+        if let Ok(syn::Meta::List(list)) = attr.parse_meta() {
+            for nested in list.nested.iter() {
+                match nested {
+                    syn::NestedMeta::Meta(syn::Meta::Path(p)) if p.is_ident("C") => has_c = true,
+                    syn::NestedMeta::Meta(syn::Meta::Path(p)) if p.is_ident("packed") => has_packed = true,
+                    _ => {}
+                }
+            }
         }
     }
+
+    if has_c && has_packed {
+        Ok(())
+    } else {
+        Err(syn::Error::new(
+            ast.ident.span(),
+            "VertexAttribPointers requires #[repr(C, packed)] so that field offsets match the vertex buffer layout"
+        ))
+    }
+}
+
+fn struct_fields(ast: &syn::DeriveInput)
+-> syn::Result<&syn::punctuated::Punctuated<syn::Field, syn::Token![,]>>
+{
+    match &ast.data {
+        syn::Data::Enum(e) => Err(syn::Error::new(
+            e.enum_token.span(),
+            "VertexAttribPointers cannot be implemented for enums"
+        )),
+        syn::Data::Union(u) => Err(syn::Error::new(
+            u.union_token.span(),
+            "VertexAttribPointers cannot be implemented for unions"
+        )),
+        syn::Data::Struct(s) => match &s.fields {
+            syn::Fields::Unit => Err(syn::Error::new(
+                ast.ident.span(),
+                "VertexAttribPointers cannot be implemented for Unit structs"
+            )),
+            syn::Fields::Unnamed(f) => Err(syn::Error::new(
+                f.span(),
+                "VertexAttribPointers cannot be implemented for Tuple Structs"
+            )),
+            syn::Fields::Named(f) => Ok(&f.named),
+        },
+    }
 }
 
-fn generate_vertex_attrib_pointer_calls(body: &syn::Body)
--> Vec<quote::Tokens>
+fn located_fields(fields: &syn::punctuated::Punctuated<syn::Field, syn::Token![,]>)
+-> syn::Result<Vec<LocatedField<'_>>>
 {
-    match body {
-        &syn::Body::Enum(_)
-            => panic!("VertexAttribPointers cannot be implemented for enums"),
-        &syn::Body::Struct(syn::VariantData::Unit)
-            => panic!("VertexAttribPointers cannot be implemented for Unit structs"),
-        &syn::Body::Struct(syn::VariantData::Tuple(_))
-            => panic!("VertexAttribPointers cannot be implemented for Tuple Structs"),
-        &syn::Body::Struct(syn::VariantData::Struct(ref s)) => {
-            s.iter()
-                .map(generate_struct_field_vertex_attrib_pointer_call)
-                .collect()
+    let mut located = Vec::new();
+    let mut errors = Vec::new();
+
+    for field in fields.iter() {
+        match located_field(field) {
+            Ok(f) => located.push(f),
+            Err(e) => errors.push(e),
         }
     }
+
+    match combine_errors(errors) {
+        Some(e) => Err(e),
+        None => Ok(located),
+    }
 }
 
-fn generate_struct_field_vertex_attrib_pointer_call(field: &syn::Field) -> quote::Tokens {
+fn located_field(field: &syn::Field)
+-> syn::Result<LocatedField<'_>>
+{
     let field_name = match field.ident {
         Some(ref i) => format!("{}", i),
         None => String::from(""),
     };
-    let location_attr = field.attrs
+
+    let mut location_attrs = field.attrs
         .iter()
-        .filter(|a| a.value.name() == "location")
-        .next()
-        .unwrap_or_else(|| panic!(
-            "Field {} is missing #[location = ?] attribute", field_name
+        .filter(|a| a.path.is_ident("location"));
+
+    let location_attr = location_attrs.next()
+        .ok_or_else(|| syn::Error::new(
+            field.span(),
+            format!("Field {} is missing #[location = ?] attribute", field_name)
+        ))?;
+
+    if let Some(extra) = location_attrs.next() {
+        return Err(syn::Error::new(
+            extra.path.span(),
+            format!("Field {} has more than one #[location = ?] attribute", field_name)
         ));
+    }
 
-    let location_value_literal: usize = match location_attr.value {
-        syn::MetaItem::NameValue(_, syn::Lit::Str(ref s, _)) => s.parse()
-            .unwrap_or_else(
-                |_| panic!("Field {} location attribute value must contain an integer", field_name)
-            ),
-        _ => panic!("Field {} location attribute value must be a string literal", field_name)
+    let value = match location_attr.parse_meta()? {
+        syn::Meta::NameValue(syn::MetaNameValue { lit, .. }) => lit,
+        other => return Err(syn::Error::new(
+            other.span(),
+            format!("Field {} location attribute must be of the form #[location = 0]", field_name)
+        )),
     };
 
-    let field_ty = &field.ty;
+    // string literals are still accepted so that older vertex definitions keep compiling
+    let location = match &value {
+        syn::Lit::Int(i) => i.base10_parse::<usize>(),
+        syn::Lit::Str(s) => s.value().trim().parse::<usize>()
+            .map_err(|_| syn::Error::new(s.span(), "")),
+        _ => Err(syn::Error::new(value.span(), "")),
+    }.map_err(|_| syn::Error::new(
+        value.span(),
+        format!("Field {} location attribute value must be an unsigned integer literal", field_name)
+    ))?;
+
+    Ok(LocatedField {
+        name: field_name,
+        ty: &field.ty,
+        location,
+        location_span: value.span(),
+    })
+}
+
+fn check_duplicate_locations(fields: &[LocatedField])
+-> syn::Result<()>
+{
+    let mut errors = Vec::new();
+
+    for (i, field) in fields.iter().enumerate() {
+        if let Some(first) = fields[..i].iter().find(|f| f.location == field.location) {
+            errors.push(syn::Error::new(
+                field.location_span,
+                format!(
+                    "Field {} uses location {} which is already used by field {}",
+                    field.name, field.location, first.name
+                )
+            ));
+        }
+    }
+
+    match combine_errors(errors) {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/**
+ * Types such as dvec3 and dvec4 take up more than one location, so a check that
+ * the ranges [location, location + SLOTS) are disjoint has to wait until the
+ * field types are known. Each pair gets its own message since const panics
+ * can not format.
+ */
+fn generate_overlap_checks(fields: &[LocatedField])
+-> Vec<TokenStream>
+{
+    let mut checks = Vec::new();
+
+    for (i, a) in fields.iter().enumerate() {
+        for b in fields[i + 1..].iter() {
+            let (a_ty, a_location) = (a.ty, a.location);
+            let (b_ty, b_location) = (b.ty, b.location);
+            let message = format!(
+                "vertex attribute locations overlap: field {} (location {}) and field {} (location {})",
+                a.name, a.location, b.name, b.location
+            );
+
+            checks.push(quote_spanned! { b.location_span =>
+                if #a_location < #b_location + <#b_ty as crate::render_gl::data::VertexAttribPointer>::SLOTS
+                    && #b_location < #a_location + <#a_ty as crate::render_gl::data::VertexAttribPointer>::SLOTS
+                {
+                    panic!(#message);
+                }
+            });
+        }
+    }
+
+    checks
+}
+
+fn generate_struct_field_vertex_attrib_pointer_call(field: &LocatedField)
+-> TokenStream
+{
+    let location_value_literal = field.location;
+    let field_ty = field.ty;
+
     quote! {
         let location = #location_value_literal;
         unsafe {
-            #field_ty::vertex_attrib_pointer(gl, stride, location, offset);
+            <#field_ty as crate::render_gl::data::VertexAttribPointer>::vertex_attrib_pointer(gl, stride, location, offset);
         }
         let offset = offset + ::std::mem::size_of::<#field_ty>();
     }
 }
 
-// fn generate_vertex_attrib_pointer_call(field: &syn::Field)
-// -> quote::Tokens
-// {
-//     let field_name = match field.ident {
-//         Some(ref i) => format!("{}", i),
-//         None => String::from(""),
-//     };
-
-//     let location_attr = field.attrs
-//         .iter()
-//         .filter(|a| a.value.name() == "location")
-//         .next()
-//         .unwrap_or_else(| | panic!(
-//             "Field {:?} is missing #[location = ? ] attribute", field_name
-//         ));
-
-//     let location_value_literal = match location_attr.value {
-//         syn::MetaItem::NameValue(_, syn::Lit::Str(ref s, _)) => s.parse::<usize>()
-//             .unwrap_or_else(
-//                 |_| panic!("Field {} location attribute value must contain an integer", field_name)
-//             ),
-//         _ => panic!("Field {} location attribute value must be an integer literal", field_name)
-//     };
-
-//     let field_ty = &field.ty;
-
-//     quote! {
-//         let location = #location_value_literal;
-//         unsafe {
-//             #field_ty::vertex_attrib_pointer(gl, stride, location, offset);
-//         }
-//         let offset = offset + std::mem::size_of::<#field_ty>();
-//     }
-// }
+fn generate_struct_field_vertex_attribute(field: &LocatedField)
+-> TokenStream
+{
+    let location_value_literal = field.location;
+    let field_ty = field.ty;
+
+    quote! {
+        attributes.push(crate::render_gl::VertexAttribute::of::<#field_ty>(#location_value_literal, offset));
+        let offset = offset + ::std::mem::size_of::<#field_ty>();
+    }
+}
@@ -0,0 +1,25 @@
+#[macro_use] extern crate render_gl_derive;
+
+include!("../support/render_gl.rs");
+
+use render_gl::data;
+
+#[derive(VertexAttribPointers)]
+#[repr(C, packed)]
+struct Vertex<P: Copy, C: Copy> {
+    #[location = 0]
+    pos: P,
+    #[location = 2]
+    clr: C,
+}
+
+fn main() {
+    // a dvec3 position still leaves location 2 free
+    let layout = Vertex::<data::f64_f64_f64, data::f32_f32_f32>::vertex_layout();
+    assert_eq!(layout.stride, 36);
+    let locations: Vec<(usize, usize, usize)> = layout.attributes.iter()
+        .map(|attribute| (attribute.location, attribute.slots, attribute.offset))
+        .collect();
+    assert_eq!(locations, vec![(0, 2, 0), (2, 1, 24)]);
+    Vertex::<data::f32_f32_f32, data::f32_f32_f32>::vertex_attrib_pointers(&Gl);
+}
@@ -0,0 +1,60 @@
+// Stand-ins for the parts of the game crate that derived code refers to, `::gl::Gl` and
+// `crate::render_gl`. Included by the ui cases that get past the derive's own checks.
+
+extern crate self as gl;
+
+pub struct Gl;
+
+pub mod render_gl {
+    pub mod data {
+        pub trait VertexAttribPointer {
+            const SLOTS: usize = 1;
+            unsafe fn vertex_attrib_pointer(gl: &::gl::Gl, stride: usize, location: usize, offset: usize);
+        }
+
+        #[allow(non_camel_case_types)]
+        #[derive(Copy, Clone)]
+        #[repr(C, packed)]
+        pub struct f32_f32_f32 {
+            pub d0: f32,
+            pub d1: f32,
+            pub d2: f32,
+        }
+
+        impl VertexAttribPointer for f32_f32_f32 {
+            unsafe fn vertex_attrib_pointer(_gl: &::gl::Gl, _stride: usize, _location: usize, _offset: usize) {}
+        }
+
+        #[allow(non_camel_case_types)]
+        #[derive(Copy, Clone)]
+        #[repr(C, packed)]
+        pub struct f64_f64_f64 {
+            pub d0: f64,
+            pub d1: f64,
+            pub d2: f64,
+        }
+
+        // a dvec3 takes two locations
+        impl VertexAttribPointer for f64_f64_f64 {
+            const SLOTS: usize = 2;
+            unsafe fn vertex_attrib_pointer(_gl: &::gl::Gl, _stride: usize, _location: usize, _offset: usize) {}
+        }
+    }
+
+    pub struct VertexAttribute {
+        pub location: usize,
+        pub slots: usize,
+        pub offset: usize,
+    }
+
+    impl VertexAttribute {
+        pub fn of<T: data::VertexAttribPointer>(location: usize, offset: usize) -> VertexAttribute {
+            VertexAttribute { location, slots: T::SLOTS, offset }
+        }
+    }
+
+    pub struct VertexLayout {
+        pub stride: usize,
+        pub attributes: Vec<VertexAttribute>,
+    }
+}
@@ -0,0 +1,14 @@
+#[macro_use] extern crate render_gl_derive;
+
+#[derive(VertexAttribPointers)]
+#[repr(C, packed)]
+struct Vertex {
+    #[location = "zero"]
+    pos: f32,
+    #[location = 1.5]
+    clr: f32,
+    #[location(2)]
+    uv: f32,
+}
+
+fn main() {}
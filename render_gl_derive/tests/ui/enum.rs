@@ -0,0 +1,10 @@
+#[macro_use] extern crate render_gl_derive;
+
+#[derive(VertexAttribPointers)]
+#[repr(C)]
+enum Vertex {
+    Position,
+    Color,
+}
+
+fn main() {}
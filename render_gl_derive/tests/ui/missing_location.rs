@@ -0,0 +1,11 @@
+#[macro_use] extern crate render_gl_derive;
+
+#[derive(VertexAttribPointers)]
+#[repr(C, packed)]
+struct Vertex {
+    #[location = 0]
+    pos: f32,
+    clr: f32,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+#[macro_use] extern crate render_gl_derive;
+
+#[derive(VertexAttribPointers)]
+struct Vertex {
+    #[location = 0]
+    pos: f32,
+}
+
+fn main() {}
@@ -0,0 +1,16 @@
+#[macro_use] extern crate render_gl_derive;
+
+include!("../support/render_gl.rs");
+
+use render_gl::data;
+
+#[derive(VertexAttribPointers)]
+#[repr(C, packed)]
+struct Vertex {
+    #[location = 0]
+    pos: data::f64_f64_f64,
+    #[location = 1]
+    clr: data::f32_f32_f32,
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+#[macro_use] extern crate render_gl_derive;
+
+#[derive(VertexAttribPointers)]
+#[repr(C, packed)]
+struct Vertex {
+    #[location = 0]
+    #[location = 1]
+    pos: f32,
+}
+
+fn main() {}
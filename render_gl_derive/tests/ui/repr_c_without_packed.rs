@@ -0,0 +1,10 @@
+#[macro_use] extern crate render_gl_derive;
+
+#[derive(VertexAttribPointers)]
+#[repr(C)]
+struct Vertex {
+    #[location = 0]
+    pos: f32,
+}
+
+fn main() {}
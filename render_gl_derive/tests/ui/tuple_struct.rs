@@ -0,0 +1,7 @@
+#[macro_use] extern crate render_gl_derive;
+
+#[derive(VertexAttribPointers)]
+#[repr(C, packed)]
+struct Vertex(f32, f32);
+
+fn main() {}
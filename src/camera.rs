@@ -57,6 +57,58 @@ impl Camera {
         }
     }
 
+    /**
+     * height / width, like the constructor takes it
+     */
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32)
+    {
+        self.aspect_ratio = aspect_ratio;
+        self.projection.set_matrix(glm::perspective(aspect_ratio, self.fov, self.near_clipping_plane, self.far_clipping_plane));
+    }
+
+    pub fn position(&self) -> glm::Vec3
+    {
+        self.camera_position
+    }
+
+    pub fn near_clipping_plane(&self) -> f32
+    {
+        self.near_clipping_plane
+    }
+
+    pub fn far_clipping_plane(&self) -> f32
+    {
+        self.far_clipping_plane
+    }
+
+    /**
+     * Unit vector the camera looks along
+     */
+    pub fn forward(&self) -> glm::Vec3
+    {
+        glm::normalize(&(self.center_of_view - self.camera_position))
+    }
+
+    pub fn view_matrix(&self) -> glm::Mat4
+    {
+        self.view.matrix
+    }
+
+    pub fn projection_matrix(&self) -> glm::Mat4
+    {
+        self.projection.matrix
+    }
+
+    /**
+     * Projection * view of the part of the frustum between the `near` and `far` distances,
+     * shadow cascades each cover one such slice
+     */
+    pub fn slice_projection_view(&self, near: f32, far: f32)
+    -> glm::Mat4
+    {
+        glm::perspective(self.aspect_ratio, self.fov, near, far) * self.view.matrix
+    }
+
     pub fn get_projection_view_matrix(&mut self)
     -> glm::Mat4
     {
@@ -0,0 +1,106 @@
+use gl;
+use std::rc::Rc;
+
+use crate::render_gl::{ self, data };
+use crate::ecs;
+
+#[derive(VertexAttribPointers)]
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+struct Vertex {
+    #[location = 0]
+    pos: data::f32_f32_f32,
+    #[location = 1]
+    normal: data::f32_f32_f32,
+    #[location = 2]
+    uv: data::f32_f32,
+    #[location = 3]
+    tangent: data::f32_f32_f32_f32,
+}
+
+/**
+ * Outward normal and the two edges spanning each face, counter clockwise seen from outside
+ */
+const FACES: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+    ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+    ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+    ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+];
+
+fn cube_vertices() -> Vec<Vertex> {
+    let mut vertices = Vec::with_capacity(36);
+    for (normal, u, v) in FACES.iter() {
+        let corner = |su: f32, sv: f32| Vertex {
+            pos: (
+                0.5 * (normal[0] + su * u[0] + sv * v[0]),
+                0.5 * (normal[1] + su * u[1] + sv * v[1]),
+                0.5 * (normal[2] + su * u[2] + sv * v[2]),
+            ).into(),
+            normal: (normal[0], normal[1], normal[2]).into(),
+            uv: ((su + 1.0) / 2.0, (sv + 1.0) / 2.0).into(),
+            tangent: (u[0], u[1], u[2], 1.0).into(),
+        };
+        vertices.extend_from_slice(&[
+            corner(-1.0, -1.0), corner(1.0, -1.0), corner(1.0, 1.0),
+            corner(-1.0, -1.0), corner(1.0, 1.0), corner(-1.0, 1.0),
+        ]);
+    }
+    vertices
+}
+
+/**
+ * A unit cube around the origin with flat normals, texture coordinates covering each face
+ * and tangents along u, for the lit materials
+ */
+pub fn cube_mesh(gl: &gl::Gl) -> ecs::Mesh {
+    let vertices = cube_vertices();
+
+    let vbo = render_gl::buffer::ArrayBuffer::new(gl);
+    vbo.set_label("cube vertices");
+    vbo.bind();
+    vbo.static_draw_data(&vertices);
+    vbo.unbind();
+
+    let vao = render_gl::buffer::VertexArray::new(gl);
+    vao.set_label("cube");
+
+    vao.bind();
+    vbo.bind();
+    Vertex::vertex_attrib_pointers(gl);
+    vbo.unbind();
+    vao.unbind();
+
+    ecs::Mesh(Rc::new(render_gl::Mesh::new(vao, vbo, gl::TRIANGLES, vertices.len() as gl::types::GLsizei)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vec(v: data::f32_f32_f32) -> glm::Vec3 {
+        glm::vec3(v.d0, v.d1, v.d2)
+    }
+
+    #[test]
+    fn faces_wind_counter_clockwise_around_their_normals() {
+        let vertices = cube_vertices();
+        assert_eq!(vertices.len(), 36);
+
+        for triangle in vertices.chunks(3) {
+            let (a, b, c) = (vec(triangle[0].pos), vec(triangle[1].pos), vec(triangle[2].pos));
+            let normal = vec(triangle[0].normal);
+            assert!(glm::dot(&(b - a).cross(&(c - a)), &normal) > 0.0);
+            assert!(glm::dot(&a, &normal) > 0.49);  // on the face
+
+            // texture coordinates grow along the tangent
+            let tangent = triangle[0].tangent;
+            let tangent = glm::vec3(tangent.d0, tangent.d1, tangent.d2);
+            let (uv_a, uv_b) = (triangle[0].uv, triangle[1].uv);
+            assert_eq!(glm::dot(&tangent, &normal), 0.0);
+            assert!(glm::dot(&(b - a), &tangent) * (uv_b.d0 - uv_a.d0) >= 0.0);
+        }
+    }
+}
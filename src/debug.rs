@@ -1,3 +1,5 @@
+use log::{ self, Log, Metadata, Record, LevelFilter };
+
 pub fn failure_to_string
 (e: failure::Error)
 -> String
@@ -25,3 +27,41 @@ pub fn failure_to_string
 
     result
 }
+
+/**
+ * Prints `log` records as `[LEVEL target] message`, errors and warnings to stderr
+ */
+struct ConsoleLogger;
+
+static LOGGER: ConsoleLogger = ConsoleLogger;
+
+impl Log for ConsoleLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record)
+    {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        if record.level() <= log::Level::Warn {
+            eprintln!("[{} {}] {}", record.level(), record.target(), record.args());
+        } else {
+            println!("[{} {}] {}", record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/**
+ * Installs the console logger, only the first call has an effect
+ */
+pub fn init_logging(level: LevelFilter)
+{
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
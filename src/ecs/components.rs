@@ -0,0 +1,125 @@
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::render_gl;
+
+/**
+ * Position, rotation and scale of an entity in world space
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub position: glm::Vec3,
+    pub rotation: na::UnitQuaternion<f32>,
+    pub scale: glm::Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform {
+            position: glm::vec3(0.0, 0.0, 0.0),
+            rotation: na::UnitQuaternion::identity(),
+            scale: glm::vec3(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Transform {
+    pub fn at(position: glm::Vec3) -> Transform {
+        Transform {
+            position,
+            ..Transform::default()
+        }
+    }
+
+    /**
+     * The model matrix, scaling first, then rotating, then translating
+     */
+    pub fn matrix(&self) -> glm::Mat4 {
+        glm::translation(&self.position) * self.rotation.to_homogeneous() * glm::scaling(&self.scale)
+    }
+}
+
+#[derive(Clone)]
+pub struct Mesh(pub Rc<render_gl::Mesh>);
+
+/**
+ * How an entity is drawn
+ */
+#[derive(Clone)]
+pub struct Material(pub Rc<render_gl::Material>);
+
+/**
+ * A light at the entity's transform, shining along the transform's -z axis
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Light(pub render_gl::Light);
+
+impl Light {
+    pub fn source(&self, transform: &Transform) -> render_gl::LightSource {
+        render_gl::LightSource {
+            light: self.0,
+            position: transform.position,
+            direction: transform.rotation * glm::vec3(0.0, 0.0, -1.0),
+        }
+    }
+}
+
+/**
+ * Rotates the entity's transform at a constant rate
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Spin {
+    pub axis: na::Unit<glm::Vec3>,
+    pub radians_per_second: f32,
+}
+
+/**
+ * Resource with the timing of the current update or frame
+ */
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Time {
+    pub step: Duration,     // of a fixed update
+    pub alpha: f32,         // between the last two updates, when rendering
+    pub elapsed: Duration,  // simulated since the world was created
+}
+
+/**
+ * Resource with the offscreen framebuffer the scene renders into, for post-processing.
+ * Without it the scene renders into the window.
+ */
+pub struct SceneTarget(pub render_gl::Framebuffer);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: &glm::Vec4, b: &glm::Vec4) -> bool {
+        (a - b).norm() < 1e-5
+    }
+
+    #[test]
+    fn transform_scales_then_rotates_then_translates() {
+        let transform = Transform {
+            position: glm::vec3(1.0, 0.0, 0.0),
+            rotation: na::UnitQuaternion::from_axis_angle(&glm::Vec3::z_axis(), glm::half_pi()),
+            scale: glm::vec3(2.0, 2.0, 2.0),
+        };
+
+        let point = transform.matrix() * glm::vec4(1.0, 0.0, 0.0, 1.0);
+        assert!(close(&point, &glm::vec4(1.0, 2.0, 0.0, 1.0)), "{:?}", point);
+        assert_eq!(Transform::default().matrix(), glm::identity::<f32, glm::U4>());
+    }
+
+    #[test]
+    fn lights_shine_along_the_transform() {
+        let light = Light(render_gl::Light::directional(glm::vec3(1.0, 1.0, 1.0), 1.0));
+        let transform = Transform {
+            rotation: na::UnitQuaternion::from_axis_angle(&glm::Vec3::x_axis(), -glm::half_pi::<f32>()),
+            ..Transform::at(glm::vec3(0.0, 5.0, 0.0))
+        };
+
+        let source = light.source(&transform);
+        assert_eq!(source.position, glm::vec3(0.0, 5.0, 0.0));
+        assert!((source.direction - glm::vec3(0.0, -1.0, 0.0)).norm() < 1e-5, "{:?}", source.direction);
+    }
+}
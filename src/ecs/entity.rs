@@ -0,0 +1,113 @@
+/**
+ * Handle to a game object. The generation changes when an index is reused, so handles to
+ * despawned entities never alias the entity that took their place.
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+impl Entity {
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+/**
+ * Allocates entities, reusing the indices of despawned ones
+ */
+#[derive(Default)]
+pub struct Entities {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+    len: usize,
+}
+
+impl Entities {
+    pub fn new() -> Entities {
+        Entities::default()
+    }
+
+    pub fn create(&mut self) -> Entity {
+        self.len += 1;
+        match self.free.pop() {
+            Some(index) => {
+                self.alive[index as usize] = true;
+                Entity { index, generation: self.generations[index as usize] }
+            },
+            None => {
+                self.generations.push(0);
+                self.alive.push(true);
+                Entity { index: self.generations.len() as u32 - 1, generation: 0 }
+            },
+        }
+    }
+
+    /**
+     * Returns false if the entity was already destroyed
+     */
+    pub fn destroy(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+        let index = entity.index as usize;
+        self.alive[index] = false;
+        self.generations[index] = self.generations[index].wrapping_add(1);
+        self.free.push(entity.index);
+        self.len -= 1;
+        true
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        let index = entity.index as usize;
+        index < self.alive.len() && self.alive[index] && self.generations[index] == entity.generation
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /**
+     * Living entities in index order
+     */
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.alive.iter().enumerate()
+            .filter(|(_, alive)| **alive)
+            .map(move |(index, _)| Entity { index: index as u32, generation: self.generations[index] })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn destroyed_indices_are_reused_with_a_new_generation() {
+        let mut entities = Entities::new();
+        let a = entities.create();
+        let b = entities.create();
+        assert_eq!((a.index(), b.index()), (0, 1));
+
+        assert!(entities.destroy(a));
+        assert!(!entities.destroy(a));
+        assert!(!entities.is_alive(a));
+
+        let c = entities.create();
+        assert_eq!(c.index(), a.index());
+        assert_ne!(c, a);
+        assert!(entities.is_alive(c));
+        assert!(!entities.is_alive(a));
+        assert_eq!(entities.len(), 2);
+        assert_eq!(entities.iter().collect::<Vec<_>>(), vec![c, b]);
+    }
+}
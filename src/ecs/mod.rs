@@ -0,0 +1,29 @@
+/*!
+ * Entity component system for game objects.
+ *
+ * A `World` holds entities and one `Storage` per component type. Systems borrow the
+ * storages they need from the world and are run in order by a `Schedule`:
+ *
+ * ```ignore
+ * let mut world = World::new();
+ * world.spawn().with(Transform::default()).with(Spin { .. }).build();
+ *
+ * let mut update = Schedule::new().with(SpinSystem);
+ * update.run(&mut world, &mut ctx)?;
+ * ```
+ *
+ * Besides its own components the render system uses `Camera` as a component directly.
+ */
+mod components;
+mod entity;
+mod schedule;
+mod storage;
+mod systems;
+mod world;
+
+pub use self::components::{Light, Material, Mesh, SceneTarget, Spin, Time, Transform};
+pub use self::entity::{Entities, Entity};
+pub use self::schedule::{Schedule, System};
+pub use self::storage::Storage;
+pub use self::systems::{RenderSystem, SpinSystem};
+pub use self::world::{EntityBuilder, World};
@@ -0,0 +1,129 @@
+use super::world::World;
+
+/**
+ * Logic that runs over the world every frame or update. `C` is whatever else the system
+ * needs from outside the world, such as the gl context.
+ */
+pub trait System<C> {
+    fn name(&self) -> &str;
+
+    /**
+     * Called once before the first run, typically to register the components the system reads
+     */
+    fn setup(&mut self, _world: &mut World) {}
+
+    fn run(&mut self, world: &mut World, ctx: &mut C) -> Result<(), failure::Error>;
+}
+
+/**
+ * Systems that run in the order they were added
+ */
+pub struct Schedule<C> {
+    systems: Vec<Box<dyn System<C>>>,
+    set_up: usize,  // systems before this index had `setup` called
+}
+
+impl<C> Default for Schedule<C> {
+    fn default() -> Schedule<C> {
+        Schedule::new()
+    }
+}
+
+impl<C> Schedule<C> {
+    pub fn new() -> Schedule<C> {
+        Schedule {
+            systems: Vec::new(),
+            set_up: 0,
+        }
+    }
+
+    pub fn with<S: System<C> + 'static>(mut self, system: S) -> Schedule<C> {
+        self.add(system);
+        self
+    }
+
+    pub fn add<S: System<C> + 'static>(&mut self, system: S) {
+        self.systems.push(Box::new(system));
+    }
+
+    /**
+     * Runs every system, stopping at the first error
+     */
+    pub fn run(&mut self, world: &mut World, ctx: &mut C) -> Result<(), failure::Error> {
+        for system in &mut self.systems[self.set_up..] {
+            system.setup(world);
+        }
+        self.set_up = self.systems.len();
+
+        for system in &mut self.systems {
+            system.run(world, ctx)?;
+        }
+        Ok(())
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.systems.iter().map(|system| system.name()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Counter;
+
+    /**
+     * Logs its name and how many counters there are into the context
+     */
+    struct Logger(&'static str);
+
+    impl System<Vec<String>> for Logger {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn setup(&mut self, world: &mut World) {
+            world.register::<Counter>();
+        }
+
+        fn run(&mut self, world: &mut World, log: &mut Vec<String>) -> Result<(), failure::Error> {
+            log.push(format!("{}:run {}", self.0, world.read::<Counter>().len()));
+            Ok(())
+        }
+    }
+
+    struct Fails;
+
+    impl System<Vec<String>> for Fails {
+        fn name(&self) -> &str {
+            "fails"
+        }
+
+        fn run(&mut self, _world: &mut World, _log: &mut Vec<String>) -> Result<(), failure::Error> {
+            Err(format_err!("broken"))
+        }
+    }
+
+    #[test]
+    fn systems_run_in_order_after_setup() {
+        let mut world = World::new();
+        let mut log = Vec::new();
+        let mut schedule = Schedule::new().with(Logger("input")).with(Logger("physics"));
+        schedule.run(&mut world, &mut log).unwrap();
+
+        world.spawn().with(Counter).build();
+        schedule.add(Logger("render"));
+        schedule.run(&mut world, &mut log).unwrap();
+
+        assert_eq!(schedule.names(), vec!["input", "physics", "render"]);
+        assert_eq!(log, vec!["input:run 0", "physics:run 0", "input:run 1", "physics:run 1", "render:run 1"]);
+    }
+
+    #[test]
+    fn errors_stop_the_schedule() {
+        let mut log = Vec::new();
+        let mut schedule = Schedule::new().with(Fails).with(Logger("after"));
+        assert!(schedule.run(&mut World::new(), &mut log).is_err());
+        assert!(log.is_empty());
+    }
+}
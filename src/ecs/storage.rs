@@ -0,0 +1,188 @@
+use std::any::Any;
+
+use super::entity::Entity;
+
+/**
+ * Components of one type, indexed by entity index. Each slot remembers the entity it
+ * belongs to, so stale handles find nothing.
+ */
+pub struct Storage<T> {
+    slots: Vec<Option<(Entity, T)>>,
+    len: usize,
+}
+
+impl<T> Default for Storage<T> {
+    fn default() -> Storage<T> {
+        Storage::new()
+    }
+}
+
+impl<T> Storage<T> {
+    pub fn new() -> Storage<T> {
+        Storage {
+            slots: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /**
+     * Returns the component the entity had before
+     */
+    pub fn insert(&mut self, entity: Entity, component: T) -> Option<T> {
+        let index = entity.index() as usize;
+        if index >= self.slots.len() {
+            self.slots.resize_with(index + 1, || None);
+        }
+
+        // a component left behind by a previous generation is dropped, not returned
+        match self.slots[index].replace((entity, component)) {
+            Some((owner, component)) if owner == entity => Some(component),
+            Some(_) => None,
+            None => {
+                self.len += 1;
+                None
+            },
+        }
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        match self.slots.get_mut(entity.index() as usize) {
+            Some(slot) if slot.as_ref().is_some_and(|(owner, _)| *owner == entity) => {
+                self.len -= 1;
+                slot.take().map(|(_, component)| component)
+            },
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        match self.slots.get(entity.index() as usize) {
+            Some(Some((owner, component))) if *owner == entity => Some(component),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        match self.slots.get_mut(entity.index() as usize) {
+            Some(Some((owner, component))) if *owner == entity => Some(component),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.get(entity).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /**
+     * Components in entity index order
+     */
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.slots.iter().filter_map(|slot| slot.as_ref().map(|(entity, component)| (*entity, component)))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.slots.iter_mut().filter_map(|slot| slot.as_mut().map(|(entity, component)| (*entity, component)))
+    }
+
+    /**
+     * Entities that have components in both storages
+     */
+    pub fn join<'a, U>(&'a self, other: &'a Storage<U>) -> impl Iterator<Item = (Entity, &'a T, &'a U)> {
+        self.iter().filter_map(move |(entity, a)| other.get(entity).map(|b| (entity, a, b)))
+    }
+
+    /**
+     * Like `join` with this storage's components mutable
+     */
+    pub fn join_mut<'a, U>(&'a mut self, other: &'a Storage<U>) -> impl Iterator<Item = (Entity, &'a mut T, &'a U)> {
+        self.iter_mut().filter_map(move |(entity, a)| other.get(entity).map(|b| (entity, a, b)))
+    }
+}
+
+/**
+ * Lets the world handle storages without knowing their component type
+ */
+pub(super) trait AnyStorage: Any {
+    fn remove_entity(&mut self, entity: Entity);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyStorage for Storage<T> {
+    fn remove_entity(&mut self, entity: Entity) {
+        self.remove(entity);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::entity::Entities;
+
+    #[test]
+    fn stale_handles_do_not_see_new_components() {
+        let mut entities = Entities::new();
+        let mut storage = Storage::new();
+
+        let old = entities.create();
+        storage.insert(old, "old");
+        entities.destroy(old);
+        let new = entities.create();
+        storage.insert(new, "new");
+
+        assert_eq!(storage.get(old), None);
+        assert_eq!(storage.remove(old), None);
+        assert_eq!(storage.get(new), Some(&"new"));
+        assert_eq!(storage.len(), 1);
+    }
+
+    #[test]
+    fn insert_returns_the_replaced_component() {
+        let mut entities = Entities::new();
+        let mut storage = Storage::new();
+        let entity = entities.create();
+
+        assert_eq!(storage.insert(entity, 1), None);
+        assert_eq!(storage.insert(entity, 2), Some(1));
+        assert_eq!(storage.len(), 1);
+        assert_eq!(storage.remove(entity), Some(2));
+        assert!(storage.is_empty());
+    }
+
+    #[test]
+    fn joins_visit_entities_with_both_components() {
+        let mut entities = Entities::new();
+        let (a, b, c) = (entities.create(), entities.create(), entities.create());
+
+        let mut positions = Storage::new();
+        let mut velocities = Storage::new();
+        positions.insert(a, 0.0);
+        positions.insert(b, 10.0);
+        velocities.insert(b, 1.0);
+        velocities.insert(c, 2.0);
+
+        assert_eq!(positions.join(&velocities).map(|(entity, _, _)| entity).collect::<Vec<_>>(), vec![b]);
+
+        for (_, position, velocity) in positions.join_mut(&velocities) {
+            *position += velocity;
+        }
+        assert_eq!(positions.get(a), Some(&0.0));
+        assert_eq!(positions.get(b), Some(&11.0));
+    }
+}
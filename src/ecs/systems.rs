@@ -0,0 +1,165 @@
+use std::rc::Rc;
+
+use crate::camera::Camera;
+use crate::render_gl;
+use crate::render_gl::{ BlendMode, DrawCommand, Environment, LightBuffer, LightList, RenderQueue, ShadowList, ShadowMaps, ShadowSettings, Skybox };
+use crate::scene::Context;
+
+use super::components::{ Light, Material, Mesh, SceneTarget, Spin, Time, Transform };
+use super::schedule::System;
+use super::world::World;
+
+/**
+ * Turns entities with a `Spin` by the `Time` step
+ */
+pub struct SpinSystem;
+
+impl<C> System<C> for SpinSystem {
+    fn name(&self) -> &str {
+        "spin"
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        world.register::<Spin>();
+        world.register::<Transform>();
+    }
+
+    fn run(&mut self, world: &mut World, _ctx: &mut C) -> Result<(), failure::Error> {
+        let step = world.resource::<Time>().map(|time| time.step.as_secs_f32()).unwrap_or(0.0);
+        let spins = world.read::<Spin>();
+        let mut transforms = world.write::<Transform>();
+
+        for (_, transform, spin) in transforms.join_mut(&spins) {
+            let turn = na::UnitQuaternion::from_axis_angle(&spin.axis, spin.radians_per_second * step);
+            transform.rotation = turn * transform.rotation;
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Draws every entity with a transform, mesh and material through the first entity with a
+ * `Camera`, sorted and batched by a render queue. Draws nothing without a camera.
+ *
+ * Entities with a transform and a `Light` light the frame, up to `MAX_LIGHTS` of them, and
+ * an `Environment` resource adds image based light. Lights that cast shadows have opaque
+ * entities render into shadow maps first, as the `ShadowSettings` resource or the default
+ * settings say. The frame is drawn into the `SceneTarget` resource if there is one, and
+ * a `Skybox` resource fills the background between the opaque and translucent draws.
+ */
+#[derive(Default)]
+pub struct RenderSystem {
+    queue: RenderQueue,
+    lights: LightList,
+    shadows: ShadowList,
+    casters: Vec<(Rc<render_gl::Mesh>, glm::Mat4)>,
+    light_buffer: Option<LightBuffer>,  // created on the first frame, the system has no gl before
+    shadow_maps: Option<ShadowMaps>,    // likewise, and again when the settings resize the atlas
+}
+
+impl RenderSystem {
+    pub fn new() -> RenderSystem {
+        RenderSystem::default()
+    }
+}
+
+impl System<Context> for RenderSystem {
+    fn name(&self) -> &str {
+        "render"
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        world.register::<Camera>();
+        world.register::<Transform>();
+        world.register::<Mesh>();
+        world.register::<Material>();
+        world.register::<Light>();
+    }
+
+    fn run(&mut self, world: &mut World, ctx: &mut Context) -> Result<(), failure::Error> {
+        let mut cameras = world.write::<Camera>();
+        let camera = match cameras.iter_mut().next() {
+            Some((_, camera)) => camera,
+            None => return Ok(()),
+        };
+
+        let transforms = world.read::<Transform>();
+        let meshes = world.read::<Mesh>();
+        let materials = world.read::<Material>();
+        let lights = world.read::<Light>();
+
+        self.lights.clear();
+        self.lights.eye = camera.position();
+        for (_, light, transform) in lights.join(&transforms) {
+            if !self.lights.push(light.source(transform)) {
+                break;
+            }
+        }
+        self.light_buffer.get_or_insert_with(|| LightBuffer::new(&ctx.gl)).upload(&self.lights);
+        if let Some(environment) = world.resource::<Environment>() {
+            environment.bind();
+        }
+
+        self.queue.begin_for(camera);
+        self.casters.clear();
+        for (entity, mesh, transform) in meshes.join(&transforms) {
+            if let Some(material) = materials.get(entity) {
+                let model = transform.matrix();
+                if material.0.blend() == BlendMode::Opaque {
+                    self.casters.push((mesh.0.clone(), model));
+                }
+                self.queue.push(DrawCommand {
+                    mesh: mesh.0.clone(),
+                    material: material.0.clone(),
+                    model,
+                });
+            }
+        }
+
+        let settings = world.resource::<ShadowSettings>().map(|settings| settings.clone()).unwrap_or_default();
+        // the atlas is sized by the settings, so it is created again when they resize it
+        let shadow_maps = match self.shadow_maps.take().filter(|shadow_maps| shadow_maps.fits(&settings)) {
+            Some(shadow_maps) => shadow_maps,
+            None => ShadowMaps::new(&ctx.gl, &ctx.res, &settings)?,
+        };
+        let shadow_maps = self.shadow_maps.insert(shadow_maps);
+        self.shadows.update(&settings, camera, &self.lights);
+        shadow_maps.render(&mut ctx.render_state, &self.shadows, &settings, &self.casters);
+        match world.resource_mut::<SceneTarget>() {
+            Some(mut target) => target.0.bind_for(&ctx.viewport)?,
+            None => ctx.viewport.set_used(&ctx.gl),
+        }
+
+        match world.resource::<Skybox>() {
+            Some(skybox) => self.queue.submit_with(&ctx.gl, &mut ctx.render_state, camera, |render_state, camera| skybox.render(render_state, camera)),
+            None => self.queue.submit(&ctx.gl, &mut ctx.render_state, camera),
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn spin_turns_by_the_time_step() {
+        let mut world = World::new();
+        world.insert_resource(Time { step: Duration::from_millis(500), ..Time::default() });
+        let entity = world.spawn()
+            .with(Transform::default())
+            .with(Spin { axis: glm::Vec3::y_axis(), radians_per_second: 1.0 })
+            .build();
+        let still = world.spawn().with(Transform::default()).build();
+
+        let mut spin = SpinSystem;
+        System::<()>::setup(&mut spin, &mut world);
+        spin.run(&mut world, &mut ()).unwrap();
+        spin.run(&mut world, &mut ()).unwrap();
+
+        let transform = world.get::<Transform>(entity).unwrap();
+        assert!((transform.rotation.angle() - 1.0).abs() < 1e-5);
+        assert_eq!(world.get::<Transform>(still), Some(Transform::default()));
+    }
+}
@@ -0,0 +1,254 @@
+use std::any::{ type_name, Any, TypeId };
+use std::cell::{ Ref, RefCell, RefMut };
+use std::collections::HashMap;
+
+use super::entity::{ Entities, Entity };
+use super::storage::{ AnyStorage, Storage };
+
+/**
+ * Entities, their components and resources shared by systems.
+ *
+ * Storages and resources are borrowed at runtime like a `RefCell`, so a system can read
+ * one storage while writing another. Borrowing the same storage mutably twice panics.
+ */
+#[derive(Default)]
+pub struct World {
+    entities: Entities,
+    storages: HashMap<TypeId, RefCell<Box<dyn AnyStorage>>>,
+    resources: HashMap<TypeId, RefCell<Box<dyn Any>>>,
+}
+
+/**
+ * Returned by `World::spawn` to add components to a new entity
+ */
+pub struct EntityBuilder<'a> {
+    world: &'a mut World,
+    entity: Entity,
+}
+
+impl<'a> EntityBuilder<'a> {
+    pub fn with<T: 'static>(self, component: T) -> EntityBuilder<'a> {
+        self.world.insert(self.entity, component);
+        self
+    }
+
+    pub fn build(self) -> Entity {
+        self.entity
+    }
+}
+
+impl World {
+    pub fn new() -> World {
+        World::default()
+    }
+
+    pub fn spawn(&mut self) -> EntityBuilder<'_> {
+        let entity = self.entities.create();
+        EntityBuilder { world: self, entity }
+    }
+
+    /**
+     * Removes the entity and all its components, false if it was already gone
+     */
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.entities.destroy(entity) {
+            return false;
+        }
+        for storage in self.storages.values_mut() {
+            storage.get_mut().remove_entity(entity);
+        }
+        true
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.entities.is_alive(entity)
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.entities.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /**
+     * Creates the storage of a component type, so it can be read before any entity has one
+     */
+    pub fn register<T: 'static>(&mut self) {
+        self.storages.entry(TypeId::of::<T>())
+            .or_insert_with(|| RefCell::new(Box::new(Storage::<T>::new())));
+    }
+
+    /**
+     * Adds or replaces a component, false if the entity is dead
+     */
+    pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) -> bool {
+        if !self.entities.is_alive(entity) {
+            return false;
+        }
+        self.register::<T>();
+        self.write::<T>().insert(entity, component);
+        true
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+        self.storages.get_mut(&TypeId::of::<T>())
+            .and_then(|storage| downcast_mut::<T>(storage.get_mut()).remove(entity))
+    }
+
+    /**
+     * Borrows the storage of `T`, panics if it was never registered or is borrowed mutably
+     */
+    pub fn read<T: 'static>(&self) -> Ref<'_, Storage<T>> {
+        Ref::map(self.storage_cell::<T>().borrow(), |storage| {
+            storage.as_any().downcast_ref::<Storage<T>>().unwrap()
+        })
+    }
+
+    /**
+     * Borrows the storage of `T` mutably, panics if it was never registered or is borrowed
+     */
+    pub fn write<T: 'static>(&self) -> RefMut<'_, Storage<T>> {
+        RefMut::map(self.storage_cell::<T>().borrow_mut(), |storage| downcast_mut::<T>(storage))
+    }
+
+    /**
+     * Copy of one component, for quick lookups outside of systems
+     */
+    pub fn get<T: Clone + 'static>(&self, entity: Entity) -> Option<T> {
+        self.storages.get(&TypeId::of::<T>())?;
+        self.read::<T>().get(entity).cloned()
+    }
+
+    pub fn has<T: 'static>(&self, entity: Entity) -> bool {
+        self.storages.contains_key(&TypeId::of::<T>()) && self.read::<T>().contains(entity)
+    }
+
+    // -----------------------------------------------------------------
+    // resources
+
+    /**
+     * Stores a value shared by systems, such as the frame time, replacing the previous one
+     */
+    pub fn insert_resource<T: 'static>(&mut self, resource: T) {
+        self.resources.insert(TypeId::of::<T>(), RefCell::new(Box::new(resource)));
+    }
+
+    pub fn remove_resource<T: 'static>(&mut self) -> Option<T> {
+        self.resources.remove(&TypeId::of::<T>())
+            .and_then(|resource| resource.into_inner().downcast::<T>().ok())
+            .map(|resource| *resource)
+    }
+
+    pub fn resource<T: 'static>(&self) -> Option<Ref<'_, T>> {
+        self.resources.get(&TypeId::of::<T>())
+            .map(|resource| Ref::map(resource.borrow(), |resource| resource.downcast_ref::<T>().unwrap()))
+    }
+
+    pub fn resource_mut<T: 'static>(&self) -> Option<RefMut<'_, T>> {
+        self.resources.get(&TypeId::of::<T>())
+            .map(|resource| RefMut::map(resource.borrow_mut(), |resource| resource.downcast_mut::<T>().unwrap()))
+    }
+
+    fn storage_cell<T: 'static>(&self) -> &RefCell<Box<dyn AnyStorage>> {
+        self.storages.get(&TypeId::of::<T>())
+            .unwrap_or_else(|| panic!("component {} is not registered", type_name::<T>()))
+    }
+}
+
+fn downcast_mut<T: 'static>(storage: &mut Box<dyn AnyStorage>) -> &mut Storage<T> {
+    storage.as_any_mut().downcast_mut::<Storage<T>>().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Position(f32);
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Velocity(f32);
+
+    #[test]
+    fn spawned_entities_have_their_components() {
+        let mut world = World::new();
+        let moving = world.spawn().with(Position(0.0)).with(Velocity(2.0)).build();
+        let still = world.spawn().with(Position(5.0)).build();
+
+        assert_eq!(world.len(), 2);
+        assert_eq!(world.get::<Position>(still), Some(Position(5.0)));
+        assert!(world.has::<Velocity>(moving));
+        assert!(!world.has::<Velocity>(still));
+
+        {
+            let velocities = world.read::<Velocity>();
+            let mut positions = world.write::<Position>();
+            for (_, position, velocity) in positions.join_mut(&velocities) {
+                position.0 += velocity.0;
+            }
+        }
+        assert_eq!(world.get::<Position>(moving), Some(Position(2.0)));
+        assert_eq!(world.get::<Position>(still), Some(Position(5.0)));
+    }
+
+    #[test]
+    fn despawning_removes_every_component() {
+        let mut world = World::new();
+        let entity = world.spawn().with(Position(1.0)).with(Velocity(1.0)).build();
+
+        assert!(world.despawn(entity));
+        assert!(!world.despawn(entity));
+        assert!(!world.is_alive(entity));
+        assert!(world.read::<Position>().is_empty());
+        assert!(world.read::<Velocity>().is_empty());
+
+        // components can not be added to dead entities
+        assert!(!world.insert(entity, Position(2.0)));
+        assert!(world.read::<Position>().is_empty());
+    }
+
+    #[test]
+    fn remove_takes_a_single_component() {
+        let mut world = World::new();
+        let entity = world.spawn().with(Position(1.0)).with(Velocity(1.0)).build();
+
+        assert_eq!(world.remove::<Velocity>(entity), Some(Velocity(1.0)));
+        assert_eq!(world.remove::<Velocity>(entity), None);
+        assert!(world.has::<Position>(entity));
+        assert_eq!(world.entities().collect::<Vec<_>>(), vec![entity]);
+    }
+
+    #[test]
+    fn registered_storages_can_be_read_empty() {
+        let mut world = World::new();
+        let entity = world.spawn().build();
+        assert_eq!(world.get::<Position>(entity), None);
+
+        world.register::<Position>();
+        assert!(world.read::<Position>().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "is not registered")]
+    fn reading_an_unknown_component_panics() {
+        World::new().read::<Position>();
+    }
+
+    #[test]
+    fn resources() {
+        let mut world = World::new();
+        assert!(world.resource::<u32>().is_none());
+
+        world.insert_resource(1u32);
+        *world.resource_mut::<u32>().unwrap() += 1;
+        assert_eq!(*world.resource::<u32>().unwrap(), 2);
+        assert_eq!(world.remove_resource::<u32>(), Some(2));
+        assert!(world.resource::<u32>().is_none());
+    }
+}
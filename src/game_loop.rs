@@ -0,0 +1,557 @@
+/*!
+ * Fixed timestep game loop.
+ *
+ * The simulation advances in steps of `LoopConfig::update_step` no matter how long frames
+ * take, so movement does not depend on the frame rate. Each frame the elapsed time is added
+ * to an accumulator and as many whole steps as fit are run; the leftover fraction of a step
+ * is the interpolation `alpha` renderers blend the previous and current state with.
+ *
+ * ```ignore
+ * let mut game_loop = GameLoop::new(LoopConfig::default(), SystemClock::new());
+ * loop {
+ *     let timing = game_loop.begin_frame();
+ *     for _ in 0..timing.updates {
+ *         update(timing.step);
+ *     }
+ *     render(timing.alpha);
+ *     window.gl_swap_window();
+ *     game_loop.end_frame();
+ * }
+ * ```
+ *
+ * Time comes from a `Clock`, tests drive the loop with a `ManualClock`.
+ */
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{ Duration, Instant };
+
+/**
+ * Source of time for the loop, `now` is measured from an arbitrary fixed origin
+ */
+pub trait Clock {
+    fn now(&self) -> Duration;
+    fn sleep(&self, duration: Duration);
+}
+
+pub struct SystemClock {
+    origin: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> SystemClock {
+        SystemClock { origin: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> SystemClock {
+        SystemClock::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        ::std::thread::sleep(duration);
+    }
+}
+
+/**
+ * A clock that only moves when told to. Sleeping advances it by exactly the requested time.
+ */
+#[derive(Default)]
+pub struct ManualClock {
+    now: Cell<Duration>,
+    slept: Cell<Duration>,
+}
+
+impl ManualClock {
+    pub fn new() -> ManualClock {
+        ManualClock::default()
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+
+    /**
+     * Total time spent in `sleep`
+     */
+    pub fn slept(&self) -> Duration {
+        self.slept.get()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.slept.set(self.slept.get() + duration);
+        self.advance(duration);
+    }
+}
+
+impl<C: Clock> Clock for &C {
+    fn now(&self) -> Duration {
+        (**self).now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        (**self).sleep(duration)
+    }
+}
+
+// -----------------------------------------------------------------
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VsyncMode {
+    Off,
+    On,
+    Adaptive,   // tears instead of waiting a whole refresh when a frame is late
+}
+
+impl VsyncMode {
+    pub fn swap_interval(&self) -> sdl2::video::SwapInterval {
+        match self {
+            VsyncMode::Off => sdl2::video::SwapInterval::Immediate,
+            VsyncMode::On => sdl2::video::SwapInterval::VSync,
+            VsyncMode::Adaptive => sdl2::video::SwapInterval::LateSwapTearing,
+        }
+    }
+
+    /**
+     * Sets the swap interval of the current gl context. Adaptive vsync falls back to
+     * regular vsync where the driver does not support it. Returns the mode in effect.
+     */
+    pub fn apply(&self, video: &sdl2::VideoSubsystem)
+    -> Result<VsyncMode, String>
+    {
+        match video.gl_set_swap_interval(self.swap_interval()) {
+            Ok(()) => Ok(*self),
+            Err(e) if *self == VsyncMode::Adaptive => {
+                warn!("adaptive vsync is not supported ({}), using vsync", e);
+                VsyncMode::On.apply(video)
+            },
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LoopConfig {
+    pub update_step: Duration,
+    pub max_updates_per_frame: u32,     // time beyond this many steps is dropped instead of caught up
+    pub frame_limit: Option<u32>,       // frames per second, on top of vsync
+    pub vsync: VsyncMode,
+    pub stats_window: usize,            // frames the fps is averaged over
+}
+
+impl Default for LoopConfig {
+    fn default() -> LoopConfig {
+        LoopConfig {
+            update_step: Duration::from_secs(1) / 60,
+            max_updates_per_frame: 5,
+            frame_limit: None,
+            vsync: VsyncMode::On,
+            stats_window: 60,
+        }
+    }
+}
+
+/**
+ * What to do this frame
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FrameTiming {
+    pub updates: u32,           // fixed steps to simulate
+    pub step: Duration,
+    pub alpha: f32,             // 0..1, how far rendering is between the last two updates
+    pub frame_time: Duration,   // since the previous frame began
+    pub dropped: Duration,      // time the simulation fell behind and will not catch up
+}
+
+pub struct GameLoop<C: Clock> {
+    clock: C,
+    config: LoopConfig,
+    accumulator: Duration,
+    frame_start: Option<Duration>,
+    stats: FrameStats,
+}
+
+impl<C: Clock> GameLoop<C> {
+    pub fn new(config: LoopConfig, clock: C)
+    -> GameLoop<C>
+    {
+        assert!(config.update_step > Duration::default(), "update step must not be zero");
+
+        GameLoop {
+            clock,
+            stats: FrameStats::new(config.stats_window),
+            config,
+            accumulator: Duration::default(),
+            frame_start: None,
+        }
+    }
+
+    /**
+     * Starts a frame and works out how many updates it runs. The first frame runs none.
+     */
+    pub fn begin_frame(&mut self) -> FrameTiming {
+        let now = self.clock.now();
+        let frame_time = self.frame_start.map_or(Duration::default(), |start| now.saturating_sub(start));
+        self.frame_start = Some(now);
+
+        if frame_time > Duration::default() {
+            self.stats.record(frame_time);
+        }
+
+        let step = self.config.update_step;
+        let max_accumulated = step * self.config.max_updates_per_frame.max(1);
+
+        self.accumulator += frame_time;
+        let dropped = self.accumulator.saturating_sub(max_accumulated);
+        self.accumulator -= dropped;
+
+        let updates = (self.accumulator.as_nanos() / step.as_nanos()) as u32;
+        self.accumulator -= step * updates;
+
+        FrameTiming {
+            updates,
+            step,
+            alpha: (self.accumulator.as_secs_f64() / step.as_secs_f64()) as f32,
+            frame_time,
+            dropped,
+        }
+    }
+
+    /**
+     * Ends the frame after the buffers were swapped, sleeping off the rest of the frame
+     * when a frame limit is set
+     */
+    pub fn end_frame(&mut self) {
+        let (limit, start) = match (self.config.frame_limit, self.frame_start) {
+            (Some(limit), Some(start)) if limit > 0 => (limit, start),
+            _ => return,
+        };
+
+        let deadline = start + Duration::from_secs(1) / limit;
+        let now = self.clock.now();
+        if now < deadline {
+            self.clock.sleep(deadline - now);
+        }
+    }
+
+    pub fn config(&self) -> &LoopConfig {
+        &self.config
+    }
+
+    /**
+     * Changes take effect from the next frame, vsync has to be re-applied by the caller
+     */
+    pub fn set_config(&mut self, config: LoopConfig) {
+        if config.stats_window != self.config.stats_window {
+            self.stats.set_window(config.stats_window);
+        }
+        self.config = config;
+    }
+
+    pub fn stats(&self) -> &FrameStats {
+        &self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = FrameStats::new(self.config.stats_window);
+    }
+}
+
+// -----------------------------------------------------------------
+
+/**
+ * Frame rate over a sliding window and a histogram of every frame time since the last reset
+ */
+#[derive(Clone, Debug)]
+pub struct FrameStats {
+    window: usize,
+    recent: VecDeque<Duration>,
+    histogram: FrameTimeHistogram,
+}
+
+impl FrameStats {
+    pub fn new(window: usize) -> FrameStats {
+        FrameStats {
+            window: window.max(1),
+            recent: VecDeque::new(),
+            histogram: FrameTimeHistogram::new(Duration::from_millis(2), 25),
+        }
+    }
+
+    fn set_window(&mut self, window: usize) {
+        self.window = window.max(1);
+        while self.recent.len() > self.window {
+            self.recent.pop_front();
+        }
+    }
+
+    pub fn record(&mut self, frame_time: Duration) {
+        if self.recent.len() == self.window {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(frame_time);
+        self.histogram.record(frame_time);
+    }
+
+    /**
+     * Frames per second over the window, 0 before the first frame
+     */
+    pub fn fps(&self) -> f64 {
+        let total: Duration = self.recent.iter().sum();
+        if total == Duration::default() {
+            return 0.0;
+        }
+        self.recent.len() as f64 / total.as_secs_f64()
+    }
+
+    pub fn average_frame_time(&self) -> Duration {
+        match self.recent.len() {
+            0 => Duration::default(),
+            n => self.recent.iter().sum::<Duration>() / n as u32,
+        }
+    }
+
+    pub fn histogram(&self) -> &FrameTimeHistogram {
+        &self.histogram
+    }
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:.1} fps, {:.2} ms average over the last {} frames",
+            self.fps(),
+            self.average_frame_time().as_secs_f64() * 1000.0,
+            self.recent.len()
+        )?;
+        write!(f, "{}", self.histogram)
+    }
+}
+
+/**
+ * Frame counts in buckets of equal width, times past the last bucket count as overflow
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameTimeHistogram {
+    bucket_width: Duration,
+    counts: Vec<u64>,
+    overflow: u64,
+}
+
+impl FrameTimeHistogram {
+    pub fn new(bucket_width: Duration, buckets: usize) -> FrameTimeHistogram {
+        FrameTimeHistogram {
+            bucket_width,
+            counts: vec![0; buckets],
+            overflow: 0,
+        }
+    }
+
+    pub fn record(&mut self, frame_time: Duration) {
+        let bucket = (frame_time.as_nanos() / self.bucket_width.as_nanos()) as usize;
+        match self.counts.get_mut(bucket) {
+            Some(count) => *count += 1,
+            None => self.overflow += 1,
+        }
+    }
+
+    /**
+     * Frames in bucket `index`, which covers `[index * width, (index + 1) * width)`
+     */
+    pub fn count(&self, index: usize) -> u64 {
+        self.counts.get(index).cloned().unwrap_or(0)
+    }
+
+    pub fn overflow(&self) -> u64 {
+        self.overflow
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum::<u64>() + self.overflow
+    }
+}
+
+impl fmt::Display for FrameTimeHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const BAR_WIDTH: u64 = 40;
+
+        let width_ms = self.bucket_width.as_secs_f64() * 1000.0;
+        let largest = self.counts.iter().cloned().chain(Some(self.overflow)).max().unwrap_or(0).max(1);
+        let bar = |count: u64| "#".repeat((count * BAR_WIDTH).div_ceil(largest) as usize);
+
+        // only the range that has frames in it
+        let first = self.counts.iter().position(|&count| count > 0);
+        let last = self.counts.iter().rposition(|&count| count > 0);
+        if let (Some(first), Some(last)) = (first, last) {
+            for index in first..=last {
+                writeln!(
+                    f,
+                    "{:6.1} - {:6.1} ms {:8} {}",
+                    index as f64 * width_ms,
+                    (index + 1) as f64 * width_ms,
+                    self.counts[index],
+                    bar(self.counts[index])
+                )?;
+            }
+        }
+        if self.overflow > 0 {
+            writeln!(f, "{:>15.1}+ ms {:8} {}", self.counts.len() as f64 * width_ms, self.overflow, bar(self.overflow))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(value: u64) -> Duration {
+        Duration::from_millis(value)
+    }
+
+    fn config(step_ms: u64) -> LoopConfig {
+        LoopConfig {
+            update_step: ms(step_ms),
+            ..LoopConfig::default()
+        }
+    }
+
+    #[test]
+    fn first_frame_runs_no_updates() {
+        let clock = ManualClock::new();
+        clock.advance(ms(1000));
+        let mut game_loop = GameLoop::new(config(10), &clock);
+
+        let timing = game_loop.begin_frame();
+        assert_eq!(timing.updates, 0);
+        assert_eq!(timing.frame_time, Duration::default());
+    }
+
+    #[test]
+    fn leftover_time_accumulates_into_alpha() {
+        let clock = ManualClock::new();
+        let mut game_loop = GameLoop::new(config(10), &clock);
+        game_loop.begin_frame();
+
+        clock.advance(ms(25));
+        let timing = game_loop.begin_frame();
+        assert_eq!(timing.updates, 2);
+        assert!((timing.alpha - 0.5).abs() < 1e-6);
+
+        clock.advance(ms(7));
+        let timing = game_loop.begin_frame();
+        assert_eq!(timing.updates, 1);  // 5 left over + 7
+        assert!((timing.alpha - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn update_count_does_not_depend_on_frame_rate() {
+        let run = |frame_ms: u64| {
+            let clock = ManualClock::new();
+            let mut game_loop = GameLoop::new(config(10), &clock);
+            let mut updates = 0;
+            game_loop.begin_frame();
+            for _ in 0..(1200 / frame_ms) {
+                clock.advance(ms(frame_ms));
+                updates += game_loop.begin_frame().updates;
+            }
+            updates
+        };
+
+        assert_eq!(run(4), 120);
+        assert_eq!(run(16), 120);
+        assert_eq!(run(40), 120);
+    }
+
+    #[test]
+    fn long_frames_are_clamped() {
+        let clock = ManualClock::new();
+        let mut game_loop = GameLoop::new(LoopConfig { max_updates_per_frame: 3, ..config(10) }, &clock);
+        game_loop.begin_frame();
+
+        clock.advance(ms(1000));
+        let timing = game_loop.begin_frame();
+        assert_eq!(timing.updates, 3);
+        assert_eq!(timing.dropped, ms(970));
+        assert_eq!(timing.alpha, 0.0);
+
+        // nothing left to catch up on
+        clock.advance(ms(10));
+        assert_eq!(game_loop.begin_frame().updates, 1);
+    }
+
+    #[test]
+    fn frame_limit_sleeps_off_the_rest_of_the_frame() {
+        let clock = ManualClock::new();
+        let mut game_loop = GameLoop::new(LoopConfig { frame_limit: Some(50), ..config(10) }, &clock);
+
+        game_loop.begin_frame();
+        clock.advance(ms(5));
+        game_loop.end_frame();
+        assert_eq!(clock.slept(), ms(15));
+
+        // a frame over budget does not sleep
+        game_loop.begin_frame();
+        clock.advance(ms(30));
+        game_loop.end_frame();
+        assert_eq!(clock.slept(), ms(15));
+    }
+
+    #[test]
+    fn no_sleep_without_a_frame_limit() {
+        let clock = ManualClock::new();
+        let mut game_loop = GameLoop::new(config(10), &clock);
+        game_loop.begin_frame();
+        game_loop.end_frame();
+        assert_eq!(clock.slept(), Duration::default());
+    }
+
+    #[test]
+    fn fps_is_averaged_over_the_window() {
+        let clock = ManualClock::new();
+        let mut game_loop = GameLoop::new(LoopConfig { stats_window: 4, ..config(10) }, &clock);
+        game_loop.begin_frame();
+        for frame_ms in &[100, 100, 10, 10, 10, 10] {
+            clock.advance(ms(*frame_ms));
+            game_loop.begin_frame();
+        }
+
+        assert!((game_loop.stats().fps() - 100.0).abs() < 1e-9);
+        assert_eq!(game_loop.stats().average_frame_time(), ms(10));
+        assert_eq!(game_loop.stats().histogram().total(), 6);
+    }
+
+    #[test]
+    fn histogram_buckets_frame_times() {
+        let mut histogram = FrameTimeHistogram::new(ms(2), 10);
+        for frame_ms in &[0, 1, 2, 3, 16, 17, 19, 20, 100] {
+            histogram.record(ms(*frame_ms));
+        }
+
+        assert_eq!(histogram.count(0), 2);
+        assert_eq!(histogram.count(1), 2);
+        assert_eq!(histogram.count(8), 2);
+        assert_eq!(histogram.count(9), 1);
+        assert_eq!(histogram.overflow(), 2);
+        assert_eq!(histogram.total(), 9);
+
+        let text = histogram.to_string();
+        assert!(text.starts_with("   0.0 -    2.0 ms        2 "));
+        assert!(text.contains("20.0+ ms        2"));
+    }
+}
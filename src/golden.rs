@@ -0,0 +1,166 @@
+/*!
+ * Golden image tests: a rendered frame is compared against a reference png in
+ * `tests/golden` with a per channel tolerance, so small rasteriser differences
+ * between drivers don't fail the test.
+ *
+ * On failure the rendered image and a diff image (mismatching pixels in red over
+ * a faded copy of the reference) are written to `target/golden-failures`.
+ * Run the tests with `UPDATE_GOLDEN=1` to (re)write the references instead.
+ */
+use std::env;
+use std::path::PathBuf;
+
+use crate::render_gl::Image;
+
+const UPDATE_ENV_VAR: &str = "UPDATE_GOLDEN";
+
+#[derive(Debug)]
+pub struct Comparison {
+    pub mismatched_pixels: usize,
+    pub max_difference: u8,
+    pub diff: Image,
+}
+
+/**
+ * Compares two images of the same size. A pixel mismatches when any channel
+ * differs by more than `tolerance`.
+ */
+pub fn compare(actual: &Image, expected: &Image, tolerance: u8)
+-> Result<Comparison, String>
+{
+    if actual.width != expected.width || actual.height != expected.height {
+        return Err(format!(
+            "image is {}x{} but the reference is {}x{}",
+            actual.width, actual.height, expected.width, expected.height
+        ));
+    }
+
+    let mut mismatched_pixels = 0;
+    let mut max_difference = 0;
+    let mut diff = Vec::with_capacity(expected.pixels.len());
+
+    for (a, e) in actual.pixels.chunks(4).zip(expected.pixels.chunks(4)) {
+        let difference = a.iter().zip(e)
+            .map(|(a, e)| (*a as i16 - *e as i16).unsigned_abs() as u8)
+            .max()
+            .unwrap_or(0);
+        max_difference = max_difference.max(difference);
+
+        if difference > tolerance {
+            mismatched_pixels += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let luminance = ((e[0] as u32 + e[1] as u32 + e[2] as u32) / 3 / 4) as u8;
+            diff.extend_from_slice(&[luminance, luminance, luminance, 255]);
+        }
+    }
+
+    Ok(Comparison {
+        mismatched_pixels,
+        max_difference,
+        diff: Image { width: expected.width, height: expected.height, pixels: diff },
+    })
+}
+
+/**
+ * Panics unless `actual` matches `tests/golden/<name>.png` with at most
+ * `max_mismatched_pixels` pixels outside of `tolerance`
+ */
+#[cfg_attr(not(feature = "headless"), allow(dead_code))]
+pub fn assert_matches_golden(actual: &Image, name: &str, tolerance: u8, max_mismatched_pixels: usize)
+{
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let reference_path = manifest_dir.join("tests").join("golden").join(format!("{}.png", name));
+
+    if env::var_os(UPDATE_ENV_VAR).is_some() {
+        actual.save_png(&reference_path).unwrap();
+        return;
+    }
+
+    let expected = Image::load_png(&reference_path).unwrap_or_else(|e| panic!(
+        "{} (run with {}=1 to create the reference)", e, UPDATE_ENV_VAR
+    ));
+
+    let failure = match compare(actual, &expected, tolerance) {
+        Ok(ref comparison) if comparison.mismatched_pixels <= max_mismatched_pixels => return,
+        Ok(comparison) => {
+            let failures_dir = manifest_dir.join("target").join("golden-failures");
+            let diff_path = failures_dir.join(format!("{}.diff.png", name));
+            comparison.diff.save_png(&diff_path).unwrap();
+            format!(
+                "{} pixels differ by more than {} (max difference {}), diff written to {}",
+                comparison.mismatched_pixels, tolerance, comparison.max_difference, diff_path.display()
+            )
+        },
+        Err(message) => message,
+    };
+
+    let actual_path = manifest_dir.join("target").join("golden-failures").join(format!("{}.png", name));
+    actual.save_png(&actual_path).unwrap();
+
+    panic!("{} does not match {}: {}, rendered image written to {}",
+        name, reference_path.display(), failure, actual_path.display());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, rgba: [u8; 4]) -> Image {
+        Image {
+            width,
+            height,
+            pixels: rgba.iter().cycle().take((width * height * 4) as usize).cloned().collect(),
+        }
+    }
+
+    #[test]
+    fn identical_images_match() {
+        let image = solid(4, 3, [10, 20, 30, 255]);
+        let comparison = compare(&image, &image, 0).unwrap();
+        assert_eq!(comparison.mismatched_pixels, 0);
+        assert_eq!(comparison.max_difference, 0);
+    }
+
+    #[test]
+    fn differences_within_tolerance_match() {
+        let actual = solid(2, 2, [12, 20, 30, 255]);
+        let expected = solid(2, 2, [10, 21, 30, 255]);
+        let comparison = compare(&actual, &expected, 2).unwrap();
+        assert_eq!(comparison.mismatched_pixels, 0);
+        assert_eq!(comparison.max_difference, 2);
+    }
+
+    #[test]
+    fn mismatching_pixels_are_counted_and_marked_red() {
+        let expected = solid(2, 1, [0, 0, 0, 255]);
+        let mut actual = expected.clone();
+        actual.pixels[4] = 200; // red channel of the second pixel
+
+        let comparison = compare(&actual, &expected, 8).unwrap();
+        assert_eq!(comparison.mismatched_pixels, 1);
+        assert_eq!(comparison.max_difference, 200);
+        assert_eq!(&comparison.diff.pixels[4..8], &[255, 0, 0, 255]);
+        assert_ne!(&comparison.diff.pixels[0..4], &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn size_mismatch_is_an_error() {
+        assert!(compare(&solid(2, 2, [0; 4]), &solid(2, 3, [0; 4]), 255).is_err());
+    }
+
+    #[cfg(feature = "headless")]
+    #[test]
+    fn triangle_matches_reference() {
+        use crate::resources::Resources;
+
+        let res = Resources::from_path(&PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets"));
+        let mut settings = crate::settings::Settings::default();
+        settings.window.width = 320;
+        settings.window.height = 240;
+        let image = crate::render_headless_frame(res, &settings, true).unwrap();
+
+        // edges may rasterise slightly differently between Mesa versions and GPUs
+        assert_matches_golden(&image, "triangle", 8, 64);
+    }
+}
@@ -0,0 +1,105 @@
+use gl;
+use khronos_egl as egl;
+
+/**
+ * An OpenGL context without a window, created through EGL.
+ *
+ * On machines without a GPU or display server this runs on Mesa's llvmpipe software
+ * rasteriser through the surfaceless platform, so rendering code can be exercised in CI.
+ * There is no default framebuffer: bind a `render_gl::Framebuffer` before drawing.
+ */
+pub struct HeadlessContext {
+    egl: egl::DynamicInstance<egl::EGL1_5>,
+    display: egl::Display,
+    context: egl::Context,
+    gl: gl::Gl,
+}
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load libEGL: {}", message)]
+    LoadEgl { message: String },
+
+    #[fail(display = "{} failed", call)]
+    Egl { call: &'static str, #[cause] error: egl::Error },
+
+    #[fail(display = "No EGL config supports desktop OpenGL")]
+    NoConfig,
+}
+
+const PLATFORM_SURFACELESS_MESA: egl::Enum = 0x31DD;
+
+impl HeadlessContext {
+    /**
+     * `debug` requests a debug context, for use with `render_gl::DebugOutput`
+     */
+    pub fn new(major_version: u8, minor_version: u8, debug: bool)
+    -> Result<HeadlessContext, Error>
+    {
+        let egl = unsafe { egl::DynamicInstance::<egl::EGL1_5>::load_required() }
+            .map_err(|e| Error::LoadEgl { message: e.to_string() })?;
+
+        // prefer the surfaceless platform, it works without X11 or Wayland
+        let display = egl.get_platform_display(PLATFORM_SURFACELESS_MESA, egl::DEFAULT_DISPLAY, &[egl::ATTRIB_NONE])
+            .or_else(|_| egl.get_display(egl::DEFAULT_DISPLAY).ok_or(egl::Error::BadDisplay))
+            .map_err(|error| Error::Egl { call: "eglGetDisplay", error })?;
+
+        egl.initialize(display)
+            .map_err(|error| Error::Egl { call: "eglInitialize", error })?;
+        egl.bind_api(egl::OPENGL_API)
+            .map_err(|error| Error::Egl { call: "eglBindAPI", error })?;
+
+        // the default surface type is a window, which the surfaceless platform has no configs for
+        let config_attributes = [
+            egl::SURFACE_TYPE, egl::PBUFFER_BIT,
+            egl::RENDERABLE_TYPE, egl::OPENGL_BIT,
+            egl::RED_SIZE, 8,
+            egl::GREEN_SIZE, 8,
+            egl::BLUE_SIZE, 8,
+            egl::ALPHA_SIZE, 8,
+            egl::NONE,
+        ];
+        let config = egl.choose_first_config(display, &config_attributes)
+            .map_err(|error| Error::Egl { call: "eglChooseConfig", error })?
+            .ok_or(Error::NoConfig)?;
+
+        let context_attributes = [
+            egl::CONTEXT_MAJOR_VERSION, major_version as egl::Int,
+            egl::CONTEXT_MINOR_VERSION, minor_version as egl::Int,
+            egl::CONTEXT_OPENGL_PROFILE_MASK, egl::CONTEXT_OPENGL_CORE_PROFILE_BIT,
+            egl::CONTEXT_OPENGL_DEBUG, if debug { egl::TRUE } else { egl::FALSE } as egl::Int,
+            egl::NONE,
+        ];
+        let context = egl.create_context(display, config, None, &context_attributes)
+            .map_err(|error| Error::Egl { call: "eglCreateContext", error })?;
+
+        // no surface at all, everything is drawn into framebuffer objects (EGL_KHR_surfaceless_context)
+        egl.make_current(display, None, None, Some(context))
+            .map_err(|error| Error::Egl { call: "eglMakeCurrent", error })?;
+
+        let gl = gl::Gl::load_with(|s| {
+            egl.get_proc_address(s)
+                .map_or(::std::ptr::null(), |f| f as *const std::os::raw::c_void)
+        });
+
+        Ok(HeadlessContext {
+            egl,
+            display,
+            context,
+            gl,
+        })
+    }
+
+    pub fn gl(&self) -> &gl::Gl {
+        &self.gl
+    }
+}
+
+impl Drop for HeadlessContext {
+    fn drop(&mut self)
+    {
+        let _ = self.egl.make_current(self.display, None, None, None);
+        let _ = self.egl.destroy_context(self.display, self.context);
+        let _ = self.egl.terminate(self.display);
+    }
+}
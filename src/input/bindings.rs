@@ -0,0 +1,492 @@
+use sdl2::controller;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{ Path, PathBuf };
+use std::str::FromStr;
+
+use super::gamepad::GamepadConfig;
+use super::keys::KEYCODES;
+use crate::resources::Resources;
+use crate::resources::Error as ResourcesError;
+use crate::settings::{ merge, user_config_dir };
+
+const USER_BINDINGS_FILE: &str = "input.toml";
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load resource {}", name)]
+    UnableToLoadResource { name: String, #[cause] error: ResourcesError },
+
+    #[fail(display = "Failed to parse input bindings {}", name)]
+    Parse { name: String, #[cause] error: toml::de::Error },
+
+    #[fail(display = "Failed to serialize input bindings")]
+    Serialize { #[cause] error: toml::ser::Error },
+
+    #[fail(display = "I/O error on {}", path)]
+    Io { path: String, #[cause] error: io::Error },
+}
+
+/**
+ * A physical button. In binding files they are written as `Key:Space`, `Mouse:Left` or
+ * `Gamepad:A`, keys use the variant names of `sdl2::keyboard::Keycode`.
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Button {
+    Key(Keycode),
+    Mouse(MouseButton),
+    Gamepad(controller::Button),
+}
+
+/**
+ * A physical analog input, written as `Gamepad:LeftX`, `Mouse:X`, `Mouse:Y` or `Mouse:Wheel`.
+ * Gamepad axes range from -1 to 1 (triggers 0 to 1), mouse axes are the motion of the frame
+ * in pixels or wheel steps.
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Analog {
+    Gamepad(controller::Axis),
+    MouseX,
+    MouseY,
+    MouseWheel,
+}
+
+const MOUSE_BUTTONS: [(&str, MouseButton); 5] = [
+    ("Left", MouseButton::Left),
+    ("Middle", MouseButton::Middle),
+    ("Right", MouseButton::Right),
+    ("X1", MouseButton::X1),
+    ("X2", MouseButton::X2),
+];
+
+const GAMEPAD_BUTTONS: [(&str, controller::Button); 15] = [
+    ("A", controller::Button::A),
+    ("B", controller::Button::B),
+    ("X", controller::Button::X),
+    ("Y", controller::Button::Y),
+    ("Back", controller::Button::Back),
+    ("Guide", controller::Button::Guide),
+    ("Start", controller::Button::Start),
+    ("LeftStick", controller::Button::LeftStick),
+    ("RightStick", controller::Button::RightStick),
+    ("LeftShoulder", controller::Button::LeftShoulder),
+    ("RightShoulder", controller::Button::RightShoulder),
+    ("DPadUp", controller::Button::DPadUp),
+    ("DPadDown", controller::Button::DPadDown),
+    ("DPadLeft", controller::Button::DPadLeft),
+    ("DPadRight", controller::Button::DPadRight),
+];
+
+const GAMEPAD_AXES: [(&str, controller::Axis); 6] = [
+    ("LeftX", controller::Axis::LeftX),
+    ("LeftY", controller::Axis::LeftY),
+    ("RightX", controller::Axis::RightX),
+    ("RightY", controller::Axis::RightY),
+    ("TriggerLeft", controller::Axis::TriggerLeft),
+    ("TriggerRight", controller::Axis::TriggerRight),
+];
+
+fn find_by_name<T: Copy>(table: &[(&str, T)], name: &str) -> Option<T> {
+    table.iter().find(|(candidate, _)| *candidate == name).map(|&(_, value)| value)
+}
+
+fn find_name<T: PartialEq>(table: &[(&'static str, T)], value: &T) -> &'static str {
+    table.iter().find(|(_, candidate)| candidate == value).map_or("Unknown", |&(name, _)| name)
+}
+
+impl fmt::Display for Button {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Button::Key(keycode) => write!(f, "Key:{}", find_name(KEYCODES, keycode)),
+            Button::Mouse(button) => write!(f, "Mouse:{}", find_name(&MOUSE_BUTTONS, button)),
+            Button::Gamepad(button) => write!(f, "Gamepad:{}", find_name(&GAMEPAD_BUTTONS, button)),
+        }
+    }
+}
+
+impl FromStr for Button {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Button, String> {
+        let button = match split_device(s)? {
+            ("Key", name) => find_by_name(KEYCODES, name).map(Button::Key),
+            ("Mouse", name) => find_by_name(&MOUSE_BUTTONS, name).map(Button::Mouse),
+            ("Gamepad", name) => find_by_name(&GAMEPAD_BUTTONS, name).map(Button::Gamepad),
+            (device, _) => return Err(format!("unknown device {} in button {}", device, s)),
+        };
+        button.ok_or_else(|| format!("unknown button {}", s))
+    }
+}
+
+impl fmt::Display for Analog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Analog::Gamepad(axis) => write!(f, "Gamepad:{}", find_name(&GAMEPAD_AXES, axis)),
+            Analog::MouseX => write!(f, "Mouse:X"),
+            Analog::MouseY => write!(f, "Mouse:Y"),
+            Analog::MouseWheel => write!(f, "Mouse:Wheel"),
+        }
+    }
+}
+
+impl FromStr for Analog {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Analog, String> {
+        let analog = match split_device(s)? {
+            ("Gamepad", name) => find_by_name(&GAMEPAD_AXES, name).map(Analog::Gamepad),
+            ("Mouse", "X") => Some(Analog::MouseX),
+            ("Mouse", "Y") => Some(Analog::MouseY),
+            ("Mouse", "Wheel") => Some(Analog::MouseWheel),
+            (device, _) => return Err(format!("unknown device {} in axis {}", device, s)),
+        };
+        analog.ok_or_else(|| format!("unknown axis {}", s))
+    }
+}
+
+fn split_device(s: &str) -> Result<(&str, &str), String> {
+    let mut parts = s.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some(device), Some(name)) => Ok((device.trim(), name.trim())),
+        _ => Err(format!("expected Device:Name, got {}", s)),
+    }
+}
+
+// serialized as their display strings
+macro_rules! impl_serde_as_string {
+    ($($t:ty),*) => { $(
+        impl serde::Serialize for $t {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $t {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<$t, D::Error> {
+                let s = String::deserialize(deserializer)?;
+                s.parse().map_err(serde::de::Error::custom)
+            }
+        }
+    )* }
+}
+
+impl_serde_as_string!(Button, Analog);
+
+// -----------------------------------------------------------------
+
+/**
+ * A named axis combining buttons (positive minus negative) and analog inputs.
+ * The input furthest from zero wins, then the value is multiplied by `scale`.
+ */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AxisBinding {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub positive: Vec<Button>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub negative: Vec<Button>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub analog: Vec<Analog>,
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+impl Default for AxisBinding {
+    fn default() -> AxisBinding {
+        AxisBinding {
+            positive: Vec::new(),
+            negative: Vec::new(),
+            analog: Vec::new(),
+            scale: default_scale(),
+        }
+    }
+}
+
+/**
+ * Named actions and axes and the inputs that drive them, for example
+ *
+ * ```toml
+ * [actions]
+ * jump = ["Key:Space", "Gamepad:A"]
+ *
+ * [axes.move_x]
+ * positive = ["Key:D"]
+ * negative = ["Key:A"]
+ * analog = ["Gamepad:LeftX"]
+ * ```
+ */
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Bindings {
+    #[serde(default)]
+    pub actions: BTreeMap<String, Vec<Button>>,
+    #[serde(default)]
+    pub axes: BTreeMap<String, AxisBinding>,
+    #[serde(default)]
+    pub gamepad: GamepadConfig,
+}
+
+impl Bindings {
+    pub fn from_res(res: &Resources, name: &str)
+    -> Result<Bindings, Error>
+    {
+        let source = res.load_string(name)
+            .map_err(|error| Error::UnableToLoadResource { name: String::from(name), error })?;
+        Bindings::from_toml(name, &source)
+    }
+
+    /**
+     * Loads the default bindings from `name` with the user's saved bindings on top,
+     * so a rebound action replaces its default inputs and new defaults still show up
+     */
+    pub fn load_with_user(res: &Resources, name: &str)
+    -> Result<Bindings, Error>
+    {
+        let defaults = res.load_string(name)
+            .map_err(|error| Error::UnableToLoadResource { name: String::from(name), error })?;
+
+        let user = match user_bindings_path() {
+            Some(path) => match fs::read_to_string(&path) {
+                Ok(source) => Some((path.display().to_string(), source)),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(error) => return Err(Error::Io { path: path.display().to_string(), error }),
+            },
+            None => None,
+        };
+
+        Bindings::layered(name, &defaults, user.as_ref().map(|(name, source)| (name.as_str(), source.as_str())))
+    }
+
+    /**
+     * Parses `defaults` with the actions, axes and gamepad keys of `overrides`, a
+     * `(name, source)` pair, replacing theirs. Names are only used in errors.
+     */
+    pub fn layered(name: &str, defaults: &str, overrides: Option<(&str, &str)>)
+    -> Result<Bindings, Error>
+    {
+        let parse = |name: &str, source: &str| toml::from_str::<toml::Value>(source)
+            .map_err(|error| Error::Parse { name: String::from(name), error });
+
+        let mut value = parse(name, defaults)?;
+        if let Some((override_name, source)) = overrides {
+            merge(&mut value, parse(override_name, source)?);
+        }
+
+        value.try_into().map_err(|error| Error::Parse { name: String::from(name), error })
+    }
+
+    /**
+     * Loads bindings saved with `save`
+     */
+    pub fn load(path: &Path)
+    -> Result<Bindings, Error>
+    {
+        let source = fs::read_to_string(path)
+            .map_err(|error| Error::Io { path: path.display().to_string(), error })?;
+        Bindings::from_toml(&path.display().to_string(), &source)
+    }
+
+    /**
+     * `name` is only used in errors
+     */
+    pub fn from_toml(name: &str, source: &str)
+    -> Result<Bindings, Error>
+    {
+        toml::from_str(source).map_err(|error| Error::Parse { name: String::from(name), error })
+    }
+
+    pub fn to_toml(&self) -> Result<String, Error> {
+        toml::to_string_pretty(self).map_err(|error| Error::Serialize { error })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let io_error = |error| Error::Io { path: path.display().to_string(), error };
+
+        let source = self.to_toml()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(io_error)?;
+        }
+        fs::write(path, source).map_err(io_error)
+    }
+
+    /**
+     * Saves to the user config directory, where `load_with_user` picks the bindings up next time
+     */
+    pub fn save_user(&self) -> Result<(), Error> {
+        match user_bindings_path() {
+            Some(path) => self.save(&path),
+            None => {
+                warn!("no user config directory, input bindings are not saved");
+                Ok(())
+            },
+        }
+    }
+
+    /**
+     * Replaces every input of `action`
+     */
+    pub fn rebind(&mut self, action: &str, buttons: Vec<Button>) {
+        self.actions.insert(String::from(action), buttons);
+    }
+
+    /**
+     * Adds `button` to `action` unless it is already bound to it
+     */
+    pub fn bind(&mut self, action: &str, button: Button) {
+        let buttons = self.actions.entry(String::from(action)).or_default();
+        if !buttons.contains(&button) {
+            buttons.push(button);
+        }
+    }
+
+    pub fn unbind(&mut self, action: &str, button: Button) {
+        if let Some(buttons) = self.actions.get_mut(action) {
+            buttons.retain(|bound| *bound != button);
+        }
+    }
+
+    /**
+     * Actions `button` is bound to, to warn about conflicts when rebinding
+     */
+    pub fn actions_bound_to(&self, button: Button) -> Vec<&str> {
+        self.actions.iter()
+            .filter(|(_, buttons)| buttons.contains(&button))
+            .map(|(action, _)| action.as_str())
+            .collect()
+    }
+}
+
+pub fn user_bindings_path() -> Option<PathBuf> {
+    user_config_dir().map(|dir| dir.join(USER_BINDINGS_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buttons_round_trip_through_their_names() {
+        let buttons = [
+            Button::Key(Keycode::Space),
+            Button::Key(Keycode::F12),
+            Button::Key(Keycode::Num1),
+            Button::Key(Keycode::LShift),
+            Button::Mouse(MouseButton::Right),
+            Button::Gamepad(controller::Button::DPadUp),
+        ];
+        for button in buttons.iter() {
+            assert_eq!(button.to_string().parse::<Button>(), Ok(*button));
+        }
+        assert_eq!("Key:Space".parse::<Button>(), Ok(Button::Key(Keycode::Space)));
+        assert_eq!(" Gamepad : A".parse::<Button>(), Ok(Button::Gamepad(controller::Button::A)));
+    }
+
+    #[test]
+    fn unknown_names_are_errors() {
+        assert!("Key:Spacebar".parse::<Button>().is_err());
+        assert!("Keyboard:Space".parse::<Button>().is_err());
+        assert!("Space".parse::<Button>().is_err());
+        assert!("Mouse:Z".parse::<Analog>().is_err());
+    }
+
+    #[test]
+    fn parses_actions_and_axes() {
+        let bindings = Bindings::from_toml("test", r#"
+            [actions]
+            jump = ["Key:Space", "Gamepad:A"]
+
+            [axes.move_x]
+            positive = ["Key:D"]
+            negative = ["Key:A"]
+            analog = ["Gamepad:LeftX"]
+
+            [axes.look_x]
+            analog = ["Mouse:X"]
+            scale = 0.1
+        "#).unwrap();
+
+        assert_eq!(bindings.actions["jump"], vec![Button::Key(Keycode::Space), Button::Gamepad(controller::Button::A)]);
+        assert_eq!(bindings.axes["move_x"].negative, vec![Button::Key(Keycode::A)]);
+        assert_eq!(bindings.axes["move_x"].analog, vec![Analog::Gamepad(controller::Axis::LeftX)]);
+        assert_eq!(bindings.axes["move_x"].scale, 1.0);
+        assert_eq!(bindings.axes["look_x"].scale, 0.1);
+    }
+
+    #[test]
+    fn bad_input_names_are_reported_with_the_file() {
+        let error = Bindings::from_toml("config/input.toml", "[actions]\njump = [\"Key:Jump\"]").unwrap_err();
+        assert_eq!(error.to_string(), "Failed to parse input bindings config/input.toml");
+        let cause = failure::Fail::cause(&error).unwrap().to_string();
+        assert!(cause.contains("unknown button Key:Jump"), "{}", cause);
+    }
+
+    #[test]
+    fn saved_bindings_load_back() {
+        let mut bindings = Bindings::default();
+        bindings.bind("jump", Button::Key(Keycode::Space));
+        bindings.bind("jump", Button::Key(Keycode::Space));
+        bindings.bind("fire", Button::Mouse(MouseButton::Left));
+        bindings.axes.insert(String::from("zoom"), AxisBinding {
+            analog: vec![Analog::MouseWheel],
+            scale: 2.0,
+            ..AxisBinding::default()
+        });
+
+        let path = std::env::temp_dir().join(format!("input-bindings-{}.toml", std::process::id()));
+        bindings.save(&path).unwrap();
+        let loaded = Bindings::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, bindings);
+        assert_eq!(loaded.actions["jump"].len(), 1);
+    }
+
+    #[test]
+    fn user_bindings_replace_defaults_action_by_action() {
+        let defaults = r#"
+            [actions]
+            jump = ["Key:Space", "Gamepad:A"]
+            fire = ["Mouse:Left"]
+
+            [gamepad]
+            rumble = true
+
+            [gamepad.left_stick]
+            dead_zone = 0.2
+        "#;
+        let user = r#"
+            [actions]
+            jump = ["Key:W"]
+
+            [gamepad.left_stick]
+            invert_y = true
+        "#;
+
+        let bindings = Bindings::layered("defaults", defaults, Some(("user", user))).unwrap();
+        assert_eq!(bindings.actions["jump"], vec![Button::Key(Keycode::W)]);
+        assert_eq!(bindings.actions["fire"], vec![Button::Mouse(MouseButton::Left)]);
+        assert!(bindings.gamepad.rumble);
+        assert_eq!(bindings.gamepad.left_stick.dead_zone, 0.2);
+        assert!(bindings.gamepad.left_stick.invert_y);
+    }
+
+    #[test]
+    fn rebinding_replaces_and_unbinding_removes() {
+        let mut bindings = Bindings::default();
+        bindings.bind("jump", Button::Key(Keycode::Space));
+        bindings.bind("use", Button::Key(Keycode::E));
+        bindings.rebind("jump", vec![Button::Key(Keycode::E)]);
+
+        assert_eq!(bindings.actions_bound_to(Button::Key(Keycode::E)), vec!["jump", "use"]);
+        assert!(bindings.actions_bound_to(Button::Key(Keycode::Space)).is_empty());
+
+        bindings.unbind("use", Button::Key(Keycode::E));
+        assert_eq!(bindings.actions_bound_to(Button::Key(Keycode::E)), vec!["jump"]);
+    }
+}
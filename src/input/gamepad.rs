@@ -0,0 +1,537 @@
+use sdl2::controller;
+use sdl2::event::Event;
+use std::collections::{ HashMap, HashSet };
+use std::time::Duration;
+
+use super::bindings::{ Analog, Button };
+use super::state::Input;
+
+/**
+ * Dead zones and response curve of an analog stick. The dead zone is radial, so it does not
+ * snap diagonal input to the axes, and the range outside it is stretched back to 0..1.
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StickConfig {
+    pub dead_zone: f32,         // fraction of the range around the centre that reads as 0
+    pub outer_dead_zone: f32,   // fraction at the edge that already reads as full deflection
+    pub exponent: f32,          // response curve, 1 is linear, above 1 gives finer control near the centre
+    pub invert_y: bool,         // sdl reports up as negative
+}
+
+impl Default for StickConfig {
+    fn default() -> StickConfig {
+        StickConfig {
+            dead_zone: 0.15,
+            outer_dead_zone: 0.05,
+            exponent: 1.0,
+            invert_y: false,
+        }
+    }
+}
+
+impl StickConfig {
+    /**
+     * Maps a raw stick position, both axes in -1..1, to the value the game sees
+     */
+    pub fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+        let magnitude = (x * x + y * y).sqrt();
+        if magnitude <= self.dead_zone || magnitude == 0.0 {
+            return (0.0, 0.0);
+        }
+
+        let live_range = (1.0 - self.dead_zone - self.outer_dead_zone).max(f32::EPSILON);
+        let scaled = ((magnitude - self.dead_zone) / live_range).min(1.0).powf(self.exponent);
+        let y = if self.invert_y { -y } else { y };
+
+        (x / magnitude * scaled, y / magnitude * scaled)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TriggerConfig {
+    pub dead_zone: f32,
+    pub exponent: f32,
+}
+
+impl Default for TriggerConfig {
+    fn default() -> TriggerConfig {
+        TriggerConfig {
+            dead_zone: 0.05,
+            exponent: 1.0,
+        }
+    }
+}
+
+impl TriggerConfig {
+    /**
+     * Maps a raw trigger value in 0..1
+     */
+    pub fn apply(&self, value: f32) -> f32 {
+        if value <= self.dead_zone {
+            return 0.0;
+        }
+        ((value - self.dead_zone) / (1.0 - self.dead_zone).max(f32::EPSILON)).min(1.0).powf(self.exponent)
+    }
+}
+
+/**
+ * The `[gamepad]` section of the input config
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GamepadConfig {
+    // toml writes plain values before tables, so `rumble` has to lead
+    pub rumble: bool,
+    pub left_stick: StickConfig,
+    pub right_stick: StickConfig,
+    pub triggers: TriggerConfig,
+}
+
+impl Default for GamepadConfig {
+    fn default() -> GamepadConfig {
+        GamepadConfig {
+            rumble: true,
+            left_stick: StickConfig::default(),
+            right_stick: StickConfig::default(),
+            triggers: TriggerConfig::default(),
+        }
+    }
+}
+
+// -----------------------------------------------------------------
+
+/**
+ * An opened game controller
+ */
+pub trait Controller {
+    /**
+     * The joystick instance id, which controller events carry in `which`
+     */
+    fn id(&self) -> u32;
+    fn name(&self) -> String;
+
+    /**
+     * Strengths are 0..1, errors when the controller has no rumble motors
+     */
+    fn rumble(&mut self, low_frequency: f32, high_frequency: f32, duration: Duration) -> Result<(), String>;
+}
+
+/**
+ * Opens controllers as they are plugged in
+ */
+pub trait ControllerBackend {
+    /**
+     * `joystick_index` is the device index of a ControllerDeviceAdded event
+     */
+    fn open(&mut self, joystick_index: u32) -> Result<Box<dyn Controller>, String>;
+}
+
+pub struct SdlControllers {
+    subsystem: sdl2::GameControllerSubsystem,
+}
+
+impl SdlControllers {
+    pub fn new(subsystem: sdl2::GameControllerSubsystem) -> SdlControllers {
+        SdlControllers { subsystem }
+    }
+}
+
+impl ControllerBackend for SdlControllers {
+    fn open(&mut self, joystick_index: u32) -> Result<Box<dyn Controller>, String> {
+        let controller = self.subsystem.open(joystick_index).map_err(|e| e.to_string())?;
+        Ok(Box::new(SdlController(controller)))
+    }
+}
+
+struct SdlController(controller::GameController);
+
+impl Controller for SdlController {
+    fn id(&self) -> u32 {
+        // instance ids are never negative, events report them as u32
+        self.0.instance_id() as u32
+    }
+
+    fn name(&self) -> String {
+        self.0.name()
+    }
+
+    fn rumble(&mut self, low_frequency: f32, high_frequency: f32, duration: Duration) -> Result<(), String> {
+        let strength = |value: f32| (value.clamp(0.0, 1.0) * u16::MAX as f32) as u16;
+        self.0.set_rumble(strength(low_frequency), strength(high_frequency), duration.as_millis() as u32)
+            .map_err(|e| e.to_string())
+    }
+}
+
+// -----------------------------------------------------------------
+
+const CONNECT_RUMBLE: (f32, f32, Duration) = (0.4, 0.4, Duration::from_millis(150));
+
+struct Gamepad {
+    controller: Box<dyn Controller>,
+    raw_axes: HashMap<controller::Axis, f32>,
+    held: HashSet<controller::Button>,
+}
+
+impl Gamepad {
+    fn raw(&self, axis: controller::Axis) -> f32 {
+        self.raw_axes.get(&axis).cloned().unwrap_or(0.0)
+    }
+}
+
+/**
+ * Connected game controllers. Handles hot-plugging and turns controller events into input,
+ * with dead zones and response curves from the `[gamepad]` section of the input bindings.
+ * Every controller drives the same gamepad buttons and axes.
+ */
+pub struct Gamepads<B: ControllerBackend> {
+    backend: B,
+    pads: Vec<Gamepad>,
+}
+
+impl<B: ControllerBackend> Gamepads<B> {
+    pub fn new(backend: B) -> Gamepads<B> {
+        Gamepads {
+            backend,
+            pads: Vec::new(),
+        }
+    }
+
+    /**
+     * Handles controller events, returns false for any other event
+     */
+    pub fn handle_event(&mut self, event: &Event, input: &mut Input) -> bool {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => self.connect(which, input.bindings().gamepad.rumble),
+            Event::ControllerDeviceRemoved { which, .. } => self.disconnect(which, input),
+            Event::ControllerDeviceRemapped { .. } => {},
+            Event::ControllerButtonDown { which, button, .. } => {
+                if let Some(pad) = self.pad_mut(which) {
+                    pad.held.insert(button);
+                }
+                input.press(Button::Gamepad(button));
+            },
+            Event::ControllerButtonUp { which, button, .. } => {
+                if let Some(pad) = self.pad_mut(which) {
+                    pad.held.remove(&button);
+                }
+                if !self.pads.iter().any(|pad| pad.held.contains(&button)) {
+                    input.release(Button::Gamepad(button));
+                }
+            },
+            Event::ControllerAxisMotion { which, axis, value, .. } => {
+                let value = (value as f32 / i16::MAX as f32).max(-1.0);
+                if let Some(pad) = self.pad_mut(which) {
+                    pad.raw_axes.insert(axis, value);
+                }
+                self.update_axis(which, axis, input);
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn connected(&self) -> usize {
+        self.pads.len()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.pads.iter().map(|pad| pad.controller.name()).collect()
+    }
+
+    /**
+     * Rumbles every connected controller that can, returns how many did
+     */
+    pub fn rumble(&mut self, low_frequency: f32, high_frequency: f32, duration: Duration) -> usize {
+        self.pads.iter_mut()
+            .map(|pad| pad.controller.rumble(low_frequency, high_frequency, duration))
+            .filter(Result::is_ok)
+            .count()
+    }
+
+    fn pad_mut(&mut self, id: u32) -> Option<&mut Gamepad> {
+        self.pads.iter_mut().find(|pad| pad.controller.id() == id)
+    }
+
+    fn connect(&mut self, joystick_index: u32, rumble: bool) {
+        let mut controller = match self.backend.open(joystick_index) {
+            Ok(controller) => controller,
+            Err(e) => {
+                warn!("failed to open game controller {}: {}", joystick_index, e);
+                return;
+            },
+        };
+
+        // sdl can report a controller that is already open again
+        if self.pads.iter().any(|pad| pad.controller.id() == controller.id()) {
+            return;
+        }
+
+        info!("game controller connected: {}", controller.name());
+        if rumble {
+            let (low, high, duration) = CONNECT_RUMBLE;
+            let _ = controller.rumble(low, high, duration);
+        }
+
+        self.pads.push(Gamepad {
+            controller,
+            raw_axes: HashMap::new(),
+            held: HashSet::new(),
+        });
+    }
+
+    /**
+     * Releases whatever the controller was holding, so nothing stays stuck down
+     */
+    fn disconnect(&mut self, id: u32, input: &mut Input) {
+        let index = match self.pads.iter().position(|pad| pad.controller.id() == id) {
+            Some(index) => index,
+            None => return,
+        };
+        let pad = self.pads.remove(index);
+        info!("game controller disconnected: {}", pad.controller.name());
+
+        for button in pad.held {
+            if !self.pads.iter().any(|other| other.held.contains(&button)) {
+                input.release(Button::Gamepad(button));
+            }
+        }
+        for &axis in pad.raw_axes.keys() {
+            input.set_analog(Analog::Gamepad(axis), 0.0);
+        }
+    }
+
+    fn update_axis(&self, id: u32, axis: controller::Axis, input: &mut Input) {
+        use sdl2::controller::Axis::*;
+
+        let pad = match self.pads.iter().find(|pad| pad.controller.id() == id) {
+            Some(pad) => pad,
+            None => return,
+        };
+        let config = input.bindings().gamepad;
+
+        // both axes of a stick change when one does, the dead zone is radial
+        let stick = match axis {
+            LeftX | LeftY => Some((LeftX, LeftY, config.left_stick)),
+            RightX | RightY => Some((RightX, RightY, config.right_stick)),
+            TriggerLeft | TriggerRight => None,
+        };
+
+        match stick {
+            Some((x_axis, y_axis, stick)) => {
+                let (x, y) = stick.apply(pad.raw(x_axis), pad.raw(y_axis));
+                input.set_analog(Analog::Gamepad(x_axis), x);
+                input.set_analog(Analog::Gamepad(y_axis), y);
+            },
+            None => input.set_analog(Analog::Gamepad(axis), config.triggers.apply(pad.raw(axis))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::bindings::Bindings;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Default)]
+    struct FakeHardware {
+        rumbles: Vec<(u32, f32, f32)>,
+        rumble_supported: bool,
+    }
+
+    struct FakeController {
+        id: u32,
+        hardware: Rc<RefCell<FakeHardware>>,
+    }
+
+    impl Controller for FakeController {
+        fn id(&self) -> u32 { self.id }
+        fn name(&self) -> String { format!("pad {}", self.id) }
+        fn rumble(&mut self, low: f32, high: f32, _duration: Duration) -> Result<(), String> {
+            let mut hardware = self.hardware.borrow_mut();
+            if !hardware.rumble_supported {
+                return Err(String::from("no rumble"));
+            }
+            hardware.rumbles.push((self.id, low, high));
+            Ok(())
+        }
+    }
+
+    /**
+     * Device index n opens a controller with instance id 100 + n
+     */
+    struct FakeBackend(Rc<RefCell<FakeHardware>>);
+
+    impl ControllerBackend for FakeBackend {
+        fn open(&mut self, joystick_index: u32) -> Result<Box<dyn Controller>, String> {
+            if joystick_index >= 10 {
+                return Err(String::from("no such device"));
+            }
+            Ok(Box::new(FakeController { id: 100 + joystick_index, hardware: self.0.clone() }))
+        }
+    }
+
+    fn setup(rumble_supported: bool) -> (Gamepads<FakeBackend>, Input, Rc<RefCell<FakeHardware>>) {
+        let hardware = Rc::new(RefCell::new(FakeHardware { rumble_supported, ..FakeHardware::default() }));
+        let bindings = Bindings::from_toml("test", r#"
+            [actions]
+            jump = ["Gamepad:A"]
+
+            [gamepad.left_stick]
+            dead_zone = 0.2
+            outer_dead_zone = 0.0
+        "#).unwrap();
+        (Gamepads::new(FakeBackend(hardware.clone())), Input::new(bindings), hardware)
+    }
+
+    fn added(index: u32) -> Event {
+        Event::ControllerDeviceAdded { timestamp: 0, which: index }
+    }
+
+    fn axis(id: u32, axis: controller::Axis, value: i16) -> Event {
+        Event::ControllerAxisMotion { timestamp: 0, which: id, axis, value }
+    }
+
+    fn button(id: u32, button: controller::Button, down: bool) -> Event {
+        if down {
+            Event::ControllerButtonDown { timestamp: 0, which: id, button }
+        } else {
+            Event::ControllerButtonUp { timestamp: 0, which: id, button }
+        }
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn stick_dead_zone_is_radial_and_rescaled() {
+        let stick = StickConfig { dead_zone: 0.2, outer_dead_zone: 0.1, exponent: 1.0, invert_y: false };
+
+        assert_eq!(stick.apply(0.1, 0.1), (0.0, 0.0));
+        assert_eq!(stick.apply(0.0, 0.0), (0.0, 0.0));
+
+        let (x, y) = stick.apply(0.55, 0.0);
+        assert!(close(x, 0.5) && y == 0.0, "{} {}", x, y);
+
+        // past the outer dead zone is full deflection, direction is kept
+        let (x, y) = stick.apply(0.7, 0.7);
+        assert!(close(x, 0.70710677) && close(y, 0.70710677), "{} {}", x, y);
+    }
+
+    #[test]
+    fn stick_response_curve_and_inversion() {
+        let stick = StickConfig { dead_zone: 0.0, outer_dead_zone: 0.0, exponent: 2.0, invert_y: true };
+        let (x, y) = stick.apply(0.0, 0.5);
+        assert!(x == 0.0 && close(y, -0.25), "{} {}", x, y);
+    }
+
+    #[test]
+    fn trigger_dead_zone() {
+        let trigger = TriggerConfig { dead_zone: 0.1, exponent: 1.0 };
+        assert_eq!(trigger.apply(0.05), 0.0);
+        assert!(close(trigger.apply(0.55), 0.5));
+        assert_eq!(trigger.apply(1.0), 1.0);
+    }
+
+    #[test]
+    fn config_round_trips_through_saved_bindings() {
+        let bindings = Bindings {
+            gamepad: GamepadConfig {
+                rumble: false,
+                left_stick: StickConfig { dead_zone: 0.25, outer_dead_zone: 0.0, exponent: 2.0, invert_y: false },
+                right_stick: StickConfig { invert_y: true, ..StickConfig::default() },
+                triggers: TriggerConfig { dead_zone: 0.1, exponent: 1.5 },
+            },
+            ..Bindings::default()
+        };
+
+        let source = bindings.to_toml().unwrap();
+        assert_eq!(Bindings::from_toml("saved", &source).unwrap().gamepad, bindings.gamepad);
+    }
+
+    #[test]
+    fn hot_plugged_controllers_drive_actions_and_axes() {
+        let (mut gamepads, mut input, hardware) = setup(true);
+
+        assert!(gamepads.handle_event(&added(0), &mut input));
+        assert_eq!(gamepads.connected(), 1);
+        assert_eq!(hardware.borrow().rumbles.len(), 1);  // connect feedback
+
+        input.begin_frame();
+        gamepads.handle_event(&button(100, controller::Button::A, true), &mut input);
+        assert!(input.is_pressed("jump"));
+
+        // inside the dead zone of the configured left stick
+        gamepads.handle_event(&axis(100, controller::Axis::LeftX, 5000), &mut input);
+        assert_eq!(input.analog(Analog::Gamepad(controller::Axis::LeftX)), 0.0);
+
+        gamepads.handle_event(&axis(100, controller::Axis::LeftX, i16::MAX), &mut input);
+        assert_eq!(input.analog(Analog::Gamepad(controller::Axis::LeftX)), 1.0);
+
+        gamepads.handle_event(&axis(100, controller::Axis::TriggerRight, i16::MAX), &mut input);
+        assert_eq!(input.analog(Analog::Gamepad(controller::Axis::TriggerRight)), 1.0);
+    }
+
+    #[test]
+    fn unplugging_releases_held_buttons_and_centres_sticks() {
+        let (mut gamepads, mut input, _) = setup(false);
+        gamepads.handle_event(&added(0), &mut input);
+        gamepads.handle_event(&added(1), &mut input);
+        gamepads.handle_event(&button(100, controller::Button::A, true), &mut input);
+        gamepads.handle_event(&axis(100, controller::Axis::LeftY, i16::MIN), &mut input);
+
+        input.begin_frame();
+        gamepads.handle_event(&Event::ControllerDeviceRemoved { timestamp: 0, which: 100 }, &mut input);
+
+        assert_eq!(gamepads.connected(), 1);
+        assert!(input.is_released("jump"));
+        assert_eq!(input.analog(Analog::Gamepad(controller::Axis::LeftY)), 0.0);
+    }
+
+    #[test]
+    fn button_held_on_another_controller_stays_held() {
+        let (mut gamepads, mut input, _) = setup(false);
+        gamepads.handle_event(&added(0), &mut input);
+        gamepads.handle_event(&added(1), &mut input);
+        gamepads.handle_event(&button(100, controller::Button::A, true), &mut input);
+        gamepads.handle_event(&button(101, controller::Button::A, true), &mut input);
+
+        gamepads.handle_event(&Event::ControllerDeviceRemoved { timestamp: 0, which: 100 }, &mut input);
+        assert!(input.is_held("jump"));
+
+        gamepads.handle_event(&added(0), &mut input);
+        gamepads.handle_event(&button(100, controller::Button::A, true), &mut input);
+        gamepads.handle_event(&button(101, controller::Button::A, false), &mut input);
+        assert!(input.is_held("jump"));
+    }
+
+    #[test]
+    fn failed_opens_and_duplicates_are_ignored() {
+        let (mut gamepads, mut input, _) = setup(false);
+        gamepads.handle_event(&added(42), &mut input);
+        gamepads.handle_event(&added(3), &mut input);
+        gamepads.handle_event(&added(3), &mut input);
+        assert_eq!(gamepads.names(), vec!["pad 103"]);
+    }
+
+    #[test]
+    fn rumble_counts_controllers_that_support_it() {
+        let (mut gamepads, mut input, hardware) = setup(false);
+        gamepads.handle_event(&added(0), &mut input);
+        assert_eq!(gamepads.rumble(1.0, 0.5, Duration::from_millis(100)), 0);
+
+        hardware.borrow_mut().rumble_supported = true;
+        assert_eq!(gamepads.rumble(1.0, 0.5, Duration::from_millis(100)), 1);
+        assert_eq!(hardware.borrow().rumbles, vec![(100, 1.0, 0.5)]);
+    }
+
+    #[test]
+    fn other_events_are_not_controller_events() {
+        let (mut gamepads, mut input, _) = setup(false);
+        assert!(!gamepads.handle_event(&Event::Quit { timestamp: 0 }, &mut input));
+    }
+}
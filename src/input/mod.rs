@@ -0,0 +1,8 @@
+mod bindings;
+mod gamepad;
+mod keys;
+mod state;
+
+pub use self::bindings::{Analog, AxisBinding, Bindings, Button, Error};
+pub use self::gamepad::{Controller, ControllerBackend, GamepadConfig, Gamepads, SdlControllers, StickConfig, TriggerConfig};
+pub use self::state::Input;
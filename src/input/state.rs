@@ -0,0 +1,352 @@
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use std::collections::{ HashMap, HashSet };
+
+use super::bindings::{ Analog, Bindings, Button };
+
+/**
+ * Per frame input state and the action / axis bindings on top of it.
+ *
+ * Call `begin_frame` before feeding the frame's events to `handle_event`, then query actions.
+ * `press`, `release`, `set_analog` and `add_motion` are the same entry points the sdl events
+ * go through, so tests can drive the input without sdl.
+ */
+pub struct Input {
+    bindings: Bindings,
+    held: HashSet<Button>,
+    pressed: HashSet<Button>,       // went down this frame
+    released: HashSet<Button>,      // went up this frame
+    analog: HashMap<Analog, f32>,
+    mouse_position: (i32, i32),
+    rebinding: Option<String>,      // action waiting for its new button
+}
+
+impl Input {
+    pub fn new(bindings: Bindings) -> Input {
+        Input {
+            bindings,
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            analog: HashMap::new(),
+            mouse_position: (0, 0),
+            rebinding: None,
+        }
+    }
+
+    /**
+     * Forgets this frame's presses, releases and mouse motion
+     */
+    pub fn begin_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+        for relative in &[Analog::MouseX, Analog::MouseY, Analog::MouseWheel] {
+            self.analog.remove(relative);
+        }
+    }
+
+    /**
+     * Updates the state from a keyboard or mouse event, returns false for other events.
+     * Controller events go through `Gamepads`, which applies dead zones first.
+     */
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        match *event {
+            Event::KeyDown { keycode: Some(keycode), repeat: false, .. } => self.press(Button::Key(keycode)),
+            Event::KeyDown { repeat: true, .. } => {},
+            Event::KeyUp { keycode: Some(keycode), .. } => self.release(Button::Key(keycode)),
+            Event::MouseButtonDown { mouse_btn, x, y, .. } => {
+                self.mouse_position = (x, y);
+                self.press(Button::Mouse(mouse_btn));
+            },
+            Event::MouseButtonUp { mouse_btn, x, y, .. } => {
+                self.mouse_position = (x, y);
+                self.release(Button::Mouse(mouse_btn));
+            },
+            Event::MouseMotion { x, y, xrel, yrel, .. } => {
+                self.mouse_position = (x, y);
+                self.add_motion(Analog::MouseX, xrel as f32);
+                self.add_motion(Analog::MouseY, yrel as f32);
+            },
+            Event::MouseWheel { y, .. } => self.add_motion(Analog::MouseWheel, y as f32),
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn press(&mut self, button: Button) {
+        // the press that picks the new button is used up, so it does not trigger
+        // the action it was just bound to, or whatever else it is bound to
+        if let Some(action) = self.rebinding.take() {
+            info!("bound {} to {}", action, button);
+            self.bindings.rebind(&action, vec![button]);
+            return;
+        }
+
+        if self.held.insert(button) {
+            self.pressed.insert(button);
+        }
+    }
+
+    pub fn release(&mut self, button: Button) {
+        if self.held.remove(&button) {
+            self.released.insert(button);
+        }
+    }
+
+    /**
+     * Sets an absolute analog input such as a stick axis
+     */
+    pub fn set_analog(&mut self, analog: Analog, value: f32) {
+        self.analog.insert(analog, value);
+    }
+
+    /**
+     * Adds to a relative analog input such as mouse motion, which resets every frame
+     */
+    pub fn add_motion(&mut self, analog: Analog, amount: f32) {
+        *self.analog.entry(analog).or_insert(0.0) += amount;
+    }
+
+    // -----------------------------------------------------------------
+    // actions
+
+    /**
+     * True in the frame any input of `action` went down
+     */
+    pub fn is_pressed(&self, action: &str) -> bool {
+        self.any_bound(action, &self.pressed)
+    }
+
+    /**
+     * True while any input of `action` is down
+     */
+    pub fn is_held(&self, action: &str) -> bool {
+        self.any_bound(action, &self.held)
+    }
+
+    /**
+     * True in the frame an input of `action` went up and no other input keeps it held
+     */
+    pub fn is_released(&self, action: &str) -> bool {
+        self.any_bound(action, &self.released) && !self.is_held(action)
+    }
+
+    /**
+     * Value of a named axis, 0 for unknown axes
+     */
+    pub fn axis(&self, name: &str) -> f32 {
+        let binding = match self.bindings.axes.get(name) {
+            Some(binding) => binding,
+            None => return 0.0,
+        };
+
+        let any_held = |buttons: &[Button]| buttons.iter().any(|button| self.held.contains(button));
+        let digital: f32 = match (any_held(&binding.positive), any_held(&binding.negative)) {
+            (true, false) => 1.0,
+            (false, true) => -1.0,
+            _ => 0.0,
+        };
+
+        let value = binding.analog.iter()
+            .map(|analog| self.analog(*analog))
+            .fold(digital, |furthest, value| if value.abs() > furthest.abs() { value } else { furthest });
+
+        value * binding.scale
+    }
+
+    fn any_bound(&self, action: &str, buttons: &HashSet<Button>) -> bool {
+        self.bindings.actions.get(action)
+            .is_some_and(|bound| bound.iter().any(|button| buttons.contains(button)))
+    }
+
+    // -----------------------------------------------------------------
+    // raw state
+
+    pub fn is_button_held(&self, button: Button) -> bool {
+        self.held.contains(&button)
+    }
+
+    pub fn is_key_held(&self, keycode: Keycode) -> bool {
+        self.is_button_held(Button::Key(keycode))
+    }
+
+    pub fn analog(&self, analog: Analog) -> f32 {
+        self.analog.get(&analog).cloned().unwrap_or(0.0)
+    }
+
+    pub fn mouse_position(&self) -> (i32, i32) {
+        self.mouse_position
+    }
+
+    // -----------------------------------------------------------------
+    // bindings
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    pub fn bindings_mut(&mut self) -> &mut Bindings {
+        &mut self.bindings
+    }
+
+    /**
+     * Binds the next button pressed to `action`, replacing its current inputs
+     */
+    pub fn rebind_next_press(&mut self, action: &str) {
+        self.rebinding = Some(String::from(action));
+    }
+
+    pub fn is_rebinding(&self) -> bool {
+        self.rebinding.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::controller;
+    use sdl2::keyboard::Mod;
+    use sdl2::mouse::{ MouseButton, MouseState };
+
+    fn input() -> Input {
+        Input::new(Bindings::from_toml("test", r#"
+            [actions]
+            jump = ["Key:Space", "Gamepad:A"]
+            fire = ["Mouse:Left"]
+
+            [axes.move_x]
+            positive = ["Key:D"]
+            negative = ["Key:A"]
+            analog = ["Gamepad:LeftX"]
+
+            [axes.look_x]
+            analog = ["Mouse:X"]
+            scale = 0.5
+        "#).unwrap())
+    }
+
+    fn key_down(keycode: Keycode, repeat: bool) -> Event {
+        Event::KeyDown { timestamp: 0, window_id: 0, keycode: Some(keycode), scancode: None, keymod: Mod::empty(), repeat }
+    }
+
+    fn key_up(keycode: Keycode) -> Event {
+        Event::KeyUp { timestamp: 0, window_id: 0, keycode: Some(keycode), scancode: None, keymod: Mod::empty(), repeat: false }
+    }
+
+    #[test]
+    fn actions_go_through_pressed_held_released() {
+        let mut input = input();
+
+        input.begin_frame();
+        input.handle_event(&key_down(Keycode::Space, false));
+        assert!(input.is_pressed("jump"));
+        assert!(input.is_held("jump"));
+        assert!(!input.is_released("jump"));
+
+        input.begin_frame();
+        input.handle_event(&key_down(Keycode::Space, true));  // key repeat is not a new press
+        assert!(!input.is_pressed("jump"));
+        assert!(input.is_held("jump"));
+
+        input.begin_frame();
+        input.handle_event(&key_up(Keycode::Space));
+        assert!(!input.is_held("jump"));
+        assert!(input.is_released("jump"));
+
+        input.begin_frame();
+        assert!(!input.is_released("jump"));
+    }
+
+    #[test]
+    fn action_stays_held_while_another_binding_is_down() {
+        let mut input = input();
+        input.press(Button::Key(Keycode::Space));
+        input.press(Button::Gamepad(controller::Button::A));
+
+        input.begin_frame();
+        input.release(Button::Key(Keycode::Space));
+        assert!(input.is_held("jump"));
+        assert!(!input.is_released("jump"));
+    }
+
+    #[test]
+    fn mouse_buttons_and_motion() {
+        let mut input = input();
+        input.begin_frame();
+        input.handle_event(&Event::MouseButtonDown {
+            timestamp: 0, window_id: 0, which: 0, mouse_btn: MouseButton::Left, clicks: 1, x: 10, y: 20,
+        });
+        for _ in 0..2 {
+            input.handle_event(&Event::MouseMotion {
+                timestamp: 0, window_id: 0, which: 0, mousestate: MouseState::from_sdl_state(0), x: 12, y: 20, xrel: 2, yrel: 0,
+            });
+        }
+
+        assert!(input.is_pressed("fire"));
+        assert_eq!(input.mouse_position(), (12, 20));
+        assert_eq!(input.axis("look_x"), 2.0);
+
+        input.begin_frame();
+        assert_eq!(input.axis("look_x"), 0.0);
+    }
+
+    #[test]
+    fn axes_combine_buttons_and_analog_inputs() {
+        let mut input = input();
+        assert_eq!(input.axis("move_x"), 0.0);
+        assert_eq!(input.axis("unknown"), 0.0);
+
+        input.press(Button::Key(Keycode::A));
+        assert_eq!(input.axis("move_x"), -1.0);
+        input.press(Button::Key(Keycode::D));
+        assert_eq!(input.axis("move_x"), 0.0);
+
+        input.set_analog(Analog::Gamepad(controller::Axis::LeftX), 0.25);
+        assert_eq!(input.axis("move_x"), 0.25);
+
+        input.release(Button::Key(Keycode::D));
+        assert_eq!(input.axis("move_x"), -1.0);
+    }
+
+    #[test]
+    fn next_press_rebinds_an_action() {
+        let mut input = input();
+        input.rebind_next_press("jump");
+        assert!(input.is_rebinding());
+
+        input.begin_frame();
+        input.press(Button::Key(Keycode::W));
+        assert!(!input.is_rebinding());
+        assert_eq!(input.bindings().actions["jump"], vec![Button::Key(Keycode::W)]);
+        assert!(!input.is_pressed("jump") && !input.is_held("jump"));
+
+        input.begin_frame();
+        input.release(Button::Key(Keycode::W));
+        input.press(Button::Key(Keycode::Space));
+        assert!(!input.is_pressed("jump"));
+        input.press(Button::Key(Keycode::W));
+        assert!(input.is_pressed("jump"));
+    }
+
+    #[test]
+    fn the_rebinding_press_triggers_no_action() {
+        let mut input = input();
+        input.rebind_next_press("fire");
+
+        input.begin_frame();
+        input.press(Button::Key(Keycode::Space));
+        assert_eq!(input.bindings().actions["fire"], vec![Button::Key(Keycode::Space)]);
+        assert!(!input.is_pressed("fire") && !input.is_pressed("jump"));
+
+        // releasing the key is not a release of either action
+        input.begin_frame();
+        input.release(Button::Key(Keycode::Space));
+        assert!(!input.is_released("fire") && !input.is_released("jump"));
+    }
+
+    #[test]
+    fn other_events_are_not_input() {
+        let mut input = input();
+        assert!(!input.handle_event(&Event::Quit { timestamp: 0 }));
+    }
+}
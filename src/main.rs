@@ -6,79 +6,117 @@
 
 #[macro_use] extern crate failure;
 #[macro_use] extern crate render_gl_derive;
+#[macro_use] extern crate log;
+#[macro_use] extern crate serde;
 
 extern crate sdl2;
 extern crate gl;
 extern crate vec_2_10_10_10;
 extern crate nalgebra as na;
 extern crate nalgebra_glm as glm;
+extern crate png;
+extern crate toml;
+#[cfg(feature = "headless")] extern crate khronos_egl;
 
 pub mod render_gl;
 pub mod resources;
 pub mod mvp_matrix;
 pub mod glm_ext;
 pub mod camera;
+pub mod profiler;
+pub mod game_loop;
+pub mod input;
+pub mod settings;
+pub mod scene;
+pub mod screens;
+pub mod ecs;
 
 mod triangle;
+mod cube;
 mod debug;
+#[cfg(feature = "headless")] mod headless;
+#[cfg(test)] mod golden;
 
 use std::path::Path;
 
 use resources::Resources;
 use render_gl::data;
-use debug::failure_to_string;
-use camera::Camera;
+use debug::{ failure_to_string, init_logging };
+#[cfg(feature = "headless")] use camera::Camera;
+use settings::{ Settings, WindowMode };
 
-const WINDOW_HEIGHT: u32 = 700;
-const WINDOW_WIDTH: u32 = 900;
-const OPENGL_CORE_VERSION_MAJOR: u8 = 4;
-const OPENGL_CORE_VERSION_MINOR: u8 = 5;
-const WINDOW_TITLE: &str = "Game";
+const CAPTURE_DIRECTORY: &str = "captures";
+const RECORD_EVERY_NTH_FRAME: u32 = 2;
+const GL_DEBUG_PANIC_AT: Option<render_gl::DebugSeverity> = None;
+const PROFILER_WINDOW: usize = 120; // frames
+const TRACE_FILE: &str = "trace.json";
+const INPUT_BINDINGS: &str = "config/input.toml";
+const SETTINGS: &str = "config/settings.toml";
 
 #[derive(VertexAttribPointers)]
 #[derive(Copy, Clone, Debug)]
 #[repr(C, packed)]
 struct Vertex {
-    #[location = "0"]
+    #[location = 0]
     pos: data::f32_f32_f32,
-    #[location = "1"]
+    #[location = 1]
     clr: data::u2_u10_u10_u10_rev_float,
 }
 
 fn main() {
-    if let Err(e) = run() {
+    init_logging(log::LevelFilter::Info);
+
+    let gl_debug = cfg!(feature = "gl_debug") || std::env::args().any(|arg| arg == "--gl-debug");
+
+    let result = if std::env::args().any(|arg| arg == "--headless") {
+        run_headless(gl_debug)
+    } else {
+        run(gl_debug)
+    };
+
+    if let Err(e) = result {
         println!("{}", failure_to_string(e));
     }
 }
 
-
-fn run() -> Result<(), failure::Error> {
+#[cfg(feature = "headless")]
+fn run_headless(gl_debug: bool) -> Result<(), failure::Error> {
     let res = Resources::from_relative_exe_path(Path::new("assets-07")).unwrap();
 
-    let sdl = sdl2::init().unwrap();
-    let video_subsystem = sdl.video().unwrap();
+    // the defaults only, so headless output does not depend on who runs it
+    let settings = Settings::from_res(&res, SETTINGS)?;
+    let image = render_headless_frame(res, &settings, gl_debug)?;
 
-    let gl_attr = video_subsystem.gl_attr();
-    gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
-    gl_attr.set_context_version(OPENGL_CORE_VERSION_MAJOR, OPENGL_CORE_VERSION_MINOR);
+    let path = Path::new(CAPTURE_DIRECTORY).join("headless.png");
+    image.save_png(&path)?;
+    println!("Saved {}", path.display());
 
-    let window = video_subsystem
-        .window(WINDOW_TITLE, WINDOW_WIDTH, WINDOW_HEIGHT)
-        .opengl() // adds an opengl flag
-        .resizable()
-        .build()
-        .unwrap();
+    Ok(())
+}
 
-    let mut viewport = render_gl::Viewport::for_window(WINDOW_WIDTH as i32, WINDOW_HEIGHT as i32);
+#[cfg(not(feature = "headless"))]
+fn run_headless(_gl_debug: bool) -> Result<(), failure::Error> {
+    Err(format_err!("--headless needs the game to be built with the `headless` feature"))
+}
 
-    let color_buffer = render_gl::ColorBuffer::from_color(na::Vector3::new(0.3, 0.3, 0.5));
+/**
+ * Renders one frame of the scene without a window and reads it back.
+ * Used by `--headless` and the golden image tests.
+ */
+#[cfg(feature = "headless")]
+fn render_headless_frame(res: Resources, settings: &Settings, gl_debug: bool)
+-> Result<render_gl::Image, failure::Error>
+{
+    // declared first so it is dropped last, after every gl object below
+    let context = headless::HeadlessContext::new(settings.graphics.gl_major, settings.graphics.gl_minor, gl_debug)?;
+    let gl = context.gl().clone();
+    let _debug_output = enable_gl_debug_output(&gl, gl_debug)?;
 
-    let mut event_pump = sdl.event_pump().unwrap();
-    let gl_context = window.gl_create_context().unwrap();
-    let gl = gl::Gl::load_with(|s| video_subsystem.gl_get_proc_address(s) as *const std::os::raw::c_void);
+    let viewport = render_gl::Viewport::for_window(settings.window.width as i32, settings.window.height as i32);
+    let framebuffer = render_gl::Framebuffer::new(&gl, render_gl::FramebufferDesc::viewport_color_depth(), &viewport)?;
 
-    viewport.set_used(&gl);
-    color_buffer.set_used(&gl);
+    let color_buffer = render_gl::ColorBuffer::from_color(na::Vector3::new(0.3, 0.3, 0.5));
+    let mut render_state = render_gl::RenderStateCache::new(&gl);
 
     let mut camera = Camera::new(
         45.0 * glm::pi::<f32>() / 180.0,
@@ -92,27 +130,186 @@ fn run() -> Result<(), failure::Error> {
 
     let mut triangle = triangle::Triangle::new(res, &gl)?;
 
+    framebuffer.bind();
+    framebuffer.set_viewport();
+    render_state.apply(&render_gl::RenderState::opaque_3d());
+    render_state.clear(
+        render_gl::ClearMask::COLOR | render_gl::ClearMask::DEPTH,
+        &render_gl::ClearValues::with_color(color_buffer.color)
+    );
+
+    triangle.render(&gl, &mut camera);
+    gl.check_errors()?;
+
+    let image = render_gl::Image::from_framebuffer(&gl, &framebuffer, 0);
+    framebuffer.unbind();
+
+    Ok(image)
+}
+
+
+fn enable_gl_debug_output(gl: &gl::Gl, gl_debug: bool)
+-> Result<Option<render_gl::DebugOutput>, failure::Error>
+{
+    if !gl_debug {
+        return Ok(None);
+    }
+
+    let config = render_gl::DebugOutputConfig {
+        panic_at: GL_DEBUG_PANIC_AT,
+        ..render_gl::DebugOutputConfig::default()
+    };
+    Ok(Some(render_gl::DebugOutput::enable(gl, config)?))
+}
+
+fn run(gl_debug: bool) -> Result<(), failure::Error> {
+    let res = Resources::from_relative_exe_path(Path::new("assets-07")).unwrap();
+
+    let settings = Settings::load(&res, SETTINGS)?;
+
+    let sdl = sdl2::init().unwrap();
+    let video_subsystem = sdl.video().unwrap();
+
+    settings.set_gl_attributes(&video_subsystem, gl_debug);
+    let mut window = settings.build_window(&video_subsystem)?;
+
+    // with hidpi the drawable is larger than the window
+    let (drawable_width, drawable_height) = window.drawable_size();
+    let viewport = render_gl::Viewport::for_window(drawable_width as i32, drawable_height as i32);
+
+    let mut event_pump = sdl.event_pump().unwrap();
+    let gl_context = window.gl_create_context().unwrap();
+    let gl = gl::Gl::load_with(|s| video_subsystem.gl_get_proc_address(s) as *const std::os::raw::c_void);
+    let _debug_output = enable_gl_debug_output(&gl, gl_debug)?;
+
+    let loop_config = game_loop::LoopConfig {
+        vsync: settings.graphics.vsync,
+        frame_limit: settings.graphics.frame_limit,
+        ..game_loop::LoopConfig::default()
+    };
+    let vsync = loop_config.vsync.apply(&video_subsystem).map_err(failure::err_msg)?;
+    info!("vsync: {:?}", vsync);
+    let mut game_loop = game_loop::GameLoop::new(loop_config, game_loop::SystemClock::new());
+
+    viewport.set_used(&gl);
+
+    let input = input::Input::new(input::Bindings::load_with_user(&res, INPUT_BINDINGS)?);
+    // sdl sends a device added event for every controller already plugged in
+    let mut gamepads = input::Gamepads::new(input::SdlControllers::new(sdl.game_controller().map_err(failure::err_msg)?));
+
+    let mut ctx = scene::Context {
+        render_state: render_gl::RenderStateCache::new(&gl),
+        gl: gl.clone(),
+        res,
+        input,
+        viewport,
+        settings,
+    };
+    let mut scenes = scene::SceneStack::new();
+    scenes.push(Box::new(screens::MainMenu::new()), &mut ctx)?;
+
+    let mut frame_capture = render_gl::FrameCapture::new(Path::new(CAPTURE_DIRECTORY));
+    let mut profiler = profiler::Profiler::new(&gl, PROFILER_WINDOW);
+
     'main: loop {
+        let timing = game_loop.begin_frame();
+        profiler.begin_scope("frame");
+
+        profiler.begin_scope("events");
+        ctx.input.begin_frame();
         for event in event_pump.poll_iter() {
+            if gamepads.handle_event(&event, &mut ctx.input) || ctx.input.handle_event(&event) {
+                continue;
+            }
             match event {
                 sdl2::event::Event::Quit {..} => break 'main,
                 sdl2::event::Event::Window {
-                    win_event: sdl2::event::WindowEvent::Resized(w, h),
+                    win_event: sdl2::event::WindowEvent::SizeChanged(..),
                     ..
                 } => {
-                    viewport.update_size(w, h);
-                    viewport.set_used(&gl);
+                    let (w, h) = window.drawable_size();
+                    ctx.viewport.update_size(w as i32, h as i32);
+                    ctx.viewport.set_used(&gl);
+                    scenes.resize(&mut ctx);
                 }
-                _ => {}
+                event => scenes.handle_event(&mut ctx, &event)?,
+            }
+        }
+
+        let input = &ctx.input;
+        if input.is_pressed("screenshot") {
+            frame_capture.request_screenshot();
+        }
+        if input.is_pressed("toggle_recording") {
+            frame_capture.toggle_recording(RECORD_EVERY_NTH_FRAME);
+        }
+        if input.is_pressed("frame_stats") {
+            print!("{}", game_loop.stats());
+        }
+        if input.is_pressed("profiler_report") {
+            print!("{}", profiler.report());
+        }
+        if input.is_pressed("toggle_fullscreen") || input.is_pressed("cycle_vsync") {
+            let previous = ctx.settings.clone();
+            if input.is_pressed("toggle_fullscreen") {
+                ctx.settings.window.mode = match ctx.settings.window.mode {
+                    WindowMode::Windowed => WindowMode::Borderless,
+                    WindowMode::Borderless | WindowMode::Fullscreen => WindowMode::Windowed,
+                };
+            }
+            if input.is_pressed("cycle_vsync") {
+                ctx.settings.graphics.vsync = match ctx.settings.graphics.vsync {
+                    game_loop::VsyncMode::Off => game_loop::VsyncMode::On,
+                    game_loop::VsyncMode::On => game_loop::VsyncMode::Adaptive,
+                    game_loop::VsyncMode::Adaptive => game_loop::VsyncMode::Off,
+                };
             }
 
+            let vsync = ctx.settings.apply(&previous, &mut window, &video_subsystem)?;
+            info!("window mode: {:?}, vsync: {:?}", ctx.settings.window.mode, vsync);
+            game_loop.set_config(game_loop::LoopConfig { vsync, ..*game_loop.config() });
+            ctx.settings.save_user()?;
+        }
+        if input.is_pressed("profiler_trace") {
+            let path = Path::new(CAPTURE_DIRECTORY).join(TRACE_FILE);
+            profiler.write_chrome_trace(&path)?;
+            info!("Saved {}", path.display());
+        }
+
+        scenes.handle_input(&mut ctx)?;
+        profiler.end_scope();
+
+        profiler.scope("update", |_| -> Result<(), failure::Error> {
+            for _ in 0..timing.updates {
+                scenes.update(&mut ctx, timing.step)?;
+            }
+            Ok(())
+        })?;
+        if scenes.should_quit() {
+            break 'main;
         }
 
-        color_buffer.clear(&gl);
+        profiler.scope("render", |_| scenes.render(&mut ctx, timing.alpha))?;
+        gl.check_errors()?;
+
+        profiler.scope("capture", |_| -> Result<(), failure::Error> {
+            for path in frame_capture.end_frame(&gl, &ctx.viewport)? {
+                if !frame_capture.is_recording() {
+                    info!("Saved {}", path.display());
+                }
+            }
+            Ok(())
+        })?;
 
-        triangle.render(&gl, &mut camera);
+        profiler.end_scope();
+        profiler.end_frame();
 
         window.gl_swap_window();
+        game_loop.end_frame();
     }
+
+    let counters = gl.state_counters();
+    info!("gl state changes: {} issued, {} skipped as redundant", counters.issued, counters.skipped);
+
     Ok(())
 }
@@ -2,9 +2,19 @@ use gl;
 use crate::glm_ext::CachedMatrix4;
 use crate::camera::Camera;
 
+/**
+ * The matrix that takes normals into world space with `model`, the inverse transpose of its
+ * upper 3x3. Unlike the model matrix it keeps normals perpendicular under non uniform scaling.
+ */
+pub fn normal_matrix(model: &glm::Mat4) -> glm::Mat3 {
+    let upper: glm::Mat3 = model.fixed_slice::<glm::U3, glm::U3>(0, 0).into_owned();
+    upper.try_inverse().unwrap_or_else(glm::Mat3::identity).transpose()
+}
+
 pub struct ModelViewProjectionMatrix {
     gl: gl::Gl,
     gl_matrix_id: Option<gl::types::GLint>,
+    gl_normal_matrix_id: Option<gl::types::GLint>,
 
     model: CachedMatrix4,           // Moves the object into the world space
 }
@@ -30,6 +40,24 @@ impl ModelViewProjectionMatrix {
     }
 
 
+    /**
+     * Also keeps the normal matrix of the model in the null terminated uniform `uniform_name`
+     * up to date, for lit shaders
+     */
+    pub fn register_normal_matrix_uniform(&mut self, program_id: gl::types::GLuint, uniform_name: &[u8])
+    {
+        if !uniform_name.ends_with(&[b'\0']) {
+            panic!("Uniform must be null terminated")
+        }
+
+        self.gl_normal_matrix_id = Some(unsafe {
+            self.gl.GetUniformLocation(
+                program_id,
+                uniform_name.as_ptr() as *const gl::types::GLchar
+            )
+        });
+    }
+
     /**
      *  Calculates and returns the ModelViewProjection matrix
      *
@@ -42,6 +70,12 @@ impl ModelViewProjectionMatrix {
         if let Some(matrix_id) = self.gl_matrix_id {
             if self.model.is_dirty() || camera.is_projection_view_dirty() {
                 let mvp = camera.get_projection_view_matrix() * self.model.matrix;
+                if let (true, Some(normal_matrix_id)) = (self.model.is_dirty(), self.gl_normal_matrix_id) {
+                    let normal_matrix = normal_matrix(&self.model.matrix);
+                    unsafe {
+                        self.gl.UniformMatrix3fv(normal_matrix_id, 1, gl::FALSE, glm::value_ptr(&normal_matrix).as_ptr());
+                    }
+                }
                 self.model.clean();
                 camera.clean_projection_view();
 
@@ -59,6 +93,14 @@ impl ModelViewProjectionMatrix {
         }
     }
 
+    /**
+     * Moves the model, the uniform is updated on the next `calculate_and_update_mvp`
+     */
+    pub fn set_model_matrix(&mut self, model: glm::Mat4)
+    {
+        self.model.set_matrix(model);
+    }
+
     /**
      * @brief: This creates a new ModelViewProjectionMatrix
      * The model view projection contains the information for a model
@@ -73,7 +115,97 @@ impl ModelViewProjectionMatrix {
         ModelViewProjectionMatrix {
             gl: gl.clone(),
             gl_matrix_id: None,
+            gl_normal_matrix_id: None,
             model: glm::identity::<f32, glm::U4>().into(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gl::recording::{ Arg, Recorder };
+
+    fn camera() -> Camera {
+        Camera::new(
+            45.0 * glm::pi::<f32>() / 180.0,
+            0.75,
+            0.1,
+            100.0,
+            glm::vec3(4.0, 3.0, 3.0),
+            glm::vec3(0.0, 0.0, 0.0),
+            glm::vec3(0.0, 1.0, 0.0)
+        )
+    }
+
+    #[test]
+    fn registers_with_the_uniform_location() {
+        let (gl, recorder) = Recorder::new();
+        let mut mvp = ModelViewProjectionMatrix::new(&gl);
+
+        mvp.register_with_program_and_uniform(7, b"MVP\0");
+
+        let lookup = recorder.last_call_to("GetUniformLocation").unwrap();
+        assert_eq!(lookup.args, vec![Arg::Int(7), Arg::Str("MVP".into())]);
+    }
+
+    #[test]
+    fn uniform_is_uploaded_only_when_dirty() {
+        let (gl, recorder) = Recorder::new();
+        let mut camera = camera();
+        let mut mvp = ModelViewProjectionMatrix::new(&gl);
+        mvp.register_with_program_and_uniform(1, b"MVP\0");
+
+        mvp.calculate_and_update_mvp(&mut camera);
+        assert_eq!(recorder.count("UniformMatrix4fv"), 1);
+
+        mvp.calculate_and_update_mvp(&mut camera);
+        mvp.calculate_and_update_mvp(&mut camera);
+        assert_eq!(recorder.count("UniformMatrix4fv"), 1);
+
+        mvp.set_model_matrix(glm::translation(&glm::vec3(1.0, 0.0, 0.0)));
+        mvp.calculate_and_update_mvp(&mut camera);
+        mvp.calculate_and_update_mvp(&mut camera);
+        assert_eq!(recorder.count("UniformMatrix4fv"), 2);
+
+        // the fake hands out location 0 for the first uniform name
+        assert_eq!(recorder.last_call_to("UniformMatrix4fv").unwrap().int(0), 0);
+    }
+
+    #[test]
+    fn normal_matrix_undoes_non_uniform_scaling() {
+        let model = glm::translation(&glm::vec3(5.0, 0.0, 0.0)) * glm::scaling(&glm::vec3(2.0, 1.0, 1.0));
+        let expected = glm::mat3(0.5, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0);
+        assert!((normal_matrix(&model) - expected).norm() < 1e-6);
+
+        let rotation = glm::rotation(1.0, &glm::vec3(0.0, 1.0, 0.0));
+        let upper: glm::Mat3 = rotation.fixed_slice::<glm::U3, glm::U3>(0, 0).into_owned();
+        assert!((normal_matrix(&rotation) - upper).norm() < 1e-6);
+    }
+
+    #[test]
+    fn normal_matrix_is_uploaded_when_the_model_moves() {
+        let (gl, recorder) = Recorder::new();
+        let mut camera = camera();
+        let mut mvp = ModelViewProjectionMatrix::new(&gl);
+        mvp.register_with_program_and_uniform(1, b"MVP\0");
+        mvp.register_normal_matrix_uniform(1, b"NormalMatrix\0");
+
+        mvp.calculate_and_update_mvp(&mut camera);
+        assert_eq!(recorder.count("UniformMatrix3fv"), 1);
+
+        camera.set_aspect_ratio(1.0);
+        camera.get_projection_view_matrix();
+        mvp.calculate_and_update_mvp(&mut camera);
+        assert_eq!(recorder.count("UniformMatrix4fv"), 2);
+        assert_eq!(recorder.count("UniformMatrix3fv"), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "unregistered")]
+    fn updating_an_unregistered_matrix_panics() {
+        let (gl, _recorder) = Recorder::new();
+        let mut mvp = ModelViewProjectionMatrix::new(&gl);
+        mvp.calculate_and_update_mvp(&mut camera());
+    }
+}
@@ -0,0 +1,581 @@
+/*!
+ * Frame profiler with nested, named scopes timed on the cpu and the gpu.
+ *
+ * Gpu times come from a pair of timestamp queries per scope (elapsed time queries can not
+ * nest). Their results are only collected once the gpu has caught up, usually a frame or two
+ * later, so the most recent frames are never in the report. A frame whose queries are still
+ * not available after `FRAMES_IN_FLIGHT` newer frames is kept without gpu times rather than
+ * stalling on it.
+ *
+ * Finished frames are kept in a sliding window, summarised by `report` or written out
+ * with `write_chrome_trace` for chrome://tracing or https://ui.perfetto.dev.
+ */
+use gl;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{ self, File };
+use std::io::{ self, BufWriter, Write };
+use std::path::Path;
+use std::time::{ Duration, Instant };
+
+use crate::render_gl::{ Query, QueryKind };
+
+const FRAMES_IN_FLIGHT: usize = 4;
+
+/**
+ * One finished scope. Times are relative to the creation of the profiler, gpu starts are
+ * shifted so the first gpu scope of a frame lines up with its cpu start.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScopeRecord {
+    pub name: &'static str,
+    pub depth: usize,
+    pub cpu_start: Duration,
+    pub cpu_duration: Duration,
+    pub gpu_start: Option<Duration>,
+    pub gpu_duration: Option<Duration>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameRecord {
+    pub index: u64,
+    pub scopes: Vec<ScopeRecord>,   // in the order they were begun
+}
+
+struct PendingScope {
+    name: &'static str,
+    depth: usize,
+    cpu_start: Duration,
+    cpu_end: Duration,
+    gpu: Option<(Query, Query)>,    // start and end timestamps
+}
+
+struct PendingFrame {
+    index: u64,
+    scopes: Vec<PendingScope>,
+}
+
+impl PendingFrame {
+    fn gpu_available(&self) -> bool {
+        self.scopes.iter().all(|scope| match &scope.gpu {
+            Some((start, end)) => start.is_available() && end.is_available(),
+            None => true,
+        })
+    }
+}
+
+pub struct Profiler {
+    gl: Option<gl::Gl>,
+    origin: Instant,
+    window: usize,
+    frame_index: u64,
+    scopes: Vec<PendingScope>,      // of the current frame
+    open: Vec<usize>,               // indices into `scopes`, innermost last
+    pending: VecDeque<PendingFrame>,
+    history: VecDeque<FrameRecord>,
+    free_queries: Vec<Query>,
+}
+
+impl Profiler {
+    /**
+     * Profiles the cpu and the gpu, keeping the last `window` frames
+     */
+    pub fn new(gl: &gl::Gl, window: usize)
+    -> Profiler
+    {
+        Profiler {
+            gl: Some(gl.clone()),
+            ..Profiler::cpu_only(window)
+        }
+    }
+
+    pub fn cpu_only(window: usize)
+    -> Profiler
+    {
+        Profiler {
+            gl: None,
+            origin: Instant::now(),
+            window: window.max(1),
+            frame_index: 0,
+            scopes: Vec::new(),
+            open: Vec::new(),
+            pending: VecDeque::new(),
+            history: VecDeque::new(),
+            free_queries: Vec::new(),
+        }
+    }
+
+    pub fn begin_scope(&mut self, name: &'static str)
+    {
+        let gpu = self.gl.clone().map(|gl| {
+            let start = self.timestamp_query(&gl);
+            start.record_timestamp();
+            let end = self.timestamp_query(&gl);
+            (start, end)
+        });
+
+        self.open.push(self.scopes.len());
+        self.scopes.push(PendingScope {
+            name,
+            depth: self.open.len() - 1,
+            cpu_start: self.origin.elapsed(),
+            cpu_end: Duration::default(),
+            gpu,
+        });
+    }
+
+    pub fn end_scope(&mut self)
+    {
+        let index = self.open.pop().expect("end_scope without begin_scope");
+        let scope = &mut self.scopes[index];
+        scope.cpu_end = self.origin.elapsed();
+        if let Some((_, end)) = &scope.gpu {
+            end.record_timestamp();
+        }
+    }
+
+    /**
+     * Runs `f` inside a scope
+     */
+    pub fn scope<T, F>(&mut self, name: &'static str, f: F)
+    -> T
+        where F: FnOnce(&mut Profiler) -> T
+    {
+        self.begin_scope(name);
+        let result = f(self);
+        self.end_scope();
+        result
+    }
+
+    /**
+     * Closes the frame and collects every earlier frame whose gpu times have arrived
+     */
+    pub fn end_frame(&mut self)
+    {
+        assert!(self.open.is_empty(), "end_frame inside scope {}", self.scopes[self.open[0]].name);
+
+        let scopes = ::std::mem::take(&mut self.scopes);
+        self.pending.push_back(PendingFrame { index: self.frame_index, scopes });
+        self.frame_index += 1;
+
+        while let Some(frame) = self.pending.front() {
+            let gpu_available = frame.gpu_available();
+            if !gpu_available && self.pending.len() <= FRAMES_IN_FLIGHT {
+                break;
+            }
+            let frame = self.pending.pop_front().unwrap();
+            let record = self.resolve(frame, gpu_available);
+            self.push_record(record);
+        }
+    }
+
+    /**
+     * Finished frames in the window, oldest first
+     */
+    pub fn frames(&self) -> impl Iterator<Item = &FrameRecord> {
+        self.history.iter()
+    }
+
+    pub fn report(&self) -> Report {
+        Report::of(self.history.iter())
+    }
+
+    pub fn write_chrome_trace(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = BufWriter::new(File::create(path)?);
+        write_chrome_trace(&mut file, self.history.iter())?;
+        file.flush()
+    }
+
+    fn timestamp_query(&mut self, gl: &gl::Gl) -> Query {
+        self.free_queries.pop().unwrap_or_else(|| Query::new(gl, QueryKind::Timestamp))
+    }
+
+    fn resolve(&mut self, frame: PendingFrame, gpu_available: bool)
+    -> FrameRecord
+    {
+        let mut gpu_origin = None;  // (gpu timestamp, cpu time) of the first gpu scope
+        let mut scopes = Vec::with_capacity(frame.scopes.len());
+
+        for scope in frame.scopes {
+            let mut gpu_start = None;
+            let mut gpu_duration = None;
+
+            if let Some((start, end)) = scope.gpu {
+                if gpu_available {
+                    let (start_ns, end_ns) = (start.wait_result(), end.wait_result());
+                    let (origin_ns, origin_cpu) = *gpu_origin.get_or_insert((start_ns, scope.cpu_start));
+                    gpu_start = Some(origin_cpu + Duration::from_nanos(start_ns.saturating_sub(origin_ns)));
+                    gpu_duration = Some(Duration::from_nanos(end_ns.saturating_sub(start_ns)));
+                }
+                self.free_queries.push(start);
+                self.free_queries.push(end);
+            }
+
+            scopes.push(ScopeRecord {
+                name: scope.name,
+                depth: scope.depth,
+                cpu_start: scope.cpu_start,
+                cpu_duration: scope.cpu_end.saturating_sub(scope.cpu_start),
+                gpu_start,
+                gpu_duration,
+            });
+        }
+
+        FrameRecord { index: frame.index, scopes }
+    }
+
+    fn push_record(&mut self, record: FrameRecord) {
+        if self.history.len() == self.window {
+            self.history.pop_front();
+        }
+        self.history.push_back(record);
+    }
+}
+
+// -----------------------------------------------------------------
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Stats {
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+}
+
+impl Stats {
+    pub fn of(samples: &[Duration]) -> Option<Stats> {
+        let min = *samples.iter().min()?;
+        let max = *samples.iter().max()?;
+        let avg = samples.iter().sum::<Duration>() / samples.len() as u32;
+        Some(Stats { min, avg, max })
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:7.3} {:7.3} {:7.3}", millis(self.min), millis(self.avg), millis(self.max))
+    }
+}
+
+/**
+ * Times of one scope over the window. Scopes are told apart by name and nesting depth.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScopeStats {
+    pub name: &'static str,
+    pub depth: usize,
+    pub frames: usize,          // number of frames the scope ran in
+    pub cpu: Stats,
+    pub gpu: Option<Stats>,     // None when no frame has gpu times
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub frames: usize,
+    pub scopes: Vec<ScopeStats>,    // in order of first appearance
+}
+
+/**
+ * Samples of one scope, summed per frame. Gpu samples carry the index of the frame their
+ * queries were issued in.
+ */
+struct ScopeSamples {
+    name: &'static str,
+    depth: usize,
+    cpu: Vec<Duration>,
+    gpu: Vec<(u64, Duration)>,
+}
+
+impl Report {
+    pub fn of<'a, I>(frames: I)
+    -> Report
+        where I: IntoIterator<Item = &'a FrameRecord>
+    {
+        let mut samples: Vec<ScopeSamples> = Vec::new();
+        let mut frame_count = 0;
+
+        for frame in frames {
+            frame_count += 1;
+            let mut in_frame: Vec<usize> = Vec::new();
+
+            for scope in &frame.scopes {
+                let index = match samples.iter().position(|samples| samples.name == scope.name && samples.depth == scope.depth) {
+                    Some(index) => index,
+                    None => {
+                        samples.push(ScopeSamples { name: scope.name, depth: scope.depth, cpu: Vec::new(), gpu: Vec::new() });
+                        samples.len() - 1
+                    }
+                };
+
+                // a scope that runs several times in a frame counts once with its total time
+                let ScopeSamples { cpu, gpu, .. } = &mut samples[index];
+                if in_frame.contains(&index) {
+                    *cpu.last_mut().unwrap() += scope.cpu_duration;
+                } else {
+                    in_frame.push(index);
+                    cpu.push(scope.cpu_duration);
+                }
+
+                // the last gpu sample is from an earlier frame when the first run in this
+                // frame had no gpu time
+                if let Some(duration) = scope.gpu_duration {
+                    match gpu.last_mut() {
+                        Some((issued_in, total)) if *issued_in == frame.index => *total += duration,
+                        _ => gpu.push((frame.index, duration)),
+                    }
+                }
+            }
+        }
+
+        Report {
+            frames: frame_count,
+            scopes: samples.into_iter()
+                .map(|samples| {
+                    let gpu: Vec<Duration> = samples.gpu.iter().map(|(_, duration)| *duration).collect();
+                    ScopeStats {
+                        name: samples.name,
+                        depth: samples.depth,
+                        frames: samples.cpu.len(),
+                        cpu: Stats::of(&samples.cpu).unwrap(),
+                        gpu: Stats::of(&gpu),
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} frames, times in ms (min avg max)", self.frames)?;
+        writeln!(f, "{:32} {:>23}   {:>23}", "scope", "cpu", "gpu")?;
+        for scope in &self.scopes {
+            let name = format!("{:indent$}{}", "", scope.name, indent = scope.depth * 2);
+            match &scope.gpu {
+                Some(gpu) => writeln!(f, "{:32} {}   {}", name, scope.cpu, gpu)?,
+                None => writeln!(f, "{:32} {}   {:>23}", name, scope.cpu, "-")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn micros(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1_000_000.0
+}
+
+/**
+ * Writes frames in the chrome trace event format, cpu scopes on one track and gpu scopes
+ * on another
+ */
+pub fn write_chrome_trace<'a, W, I>(out: &mut W, frames: I)
+-> io::Result<()>
+    where W: Write, I: IntoIterator<Item = &'a FrameRecord>
+{
+    const CPU_TRACK: u32 = 1;
+    const GPU_TRACK: u32 = 2;
+
+    writeln!(out, "{{\"traceEvents\":[")?;
+    write!(out, "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":\"CPU\"}}}}", CPU_TRACK)?;
+    write!(out, ",\n{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":\"GPU\"}}}}", GPU_TRACK)?;
+
+    for frame in frames {
+        for scope in &frame.scopes {
+            let mut event = |track: u32, start: Duration, duration: Duration| write!(
+                out,
+                ",\n{{\"name\":{},\"cat\":\"{}\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":1,\"tid\":{},\"args\":{{\"frame\":{}}}}}",
+                json_string(scope.name),
+                if track == CPU_TRACK { "cpu" } else { "gpu" },
+                micros(start),
+                micros(duration),
+                track,
+                frame.index
+            );
+
+            event(CPU_TRACK, scope.cpu_start, scope.cpu_duration)?;
+            if let (Some(start), Some(duration)) = (scope.gpu_start, scope.gpu_duration) {
+                event(GPU_TRACK, start, duration)?;
+            }
+        }
+    }
+
+    writeln!(out, "\n],\"displayTimeUnit\":\"ms\"}}")
+}
+
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gl::recording::Recorder;
+
+    fn ms(value: u64) -> Duration {
+        Duration::from_millis(value)
+    }
+
+    fn scope(name: &'static str, depth: usize, cpu: u64, gpu: Option<u64>) -> ScopeRecord {
+        ScopeRecord {
+            name,
+            depth,
+            cpu_start: ms(0),
+            cpu_duration: ms(cpu),
+            gpu_start: gpu.map(|_| ms(0)),
+            gpu_duration: gpu.map(ms),
+        }
+    }
+
+    fn frame(index: u64, scopes: Vec<ScopeRecord>) -> FrameRecord {
+        FrameRecord { index, scopes }
+    }
+
+    #[test]
+    fn report_has_min_avg_max_per_scope() {
+        let frames = vec![
+            frame(0, vec![scope("frame", 0, 10, Some(4)), scope("draw", 1, 2, Some(3))]),
+            frame(1, vec![scope("frame", 0, 20, None), scope("draw", 1, 4, None)]),
+            frame(2, vec![scope("frame", 0, 30, Some(8))]),
+        ];
+
+        let report = Report::of(&frames);
+
+        assert_eq!(report.frames, 3);
+        assert_eq!(report.scopes.len(), 2);
+        assert_eq!(report.scopes[0].cpu, Stats { min: ms(10), avg: ms(20), max: ms(30) });
+        assert_eq!(report.scopes[0].gpu, Some(Stats { min: ms(4), avg: ms(6), max: ms(8) }));
+        assert_eq!(report.scopes[1].name, "draw");
+        assert_eq!(report.scopes[1].depth, 1);
+        assert_eq!(report.scopes[1].frames, 2);
+        assert_eq!(report.scopes[1].gpu, Some(Stats { min: ms(3), avg: ms(3), max: ms(3) }));
+    }
+
+    #[test]
+    fn repeated_scopes_are_summed_per_frame() {
+        let frames = vec![frame(0, vec![scope("draw", 0, 1, Some(2)), scope("draw", 0, 3, Some(2))])];
+
+        let report = Report::of(&frames);
+
+        assert_eq!(report.scopes.len(), 1);
+        assert_eq!(report.scopes[0].cpu.max, ms(4));
+        assert_eq!(report.scopes[0].gpu.unwrap().max, ms(4));
+    }
+
+    #[test]
+    fn repeated_gpu_times_stay_in_their_own_frame() {
+        let frames = vec![
+            frame(0, vec![scope("draw", 0, 1, Some(5))]),
+            frame(1, vec![scope("draw", 0, 1, None), scope("draw", 0, 1, Some(2))]),
+        ];
+
+        let report = Report::of(&frames);
+
+        assert_eq!(report.scopes[0].gpu, Some(Stats { min: ms(2), avg: Duration::from_micros(3500), max: ms(5) }));
+    }
+
+    #[test]
+    fn history_is_a_sliding_window() {
+        let mut profiler = Profiler::cpu_only(2);
+        for _ in 0..5 {
+            profiler.scope("frame", |_| ());
+            profiler.end_frame();
+        }
+
+        let indices: Vec<u64> = profiler.frames().map(|frame| frame.index).collect();
+        assert_eq!(indices, vec![3, 4]);
+    }
+
+    #[test]
+    fn scopes_nest() {
+        let mut profiler = Profiler::cpu_only(1);
+        profiler.scope("frame", |profiler| {
+            profiler.scope("update", |_| ());
+            profiler.scope("render", |profiler| profiler.scope("triangle", |_| ()));
+        });
+        profiler.end_frame();
+
+        let scopes: Vec<(&str, usize)> = profiler.frames().next().unwrap().scopes.iter()
+            .map(|scope| (scope.name, scope.depth))
+            .collect();
+        assert_eq!(scopes, vec![("frame", 0), ("update", 1), ("render", 1), ("triangle", 2)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "end_frame inside scope frame")]
+    fn frame_can_not_end_inside_a_scope() {
+        let mut profiler = Profiler::cpu_only(1);
+        profiler.begin_scope("frame");
+        profiler.end_frame();
+    }
+
+    #[test]
+    fn gpu_times_are_read_back_without_blocking() {
+        let (gl, recorder) = Recorder::new();
+        let mut profiler = Profiler::new(&gl, 10);
+
+        profiler.scope("frame", |_| ());
+        profiler.end_frame();
+        assert_eq!(recorder.count("QueryCounter"), 2);
+        assert_eq!(profiler.frames().count(), 0);   // results not available yet
+        assert_eq!(recorder.count("GetQueryObjectui64v"), 0);
+
+        recorder.set_integer(gl::QUERY_RESULT_AVAILABLE, 1);
+        profiler.scope("frame", |_| ());
+        profiler.end_frame();
+
+        assert_eq!(profiler.frames().count(), 2);
+        assert!(profiler.frames().all(|frame| frame.scopes[0].gpu_duration.is_some()));
+        // queries of collected frames are reused
+        assert_eq!(recorder.count("GenQueries"), 4);
+    }
+
+    #[test]
+    fn frames_stuck_on_the_gpu_are_kept_without_gpu_times() {
+        let (gl, _recorder) = Recorder::new();
+        let mut profiler = Profiler::new(&gl, 10);
+
+        for _ in 0..FRAMES_IN_FLIGHT + 1 {
+            profiler.scope("frame", |_| ());
+            profiler.end_frame();
+        }
+
+        let frames: Vec<&FrameRecord> = profiler.frames().collect();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].scopes[0].gpu_duration, None);
+    }
+
+    #[test]
+    fn chrome_trace_has_a_track_per_processor() {
+        let mut record = scope("draw \"a\"", 0, 2, Some(1));
+        record.cpu_start = Duration::from_micros(1500);
+        record.gpu_start = Some(Duration::from_micros(1600));
+
+        let mut out = Vec::new();
+        write_chrome_trace(&mut out, &[frame(7, vec![record])]).unwrap();
+        let trace = String::from_utf8(out).unwrap();
+
+        assert!(trace.starts_with("{\"traceEvents\":["));
+        assert!(trace.contains(
+            "{\"name\":\"draw \\\"a\\\"\",\"cat\":\"cpu\",\"ph\":\"X\",\"ts\":1500.000,\"dur\":2000.000,\"pid\":1,\"tid\":1,\"args\":{\"frame\":7}}"
+        ));
+        assert!(trace.contains("\"cat\":\"gpu\",\"ph\":\"X\",\"ts\":1600.000,\"dur\":1000.000,\"pid\":1,\"tid\":2"));
+        assert!(trace.trim_end().ends_with("],\"displayTimeUnit\":\"ms\"}"));
+    }
+}
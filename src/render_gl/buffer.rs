@@ -1,5 +1,7 @@
 use gl;
 
+use super::debug_output::label_object;
+
 pub trait BufferType {
     const BUFFER_TYPE: gl::types::GLuint;
 }
@@ -22,6 +24,14 @@ pub type ElementArrayBuffer = Buffer<BufferTypeElementArray>;
 
 // ----------------------------------------------------------------
 
+pub struct BufferTypeUniform;
+impl BufferType for BufferTypeUniform {
+    const BUFFER_TYPE: gl::types::GLuint = gl::UNIFORM_BUFFER;
+}
+pub type UniformBuffer = Buffer<BufferTypeUniform>;
+
+// ----------------------------------------------------------------
+
 pub struct Buffer<B> where B : BufferType {
     vbo: gl::types::GLuint,
     gl: gl::Gl,
@@ -46,34 +56,69 @@ impl<B> Buffer<B> where B : BufferType {
 
     pub fn bind(&self)
     {
-        unsafe {
-            self.gl.BindBuffer(B::BUFFER_TYPE, self.vbo);
-        }
+        self.gl.bind_buffer(B::BUFFER_TYPE, self.vbo);
     }
 
     pub fn unbind(&self)
     {
-        unsafe {
-            self.gl.BindBuffer(B::BUFFER_TYPE, self.vbo);
-        }
+        self.gl.bind_buffer(B::BUFFER_TYPE, 0);
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.vbo
+    }
+
+    /**
+     * Names the buffer in driver debug messages and graphics debuggers
+     */
+    pub fn set_label(&self, label: &str)
+    {
+        label_object(&self.gl, gl::BUFFER, self.vbo, label);
     }
 
     pub fn static_draw_data<T>(&self, data: &[T])
     {
         unsafe {
             self.gl.BufferData(
-                gl::ARRAY_BUFFER, // target
+                B::BUFFER_TYPE, // target
                 (data.len() * ::std::mem::size_of::<T>()) as gl::types::GLsizeiptr, // size of the data in bytes
                 data.as_ptr() as *const gl::types::GLvoid, // pointer to raw data
                 gl::STATIC_DRAW, // usage
             )
         }
     }
+
+    /**
+     * Replaces the data of a buffer that is rewritten every frame
+     */
+    pub fn dynamic_draw_data<T>(&self, data: &[T])
+    {
+        unsafe {
+            self.gl.BufferData(
+                B::BUFFER_TYPE,
+                (data.len() * ::std::mem::size_of::<T>()) as gl::types::GLsizeiptr,
+                data.as_ptr() as *const gl::types::GLvoid,
+                gl::DYNAMIC_DRAW,
+            )
+        }
+    }
+
+    /**
+     * Binds the buffer to the indexed binding point `index`, for uniform blocks
+     */
+    pub fn bind_base(&self, index: u32)
+    {
+        self.bind(); // BindBufferBase also binds the generic target, keeps the cache right
+        unsafe {
+            self.gl.BindBufferBase(B::BUFFER_TYPE, index, self.vbo);
+        }
+    }
 }
 
 impl<B> Drop for Buffer<B> where B : BufferType {
     fn drop(&mut self)
     {
+        self.gl.forget_buffer(self.vbo);
         unsafe {
             self.gl.DeleteBuffers(1, &mut self.vbo);
         }
@@ -102,26 +147,148 @@ impl VertexArray {
         }
     }
 
+    pub fn id(&self) -> gl::types::GLuint {
+        self.vao
+    }
+
     pub fn bind(&self)
     {
-        unsafe {
-            self.gl.BindVertexArray(self.vao);
-        }
+        self.gl.bind_vertex_array(self.vao);
     }
 
     pub fn unbind(&self)
     {
-        unsafe {
-            self.gl.BindVertexArray(0);
-        }
+        self.gl.bind_vertex_array(0);
+    }
+
+    /**
+     * Names the vertex array in driver debug messages and graphics debuggers
+     */
+    pub fn set_label(&self, label: &str)
+    {
+        label_object(&self.gl, gl::VERTEX_ARRAY, self.vao, label);
     }
 }
 
 impl Drop for VertexArray {
     fn drop(&mut self)
     {
+        self.gl.forget_vertex_array(self.vao);
         unsafe {
             self.gl.DeleteVertexArrays(1, &mut self.vao);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gl::recording::Recorder;
+
+    #[test]
+    fn bind_and_unbind() {
+        let (gl, recorder) = Recorder::new();
+        let buffer = ElementArrayBuffer::new(&gl);
+        let name = recorder.last_call_to("GenBuffers").unwrap().names(1)[0];
+
+        buffer.bind();
+        buffer.unbind();
+
+        let binds = recorder.calls_to("BindBuffer");
+        assert_eq!(binds.len(), 2);
+        assert_eq!(binds[0].int(0), gl::ELEMENT_ARRAY_BUFFER as i64);
+        assert_eq!(binds[0].int(1), name as i64);
+        assert_eq!(binds[1].int(0), gl::ELEMENT_ARRAY_BUFFER as i64);
+        assert_eq!(binds[1].int(1), 0);
+    }
+
+    #[test]
+    fn redundant_binds_are_skipped() {
+        let (gl, recorder) = Recorder::new();
+        let buffer = ArrayBuffer::new(&gl);
+        let vao = VertexArray::new(&gl);
+
+        for _ in 0..3 {
+            vao.bind();
+            buffer.bind();
+        }
+        buffer.unbind();
+
+        assert_eq!(recorder.count("BindVertexArray"), 1);
+        assert_eq!(recorder.count("BindBuffer"), 2);
+        assert_eq!(gl.state_counters(), gl::StateCounters { issued: 3, skipped: 4 });
+    }
+
+    #[test]
+    fn element_array_binding_follows_the_vertex_array() {
+        let (gl, recorder) = Recorder::new();
+        let indices = ElementArrayBuffer::new(&gl);
+        let first = VertexArray::new(&gl);
+        let second = VertexArray::new(&gl);
+
+        first.bind();
+        indices.bind();
+        second.bind();
+        indices.bind();  // a different vertex array has its own element array binding
+
+        assert_eq!(recorder.count("BindBuffer"), 2);
+    }
+
+    #[test]
+    fn deleted_names_are_not_assumed_bound() {
+        let (gl, recorder) = Recorder::new();
+        let buffer = ArrayBuffer::new(&gl);
+        buffer.bind();
+        drop(buffer);
+
+        // the fake never reuses names, but a driver may hand the same name out again
+        gl.bind_buffer(gl::ARRAY_BUFFER, 1);
+
+        assert_eq!(recorder.count("BindBuffer"), 2);
+    }
+
+    #[test]
+    fn data_is_uploaded_to_the_buffer_target() {
+        let (gl, recorder) = Recorder::new();
+        let buffer = ElementArrayBuffer::new(&gl);
+
+        buffer.static_draw_data(&[0u16, 1, 2]);
+
+        let upload = recorder.last_call_to("BufferData").unwrap();
+        assert_eq!(upload.int(0), gl::ELEMENT_ARRAY_BUFFER as i64);
+        assert_eq!(upload.int(1), 6);
+        assert_eq!(upload.int(3), gl::STATIC_DRAW as i64);
+    }
+
+    #[test]
+    fn drop_deletes_own_names() {
+        let (gl, recorder) = Recorder::new();
+        let first = ArrayBuffer::new(&gl);
+        let second = ArrayBuffer::new(&gl);
+        let vao = VertexArray::new(&gl);
+
+        let buffer_names: Vec<_> = recorder.calls_to("GenBuffers").iter().map(|c| c.names(1)[0]).collect();
+        let vao_name = recorder.last_call_to("GenVertexArrays").unwrap().names(1)[0];
+
+        drop(second);
+        drop(vao);
+        drop(first);
+
+        let deleted: Vec<_> = recorder.calls_to("DeleteBuffers").iter().map(|c| c.names(1).to_vec()).collect();
+        assert_eq!(deleted, vec![vec![buffer_names[1]], vec![buffer_names[0]]]);
+        assert_eq!(recorder.last_call_to("DeleteVertexArrays").unwrap().names(1), &[vao_name]);
+    }
+
+    #[test]
+    fn vertex_array_unbind_binds_zero() {
+        let (gl, recorder) = Recorder::new();
+        let vao = VertexArray::new(&gl);
+
+        vao.bind();
+        vao.unbind();
+
+        let binds = recorder.calls_to("BindVertexArray");
+        assert_ne!(binds[0].int(0), 0);
+        assert_eq!(binds[1].int(0), 0);
+    }
+}
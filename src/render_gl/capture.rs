@@ -0,0 +1,315 @@
+use gl;
+use png;
+use std::fs::{ self, File };
+use std::io::{ self, BufWriter };
+use std::path::{ Path, PathBuf };
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+use super::framebuffer::Framebuffer;
+use super::viewport::Viewport;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "I/O error on {}", path)]
+    Io { path: String, #[cause] error: io::Error },
+
+    #[fail(display = "Failed to encode {} as png", path)]
+    Png { path: String, #[cause] error: png::EncodingError },
+
+    #[fail(display = "Failed to decode png {}", path)]
+    PngDecode { path: String, #[cause] error: png::DecodingError },
+
+    #[fail(display = "Png {} is not 8 bit RGBA", path)]
+    UnsupportedPngFormat { path: String },
+}
+
+/**
+ * Tightly packed 8 bit RGBA pixels, top row first (already flipped from gl's bottom up order)
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    /**
+     * Reads a rectangle of the current read framebuffer, which is the window unless
+     * a framebuffer object is bound
+     */
+    pub fn read_pixels(gl: &gl::Gl, x: i32, y: i32, width: i32, height: i32)
+    -> Image
+    {
+        let mut pixels = vec![0u8; width as usize * height as usize * 4];
+
+        unsafe {
+            gl.PixelStorei(gl::PACK_ALIGNMENT, 1); // rows are not padded
+            gl.ReadPixels(
+                x,
+                y,
+                width,
+                height,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as *mut gl::types::GLvoid,
+            );
+        }
+
+        let mut image = Image { width: width as u32, height: height as u32, pixels };
+        image.flip_vertically();
+        image
+    }
+
+    /**
+     * Reads what has been drawn into the window so far (call before swapping)
+     */
+    pub fn from_viewport(gl: &gl::Gl, viewport: &Viewport)
+    -> Image
+    {
+        unsafe {
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+        }
+        Image::read_pixels(gl, viewport.x, viewport.y, viewport.w, viewport.h)
+    }
+
+    /**
+     * Reads color attachment `attachment` of a single sampled framebuffer.
+     * Multisampled framebuffers have to be resolved with `Framebuffer::blit_to` first.
+     */
+    pub fn from_framebuffer(gl: &gl::Gl, framebuffer: &Framebuffer, attachment: u32)
+    -> Image
+    {
+        unsafe {
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, framebuffer.id());
+            gl.ReadBuffer(gl::COLOR_ATTACHMENT0 + attachment);
+        }
+
+        let image = Image::read_pixels(gl, 0, 0, framebuffer.width(), framebuffer.height());
+
+        unsafe {
+            gl.ReadBuffer(gl::COLOR_ATTACHMENT0);
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+        }
+
+        image
+    }
+
+    pub fn flip_vertically(&mut self)
+    {
+        let row_len = self.width as usize * 4;
+        let height = self.height as usize;
+
+        for row in 0..height / 2 {
+            let (top, bottom) = self.pixels.split_at_mut((height - row - 1) * row_len);
+            top[row * row_len..(row + 1) * row_len].swap_with_slice(&mut bottom[..row_len]);
+        }
+    }
+
+    /**
+     * Loads an 8 bit RGBA png, such as one written by `save_png`
+     */
+    pub fn load_png(path: &Path)
+    -> Result<Image, Error>
+    {
+        let path_string = path.to_string_lossy().into_owned();
+
+        let file = File::open(path)
+            .map_err(|error| Error::Io { path: path_string.clone(), error })?;
+
+        let (info, mut reader) = png::Decoder::new(file).read_info()
+            .map_err(|error| Error::PngDecode { path: path_string.clone(), error })?;
+
+        if info.color_type != png::ColorType::RGBA || info.bit_depth != png::BitDepth::Eight {
+            return Err(Error::UnsupportedPngFormat { path: path_string });
+        }
+
+        let mut pixels = vec![0u8; info.buffer_size()];
+        reader.next_frame(&mut pixels)
+            .map_err(|error| Error::PngDecode { path: path_string, error })?;
+
+        Ok(Image { width: info.width, height: info.height, pixels })
+    }
+
+    pub fn save_png(&self, path: &Path)
+    -> Result<(), Error>
+    {
+        let path_string = path.to_string_lossy().into_owned();
+
+        if let Some(directory) = path.parent() {
+            create_dir(directory)?;
+        }
+
+        let file = File::create(path)
+            .map_err(|error| Error::Io { path: path_string.clone(), error })?;
+
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        encoder.write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.pixels))
+            .map_err(|error| Error::Png { path: path_string, error })
+    }
+}
+
+/**
+ * Takes single screenshots on request, and optionally records every Nth frame
+ * into a numbered image sequence (frame-000000.png, frame-000001.png, ...)
+ * that can be turned into a video with eg. ffmpeg.
+ *
+ * Call `end_frame` once per frame after everything is drawn and before swapping the window.
+ */
+pub struct FrameCapture {
+    directory: PathBuf,
+    screenshot_requested: bool,
+    recording: Option<Recording>,
+}
+
+struct Recording {
+    directory: PathBuf,
+    every_nth_frame: u32,
+    frame: u32,
+    images_written: u32,
+}
+
+impl FrameCapture {
+    pub fn new(directory: &Path)
+    -> FrameCapture
+    {
+        FrameCapture {
+            directory: directory.into(),
+            screenshot_requested: false,
+            recording: None,
+        }
+    }
+
+    /**
+     * The next `end_frame` saves a screenshot
+     */
+    pub fn request_screenshot(&mut self)
+    {
+        self.screenshot_requested = true;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /**
+     * Starts writing every Nth frame into a new subdirectory of the capture directory
+     */
+    pub fn start_recording(&mut self, every_nth_frame: u32)
+    {
+        self.recording = Some(Recording {
+            directory: self.directory.join(format!("recording-{}", timestamp())),
+            every_nth_frame: every_nth_frame.max(1),
+            frame: 0,
+            images_written: 0,
+        });
+    }
+
+    pub fn stop_recording(&mut self)
+    {
+        self.recording = None;
+    }
+
+    pub fn toggle_recording(&mut self, every_nth_frame: u32)
+    {
+        if self.is_recording() {
+            self.stop_recording();
+        } else {
+            self.start_recording(every_nth_frame);
+        }
+    }
+
+    /**
+     * Writes whatever was requested for this frame and returns the paths of the written files
+     */
+    pub fn end_frame(&mut self, gl: &gl::Gl, viewport: &Viewport)
+    -> Result<Vec<PathBuf>, Error>
+    {
+        let mut written = Vec::new();
+        let mut image = None;
+
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+
+            let path = self.directory.join(format!("screenshot-{}.png", timestamp()));
+            image.get_or_insert_with(|| Image::from_viewport(gl, viewport)).save_png(&path)?;
+            written.push(path);
+        }
+
+        if let Some(recording) = &mut self.recording {
+            if recording.frame % recording.every_nth_frame == 0 {
+                let path = recording.directory.join(format!("frame-{:06}.png", recording.images_written));
+                image.get_or_insert_with(|| Image::from_viewport(gl, viewport)).save_png(&path)?;
+                recording.images_written += 1;
+                written.push(path);
+            }
+            recording.frame += 1;
+        }
+
+        Ok(written)
+    }
+}
+
+fn create_dir(directory: &Path)
+-> Result<(), Error>
+{
+    fs::create_dir_all(directory)
+        .map_err(|error| Error::Io { path: directory.to_string_lossy().into_owned(), error })
+}
+
+fn timestamp() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /**
+     * `height` rows of `width` pixels, every channel of row `y` is `y`
+     */
+    fn rows(width: u32, height: u32) -> Image {
+        let pixels = (0..height).flat_map(|y| vec![y as u8; width as usize * 4]).collect();
+        Image { width, height, pixels }
+    }
+
+    fn row_values(image: &Image) -> Vec<u8> {
+        image.pixels.chunks(image.width as usize * 4).map(|row| row[0]).collect()
+    }
+
+    #[test]
+    fn flipping_reverses_the_rows() {
+        let mut even = rows(3, 4);
+        even.flip_vertically();
+        assert_eq!(row_values(&even), vec![3, 2, 1, 0]);
+
+        // the middle row of an odd height stays put
+        let mut odd = rows(3, 5);
+        odd.flip_vertically();
+        assert_eq!(row_values(&odd), vec![4, 3, 2, 1, 0]);
+
+        odd.flip_vertically();
+        assert_eq!(odd, rows(3, 5));
+    }
+
+    #[test]
+    fn saved_pngs_load_back_unchanged() {
+        let directory = std::env::temp_dir().join(format!("capture-test-{}-{}", std::process::id(), timestamp()));
+        let path = directory.join("nested").join("image.png");
+
+        let mut image = rows(5, 3);
+        image.pixels[7] = 128;                          // alpha of the second pixel
+        image.save_png(&path).unwrap();
+        assert_eq!(Image::load_png(&path).unwrap(), image);
+
+        assert!(Image::load_png(&directory.join("missing.png")).is_err());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -14,6 +14,14 @@ impl ColorBuffer {
         }
     }
 
+    pub fn from_color_alpha(color: na::Vector4<f32>)
+    -> ColorBuffer
+    {
+        ColorBuffer {
+            color,
+        }
+    }
+
     pub fn update_color(&mut self, color: na::Vector3<f32>)
     {
         self.color = color.fixed_resize::<na::U4, na::U1>(1.0);
@@ -22,10 +30,13 @@ impl ColorBuffer {
     pub fn set_used(&self, gl: &gl::Gl)
     {
         unsafe {
-            gl.ClearColor(self.color.x, self.color.y, self.color.z, 1.0);
+            gl.ClearColor(self.color.x, self.color.y, self.color.z, self.color.w);
         }
     }
 
+    /**
+     * Only clears color, use `RenderStateCache::clear` when depth or stencil need clearing as well
+     */
     pub fn clear(&self, gl: &gl::Gl)
     {
         unsafe {
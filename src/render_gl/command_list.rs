@@ -0,0 +1,694 @@
+/*!
+ * Safe wrapper for NV_command_list.
+ *
+ * A command list replays a stream of binary tokens (draws, vertex / index / uniform buffer
+ * addresses and a few pieces of dynamic state) against pre-validated state objects, which
+ * removes most of the driver work of a regular draw. Buffers are referenced by gpu address
+ * (NV_shader_buffer_load), so the three extensions are only used together.
+ *
+ * Only NVIDIA drivers implement the extension. Check `command_lists_supported` and keep the
+ * regular draw path for everything else, including Mesa which the tests run on.
+ */
+use gl;
+use std::ffi::CStr;
+use std::os::raw::c_void;
+
+use super::buffer::VertexArray;
+use super::render_state::FrontFace;
+use super::vertex_layout::{ AttribKind, VertexLayout };
+
+const REQUIRED_EXTENSIONS: [&str; 3] = [
+    "GL_NV_command_list",
+    "GL_NV_shader_buffer_load",
+    "GL_NV_vertex_buffer_unified_memory",
+];
+
+/**
+ * True if the context advertises `extension` in its extension list
+ */
+pub fn has_extension(gl: &gl::Gl, extension: &str) -> bool
+{
+    let mut count: gl::types::GLint = 0;
+    unsafe {
+        gl.GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+    }
+
+    (0..count.max(0) as gl::types::GLuint).any(|index| {
+        let name = unsafe { gl.GetStringi(gl::EXTENSIONS, index) };
+        !name.is_null() && unsafe { CStr::from_ptr(name as *const _) }.to_bytes() == extension.as_bytes()
+    })
+}
+
+/**
+ * True if command lists can be used, otherwise draw the regular way
+ */
+pub fn command_lists_supported(gl: &gl::Gl) -> bool
+{
+    gl.CreateCommandListsNV.is_loaded()
+        && gl.GetNamedBufferParameterui64vNV.is_loaded()
+        && REQUIRED_EXTENSIONS.iter().all(|extension| has_extension(gl, extension))
+}
+
+/**
+ * Makes `buffer` resident for reading by the gpu and returns its address for use in tokens.
+ * The buffer has to have its storage allocated already.
+ */
+pub fn buffer_address(gl: &gl::Gl, buffer: gl::types::GLuint)
+-> u64
+{
+    let mut address: gl::types::GLuint64EXT = 0;
+    unsafe {
+        if gl.IsNamedBufferResidentNV(buffer) == gl::FALSE {
+            gl.MakeNamedBufferResidentNV(buffer, gl::READ_ONLY);
+        }
+        gl.GetNamedBufferParameterui64vNV(buffer, gl::BUFFER_GPU_ADDRESS_NV, &mut address);
+    }
+    address
+}
+
+// -----------------------------------------------------------------
+
+/**
+ * One command of a token stream, laid out like the `*CommandNV` structs of the spec
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Token {
+    TerminateSequence,
+    Nop,
+    DrawElements { count: u32, first_index: u32, base_vertex: u32 },
+    DrawArrays { count: u32, first: u32 },
+    DrawElementsStrip { count: u32, first_index: u32, base_vertex: u32 },
+    DrawArraysStrip { count: u32, first: u32 },
+    DrawElementsInstanced {
+        mode: gl::types::GLenum,
+        count: u32,
+        instance_count: u32,
+        first_index: u32,
+        base_vertex: u32,
+        base_instance: u32,
+    },
+    DrawArraysInstanced {
+        mode: gl::types::GLenum,
+        count: u32,
+        instance_count: u32,
+        first: u32,
+        base_instance: u32,
+    },
+    ElementAddress { address: u64, type_size: u32 },        // type_size is 1, 2 or 4 bytes
+    AttributeAddress { index: u32, address: u64 },
+    UniformAddress { index: u16, stage: u16, address: u64 }, // stage from glGetStageIndexNV
+    BlendColor { red: f32, green: f32, blue: f32, alpha: f32 },
+    StencilRef { front: u32, back: u32 },
+    LineWidth(f32),
+    PolygonOffset { scale: f32, bias: f32 },
+    AlphaRef(f32),
+    Viewport { x: u32, y: u32, width: u32, height: u32 },
+    Scissor { x: u32, y: u32, width: u32, height: u32 },
+    FrontFace(FrontFace),
+}
+
+/**
+ * Number of token ids, they run from TERMINATE_SEQUENCE_COMMAND_NV to FRONT_FACE_COMMAND_NV
+ */
+const TOKEN_IDS: usize = gl::FRONT_FACE_COMMAND_NV as usize + 1;
+
+impl Token {
+    pub fn id(&self) -> gl::types::GLenum {
+        match self {
+            Token::TerminateSequence => gl::TERMINATE_SEQUENCE_COMMAND_NV,
+            Token::Nop => gl::NOP_COMMAND_NV,
+            Token::DrawElements { .. } => gl::DRAW_ELEMENTS_COMMAND_NV,
+            Token::DrawArrays { .. } => gl::DRAW_ARRAYS_COMMAND_NV,
+            Token::DrawElementsStrip { .. } => gl::DRAW_ELEMENTS_STRIP_COMMAND_NV,
+            Token::DrawArraysStrip { .. } => gl::DRAW_ARRAYS_STRIP_COMMAND_NV,
+            Token::DrawElementsInstanced { .. } => gl::DRAW_ELEMENTS_INSTANCED_COMMAND_NV,
+            Token::DrawArraysInstanced { .. } => gl::DRAW_ARRAYS_INSTANCED_COMMAND_NV,
+            Token::ElementAddress { .. } => gl::ELEMENT_ADDRESS_COMMAND_NV,
+            Token::AttributeAddress { .. } => gl::ATTRIBUTE_ADDRESS_COMMAND_NV,
+            Token::UniformAddress { .. } => gl::UNIFORM_ADDRESS_COMMAND_NV,
+            Token::BlendColor { .. } => gl::BLEND_COLOR_COMMAND_NV,
+            Token::StencilRef { .. } => gl::STENCIL_REF_COMMAND_NV,
+            Token::LineWidth(_) => gl::LINE_WIDTH_COMMAND_NV,
+            Token::PolygonOffset { .. } => gl::POLYGON_OFFSET_COMMAND_NV,
+            Token::AlphaRef(_) => gl::ALPHA_REF_COMMAND_NV,
+            Token::Viewport { .. } => gl::VIEWPORT_COMMAND_NV,
+            Token::Scissor { .. } => gl::SCISSOR_COMMAND_NV,
+            Token::FrontFace(_) => gl::FRONT_FACE_COMMAND_NV,
+        }
+    }
+
+    /**
+     * Size of the encoded token in bytes, including the header
+     */
+    pub fn size(&self) -> usize {
+        Token::size_of_id(self.id())
+    }
+
+    fn size_of_id(id: gl::types::GLenum) -> usize {
+        let words = match id {
+            gl::TERMINATE_SEQUENCE_COMMAND_NV | gl::NOP_COMMAND_NV => 1,
+            gl::DRAW_ELEMENTS_COMMAND_NV | gl::DRAW_ELEMENTS_STRIP_COMMAND_NV => 4,
+            gl::DRAW_ARRAYS_COMMAND_NV | gl::DRAW_ARRAYS_STRIP_COMMAND_NV => 3,
+            gl::DRAW_ELEMENTS_INSTANCED_COMMAND_NV => 7,
+            gl::DRAW_ARRAYS_INSTANCED_COMMAND_NV => 6,
+            gl::ELEMENT_ADDRESS_COMMAND_NV
+                | gl::ATTRIBUTE_ADDRESS_COMMAND_NV
+                | gl::UNIFORM_ADDRESS_COMMAND_NV => 4,
+            gl::BLEND_COLOR_COMMAND_NV => 5,
+            gl::STENCIL_REF_COMMAND_NV | gl::POLYGON_OFFSET_COMMAND_NV => 3,
+            gl::LINE_WIDTH_COMMAND_NV | gl::ALPHA_REF_COMMAND_NV | gl::FRONT_FACE_COMMAND_NV => 2,
+            gl::VIEWPORT_COMMAND_NV | gl::SCISSOR_COMMAND_NV => 5,
+            _ => panic!("unknown command list token id 0x{:04X}", id),
+        };
+        words * 4
+    }
+
+    fn encode(&self, header: u32, out: &mut Vec<u8>) {
+        fn u32s(out: &mut Vec<u8>, values: &[u32]) {
+            for value in values {
+                out.extend_from_slice(&value.to_ne_bytes());
+            }
+        }
+        fn f32s(out: &mut Vec<u8>, values: &[f32]) {
+            for value in values {
+                out.extend_from_slice(&value.to_ne_bytes());
+            }
+        }
+        // addresses are split into two 32 bit words, low word first
+        fn address(out: &mut Vec<u8>, address: u64) {
+            u32s(out, &[address as u32, (address >> 32) as u32]);
+        }
+
+        let start = out.len();
+        u32s(out, &[header]);
+
+        match *self {
+            Token::TerminateSequence | Token::Nop => {},
+            Token::DrawElements { count, first_index, base_vertex }
+                | Token::DrawElementsStrip { count, first_index, base_vertex } =>
+                u32s(out, &[count, first_index, base_vertex]),
+            Token::DrawArrays { count, first } | Token::DrawArraysStrip { count, first } =>
+                u32s(out, &[count, first]),
+            Token::DrawElementsInstanced { mode, count, instance_count, first_index, base_vertex, base_instance } =>
+                u32s(out, &[mode, count, instance_count, first_index, base_vertex, base_instance]),
+            Token::DrawArraysInstanced { mode, count, instance_count, first, base_instance } =>
+                u32s(out, &[mode, count, instance_count, first, base_instance]),
+            Token::ElementAddress { address: element_address, type_size } => {
+                address(out, element_address);
+                u32s(out, &[type_size]);
+            },
+            Token::AttributeAddress { index, address: attribute_address } => {
+                u32s(out, &[index]);
+                address(out, attribute_address);
+            },
+            Token::UniformAddress { index, stage, address: uniform_address } => {
+                out.extend_from_slice(&index.to_ne_bytes());
+                out.extend_from_slice(&stage.to_ne_bytes());
+                address(out, uniform_address);
+            },
+            Token::BlendColor { red, green, blue, alpha } => f32s(out, &[red, green, blue, alpha]),
+            Token::StencilRef { front, back } => u32s(out, &[front, back]),
+            Token::LineWidth(width) => f32s(out, &[width]),
+            Token::PolygonOffset { scale, bias } => f32s(out, &[scale, bias]),
+            Token::AlphaRef(reference) => f32s(out, &[reference]),
+            Token::Viewport { x, y, width, height } | Token::Scissor { x, y, width, height } =>
+                u32s(out, &[x, y, width, height]),
+            Token::FrontFace(face) => u32s(out, &[match face {
+                FrontFace::Clockwise => 0,
+                FrontFace::CounterClockwise => 1,
+            }]),
+        }
+
+        debug_assert_eq!(out.len() - start, self.size());
+    }
+}
+
+/**
+ * The header word of every token type. Headers are driver specific and have to be queried
+ * with glGetCommandHeaderNV once per context.
+ */
+#[derive(Clone, Debug)]
+pub struct TokenHeaders {
+    headers: [u32; TOKEN_IDS],
+}
+
+impl TokenHeaders {
+    pub fn query(gl: &gl::Gl)
+    -> TokenHeaders
+    {
+        TokenHeaders::from_fn(|id, size| unsafe { gl.GetCommandHeaderNV(id, size) })
+    }
+
+    /**
+     * Builds the table from `header(token id, token size in bytes)`
+     */
+    pub fn from_fn<F>(header: F)
+    -> TokenHeaders
+        where F: Fn(gl::types::GLenum, u32) -> u32
+    {
+        let mut headers = [0; TOKEN_IDS];
+        for (id, slot) in headers.iter_mut().enumerate() {
+            let id = id as gl::types::GLenum;
+            *slot = header(id, Token::size_of_id(id) as u32);
+        }
+        TokenHeaders { headers }
+    }
+
+    pub fn header(&self, token: &Token) -> u32 {
+        self.headers[token.id() as usize]
+    }
+}
+
+/**
+ * Encoded tokens, ready to be handed to a command list
+ */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TokenStream {
+    bytes: Vec<u8>,
+}
+
+impl TokenStream {
+    pub fn new() -> TokenStream {
+        TokenStream::default()
+    }
+
+    pub fn encode(headers: &TokenHeaders, tokens: &[Token])
+    -> TokenStream
+    {
+        let mut stream = TokenStream::new();
+        for token in tokens {
+            stream.push(headers, token);
+        }
+        stream
+    }
+
+    pub fn push(&mut self, headers: &TokenHeaders, token: &Token) {
+        token.encode(headers.header(token), &mut self.bytes);
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+/**
+ * Tokens drawing a non indexed mesh from the vertex buffer at `vertex_address`.
+ * The vertex format itself comes from `StateObject::capture`, which gives every location
+ * its own binding, so the attribute offset is folded into the address of each location.
+ */
+pub fn mesh_tokens(layout: &VertexLayout, vertex_address: u64, first: u32, count: u32)
+-> Vec<Token>
+{
+    let mut tokens: Vec<Token> = layout.attributes.iter()
+        .map(|attribute| Token::AttributeAddress {
+            index: attribute.location as u32,
+            address: vertex_address + attribute.offset as u64,
+        })
+        .collect();
+    tokens.push(Token::DrawArrays { count, first });
+    tokens
+}
+
+// -----------------------------------------------------------------
+
+/**
+ * Captured program, vertex format, framebuffer formats and render state for a primitive type
+ */
+pub struct StateObject {
+    gl: gl::Gl,
+    id: gl::types::GLuint,
+    formats: VertexArray,   // the vertex format as the tokens address it, see `capture`
+}
+
+impl StateObject {
+    pub fn new(gl: &gl::Gl)
+    -> StateObject
+    {
+        let mut id: gl::types::GLuint = 0;
+        unsafe {
+            gl.CreateStatesNV(1, &mut id);
+        }
+
+        StateObject {
+            gl: gl.clone(),
+            id,
+            formats: VertexArray::new(gl),
+        }
+    }
+
+    /**
+     * Captures the currently bound program, framebuffer and render state for drawing
+     * `basic_mode` primitives (eg. gl::TRIANGLES) of vertices in `layout`.
+     *
+     * Tokens hand over vertex buffers by address, so the vertex format is captured from a
+     * vertex array of the state object's own with unified vertex memory enabled, one binding
+     * per location as `mesh_tokens` addresses them. The bound vertex array is left alone and
+     * the unified client states are turned off again afterwards, the regular draw path
+     * would read addresses instead of buffers otherwise.
+     */
+    pub fn capture(&self, basic_mode: gl::types::GLenum, layout: &VertexLayout)
+    {
+        let mut previous: gl::types::GLint = 0;
+        unsafe {
+            self.gl.GetIntegerv(gl::VERTEX_ARRAY_BINDING, &mut previous);
+        }
+        self.formats.bind();
+
+        for attribute in layout.attributes.iter() {
+            let index = attribute.location as gl::types::GLuint;
+            unsafe {
+                match attribute.kind {
+                    AttribKind::Float { normalized } => self.gl.VertexAttribFormat(
+                        index,
+                        attribute.components,
+                        attribute.data_type,
+                        normalized as gl::types::GLboolean,
+                        0,                              // the offset is part of the address
+                    ),
+                    AttribKind::Integer => self.gl.VertexAttribIFormat(index, attribute.components, attribute.data_type, 0),
+                    AttribKind::Double => self.gl.VertexAttribLFormat(index, attribute.components, attribute.data_type, 0),
+                }
+                self.gl.VertexAttribBinding(index, index);
+                self.gl.BindVertexBuffer(index, 0, 0, layout.stride as gl::types::GLsizei);
+                self.gl.EnableVertexAttribArray(index);
+            }
+        }
+
+        unsafe {
+            self.gl.EnableClientState(gl::VERTEX_ATTRIB_ARRAY_UNIFIED_NV);
+            self.gl.EnableClientState(gl::ELEMENT_ARRAY_UNIFIED_NV);
+            self.gl.StateCaptureNV(self.id, basic_mode);
+            self.gl.DisableClientState(gl::VERTEX_ATTRIB_ARRAY_UNIFIED_NV);
+            self.gl.DisableClientState(gl::ELEMENT_ARRAY_UNIFIED_NV);
+        }
+        self.gl.bind_vertex_array(previous as gl::types::GLuint);
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+}
+
+impl Drop for StateObject {
+    fn drop(&mut self)
+    {
+        unsafe {
+            self.gl.DeleteStatesNV(1, &self.id);
+        }
+    }
+}
+
+/**
+ * Tokens drawn with one state object into one framebuffer (0 for the default framebuffer)
+ */
+pub struct CommandSequence<'a> {
+    pub tokens: &'a TokenStream,
+    pub state: &'a StateObject,
+    pub framebuffer: gl::types::GLuint,
+}
+
+/**
+ * A compiled, immutable command list
+ */
+pub struct CommandList {
+    gl: gl::Gl,
+    id: gl::types::GLuint,
+}
+
+impl CommandList {
+    /**
+     * Records `sequences` into a single segment and compiles the list.
+     * The token streams are copied, the state objects have to outlive the list.
+     */
+    pub fn compile(gl: &gl::Gl, sequences: &[CommandSequence])
+    -> CommandList
+    {
+        let mut id: gl::types::GLuint = 0;
+
+        let indirects: Vec<*const c_void> = sequences.iter()
+            .map(|sequence| sequence.tokens.as_bytes().as_ptr() as *const c_void)
+            .collect();
+        let sizes: Vec<gl::types::GLsizei> = sequences.iter()
+            .map(|sequence| sequence.tokens.len() as gl::types::GLsizei)
+            .collect();
+        let states: Vec<gl::types::GLuint> = sequences.iter().map(|sequence| sequence.state.id()).collect();
+        let framebuffers: Vec<gl::types::GLuint> = sequences.iter().map(|sequence| sequence.framebuffer).collect();
+
+        unsafe {
+            gl.CreateCommandListsNV(1, &mut id);
+            gl.CommandListSegmentsNV(id, 1);
+            gl.ListDrawCommandsStatesClientNV(
+                id,
+                0, // segment
+                indirects.as_ptr(),
+                sizes.as_ptr(),
+                states.as_ptr(),
+                framebuffers.as_ptr(),
+                sequences.len() as gl::types::GLuint
+            );
+            gl.CompileCommandListNV(id);
+        }
+
+        CommandList {
+            gl: gl.clone(),
+            id,
+        }
+    }
+
+    /**
+     * Executes the list. The bindings it draws with are restored to unknown afterwards,
+     * since the driver may leave any of them changed.
+     */
+    pub fn call(&self)
+    {
+        unsafe {
+            self.gl.CallCommandListNV(self.id);
+        }
+        self.gl.invalidate_state_cache();
+    }
+}
+
+impl Drop for CommandList {
+    fn drop(&mut self)
+    {
+        unsafe {
+            self.gl.DeleteCommandListsNV(1, &self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gl::recording::{ Arg, Recorder };
+    use crate::render_gl::data;
+    use crate::render_gl::vertex_layout::VertexAttribute;
+
+    /**
+     * Fake headers that carry the token id and size, so tests can check them
+     */
+    fn headers() -> TokenHeaders {
+        TokenHeaders::from_fn(|id, size| 0xAB00_0000 | (size << 8) | id)
+    }
+
+    fn words(stream: &TokenStream) -> Vec<u32> {
+        stream.as_bytes()
+            .chunks(4)
+            .map(|word| u32::from_ne_bytes([word[0], word[1], word[2], word[3]]))
+            .collect()
+    }
+
+    #[test]
+    fn draw_tokens_follow_the_spec_layout() {
+        let headers = headers();
+        let stream = TokenStream::encode(&headers, &[
+            Token::DrawArrays { count: 3, first: 1 },
+            Token::DrawElements { count: 6, first_index: 2, base_vertex: 4 },
+            Token::DrawArraysInstanced { mode: gl::TRIANGLES, count: 3, instance_count: 10, first: 0, base_instance: 5 },
+            Token::TerminateSequence,
+        ]);
+
+        assert_eq!(words(&stream), vec![
+            0xAB00_0C03, 3, 1,
+            0xAB00_1002, 6, 2, 4,
+            0xAB00_1807, gl::TRIANGLES, 3, 10, 0, 5,
+            0xAB00_0400,
+        ]);
+    }
+
+    #[test]
+    fn addresses_are_split_low_word_first() {
+        let headers = headers();
+        let stream = TokenStream::encode(&headers, &[
+            Token::AttributeAddress { index: 1, address: 0x1234_5678_9ABC_DEF0 },
+            Token::ElementAddress { address: 0x0000_0001_0000_0002, type_size: 2 },
+        ]);
+
+        assert_eq!(words(&stream), vec![
+            0xAB00_1009, 1, 0x9ABC_DEF0, 0x1234_5678,
+            0xAB00_1008, 2, 1, 2,
+        ]);
+    }
+
+    #[test]
+    fn uniform_address_packs_index_and_stage_into_one_word() {
+        let stream = TokenStream::encode(&headers(), &[
+            Token::UniformAddress { index: 3, stage: 1, address: 0xFFFF_0000_0000_0010 },
+        ]);
+
+        let bytes = stream.as_bytes();
+        assert_eq!(stream.len(), 16);
+        assert_eq!(u16::from_ne_bytes([bytes[4], bytes[5]]), 3);
+        assert_eq!(u16::from_ne_bytes([bytes[6], bytes[7]]), 1);
+        assert_eq!(&words(&stream)[2..], &[0x0000_0010, 0xFFFF_0000]);
+    }
+
+    #[test]
+    fn state_tokens_encode_floats_and_front_face() {
+        let stream = TokenStream::encode(&headers(), &[
+            Token::BlendColor { red: 1.0, green: 0.5, blue: 0.25, alpha: 0.0 },
+            Token::FrontFace(FrontFace::Clockwise),
+            Token::FrontFace(FrontFace::CounterClockwise),
+            Token::Viewport { x: 0, y: 0, width: 640, height: 480 },
+        ]);
+
+        assert_eq!(words(&stream), vec![
+            0xAB00_140B, 1.0f32.to_bits(), 0.5f32.to_bits(), 0.25f32.to_bits(), 0.0f32.to_bits(),
+            0xAB00_0812, 0,
+            0xAB00_0812, 1,
+            0xAB00_1410, 0, 0, 640, 480,
+        ]);
+    }
+
+    #[test]
+    fn every_token_encodes_to_its_size() {
+        let tokens = [
+            Token::Nop,
+            Token::DrawElementsStrip { count: 0, first_index: 0, base_vertex: 0 },
+            Token::DrawArraysStrip { count: 0, first: 0 },
+            Token::DrawElementsInstanced { mode: 0, count: 0, instance_count: 0, first_index: 0, base_vertex: 0, base_instance: 0 },
+            Token::StencilRef { front: 0, back: 0 },
+            Token::LineWidth(1.0),
+            Token::PolygonOffset { scale: 0.0, bias: 0.0 },
+            Token::AlphaRef(0.0),
+            Token::Scissor { x: 0, y: 0, width: 0, height: 0 },
+        ];
+        for token in tokens.iter() {
+            let stream = TokenStream::encode(&headers(), &[*token]);
+            assert_eq!(stream.len(), token.size(), "{:?}", token);
+            assert_eq!(words(&stream)[0], headers().header(token));
+        }
+    }
+
+    #[test]
+    fn mesh_tokens_address_every_attribute_then_draw() {
+        let layout = VertexLayout {
+            stride: 16,
+            attributes: vec![
+                VertexAttribute::of::<data::f32_f32_f32>(0, 0),
+                VertexAttribute::of::<data::u2_u10_u10_u10_rev_float>(1, 12),
+            ],
+        };
+
+        assert_eq!(mesh_tokens(&layout, 0x1000, 0, 3), vec![
+            Token::AttributeAddress { index: 0, address: 0x1000 },
+            Token::AttributeAddress { index: 1, address: 0x100C },
+            Token::DrawArrays { count: 3, first: 0 },
+        ]);
+    }
+
+    #[test]
+    fn headers_are_queried_with_token_sizes() {
+        let (gl, recorder) = Recorder::new();
+        recorder.set_return("GetCommandHeaderNV", 42);
+
+        let headers = TokenHeaders::query(&gl);
+
+        let calls = recorder.calls_to("GetCommandHeaderNV");
+        assert_eq!(calls.len(), TOKEN_IDS);
+        assert_eq!(calls[gl::DRAW_ARRAYS_COMMAND_NV as usize].args, vec![Arg::Int(gl::DRAW_ARRAYS_COMMAND_NV as i64), Arg::Int(12)]);
+        assert_eq!(headers.header(&Token::Nop), 42);
+    }
+
+    #[test]
+    fn unsupported_without_the_extensions() {
+        let (gl, recorder) = Recorder::new();
+        assert!(!command_lists_supported(&gl));
+
+        recorder.set_integer(gl::NUM_EXTENSIONS, 1);
+        recorder.set_return("GetStringi", b"GL_NV_command_list\0".as_ptr() as i64);
+        assert!(has_extension(&gl, "GL_NV_command_list"));
+        assert!(!has_extension(&gl, "GL_NV_command"));
+        assert!(!command_lists_supported(&gl));
+    }
+
+    #[test]
+    fn capture_sees_unified_formats_and_restores_the_vertex_array() {
+        let (gl, recorder) = Recorder::new();
+        let state = StateObject::new(&gl);
+        let layout = VertexLayout {
+            stride: 16,
+            attributes: vec![
+                VertexAttribute::of::<data::f32_f32_f32>(0, 0),
+                VertexAttribute::of::<data::u2_u10_u10_u10_rev_float>(1, 12),
+            ],
+        };
+        recorder.set_integer(gl::VERTEX_ARRAY_BINDING, 7);
+        recorder.take_calls();
+
+        state.capture(gl::TRIANGLES, &layout);
+
+        let formats: Vec<(i64, i64)> = recorder.calls_to("VertexAttribFormat").iter()
+            .map(|call| (call.int(0), call.int(4)))
+            .collect();
+        assert_eq!(formats, vec![(0, 0), (1, 0)]);
+        let strides: Vec<i64> = recorder.calls_to("BindVertexBuffer").iter().map(|call| call.int(3)).collect();
+        assert_eq!(strides, vec![16, 16]);
+
+        let names: Vec<&str> = recorder.calls().iter()
+            .map(|call| call.name)
+            .filter(|name| name.ends_with("ClientState") || *name == "StateCaptureNV")
+            .collect();
+        assert_eq!(names, vec![
+            "EnableClientState",
+            "EnableClientState",
+            "StateCaptureNV",
+            "DisableClientState",
+            "DisableClientState",
+        ]);
+        assert_eq!(recorder.last_call_to("BindVertexArray").unwrap().int(0), 7);
+    }
+
+    #[test]
+    fn compile_lists_every_sequence() {
+        let (gl, recorder) = Recorder::new();
+        let state = StateObject::new(&gl);
+        let tokens = TokenStream::encode(&headers(), &[Token::DrawArrays { count: 3, first: 0 }]);
+
+        let list = CommandList::compile(&gl, &[
+            CommandSequence { tokens: &tokens, state: &state, framebuffer: 0 },
+        ]);
+        list.call();
+
+        let names: Vec<&str> = recorder.calls().iter().map(|call| call.name).collect();
+        assert_eq!(names, vec![
+            "CreateStatesNV",
+            "GenVertexArrays",
+            "CreateCommandListsNV",
+            "CommandListSegmentsNV",
+            "ListDrawCommandsStatesClientNV",
+            "CompileCommandListNV",
+            "CallCommandListNV",
+        ]);
+        let listed = recorder.last_call_to("ListDrawCommandsStatesClientNV").unwrap();
+        assert_eq!(listed.int(6), 1);
+    }
+}
@@ -0,0 +1,258 @@
+use gl;
+
+use super::texture::{ TextureFilter, TextureFormat };
+
+/**
+ * Looking out of the center of a cube: the direction and up vector of each face, in the
+ * order of TEXTURE_CUBE_MAP_POSITIVE_X + i. Cubemap faces are stored upside down, hence
+ * the negative up vectors on the side faces.
+ */
+pub const CUBE_FACES: [([f32; 3], [f32; 3]); 6] = [
+    ([1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ([-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, -1.0, 0.0], [0.0, 0.0, -1.0]),
+    ([0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+];
+
+/**
+ * View matrices from the origin towards each face, to render into them with a 90 degree
+ * square projection
+ */
+pub fn cube_face_views() -> [glm::Mat4; 6] {
+    let origin = glm::vec3(0.0, 0.0, 0.0);
+    let view = |(direction, up): ([f32; 3], [f32; 3])| glm::look_at(
+        &origin,
+        &glm::vec3(direction[0], direction[1], direction[2]),
+        &glm::vec3(up[0], up[1], up[2]),
+    );
+    [
+        view(CUBE_FACES[0]), view(CUBE_FACES[1]), view(CUBE_FACES[2]),
+        view(CUBE_FACES[3]), view(CUBE_FACES[4]), view(CUBE_FACES[5]),
+    ]
+}
+
+/**
+ * The projection that goes with `cube_face_views`
+ */
+pub fn cube_face_projection() -> glm::Mat4 {
+    glm::perspective(1.0, glm::half_pi(), 0.1, 10.0)
+}
+
+/**
+ * Number of mip levels down to 1x1 for a face of `size` texels
+ */
+pub fn mip_levels(size: i32) -> i32 {
+    32 - (size.max(1) as u32).leading_zeros() as i32
+}
+
+/**
+ * A cube map texture with square faces, sampled with a direction. Seams between faces are
+ * filtered across once `enable_seamless` has been called.
+ */
+pub struct Cubemap {
+    gl: gl::Gl,
+    id: gl::types::GLuint,
+    format: TextureFormat,
+    size: i32,
+    levels: i32,
+}
+
+impl Cubemap {
+    /**
+     * Allocates `levels` mip levels of all faces without data, 1 for no mipmaps
+     */
+    pub fn new(gl: &gl::Gl, size: i32, format: TextureFormat, levels: i32)
+    -> Cubemap
+    {
+        let mut id: gl::types::GLuint = 0;
+        unsafe {
+            gl.GenTextures(1, &mut id);
+        }
+        let cubemap = Cubemap { gl: gl.clone(), id, format, size, levels: levels.clamp(1, mip_levels(size)) };
+
+        cubemap.bind();
+        for level in 0..cubemap.levels {
+            let level_size = cubemap.level_size(level);
+            for face in 0..6 {
+                unsafe {
+                    gl.TexImage2D(
+                        gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                        level,
+                        format.internal_format() as gl::types::GLint,
+                        level_size,
+                        level_size,
+                        0,
+                        format.pixel_format(),
+                        format.pixel_type(),
+                        ::std::ptr::null(),
+                    );
+                }
+            }
+        }
+
+        let min_filter = if cubemap.levels > 1 { TextureFilter::LinearMipmapLinear } else { TextureFilter::Linear };
+        unsafe {
+            gl.TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER, min_filter.gl_enum() as gl::types::GLint);
+            gl.TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER, gl::LINEAR as gl::types::GLint);
+            gl.TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAX_LEVEL, cubemap.levels - 1);
+            for wrap in &[gl::TEXTURE_WRAP_S, gl::TEXTURE_WRAP_T, gl::TEXTURE_WRAP_R] {
+                gl.TexParameteri(gl::TEXTURE_CUBE_MAP, *wrap, gl::CLAMP_TO_EDGE as gl::types::GLint);
+            }
+        }
+        cubemap.unbind();
+
+        cubemap
+    }
+
+    /**
+     * A cube map with pixels for each face, in the order of `CUBE_FACES`. `faces` are in the
+     * pixel format and type of `format`, top row first as cube map faces want. The mip
+     * levels are generated, so it can be sampled for rough reflections.
+     */
+    pub fn from_faces<T>(gl: &gl::Gl, size: i32, format: TextureFormat, faces: [&[T]; 6])
+    -> Cubemap
+    {
+        let cubemap = Cubemap::new(gl, size, format, mip_levels(size));
+
+        cubemap.bind();
+        for (face, data) in faces.iter().enumerate() {
+            assert!(!data.is_empty(), "cube map faces can not be empty");
+            unsafe {
+                gl.PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+                gl.TexSubImage2D(
+                    gl::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32,
+                    0,
+                    0,
+                    0,
+                    size,
+                    size,
+                    format.pixel_format(),
+                    format.pixel_type(),
+                    data.as_ptr() as *const gl::types::GLvoid,
+                );
+            }
+        }
+        cubemap.unbind();
+        cubemap.generate_mipmaps();
+
+        cubemap
+    }
+
+    /**
+     * Filters across face edges when sampling any cube map, a global switch in gl
+     */
+    pub fn enable_seamless(gl: &gl::Gl) {
+        gl.set_capability(gl::TEXTURE_CUBE_MAP_SEAMLESS, true);
+    }
+
+    pub fn bind(&self) {
+        self.gl.bind_texture(gl::TEXTURE_CUBE_MAP, self.id);
+    }
+
+    pub fn bind_to_unit(&self, unit: u32) {
+        self.gl.bind_texture_to_unit(unit, gl::TEXTURE_CUBE_MAP, self.id);
+    }
+
+    pub fn unbind(&self) {
+        self.gl.bind_texture(gl::TEXTURE_CUBE_MAP, 0);
+    }
+
+    /**
+     * Fills the mip levels below the first from it
+     */
+    pub fn generate_mipmaps(&self) {
+        self.bind();
+        unsafe {
+            self.gl.GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+        }
+        self.unbind();
+    }
+
+    /**
+     * Attaches one face of one mip level as color attachment 0 of the bound framebuffer
+     */
+    pub fn attach_face(&self, face: u32, level: i32) {
+        unsafe {
+            self.gl.FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                self.id,
+                level,
+            );
+        }
+    }
+
+    pub fn level_size(&self, level: i32) -> i32 {
+        (self.size >> level).max(1)
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+
+    pub fn format(&self) -> TextureFormat {
+        self.format
+    }
+
+    pub fn size(&self) -> i32 {
+        self.size
+    }
+
+    pub fn levels(&self) -> i32 {
+        self.levels
+    }
+}
+
+impl Drop for Cubemap {
+    fn drop(&mut self)
+    {
+        self.gl.forget_texture(self.id);
+        unsafe {
+            self.gl.DeleteTextures(1, &self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gl::recording::Recorder;
+
+    #[test]
+    fn face_views_look_down_their_axis() {
+        for (view, (direction, _)) in cube_face_views().iter().zip(CUBE_FACES.iter()) {
+            let direction = glm::vec4(direction[0], direction[1], direction[2], 0.0);
+            // the camera looks down -z in view space
+            assert!((view * direction - glm::vec4(0.0, 0.0, -1.0, 0.0)).norm() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn every_level_of_every_face_is_allocated() {
+        assert_eq!(mip_levels(1), 1);
+        assert_eq!(mip_levels(128), 8);
+        assert_eq!(mip_levels(100), 7);
+
+        let (gl, recorder) = Recorder::new();
+        let cubemap = Cubemap::new(&gl, 16, TextureFormat::Rgba16F, 3);
+        assert_eq!(recorder.count("TexImage2D"), 18);
+        assert_eq!(cubemap.level_size(2), 4);
+        assert_eq!(Cubemap::new(&gl, 4, TextureFormat::Rgba16F, 10).levels(), 3);
+    }
+
+    #[test]
+    fn faces_are_uploaded_in_order_with_mipmaps() {
+        let (gl, recorder) = Recorder::new();
+        let pixels = [0u8; 4 * 4 * 4];
+        let cubemap = Cubemap::from_faces(&gl, 4, TextureFormat::Rgba8, [&pixels[..]; 6]);
+
+        let targets: Vec<i64> = recorder.calls_to("TexSubImage2D").iter().map(|call| call.int(0)).collect();
+        let expected: Vec<i64> = (0..6).map(|face| (gl::TEXTURE_CUBE_MAP_POSITIVE_X + face) as i64).collect();
+        assert_eq!(targets, expected);
+        assert_eq!(recorder.count("GenerateMipmap"), 1);
+        assert_eq!(cubemap.levels(), 3);
+    }
+}
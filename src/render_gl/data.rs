@@ -1,6 +1,57 @@
 #![allow(non_camel_case_types)]
 
 use gl;
+use super::vertex_layout::AttribKind;
+use super::vertex_layout::AttribKind::*;
+
+/**
+ * Implemented by every type in this module so that `#[derive(VertexAttribPointers)]`
+ * can set up a field, and describe it in a `VertexLayout`, without knowing its concrete type.
+ * The constants mirror the arguments passed to gl in `vertex_attrib_pointer`.
+ */
+pub trait VertexAttribPointer {
+    /// Number of consecutive shader locations the attribute consumes (dvec3 and dvec4 take two)
+    const SLOTS: usize = 1;
+    /// The number of components per generic vertex attribute
+    const COMPONENTS: i32;
+    /// Data type of each component, eg. gl::FLOAT
+    const DATA_TYPE: gl::types::GLenum;
+    /// How the shader sees the attribute
+    const KIND: AttribKind;
+
+    /// # Safety
+    /// A vertex array and the array buffer holding the vertices must be bound
+    unsafe fn vertex_attrib_pointer(
+        gl: &gl::Gl,
+        stride: usize,
+        location: usize,
+        offset: usize,
+    );
+}
+
+macro_rules! impl_vertex_attrib_pointer {
+    ($($t:ident => ($components:expr, $data_type:expr, $kind:expr $(, slots = $slots:expr)?)),* $(,)?) => {
+        $(
+            impl VertexAttribPointer for $t {
+                $(const SLOTS: usize = $slots;)?
+                const COMPONENTS: i32 = $components;
+                const DATA_TYPE: gl::types::GLenum = $data_type;
+                const KIND: AttribKind = $kind;
+
+                unsafe fn vertex_attrib_pointer(
+                    gl: &gl::Gl,
+                    stride: usize,
+                    location: usize,
+                    offset: usize,
+                ) {
+                    $t::vertex_attrib_pointer(gl, stride, location, offset)
+                }
+            }
+        )*
+    }
+}
+
+// -----------------------------------------
 
 #[derive(Copy, Clone, Debug)]
 #[repr(C, packed)]
@@ -2489,3 +2540,74 @@ impl u10_u11_u11_rev_float {
         );
     }
 }
+
+// -----------------------------------------
+
+impl_vertex_attrib_pointer! {
+    i8_ => (1, gl::BYTE, Integer),
+    i8_i8 => (2, gl::BYTE, Integer),
+    i8_i8_i8 => (3, gl::BYTE, Integer),
+    i8_i8_i8_i8 => (4, gl::BYTE, Integer),
+    i8_float => (1, gl::BYTE, Float { normalized: true }),
+    i8_i8_float => (2, gl::BYTE, Float { normalized: true }),
+    i8_i8_i8_float => (3, gl::BYTE, Float { normalized: true }),
+    i8_i8_i8_i8_float => (4, gl::BYTE, Float { normalized: true }),
+    i16_ => (1, gl::SHORT, Integer),
+    i16_i16 => (2, gl::SHORT, Integer),
+    i16_i16_i16 => (3, gl::SHORT, Integer),
+    i16_i16_i16_i16 => (4, gl::SHORT, Integer),
+    i16_float => (1, gl::SHORT, Float { normalized: true }),
+    i16_i16_float => (2, gl::SHORT, Float { normalized: true }),
+    i16_i16_i16_float => (3, gl::SHORT, Float { normalized: true }),
+    i16_i16_i16_i16_float => (4, gl::SHORT, Float { normalized: true }),
+    i32_ => (1, gl::INT, Integer),
+    i32_i32 => (2, gl::INT, Integer),
+    i32_i32_i32 => (3, gl::INT, Integer),
+    i32_i32_i32_i32 => (4, gl::INT, Integer),
+    i32_float => (1, gl::INT, Float { normalized: true }),
+    i32_i32_float => (2, gl::INT, Float { normalized: true }),
+    i32_i32_i32_float => (3, gl::INT, Float { normalized: true }),
+    i32_i32_i32_i32_float => (4, gl::INT, Float { normalized: true }),
+    u8_ => (1, gl::UNSIGNED_BYTE, Integer),
+    u8_u8 => (2, gl::UNSIGNED_BYTE, Integer),
+    u8_u8_u8 => (3, gl::UNSIGNED_BYTE, Integer),
+    u8_u8_u8_u8 => (4, gl::UNSIGNED_BYTE, Integer),
+    u8_float => (1, gl::UNSIGNED_BYTE, Float { normalized: true }),
+    u8_u8_float => (2, gl::UNSIGNED_BYTE, Float { normalized: true }),
+    u8_u8_u8_float => (3, gl::UNSIGNED_BYTE, Float { normalized: true }),
+    u8_u8_u8_u8_float => (4, gl::UNSIGNED_BYTE, Float { normalized: true }),
+    u16_ => (1, gl::UNSIGNED_SHORT, Integer),
+    u16_u16 => (2, gl::UNSIGNED_SHORT, Integer),
+    u16_u16_u16 => (3, gl::UNSIGNED_SHORT, Integer),
+    u16_u16_u16_u16 => (4, gl::UNSIGNED_SHORT, Integer),
+    u16_float => (1, gl::UNSIGNED_SHORT, Float { normalized: true }),
+    u16_u16_float => (2, gl::UNSIGNED_SHORT, Float { normalized: true }),
+    u16_u16_u16_float => (3, gl::UNSIGNED_SHORT, Float { normalized: true }),
+    u16_u16_u16_u16_float => (4, gl::UNSIGNED_SHORT, Float { normalized: true }),
+    u32_ => (1, gl::UNSIGNED_INT, Integer),
+    u32_u32 => (2, gl::UNSIGNED_INT, Integer),
+    u32_u32_u32 => (3, gl::UNSIGNED_INT, Integer),
+    u32_u32_u32_u32 => (4, gl::UNSIGNED_INT, Integer),
+    u32_float => (1, gl::UNSIGNED_INT, Float { normalized: true }),
+    u32_u32_float => (2, gl::UNSIGNED_INT, Float { normalized: true }),
+    u32_u32_u32_float => (3, gl::UNSIGNED_INT, Float { normalized: true }),
+    u32_u32_u32_u32_float => (4, gl::UNSIGNED_INT, Float { normalized: true }),
+    f16_ => (1, gl::HALF_FLOAT, Float { normalized: false }),
+    f16_f16 => (2, gl::HALF_FLOAT, Float { normalized: false }),
+    f16_f16_f16 => (3, gl::HALF_FLOAT, Float { normalized: false }),
+    f16_f16_f16_f16 => (4, gl::HALF_FLOAT, Float { normalized: false }),
+    f32_ => (1, gl::FLOAT, Float { normalized: false }),
+    f32_f32 => (2, gl::FLOAT, Float { normalized: false }),
+    f32_f32_f32 => (3, gl::FLOAT, Float { normalized: false }),
+    f32_f32_f32_f32 => (4, gl::FLOAT, Float { normalized: false }),
+    f64_ => (1, gl::DOUBLE, Double),
+    f64_f64 => (2, gl::DOUBLE, Double),
+    f64_f64_f64 => (3, gl::DOUBLE, Double, slots = 2),
+    f64_f64_f64_f64 => (4, gl::DOUBLE, Double, slots = 2),
+    i2_i10_i10_i10_rev => (4, gl::INT_2_10_10_10_REV, Float { normalized: false }),
+    u2_u10_u10_u10_rev => (4, gl::UNSIGNED_INT_2_10_10_10_REV, Float { normalized: false }),
+    u10_u11_u11_rev => (3, gl::UNSIGNED_INT_10F_11F_11F_REV, Float { normalized: false }),
+    i2_i10_i10_i10_rev_float => (4, gl::INT_2_10_10_10_REV, Float { normalized: true }),
+    u2_u10_u10_u10_rev_float => (4, gl::UNSIGNED_INT_2_10_10_10_REV, Float { normalized: true }),
+    u10_u11_u11_rev_float => (3, gl::UNSIGNED_INT_10F_11F_11F_REV, Float { normalized: true }),
+}
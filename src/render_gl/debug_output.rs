@@ -0,0 +1,398 @@
+use gl;
+use log::Level;
+use std::ffi::{ CStr, CString };
+use std::fmt;
+use std::os::raw::c_void;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "The gl context does not support debug output (KHR_debug)")]
+    NotSupported,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DebugSource {
+    Api,
+    WindowSystem,
+    ShaderCompiler,
+    ThirdParty,
+    Application,
+    Other,
+}
+
+impl DebugSource {
+    pub fn from_gl(source: gl::types::GLenum) -> DebugSource {
+        match source {
+            gl::DEBUG_SOURCE_API => DebugSource::Api,
+            gl::DEBUG_SOURCE_WINDOW_SYSTEM => DebugSource::WindowSystem,
+            gl::DEBUG_SOURCE_SHADER_COMPILER => DebugSource::ShaderCompiler,
+            gl::DEBUG_SOURCE_THIRD_PARTY => DebugSource::ThirdParty,
+            gl::DEBUG_SOURCE_APPLICATION => DebugSource::Application,
+            _ => DebugSource::Other,
+        }
+    }
+
+    pub fn gl_enum(&self) -> gl::types::GLenum {
+        match self {
+            DebugSource::Api => gl::DEBUG_SOURCE_API,
+            DebugSource::WindowSystem => gl::DEBUG_SOURCE_WINDOW_SYSTEM,
+            DebugSource::ShaderCompiler => gl::DEBUG_SOURCE_SHADER_COMPILER,
+            DebugSource::ThirdParty => gl::DEBUG_SOURCE_THIRD_PARTY,
+            DebugSource::Application => gl::DEBUG_SOURCE_APPLICATION,
+            DebugSource::Other => gl::DEBUG_SOURCE_OTHER,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DebugType {
+    Error,
+    DeprecatedBehavior,
+    UndefinedBehavior,
+    Portability,
+    Performance,
+    Marker,
+    PushGroup,
+    PopGroup,
+    Other,
+}
+
+impl DebugType {
+    pub fn from_gl(kind: gl::types::GLenum) -> DebugType {
+        match kind {
+            gl::DEBUG_TYPE_ERROR => DebugType::Error,
+            gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR => DebugType::DeprecatedBehavior,
+            gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR => DebugType::UndefinedBehavior,
+            gl::DEBUG_TYPE_PORTABILITY => DebugType::Portability,
+            gl::DEBUG_TYPE_PERFORMANCE => DebugType::Performance,
+            gl::DEBUG_TYPE_MARKER => DebugType::Marker,
+            gl::DEBUG_TYPE_PUSH_GROUP => DebugType::PushGroup,
+            gl::DEBUG_TYPE_POP_GROUP => DebugType::PopGroup,
+            _ => DebugType::Other,
+        }
+    }
+
+    pub fn gl_enum(&self) -> gl::types::GLenum {
+        match self {
+            DebugType::Error => gl::DEBUG_TYPE_ERROR,
+            DebugType::DeprecatedBehavior => gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR,
+            DebugType::UndefinedBehavior => gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR,
+            DebugType::Portability => gl::DEBUG_TYPE_PORTABILITY,
+            DebugType::Performance => gl::DEBUG_TYPE_PERFORMANCE,
+            DebugType::Marker => gl::DEBUG_TYPE_MARKER,
+            DebugType::PushGroup => gl::DEBUG_TYPE_PUSH_GROUP,
+            DebugType::PopGroup => gl::DEBUG_TYPE_POP_GROUP,
+            DebugType::Other => gl::DEBUG_TYPE_OTHER,
+        }
+    }
+}
+
+/**
+ * Ordered from least to most severe, so severities can be compared against a threshold
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DebugSeverity {
+    Notification,
+    Low,
+    Medium,
+    High,
+}
+
+impl DebugSeverity {
+    pub fn from_gl(severity: gl::types::GLenum) -> DebugSeverity {
+        match severity {
+            gl::DEBUG_SEVERITY_HIGH => DebugSeverity::High,
+            gl::DEBUG_SEVERITY_MEDIUM => DebugSeverity::Medium,
+            gl::DEBUG_SEVERITY_LOW => DebugSeverity::Low,
+            _ => DebugSeverity::Notification,
+        }
+    }
+
+    pub fn gl_enum(&self) -> gl::types::GLenum {
+        match self {
+            DebugSeverity::High => gl::DEBUG_SEVERITY_HIGH,
+            DebugSeverity::Medium => gl::DEBUG_SEVERITY_MEDIUM,
+            DebugSeverity::Low => gl::DEBUG_SEVERITY_LOW,
+            DebugSeverity::Notification => gl::DEBUG_SEVERITY_NOTIFICATION,
+        }
+    }
+
+    /**
+     * The log level messages of this severity are logged at
+     */
+    pub fn log_level(&self) -> Level {
+        match self {
+            DebugSeverity::High => Level::Error,
+            DebugSeverity::Medium => Level::Warn,
+            DebugSeverity::Low => Level::Info,
+            DebugSeverity::Notification => Level::Debug,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DebugMessage {
+    pub source: DebugSource,
+    pub kind: DebugType,
+    pub id: gl::types::GLuint,
+    pub severity: DebugSeverity,
+    pub message: String,
+}
+
+impl fmt::Display for DebugMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} {:?} #{}: {}", self.source, self.kind, self.id, self.message)
+    }
+}
+
+/**
+ * Which driver messages reach the log. Sources, types and severities are filtered by the
+ * driver with `glDebugMessageControl`, ids are filtered in the callback.
+ */
+#[derive(Clone, Debug)]
+pub struct DebugOutputConfig {
+    pub min_severity: DebugSeverity,
+    pub ignored_sources: Vec<DebugSource>,
+    pub ignored_types: Vec<DebugType>,
+    pub ignored_ids: Vec<gl::types::GLuint>,
+    pub panic_at: Option<DebugSeverity>,        // panic on messages at least this severe
+    pub synchronous: bool,                      // report messages inside the offending gl call
+}
+
+impl Default for DebugOutputConfig {
+    fn default() -> DebugOutputConfig {
+        DebugOutputConfig {
+            min_severity: DebugSeverity::Low,
+            ignored_sources: Vec::new(),
+            ignored_types: vec![DebugType::PushGroup, DebugType::PopGroup],
+            ignored_ids: Vec::new(),
+            panic_at: None,
+            synchronous: true,
+        }
+    }
+}
+
+impl DebugOutputConfig {
+    pub fn allows(&self, message: &DebugMessage) -> bool {
+        message.severity >= self.min_severity
+            && !self.ignored_sources.contains(&message.source)
+            && !self.ignored_types.contains(&message.kind)
+            && !self.ignored_ids.contains(&message.id)
+    }
+
+    pub fn panics_on(&self, message: &DebugMessage) -> bool {
+        self.panic_at.is_some_and(|severity| message.severity >= severity)
+    }
+}
+
+/**
+ * Routes `glDebugMessageCallback` messages into the `log` facade under the `gl` target.
+ * The context should be created with the debug flag, otherwise drivers may report little or nothing.
+ *
+ * Panicking on a message can not unwind through the driver, so it aborts the process after
+ * printing the message. With synchronous output the backtrace ends in the offending gl call.
+ *
+ * Debug output is switched off again when this is dropped.
+ */
+pub struct DebugOutput {
+    gl: gl::Gl,
+    config: Box<DebugOutputConfig>,
+}
+
+impl DebugOutput {
+    pub fn enable(gl: &gl::Gl, config: DebugOutputConfig)
+    -> Result<DebugOutput, Error>
+    {
+        if !gl.DebugMessageCallback.is_loaded() || !gl.DebugMessageControl.is_loaded() {
+            return Err(Error::NotSupported);
+        }
+
+        let mut context_flags: gl::types::GLint = 0;
+        unsafe {
+            gl.GetIntegerv(gl::CONTEXT_FLAGS, &mut context_flags);
+        }
+        if context_flags as gl::types::GLenum & gl::CONTEXT_FLAG_DEBUG_BIT == 0 {
+            warn!(target: "gl", "debug output enabled on a context without the debug flag");
+        }
+
+        let config = Box::new(config);
+
+        unsafe {
+            gl.Enable(gl::DEBUG_OUTPUT);
+            if config.synchronous {
+                gl.Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+            }
+
+            // everything on, then switch off what the config ignores
+            gl.DebugMessageControl(gl::DONT_CARE, gl::DONT_CARE, gl::DONT_CARE, 0, ::std::ptr::null(), gl::TRUE);
+
+            for severity in &[DebugSeverity::Notification, DebugSeverity::Low, DebugSeverity::Medium] {
+                if *severity < config.min_severity {
+                    gl.DebugMessageControl(gl::DONT_CARE, gl::DONT_CARE, severity.gl_enum(), 0, ::std::ptr::null(), gl::FALSE);
+                }
+            }
+            for source in &config.ignored_sources {
+                gl.DebugMessageControl(source.gl_enum(), gl::DONT_CARE, gl::DONT_CARE, 0, ::std::ptr::null(), gl::FALSE);
+            }
+            for kind in &config.ignored_types {
+                gl.DebugMessageControl(gl::DONT_CARE, kind.gl_enum(), gl::DONT_CARE, 0, ::std::ptr::null(), gl::FALSE);
+            }
+
+            gl.DebugMessageCallback(
+                debug_message_callback,
+                &*config as *const DebugOutputConfig as *const c_void  // the box keeps this address stable
+            );
+        }
+
+        Ok(DebugOutput {
+            gl: gl.clone(),
+            config,
+        })
+    }
+
+    pub fn config(&self) -> &DebugOutputConfig {
+        &self.config
+    }
+
+    /**
+     * Inserts an application message into the debug stream, eg. to mark where a frame starts
+     */
+    pub fn insert_message(&self, severity: DebugSeverity, message: &str)
+    {
+        let message = CString::new(message).unwrap_or_default();
+        unsafe {
+            self.gl.DebugMessageInsert(
+                gl::DEBUG_SOURCE_APPLICATION,
+                gl::DEBUG_TYPE_MARKER,
+                0,
+                severity.gl_enum(),
+                -1,                                 // null terminated
+                message.as_ptr()
+            );
+        }
+    }
+}
+
+impl Drop for DebugOutput {
+    fn drop(&mut self)
+    {
+        unsafe {
+            // the callback can't be unset, so no messages may reach it once the config is freed
+            self.gl.Disable(gl::DEBUG_OUTPUT);
+        }
+    }
+}
+
+extern "system" fn debug_message_callback(
+    source: gl::types::GLenum,
+    kind: gl::types::GLenum,
+    id: gl::types::GLuint,
+    severity: gl::types::GLenum,
+    _length: gl::types::GLsizei,
+    message: *const gl::types::GLchar,
+    user_param: *mut c_void,
+)
+{
+    if user_param.is_null() || message.is_null() {
+        return;
+    }
+    let config = unsafe { &*(user_param as *const DebugOutputConfig) };
+
+    let message = DebugMessage {
+        source: DebugSource::from_gl(source),
+        kind: DebugType::from_gl(kind),
+        id,
+        severity: DebugSeverity::from_gl(severity),
+        message: unsafe { CStr::from_ptr(message) }.to_string_lossy().trim_end().to_owned(),
+    };
+
+    if !config.allows(&message) {
+        return;
+    }
+
+    log!(target: "gl", message.severity.log_level(), "{}", message);
+
+    if config.panics_on(&message) {
+        panic!("gl debug message at {:?} severity: {}", message.severity, message);
+    }
+}
+
+/**
+ * Names a gl object (`identifier` is eg. gl::BUFFER or gl::PROGRAM) so driver messages and
+ * graphics debuggers refer to it by name. Does nothing when the driver lacks KHR_debug.
+ */
+pub fn label_object(gl: &gl::Gl, identifier: gl::types::GLenum, name: gl::types::GLuint, label: &str)
+{
+    if !gl.ObjectLabel.is_loaded() {
+        return;
+    }
+
+    unsafe {
+        gl.ObjectLabel(
+            identifier,
+            name,
+            label.len() as gl::types::GLsizei,
+            label.as_ptr() as *const gl::types::GLchar
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(severity: DebugSeverity, kind: DebugType, id: u32) -> DebugMessage {
+        DebugMessage { source: DebugSource::Api, kind, id, severity, message: String::new() }
+    }
+
+    #[test]
+    fn config_filters_by_severity_type_and_id() {
+        let config = DebugOutputConfig {
+            min_severity: DebugSeverity::Medium,
+            ignored_ids: vec![131185],
+            ..DebugOutputConfig::default()
+        };
+
+        assert!(config.allows(&message(DebugSeverity::High, DebugType::Error, 1)));
+        assert!(config.allows(&message(DebugSeverity::Medium, DebugType::Performance, 1)));
+        assert!(!config.allows(&message(DebugSeverity::Low, DebugType::Error, 1)));
+        assert!(!config.allows(&message(DebugSeverity::High, DebugType::PushGroup, 1)));
+        assert!(!config.allows(&message(DebugSeverity::High, DebugType::Error, 131185)));
+    }
+
+    #[test]
+    fn panics_only_at_or_above_threshold() {
+        let config = DebugOutputConfig { panic_at: Some(DebugSeverity::High), ..DebugOutputConfig::default() };
+        assert!(config.panics_on(&message(DebugSeverity::High, DebugType::Error, 0)));
+        assert!(!config.panics_on(&message(DebugSeverity::Medium, DebugType::Error, 0)));
+        assert!(!DebugOutputConfig::default().panics_on(&message(DebugSeverity::High, DebugType::Error, 0)));
+    }
+
+    #[test]
+    fn enable_sets_up_driver_side_filtering() {
+        let (gl, recorder) = gl::recording::Recorder::new();
+        let config = DebugOutputConfig {
+            min_severity: DebugSeverity::Medium,
+            ignored_sources: vec![DebugSource::ThirdParty],
+            ignored_types: Vec::new(),
+            ..DebugOutputConfig::default()
+        };
+
+        let output = DebugOutput::enable(&gl, config).unwrap();
+
+        let disabled: Vec<_> = recorder.calls_to("DebugMessageControl").iter()
+            .filter(|call| call.int(5) == gl::FALSE as i64)
+            .map(|call| (call.int(0) as u32, call.int(2) as u32))
+            .collect();
+        assert_eq!(disabled, vec![
+            (gl::DONT_CARE, gl::DEBUG_SEVERITY_NOTIFICATION),
+            (gl::DONT_CARE, gl::DEBUG_SEVERITY_LOW),
+            (gl::DEBUG_SOURCE_THIRD_PARTY, gl::DONT_CARE),
+        ]);
+        assert_eq!(recorder.count("DebugMessageCallback"), 1);
+
+        drop(output);
+        assert_eq!(recorder.last_call_to("Disable").unwrap().int(0), gl::DEBUG_OUTPUT as i64);
+        assert_eq!(recorder.count("DebugMessageCallback"), 1);
+    }
+}
@@ -0,0 +1,652 @@
+use gl;
+
+use super::texture::{ Texture, TextureFormat };
+use super::viewport::Viewport;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Framebuffer is incomplete ({}): {}", status, reason)]
+    Incomplete { status: &'static str, reason: &'static str },
+
+    #[fail(display = "Framebuffer attachment {} uses {:?} which can not be attached there", attachment, format)]
+    WrongAttachmentFormat { attachment: String, format: TextureFormat },
+
+    #[fail(display = "Framebuffer has {} color attachments but the driver supports {}", requested, supported)]
+    TooManyColorAttachments { requested: usize, supported: i32 },
+
+    #[fail(display = "Framebuffer size must be positive, got {}x{}", width, height)]
+    InvalidSize { width: i32, height: i32 },
+}
+
+/**
+ * Storage that can back a framebuffer attachment. Renderbuffers are cheaper when
+ * the result never has to be sampled, eg. the depth buffer of the main scene.
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AttachmentKind {
+    Texture,
+    Renderbuffer,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AttachmentDesc {
+    pub format: TextureFormat,
+    pub kind: AttachmentKind,
+}
+
+impl AttachmentDesc {
+    pub fn texture(format: TextureFormat) -> AttachmentDesc {
+        AttachmentDesc { format, kind: AttachmentKind::Texture }
+    }
+
+    pub fn renderbuffer(format: TextureFormat) -> AttachmentDesc {
+        AttachmentDesc { format, kind: AttachmentKind::Renderbuffer }
+    }
+}
+
+/**
+ * How big the framebuffer is. `Viewport` framebuffers follow the window around,
+ * scaled by the factor (0.5 renders at half resolution), and are recreated at the new size by `bind_for`.
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FramebufferSize {
+    Fixed { width: i32, height: i32 },
+    Viewport { scale: f32 },
+}
+
+impl FramebufferSize {
+    pub fn resolve(&self, viewport: &Viewport) -> (i32, i32) {
+        match *self {
+            FramebufferSize::Fixed { width, height } => (width, height),
+            FramebufferSize::Viewport { scale } => (
+                ((viewport.w as f32 * scale) as i32).max(1),
+                ((viewport.h as f32 * scale) as i32).max(1),
+            ),
+        }
+    }
+}
+
+/**
+ * Everything needed to (re)create a framebuffer. Color attachments are bound
+ * to `layout (location = N) out` of the fragment shader in order.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct FramebufferDesc {
+    pub size: FramebufferSize,
+    pub samples: i32,                               // 1 for no multisampling
+    pub color: Vec<AttachmentDesc>,
+    pub depth_stencil: Option<AttachmentDesc>,
+}
+
+impl FramebufferDesc {
+    /**
+     * A single RGBA8 texture with a depth renderbuffer, sized to the viewport
+     */
+    pub fn viewport_color_depth() -> FramebufferDesc {
+        FramebufferDesc {
+            size: FramebufferSize::Viewport { scale: 1.0 },
+            samples: 1,
+            color: vec![AttachmentDesc::texture(TextureFormat::Rgba8)],
+            depth_stencil: Some(AttachmentDesc::renderbuffer(TextureFormat::Depth24Stencil8)),
+        }
+    }
+
+    /**
+     * A half float RGBA texture with a depth renderbuffer, sized to the viewport, for scenes
+     * that are post-processed before they reach the window
+     */
+    pub fn viewport_hdr_depth() -> FramebufferDesc {
+        FramebufferDesc {
+            color: vec![AttachmentDesc::texture(TextureFormat::Rgba16F)],
+            ..FramebufferDesc::viewport_color_depth()
+        }
+    }
+}
+
+pub struct Renderbuffer {
+    gl: gl::Gl,
+    id: gl::types::GLuint,
+    format: TextureFormat,
+}
+
+impl Renderbuffer {
+    pub fn new(gl: &gl::Gl, width: i32, height: i32, format: TextureFormat, samples: i32)
+    -> Renderbuffer
+    {
+        let mut id: gl::types::GLuint = 0;
+        unsafe {
+            gl.GenRenderbuffers(1, &mut id);
+            gl.BindRenderbuffer(gl::RENDERBUFFER, id);
+            if samples > 1 {
+                gl.RenderbufferStorageMultisample(gl::RENDERBUFFER, samples, format.internal_format(), width, height);
+            } else {
+                gl.RenderbufferStorage(gl::RENDERBUFFER, format.internal_format(), width, height);
+            }
+            gl.BindRenderbuffer(gl::RENDERBUFFER, 0);
+        }
+
+        Renderbuffer {
+            gl: gl.clone(),
+            id,
+            format,
+        }
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+
+    pub fn format(&self) -> TextureFormat {
+        self.format
+    }
+}
+
+impl Drop for Renderbuffer {
+    fn drop(&mut self)
+    {
+        unsafe {
+            self.gl.DeleteRenderbuffers(1, &self.id);
+        }
+    }
+}
+
+pub enum Attachment {
+    Texture(Texture),
+    Renderbuffer(Renderbuffer),
+}
+
+impl Attachment {
+    fn new(gl: &gl::Gl, desc: &AttachmentDesc, width: i32, height: i32, samples: i32)
+    -> Attachment
+    {
+        match desc.kind {
+            AttachmentKind::Texture if samples > 1 => Attachment::Texture(
+                Texture::new_2d_multisample(gl, width, height, desc.format, samples)
+            ),
+            AttachmentKind::Texture => Attachment::Texture(
+                Texture::new_2d(gl, width, height, desc.format)
+            ),
+            AttachmentKind::Renderbuffer => Attachment::Renderbuffer(
+                Renderbuffer::new(gl, width, height, desc.format, samples)
+            ),
+        }
+    }
+
+    fn attach(&self, gl: &gl::Gl, attachment_point: gl::types::GLenum)
+    {
+        unsafe {
+            match self {
+                Attachment::Texture(texture) => gl.FramebufferTexture2D(
+                    gl::FRAMEBUFFER,
+                    attachment_point,
+                    texture.target(),
+                    texture.id(),
+                    0,                                  // mipmap level
+                ),
+                Attachment::Renderbuffer(renderbuffer) => gl.FramebufferRenderbuffer(
+                    gl::FRAMEBUFFER,
+                    attachment_point,
+                    gl::RENDERBUFFER,
+                    renderbuffer.id(),
+                ),
+            }
+        }
+    }
+
+    pub fn texture(&self) -> Option<&Texture> {
+        match self {
+            Attachment::Texture(texture) => Some(texture),
+            Attachment::Renderbuffer(_) => None,
+        }
+    }
+}
+
+/**
+ * An offscreen render target. Bind it, draw, then either sample its textures
+ * or blit it into another framebuffer (or the window with `blit_to_default`).
+ */
+pub struct Framebuffer {
+    gl: gl::Gl,
+    fbo: gl::types::GLuint,
+    desc: FramebufferDesc,
+    width: i32,
+    height: i32,
+    color: Vec<Attachment>,
+    depth_stencil: Option<Attachment>,
+}
+
+impl Framebuffer {
+    pub fn new(gl: &gl::Gl, desc: FramebufferDesc, viewport: &Viewport)
+    -> Result<Framebuffer, Error>
+    {
+        let (width, height) = desc.size.resolve(viewport);
+
+        let mut fbo: gl::types::GLuint = 0;
+        unsafe {
+            gl.GenFramebuffers(1, &mut fbo);
+        }
+
+        let mut framebuffer = Framebuffer {
+            gl: gl.clone(),
+            fbo,
+            desc,
+            width,
+            height,
+            color: Vec::new(),
+            depth_stencil: None,
+        };
+        framebuffer.create_attachments(width, height)?;
+
+        Ok(framebuffer)
+    }
+
+    fn create_attachments(&mut self, width: i32, height: i32)
+    -> Result<(), Error>
+    {
+        if width <= 0 || height <= 0 {
+            return Err(Error::InvalidSize { width, height });
+        }
+
+        let mut max_color_attachments: gl::types::GLint = 0;
+        unsafe {
+            self.gl.GetIntegerv(gl::MAX_COLOR_ATTACHMENTS, &mut max_color_attachments);
+        }
+        if self.desc.color.len() > max_color_attachments as usize {
+            return Err(Error::TooManyColorAttachments {
+                requested: self.desc.color.len(),
+                supported: max_color_attachments,
+            });
+        }
+
+        for (i, color) in self.desc.color.iter().enumerate() {
+            if !color.format.is_color() {
+                return Err(Error::WrongAttachmentFormat { attachment: format!("color {}", i), format: color.format });
+            }
+        }
+        if let Some(depth_stencil) = &self.desc.depth_stencil {
+            if depth_stencil.format.is_color() {
+                return Err(Error::WrongAttachmentFormat { attachment: "depth/stencil".into(), format: depth_stencil.format });
+            }
+        }
+
+        // dropping the old attachments deletes them
+        let samples = self.desc.samples;
+        self.color = self.desc.color.iter()
+            .map(|desc| Attachment::new(&self.gl, desc, width, height, samples))
+            .collect();
+        self.depth_stencil = self.desc.depth_stencil.as_ref()
+            .map(|desc| Attachment::new(&self.gl, desc, width, height, samples));
+        self.width = width;
+        self.height = height;
+
+        self.bind();
+
+        for (i, attachment) in self.color.iter().enumerate() {
+            attachment.attach(&self.gl, gl::COLOR_ATTACHMENT0 + i as gl::types::GLenum);
+        }
+
+        if let (Some(attachment), Some(desc)) = (&self.depth_stencil, &self.desc.depth_stencil) {
+            let attachment_point = match (desc.format.has_depth(), desc.format.has_stencil()) {
+                (true, true) => gl::DEPTH_STENCIL_ATTACHMENT,
+                (true, false) => gl::DEPTH_ATTACHMENT,
+                _ => gl::STENCIL_ATTACHMENT,
+            };
+            attachment.attach(&self.gl, attachment_point);
+        }
+
+        self.set_buffers();
+
+        let status = unsafe { self.gl.CheckFramebufferStatus(gl::FRAMEBUFFER) };
+        self.unbind();
+
+        check_status(status)
+    }
+
+    /**
+     * Draws into every color attachment and reads from the first, the buffers of the bound
+     * framebuffer object
+     */
+    fn set_buffers(&self)
+    {
+        let draw_buffers: Vec<gl::types::GLenum> = (0..self.color.len())
+            .map(|i| gl::COLOR_ATTACHMENT0 + i as gl::types::GLenum)
+            .collect();
+
+        unsafe {
+            if draw_buffers.is_empty() {
+                // depth only, eg. shadow maps
+                self.gl.DrawBuffer(gl::NONE);
+                self.gl.ReadBuffer(gl::NONE);
+            } else {
+                self.gl.DrawBuffers(draw_buffers.len() as gl::types::GLsizei, draw_buffers.as_ptr());
+                self.gl.ReadBuffer(gl::COLOR_ATTACHMENT0);
+            }
+        }
+    }
+
+    /**
+     * Recreates the attachments at the new viewport size when the framebuffer follows the viewport.
+     * `bind_for` does this on its own. Returns whether anything was recreated, in which case
+     * textures obtained from `color_texture` earlier are no longer valid.
+     */
+    pub fn resize_to_viewport(&mut self, viewport: &Viewport)
+    -> Result<bool, Error>
+    {
+        let (width, height) = self.desc.size.resolve(viewport);
+        if (width, height) == (self.width, self.height) {
+            return Ok(false);
+        }
+
+        self.create_attachments(width, height)?;
+        Ok(true)
+    }
+
+    /**
+     * Binds the framebuffer and sets the gl viewport to cover it, after taking on the size
+     * `Viewport::update_size` last gave `viewport` if the framebuffer follows it. Drawing
+     * through this keeps offscreen targets the size of the window without resize handlers.
+     */
+    pub fn bind_for(&mut self, viewport: &Viewport)
+    -> Result<(), Error>
+    {
+        self.resize_to_viewport(viewport)?;
+        self.bind();
+        self.set_viewport();
+        Ok(())
+    }
+
+    pub fn bind(&self)
+    {
+        unsafe {
+            self.gl.BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+        }
+    }
+
+    /**
+     * Binds the default (window) framebuffer again
+     */
+    pub fn unbind(&self)
+    {
+        unsafe {
+            self.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    /**
+     * Sets the gl viewport to cover the whole framebuffer. The window viewport
+     * has to be set again with `Viewport::set_used` after rendering offscreen.
+     */
+    pub fn set_viewport(&self)
+    {
+        unsafe {
+            self.gl.Viewport(0, 0, self.width, self.height);
+        }
+    }
+
+    /**
+     * Copies color (and depth / stencil when requested in `mask`) into `target`.
+     * This is how a multisampled framebuffer is resolved: blit it into a single sampled
+     * framebuffer of the same size. Color attachment `i` is copied into attachment `i`.
+     * Both framebuffers keep their own draw and read buffers.
+     */
+    pub fn blit_to(&self, target: &Framebuffer, mask: gl::types::GLbitfield)
+    {
+        let color_attachments = self.color.len().min(target.color.len()).max(1);
+        let filter = self.blit_filter(target.width, target.height, mask);
+
+        unsafe {
+            self.gl.BindFramebuffer(gl::READ_FRAMEBUFFER, self.fbo);
+            self.gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, target.fbo);
+
+            for i in 0..color_attachments {
+                // only the first pass copies depth / stencil, later ones are color only
+                let pass_mask = if i == 0 { mask } else { mask & gl::COLOR_BUFFER_BIT };
+                if pass_mask == 0 {
+                    break;
+                }
+
+                let attachment_point = gl::COLOR_ATTACHMENT0 + i as gl::types::GLenum;
+                self.gl.ReadBuffer(attachment_point);
+                self.gl.DrawBuffer(attachment_point);
+                self.gl.BlitFramebuffer(
+                    0, 0, self.width, self.height,
+                    0, 0, target.width, target.height,
+                    pass_mask,
+                    filter,
+                );
+            }
+
+            // the read and draw buffers belong to the framebuffer objects, put them back
+            self.bind();
+            self.set_buffers();
+            target.bind();
+            target.set_buffers();
+            self.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    /**
+     * Copies color attachment 0 into the window, stretched to `viewport`
+     */
+    pub fn blit_to_default(&self, viewport: &Viewport)
+    {
+        unsafe {
+            self.gl.BindFramebuffer(gl::READ_FRAMEBUFFER, self.fbo);
+            self.gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, 0);
+            self.gl.ReadBuffer(gl::COLOR_ATTACHMENT0);
+            self.gl.BlitFramebuffer(
+                0, 0, self.width, self.height,
+                viewport.x, viewport.y, viewport.x + viewport.w, viewport.y + viewport.h,
+                gl::COLOR_BUFFER_BIT,
+                self.blit_filter(viewport.w, viewport.h, gl::COLOR_BUFFER_BIT),
+            );
+            self.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    /**
+     * Depth / stencil blits and multisample resolves must use NEAREST, stretched color can be smoothed
+     */
+    fn blit_filter(&self, target_width: i32, target_height: i32, mask: gl::types::GLbitfield)
+    -> gl::types::GLenum
+    {
+        let same_size = (target_width, target_height) == (self.width, self.height);
+        if same_size || mask != gl::COLOR_BUFFER_BIT || self.desc.samples > 1 {
+            gl::NEAREST
+        } else {
+            gl::LINEAR
+        }
+    }
+
+    pub fn color_texture(&self, index: usize) -> Option<&Texture> {
+        self.color.get(index).and_then(Attachment::texture)
+    }
+
+    pub fn depth_stencil_texture(&self) -> Option<&Texture> {
+        self.depth_stencil.as_ref().and_then(Attachment::texture)
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.fbo
+    }
+
+    pub fn desc(&self) -> &FramebufferDesc {
+        &self.desc
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self)
+    {
+        unsafe {
+            self.gl.DeleteFramebuffers(1, &self.fbo);
+        }
+    }
+}
+
+pub(super) fn check_status(status: gl::types::GLenum)
+-> Result<(), Error>
+{
+    let (status, reason) = match status {
+        gl::FRAMEBUFFER_COMPLETE => return Ok(()),
+        gl::FRAMEBUFFER_UNDEFINED =>
+            ("GL_FRAMEBUFFER_UNDEFINED", "the default framebuffer does not exist"),
+        gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT =>
+            ("GL_FRAMEBUFFER_INCOMPLETE_ATTACHMENT", "an attachment is not renderable or has zero size"),
+        gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT =>
+            ("GL_FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT", "nothing is attached"),
+        gl::FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER =>
+            ("GL_FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER", "a draw buffer names an attachment point with nothing attached"),
+        gl::FRAMEBUFFER_INCOMPLETE_READ_BUFFER =>
+            ("GL_FRAMEBUFFER_INCOMPLETE_READ_BUFFER", "the read buffer names an attachment point with nothing attached"),
+        gl::FRAMEBUFFER_UNSUPPORTED =>
+            ("GL_FRAMEBUFFER_UNSUPPORTED", "the driver does not support this combination of formats"),
+        gl::FRAMEBUFFER_INCOMPLETE_MULTISAMPLE =>
+            ("GL_FRAMEBUFFER_INCOMPLETE_MULTISAMPLE", "attachments have different sample counts, or mix textures and renderbuffers when multisampling"),
+        gl::FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS =>
+            ("GL_FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS", "layered and non layered attachments are mixed"),
+        _ => ("unknown status", "the driver returned a status this code does not know about"),
+    };
+
+    Err(Error::Incomplete { status, reason })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gl::recording::Recorder;
+
+    fn recorder() -> (gl::Gl, Recorder) {
+        let (gl, recorder) = Recorder::new();
+        recorder.set_integer(gl::MAX_COLOR_ATTACHMENTS, 8);
+        (gl, recorder)
+    }
+
+    fn mrt(count: usize) -> FramebufferDesc {
+        FramebufferDesc {
+            color: vec![AttachmentDesc::texture(TextureFormat::Rgba16F); count],
+            ..FramebufferDesc::viewport_color_depth()
+        }
+    }
+
+    #[test]
+    fn attachments_are_created_at_their_points() {
+        let (gl, recorder) = recorder();
+        let framebuffer = Framebuffer::new(&gl, mrt(2), &Viewport::for_window(64, 32)).unwrap();
+        assert_eq!((framebuffer.width(), framebuffer.height()), (64, 32));
+
+        let textures: Vec<i64> = recorder.calls_to("FramebufferTexture2D").iter().map(|call| call.int(1)).collect();
+        assert_eq!(textures, vec![gl::COLOR_ATTACHMENT0 as i64, gl::COLOR_ATTACHMENT1 as i64]);
+        assert_eq!(recorder.last_call_to("FramebufferRenderbuffer").unwrap().int(1), gl::DEPTH_STENCIL_ATTACHMENT as i64);
+        assert_eq!(recorder.last_call_to("DrawBuffers").unwrap().int(0), 2);
+        assert_eq!(recorder.last_call_to("ReadBuffer").unwrap().int(0), gl::COLOR_ATTACHMENT0 as i64);
+    }
+
+    #[test]
+    fn depth_only_framebuffers_draw_nowhere() {
+        let (gl, recorder) = recorder();
+        let desc = FramebufferDesc {
+            size: FramebufferSize::Fixed { width: 16, height: 16 },
+            samples: 1,
+            color: Vec::new(),
+            depth_stencil: Some(AttachmentDesc::texture(TextureFormat::Depth32F)),
+        };
+        Framebuffer::new(&gl, desc, &Viewport::for_window(1, 1)).unwrap();
+
+        assert_eq!(recorder.last_call_to("FramebufferTexture2D").unwrap().int(1), gl::DEPTH_ATTACHMENT as i64);
+        assert_eq!(recorder.last_call_to("DrawBuffer").unwrap().int(0), gl::NONE as i64);
+        assert_eq!(recorder.last_call_to("ReadBuffer").unwrap().int(0), gl::NONE as i64);
+    }
+
+    #[test]
+    fn invalid_framebuffers_are_rejected() {
+        let (gl, recorder) = recorder();
+        let viewport = Viewport::for_window(64, 32);
+
+        recorder.set_return("CheckFramebufferStatus", gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT as i64);
+        match Framebuffer::new(&gl, mrt(1), &viewport) {
+            Err(Error::Incomplete { status, .. }) => assert_eq!(status, "GL_FRAMEBUFFER_INCOMPLETE_ATTACHMENT"),
+            other => panic!("expected an incomplete framebuffer, got {:?}", other.err()),
+        }
+        recorder.set_return("CheckFramebufferStatus", gl::FRAMEBUFFER_COMPLETE as i64);
+
+        match Framebuffer::new(&gl, mrt(9), &viewport) {
+            Err(Error::TooManyColorAttachments { requested: 9, supported: 8 }) => {},
+            other => panic!("expected too many color attachments, got {:?}", other.err()),
+        }
+
+        let depth_as_color = FramebufferDesc {
+            color: vec![AttachmentDesc::texture(TextureFormat::Depth24Stencil8)],
+            ..FramebufferDesc::viewport_color_depth()
+        };
+        match Framebuffer::new(&gl, depth_as_color, &viewport) {
+            Err(Error::WrongAttachmentFormat { attachment, .. }) => assert_eq!(attachment, "color 0"),
+            other => panic!("expected a wrong attachment format, got {:?}", other.err()),
+        }
+
+        let empty = FramebufferDesc {
+            size: FramebufferSize::Fixed { width: 0, height: 16 },
+            ..FramebufferDesc::viewport_color_depth()
+        };
+        match Framebuffer::new(&gl, empty, &viewport) {
+            Err(Error::InvalidSize { width: 0, height: 16 }) => {},
+            other => panic!("expected an invalid size, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn blits_leave_both_framebuffers_with_their_own_buffers() {
+        let (gl, recorder) = recorder();
+        let viewport = Viewport::for_window(64, 32);
+        let source = Framebuffer::new(&gl, mrt(1), &viewport).unwrap();
+        let target = Framebuffer::new(&gl, mrt(3), &viewport).unwrap();
+        recorder.take_calls();
+
+        source.blit_to(&target, gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        assert_eq!(recorder.count("BlitFramebuffer"), 1);
+
+        // the restore binds each framebuffer in turn and sets its buffers
+        let calls = recorder.calls();
+        let buffers_of = |fbo: gl::types::GLuint| {
+            let bound = calls.iter()
+                .rposition(|call| call.name == "BindFramebuffer" && call.int(0) == gl::FRAMEBUFFER as i64 && call.int(1) == fbo as i64)
+                .unwrap();
+            let draw = calls[bound..].iter().find(|call| call.name == "DrawBuffers").unwrap().int(0);
+            let read = calls[bound..].iter().find(|call| call.name == "ReadBuffer").unwrap().int(0);
+            (draw, read)
+        };
+        assert_eq!(buffers_of(source.id()), (1, gl::COLOR_ATTACHMENT0 as i64));
+        assert_eq!(buffers_of(target.id()), (3, gl::COLOR_ATTACHMENT0 as i64));
+        assert_eq!(recorder.last_call_to("BindFramebuffer").unwrap().int(1), 0);
+    }
+
+    #[test]
+    fn bind_for_follows_the_viewport_size() {
+        let (gl, recorder) = recorder();
+        let mut viewport = Viewport::for_window(64, 32);
+        let mut framebuffer = Framebuffer::new(&gl, FramebufferDesc {
+            size: FramebufferSize::Viewport { scale: 0.5 },
+            ..mrt(1)
+        }, &viewport).unwrap();
+        recorder.take_calls();
+
+        framebuffer.bind_for(&viewport).unwrap();
+        assert_eq!(recorder.count("FramebufferTexture2D"), 0);
+
+        viewport.update_size(128, 64);
+        framebuffer.bind_for(&viewport).unwrap();
+        assert_eq!((framebuffer.width(), framebuffer.height()), (64, 32));
+        assert_eq!(recorder.count("FramebufferTexture2D"), 1);
+        let set = recorder.last_call_to("Viewport").unwrap();
+        assert_eq!((set.int(2), set.int(3)), (64, 32));
+        assert_eq!(recorder.last_call_to("BindFramebuffer").unwrap().int(1), framebuffer.id() as i64);
+    }
+}
@@ -0,0 +1,202 @@
+/*!
+ * Radiance .hdr images, the usual format of high dynamic range environment panoramas.
+ *
+ * Pixels are stored as RGBE, 8 bit mantissas sharing an exponent, either flat or with the
+ * per channel run length encoding of newer writers. Only the common `-Y height +X width`
+ * orientation is read.
+ */
+use crate::resources::Resources;
+use crate::resources::Error as ResourcesError;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load resource {}", name)]
+    UnableToLoadResource { name: String, #[cause] error: ResourcesError },
+
+    #[fail(display = "Invalid hdr image {}: {}", name, message)]
+    Invalid { name: String, message: String },
+}
+
+/**
+ * Linear RGBA float pixels with alpha 1, top row first
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct HdrImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<f32>,
+}
+
+impl HdrImage {
+    pub fn from_res(res: &Resources, name: &str) -> Result<HdrImage, Error> {
+        let bytes = res.load_bytes(name)
+            .map_err(|error| Error::UnableToLoadResource { name: String::from(name), error })?;
+        HdrImage::decode(name, &bytes)
+    }
+
+    pub fn decode(name: &str, bytes: &[u8]) -> Result<HdrImage, Error> {
+        let invalid = |message: &str| Error::Invalid { name: String::from(name), message: String::from(message) };
+
+        let mut lines = Lines { bytes, position: 0 };
+        let magic = lines.next().ok_or_else(|| invalid("empty file"))?;
+        if !magic.starts_with("#?") {
+            return Err(invalid("missing #? signature"));
+        }
+        loop {
+            match lines.next() {
+                Some(line) if line.is_empty() => break,
+                Some(line) if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" => {
+                    return Err(invalid(&format!("unsupported {}", line)));
+                },
+                Some(_) => {},
+                None => return Err(invalid("header does not end")),
+            }
+        }
+
+        let resolution = lines.next().ok_or_else(|| invalid("missing resolution"))?;
+        let words: Vec<&str> = resolution.split_whitespace().collect();
+        let (height, width) = match words.as_slice() {
+            ["-Y", height, "+X", width] => (height.parse::<u32>().ok(), width.parse::<u32>().ok()),
+            _ => return Err(invalid(&format!("unsupported orientation \"{}\"", resolution))),
+        };
+        let (width, height) = match (width, height) {
+            (Some(width), Some(height)) if width > 0 && height > 0 => (width, height),
+            _ => return Err(invalid(&format!("invalid resolution \"{}\"", resolution))),
+        };
+
+        let mut data = &bytes[lines.position..];
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        let mut scanline = vec![0u8; width as usize * 4];
+        for _ in 0..height {
+            data = read_scanline(data, &mut scanline).map_err(|message| invalid(&message))?;
+            for rgbe in scanline.chunks(4) {
+                let [red, green, blue] = rgbe_to_rgb([rgbe[0], rgbe[1], rgbe[2], rgbe[3]]);
+                pixels.extend_from_slice(&[red, green, blue, 1.0]);
+            }
+        }
+        Ok(HdrImage { width, height, pixels })
+    }
+}
+
+/**
+ * The header lines, which are ascii, then the binary pixels from `position`
+ */
+struct Lines<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let rest = &self.bytes[self.position..];
+        let end = rest.iter().position(|&byte| byte == b'\n')?;
+        self.position += end + 1;
+        Some(String::from_utf8_lossy(&rest[..end]).trim_end_matches('\r').to_string())
+    }
+}
+
+pub fn rgbe_to_rgb(rgbe: [u8; 4]) -> [f32; 3] {
+    if rgbe[3] == 0 {
+        return [0.0; 3];
+    }
+    // the mantissas are fractions of 256
+    let scale = 2.0f32.powi(rgbe[3] as i32 - 136);
+    [rgbe[0] as f32 * scale, rgbe[1] as f32 * scale, rgbe[2] as f32 * scale]
+}
+
+/**
+ * Fills `scanline` with RGBE pixels and returns the data after them
+ */
+fn read_scanline<'a>(data: &'a [u8], scanline: &mut [u8]) -> Result<&'a [u8], String> {
+    let width = scanline.len() / 4;
+    let is_rle = (8..0x8000).contains(&width)
+        && data.len() >= 4
+        && data[0] == 2 && data[1] == 2
+        && ((data[2] as usize) << 8 | data[3] as usize) == width;
+
+    if !is_rle {
+        if data.len() >= 3 && data[..3] == [1, 1, 1] {
+            return Err(String::from("old run length encoding is not supported"));
+        }
+        if data.len() < scanline.len() {
+            return Err(String::from("truncated pixels"));
+        }
+        scanline.copy_from_slice(&data[..scanline.len()]);
+        return Ok(&data[scanline.len()..]);
+    }
+
+    // each channel of the whole line, one after another
+    let mut data = &data[4..];
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let (&count, rest) = data.split_first().ok_or("truncated run")?;
+            let (run, literal) = if count > 128 { (count as usize - 128, false) } else { (count as usize, true) };
+            if run == 0 || x + run > width {
+                return Err(String::from("run overflows the scanline"));
+            }
+            let used = if literal { run } else { 1 };
+            if rest.len() < used {
+                return Err(String::from("truncated run"));
+            }
+            for i in 0..run {
+                scanline[(x + i) * 4 + channel] = if literal { rest[i] } else { rest[0] };
+            }
+            x += run;
+            data = &rest[used..];
+        }
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(width: u32, height: u32) -> Vec<u8> {
+        format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\nEXPOSURE=1.0\n\n-Y {} +X {}\n", height, width).into_bytes()
+    }
+
+    #[test]
+    fn rgbe_shares_the_exponent() {
+        assert_eq!(rgbe_to_rgb([128, 64, 0, 129]), [1.0, 0.5, 0.0]);
+        assert_eq!(rgbe_to_rgb([128, 128, 128, 131]), [4.0, 4.0, 4.0]);
+        assert_eq!(rgbe_to_rgb([255, 255, 255, 0]), [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn flat_pixels_are_read_top_row_first() {
+        let mut bytes = header(2, 2);
+        bytes.extend_from_slice(&[128, 0, 0, 129, 0, 128, 0, 129, 0, 0, 128, 129, 0, 0, 0, 0]);
+        let image = HdrImage::decode("flat", &bytes).unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(&image.pixels[..8], &[1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0]);
+        assert_eq!(&image.pixels[12..], &[0.0, 0.0, 0.0, 1.0]);
+
+        bytes.pop();
+        assert!(HdrImage::decode("truncated", &bytes).is_err());
+    }
+
+    #[test]
+    fn run_length_encoded_channels_are_expanded() {
+        let mut bytes = header(8, 1);
+        bytes.extend_from_slice(&[2, 2, 0, 8]);
+        bytes.extend_from_slice(&[128 + 8, 128]);                       // red, one run
+        bytes.extend_from_slice(&[4, 0, 32, 64, 128, 128 + 4, 0]);      // green, literals then a run
+        bytes.extend_from_slice(&[128 + 8, 0]);                         // blue
+        bytes.extend_from_slice(&[128 + 8, 129]);                       // exponent
+        let image = HdrImage::decode("rle", &bytes).unwrap();
+        let green: Vec<f32> = image.pixels.chunks(4).map(|pixel| pixel[1]).collect();
+        assert_eq!(green, vec![0.0, 0.25, 0.5, 1.0, 0.0, 0.0, 0.0, 0.0]);
+        assert!(image.pixels.chunks(4).all(|pixel| pixel[0] == 1.0));
+    }
+
+    #[test]
+    fn unsupported_files_are_rejected() {
+        assert!(HdrImage::decode("png", b"\x89PNG\r\n").is_err());
+        assert!(HdrImage::decode("flipped", b"#?RADIANCE\n\n+Y 1 +X 1\n\0\0\0\0").is_err());
+        assert!(HdrImage::decode("xyze", b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n\0\0\0\0").is_err());
+    }
+}
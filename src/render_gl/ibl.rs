@@ -0,0 +1,330 @@
+/*!
+ * Image based lighting from an environment cube map.
+ *
+ * `Environment::generate` renders, once at load time:
+ *
+ * - an irradiance map, the cosine weighted average of the environment around each normal,
+ *   lighting diffuse surfaces
+ * - a prefiltered map whose mip levels hold the environment convolved with the GGX lobe of
+ *   increasing roughness, `prefilter_roughness` gives the roughness of a level
+ * - the BRDF lookup table, the split sum scale and bias applied to F0 by n.v and roughness
+ *
+ * Programs loaded through `Materials` find the maps on fixed texture units through the
+ * samplers named in `SAMPLERS`, `Environment::bind` puts them there.
+ */
+use gl;
+
+use crate::resources::Resources;
+use super::cubemap::{ cube_face_projection, cube_face_views, mip_levels, Cubemap };
+use super::framebuffer::{ check_status, Error as FramebufferError };
+use super::mesh::FullscreenTriangle;
+use super::render_state::{ RenderState, RenderStateCache };
+use super::shader::{ Error as ShaderError, Program, Shader };
+use super::texture::{ Texture, TextureFormat };
+use super::uniform::{ UniformValue, Uniforms };
+
+pub const IRRADIANCE_UNIT: u32 = 13;
+pub const PREFILTERED_UNIT: u32 = 14;
+pub const BRDF_LUT_UNIT: u32 = 15;
+
+/**
+ * Sampler uniforms of the lit programs and the units the environment is bound to
+ */
+pub const SAMPLERS: [(&str, u32); 3] = [
+    ("irradiance_map", IRRADIANCE_UNIT),
+    ("prefiltered_map", PREFILTERED_UNIT),
+    ("brdf_lut", BRDF_LUT_UNIT),
+];
+
+/**
+ * Mip levels of the prefiltered map, `PREFILTERED_LEVELS` in pbr.frag has to match
+ */
+pub const PREFILTERED_LEVELS: i32 = 5;
+
+const FULLSCREEN_SHADER: &str = "shaders/ibl/fullscreen.vert";
+const SOURCE_UNIT: u32 = 0;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load shader {}", name)]
+    Shader { name: String, #[cause] error: ShaderError },
+
+    #[fail(display = "Failed to link {}: {}", name, message)]
+    Link { name: String, message: String },
+
+    #[fail(display = "Failed to render the environment maps")]
+    Framebuffer { #[cause] error: FramebufferError },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct IblSettings {
+    pub irradiance_size: i32,
+    pub prefiltered_size: i32,
+    pub brdf_lut_size: i32,
+    pub sample_count: i32,      // importance samples per texel of the prefiltered map and the lut
+}
+
+impl Default for IblSettings {
+    fn default() -> IblSettings {
+        IblSettings {
+            irradiance_size: 32,
+            prefiltered_size: 128,
+            brdf_lut_size: 256,
+            sample_count: 512,
+        }
+    }
+}
+
+/**
+ * The roughness the prefiltered map is convolved with at `level`, 0 is a mirror
+ */
+pub fn prefilter_roughness(level: i32, levels: i32) -> f32 {
+    if levels <= 1 {
+        return 0.0;
+    }
+    level as f32 / (levels - 1) as f32
+}
+
+/**
+ * Points the environment samplers of `program` at their units, the program is left in use
+ */
+pub fn bind_samplers(gl: &gl::Gl, program: &Program) {
+    program.set_used();
+    let uniforms = SAMPLERS.iter()
+        .fold(Uniforms::new(), |uniforms, (name, unit)| uniforms.with(name, UniformValue::Int(*unit as i32)));
+    uniforms.apply(gl, program);
+}
+
+pub struct Environment {
+    irradiance: Cubemap,
+    prefiltered: Cubemap,
+    brdf_lut: Texture,
+}
+
+impl Environment {
+    /**
+     * Renders the maps for `source`. Leaves the window framebuffer bound, the viewport has to
+     * be set again afterwards.
+     */
+    pub fn generate(
+        gl: &gl::Gl,
+        res: &Resources,
+        render_state: &mut RenderStateCache,
+        source: &Cubemap,
+        settings: &IblSettings
+    )
+    -> Result<Environment, Error>
+    {
+        let capture = Capture::new(gl, res, render_state);
+        Cubemap::enable_seamless(gl);
+        // the prefilter reads lower mips of the source to avoid bright speckles
+        source.generate_mipmaps();
+        source.bind_to_unit(SOURCE_UNIT);
+
+        let irradiance = Cubemap::new(gl, settings.irradiance_size, TextureFormat::Rgba16F, 1);
+        let program = capture.program("shaders/ibl/irradiance")?;
+        let uniforms = Uniforms::new().with("environment", UniformValue::Int(SOURCE_UNIT as i32));
+        capture.render_cubemap(&program, &uniforms, &irradiance, 0)?;
+
+        let prefiltered = Cubemap::new(gl, settings.prefiltered_size, TextureFormat::Rgba16F, PREFILTERED_LEVELS);
+        let program = capture.program("shaders/ibl/prefilter")?;
+        for level in 0..prefiltered.levels() {
+            let uniforms = Uniforms::new()
+                .with("environment", UniformValue::Int(SOURCE_UNIT as i32))
+                .with("roughness", UniformValue::Float(prefilter_roughness(level, prefiltered.levels())))
+                .with("sample_count", UniformValue::Int(settings.sample_count))
+                .with("source_size", UniformValue::Float(source.size() as f32));
+            capture.render_cubemap(&program, &uniforms, &prefiltered, level)?;
+        }
+
+        let brdf_lut = Texture::new_2d(gl, settings.brdf_lut_size, settings.brdf_lut_size, TextureFormat::Rg16F);
+        let program = capture.program("shaders/ibl/brdf_lut")?;
+        let uniforms = Uniforms::new().with("sample_count", UniformValue::Int(settings.sample_count));
+        capture.render_texture(&program, &uniforms, &brdf_lut)?;
+
+        Ok(Environment { irradiance, prefiltered, brdf_lut })
+    }
+
+    /**
+     * A sky with a sun to light scenes that have no environment map of their own
+     */
+    pub fn sky(gl: &gl::Gl, res: &Resources, render_state: &mut RenderStateCache, size: i32)
+    -> Result<Cubemap, Error>
+    {
+        let capture = Capture::new(gl, res, render_state);
+        // room for mipmaps, `generate` fills them
+        let sky = Cubemap::new(gl, size, TextureFormat::Rgba16F, mip_levels(size));
+        let program = capture.program("shaders/ibl/sky")?;
+        capture.render_cubemap(&program, &Uniforms::new(), &sky, 0)?;
+        Ok(sky)
+    }
+
+    /**
+     * Converts a latitude longitude `panorama`, such as an `HdrImage`, to a cube map with
+     * faces of `size`. Leaves the window framebuffer bound, like `generate`.
+     */
+    pub fn equirectangular(gl: &gl::Gl, res: &Resources, render_state: &mut RenderStateCache, panorama: &Texture, size: i32)
+    -> Result<Cubemap, Error>
+    {
+        let capture = Capture::new(gl, res, render_state);
+        let cubemap = Cubemap::new(gl, size, TextureFormat::Rgba16F, mip_levels(size));
+        panorama.bind_to_unit(SOURCE_UNIT);
+        let program = capture.program("shaders/ibl/equirectangular")?;
+        let uniforms = Uniforms::new().with("panorama", UniformValue::Int(SOURCE_UNIT as i32));
+        capture.render_cubemap(&program, &uniforms, &cubemap, 0)?;
+        cubemap.generate_mipmaps();
+        Ok(cubemap)
+    }
+
+    /**
+     * Binds the maps to the units of `SAMPLERS`
+     */
+    pub fn bind(&self) {
+        self.irradiance.bind_to_unit(IRRADIANCE_UNIT);
+        self.prefiltered.bind_to_unit(PREFILTERED_UNIT);
+        self.brdf_lut.bind_to_unit(BRDF_LUT_UNIT);
+    }
+
+    pub fn irradiance(&self) -> &Cubemap {
+        &self.irradiance
+    }
+
+    pub fn prefiltered(&self) -> &Cubemap {
+        &self.prefiltered
+    }
+
+    pub fn brdf_lut(&self) -> &Texture {
+        &self.brdf_lut
+    }
+}
+
+/**
+ * Renders a fullscreen triangle into cube map faces or a texture. Fragment shaders get the
+ * view direction of their texel in `IN.Direction`.
+ */
+struct Capture {
+    gl: gl::Gl,
+    fbo: gl::types::GLuint,
+    triangle: FullscreenTriangle,
+    res: Resources,
+}
+
+impl Capture {
+    fn new(gl: &gl::Gl, res: &Resources, render_state: &mut RenderStateCache)
+    -> Capture
+    {
+        render_state.apply(&RenderState::default());
+
+        let mut fbo: gl::types::GLuint = 0;
+        unsafe {
+            gl.GenFramebuffers(1, &mut fbo);
+        }
+        Capture { gl: gl.clone(), fbo, triangle: FullscreenTriangle::new(gl), res: res.clone() }
+    }
+
+    /**
+     * The fullscreen vertex shader linked with `name`.frag
+     */
+    fn program(&self, name: &str) -> Result<Program, Error> {
+        let vertex_shader = Shader::from_res(&self.gl, &self.res, FULLSCREEN_SHADER)
+            .map_err(|error| Error::Shader { name: String::from(FULLSCREEN_SHADER), error })?;
+        let fragment = format!("{}.frag", name);
+        let fragment_shader = Shader::from_res(&self.gl, &self.res, &fragment)
+            .map_err(|error| Error::Shader { name: fragment, error })?;
+        let program = Program::from_shaders(&self.gl, &[vertex_shader, fragment_shader])
+            .map_err(|message| Error::Link { name: String::from(name), message })?;
+        program.set_label(name);
+        Ok(program)
+    }
+
+    fn render_cubemap(&self, program: &Program, uniforms: &Uniforms, target: &Cubemap, level: i32)
+    -> Result<(), Error>
+    {
+        let projection = cube_face_projection();
+        let size = target.level_size(level);
+
+        self.begin(program, uniforms);
+        for (face, view) in cube_face_views().iter().enumerate() {
+            target.attach_face(face as u32, level);
+            self.draw(program, size, size, Some(projection * view))?;
+        }
+        self.end();
+        Ok(())
+    }
+
+    fn render_texture(&self, program: &Program, uniforms: &Uniforms, target: &Texture)
+    -> Result<(), Error>
+    {
+        self.begin(program, uniforms);
+        unsafe {
+            self.gl.FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, target.id(), 0);
+        }
+        self.draw(program, target.width(), target.height(), None)?;
+        self.end();
+        Ok(())
+    }
+
+    fn begin(&self, program: &Program, uniforms: &Uniforms) {
+        unsafe {
+            self.gl.BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+        }
+        program.set_used();
+        uniforms.apply(&self.gl, program);
+    }
+
+    fn draw(&self, program: &Program, width: i32, height: i32, view_projection: Option<glm::Mat4>)
+    -> Result<(), Error>
+    {
+        check_status(unsafe { self.gl.CheckFramebufferStatus(gl::FRAMEBUFFER) })
+            .map_err(|error| Error::Framebuffer { error })?;
+
+        if let (Some(view_projection), Some(location)) = (view_projection, program.uniform_location("ClipToWorld")) {
+            let clip_to_world = view_projection.try_inverse().unwrap_or_else(glm::Mat4::identity);
+            UniformValue::Mat4(clip_to_world).upload(&self.gl, location);
+        }
+        unsafe {
+            self.gl.Viewport(0, 0, width, height);
+        }
+        self.triangle.draw();
+        Ok(())
+    }
+
+    fn end(&self) {
+        unsafe {
+            self.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self)
+    {
+        unsafe {
+            self.gl.DeleteFramebuffers(1, &self.fbo);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gl::recording::Recorder;
+
+    #[test]
+    fn prefiltered_levels_go_from_mirror_to_fully_rough() {
+        let roughness: Vec<f32> = (0..PREFILTERED_LEVELS).map(|level| prefilter_roughness(level, PREFILTERED_LEVELS)).collect();
+        assert_eq!(roughness, vec![0.0, 0.25, 0.5, 0.75, 1.0]);
+        assert_eq!(prefilter_roughness(0, 1), 0.0);
+    }
+
+    #[test]
+    fn samplers_are_pointed_at_their_units() {
+        let (gl, recorder) = Recorder::new();
+        let program = Program::from_shaders(&gl, &[]).unwrap();
+        recorder.take_calls();
+
+        bind_samplers(&gl, &program);
+        let units: Vec<i64> = recorder.calls_to("Uniform1i").iter().map(|call| call.int(1)).collect();
+        assert_eq!(units, vec![13, 14, 15]);
+    }
+}
@@ -0,0 +1,419 @@
+/*!
+ * Lights for Blinn-Phong shading.
+ *
+ * The lights of a frame are collected in a `LightList` and uploaded to the `Lights` uniform
+ * block, which `assets/shaders/lit.frag` declares as:
+ *
+ * ```glsl
+ * struct Light {
+ *   vec4 position;     // xyz, kind in w: 0 directional, 1 point, 2 spot
+ *   vec4 direction;    // xyz the light travels in, cos of the inner cone in w
+ *   vec4 color;        // rgb, intensity in w
+ *   vec4 attenuation;  // constant, linear, quadratic, cos of the outer cone in w
+ * };
+ *
+ * layout (std140) uniform Lights {
+ *   vec4 ambient;      // rgb, number of lights in w
+ *   vec4 eye;          // camera position in xyz
+ *   Light lights[MAX_LIGHTS];
+ * };
+ * ```
+ *
+ * `shade` does the same math on the cpu.
+ */
+use gl;
+
+use super::buffer::UniformBuffer;
+
+pub const MAX_LIGHTS: usize = 16;
+pub const LIGHTS_BLOCK: &str = "Lights";
+pub const LIGHTS_BINDING: u32 = 0;
+
+const HEADER_FLOATS: usize = 8;
+const LIGHT_FLOATS: usize = 16;
+
+/**
+ * Light falls off with 1 / (constant + linear * d + quadratic * d^2) at distance d
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl Default for Attenuation {
+    fn default() -> Attenuation {
+        Attenuation::for_range(50.0)
+    }
+}
+
+impl Attenuation {
+    pub fn none() -> Attenuation {
+        Attenuation { constant: 1.0, linear: 0.0, quadratic: 0.0 }
+    }
+
+    /**
+     * Falls off to about 1% of the intensity at `range`
+     */
+    pub fn for_range(range: f32) -> Attenuation {
+        Attenuation {
+            constant: 1.0,
+            linear: 4.5 / range,
+            quadratic: 75.0 / (range * range),
+        }
+    }
+
+    pub fn factor(&self, distance: f32) -> f32 {
+        1.0 / (self.constant + self.linear * distance + self.quadratic * distance * distance)
+    }
+
+    /**
+     * Distance at which the light has fallen off to `cutoff`, infinite when it never does
+     */
+    pub fn range(&self, cutoff: f32) -> f32 {
+        // quadratic * d^2 + linear * d + constant - 1 / cutoff = 0
+        let c = self.constant - 1.0 / cutoff;
+        if self.quadratic > 0.0 {
+            let discriminant = self.linear * self.linear - 4.0 * self.quadratic * c;
+            (-self.linear + discriminant.max(0.0).sqrt()) / (2.0 * self.quadratic)
+        } else if self.linear > 0.0 {
+            -c / self.linear
+        } else {
+            f32::INFINITY
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightKind {
+    Directional,
+    Point { attenuation: Attenuation },
+    /**
+     * Full intensity within `inner_cone` radians of the direction, none past `outer_cone`
+     */
+    Spot { attenuation: Attenuation, inner_cone: f32, outer_cone: f32 },
+}
+
+impl LightKind {
+    fn index(&self) -> f32 {
+        match self {
+            LightKind::Directional => 0.0,
+            LightKind::Point { .. } => 1.0,
+            LightKind::Spot { .. } => 2.0,
+        }
+    }
+}
+
+/**
+ * What a light emits, where it is comes from the `LightSource`. Directional and spot lights
+ * with `casts_shadows` get shadow maps.
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: glm::Vec3,
+    pub intensity: f32,
+    pub casts_shadows: bool,
+}
+
+impl Light {
+    pub fn directional(color: glm::Vec3, intensity: f32) -> Light {
+        Light { kind: LightKind::Directional, color, intensity, casts_shadows: false }
+    }
+
+    pub fn point(color: glm::Vec3, intensity: f32, attenuation: Attenuation) -> Light {
+        Light { kind: LightKind::Point { attenuation }, color, intensity, casts_shadows: false }
+    }
+
+    pub fn spot(color: glm::Vec3, intensity: f32, attenuation: Attenuation, inner_cone: f32, outer_cone: f32)
+    -> Light
+    {
+        Light { kind: LightKind::Spot { attenuation, inner_cone, outer_cone }, color, intensity, casts_shadows: false }
+    }
+
+    pub fn with_shadows(mut self) -> Light {
+        self.casts_shadows = true;
+        self
+    }
+}
+
+/**
+ * A light placed in world space. `direction` is the way the light travels, directional
+ * lights ignore `position` and point lights ignore `direction`.
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LightSource {
+    pub light: Light,
+    pub position: glm::Vec3,
+    pub direction: glm::Vec3,
+}
+
+impl LightSource {
+    /**
+     * Unit vector from `point` towards the light and how much of the light reaches it
+     */
+    pub fn incoming(&self, point: &glm::Vec3) -> (glm::Vec3, f32) {
+        let (attenuation, cone) = match self.light.kind {
+            LightKind::Directional => return (-glm::normalize(&self.direction), 1.0),
+            LightKind::Point { attenuation } => (attenuation, None),
+            LightKind::Spot { attenuation, inner_cone, outer_cone } => (attenuation, Some((inner_cone.cos(), outer_cone.cos()))),
+        };
+
+        let to_light = self.position - point;
+        let distance = glm::length(&to_light);
+        let direction = to_light / distance.max(f32::EPSILON);
+        let mut amount = attenuation.factor(distance);
+        if let Some((cos_inner, cos_outer)) = cone {
+            let cos_angle = glm::dot(&-direction, &glm::normalize(&self.direction));
+            amount *= spot_factor(cos_angle, cos_inner, cos_outer);
+        }
+        (direction, amount)
+    }
+}
+
+fn spot_factor(cos_angle: f32, cos_inner: f32, cos_outer: f32) -> f32 {
+    ((cos_angle - cos_outer) / (cos_inner - cos_outer).max(f32::EPSILON)).clamp(0.0, 1.0)
+}
+
+/**
+ * The lights of a frame and what the shaders need to light with them
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightList {
+    pub ambient: glm::Vec3,
+    pub eye: glm::Vec3,
+    sources: Vec<LightSource>,
+}
+
+impl Default for LightList {
+    fn default() -> LightList {
+        LightList::new()
+    }
+}
+
+impl LightList {
+    pub fn new() -> LightList {
+        LightList {
+            ambient: glm::vec3(0.05, 0.05, 0.05),
+            eye: glm::vec3(0.0, 0.0, 0.0),
+            sources: Vec::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.sources.clear();
+    }
+
+    /**
+     * False when the list already has `MAX_LIGHTS` lights, the light is left out
+     */
+    pub fn push(&mut self, source: LightSource) -> bool {
+        if self.sources.len() >= MAX_LIGHTS {
+            return false;
+        }
+        self.sources.push(source);
+        true
+    }
+
+    pub fn sources(&self) -> &[LightSource] {
+        &self.sources
+    }
+
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    /**
+     * The list laid out as the std140 `Lights` block
+     */
+    pub fn std140(&self) -> Vec<f32> {
+        let mut data = vec![0.0; HEADER_FLOATS + MAX_LIGHTS * LIGHT_FLOATS];
+        data[0..4].copy_from_slice(&[self.ambient.x, self.ambient.y, self.ambient.z, self.sources.len() as f32]);
+        data[4..7].copy_from_slice(&[self.eye.x, self.eye.y, self.eye.z]);
+
+        for (source, light) in self.sources.iter().zip(data[HEADER_FLOATS..].chunks_mut(LIGHT_FLOATS)) {
+            let (attenuation, cos_inner, cos_outer) = match source.light.kind {
+                LightKind::Directional => (Attenuation::none(), 0.0, 0.0),
+                LightKind::Point { attenuation } => (attenuation, 0.0, 0.0),
+                LightKind::Spot { attenuation, inner_cone, outer_cone } => (attenuation, inner_cone.cos(), outer_cone.cos()),
+            };
+            let (position, direction, color) = (source.position, source.direction, source.light.color);
+
+            light.copy_from_slice(&[
+                position.x, position.y, position.z, source.light.kind.index(),
+                direction.x, direction.y, direction.z, cos_inner,
+                color.x, color.y, color.z, source.light.intensity,
+                attenuation.constant, attenuation.linear, attenuation.quadratic, cos_outer,
+            ]);
+        }
+        data
+    }
+}
+
+/**
+ * The Blinn-Phong surface parameters of the lit shaders
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BlinnPhong {
+    pub diffuse: glm::Vec3,
+    pub specular: glm::Vec3,
+    pub shininess: f32,
+}
+
+/**
+ * The color `lit.frag` gives a surface at `point` with `normal`, before it is clamped
+ */
+pub fn shade(lights: &LightList, surface: &BlinnPhong, point: &glm::Vec3, normal: &glm::Vec3) -> glm::Vec3 {
+    let normal = glm::normalize(normal);
+    let to_eye = glm::normalize(&(lights.eye - point));
+    let mut color = lights.ambient.component_mul(&surface.diffuse);
+
+    for source in &lights.sources {
+        let (to_light, amount) = source.incoming(point);
+        let diffuse = glm::dot(&normal, &to_light).max(0.0);
+        if diffuse <= 0.0 {
+            continue;
+        }
+        let halfway = glm::normalize(&(to_light + to_eye));
+        let specular = glm::dot(&normal, &halfway).max(0.0).powf(surface.shininess);
+
+        let radiance = source.light.color * (source.light.intensity * amount);
+        color += radiance.component_mul(&(surface.diffuse * diffuse + surface.specular * specular));
+    }
+    color
+}
+
+/**
+ * The uniform buffer the `Lights` block of every lit program reads from
+ */
+pub struct LightBuffer {
+    buffer: UniformBuffer,
+}
+
+impl LightBuffer {
+    pub fn new(gl: &gl::Gl) -> LightBuffer {
+        let buffer = UniformBuffer::new(gl);
+        buffer.set_label("lights");
+        buffer.bind_base(LIGHTS_BINDING);
+        buffer.dynamic_draw_data(&LightList::new().std140());
+        LightBuffer { buffer }
+    }
+
+    pub fn upload(&self, lights: &LightList) {
+        self.buffer.bind_base(LIGHTS_BINDING);
+        self.buffer.dynamic_draw_data(&lights.std140());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: &glm::Vec3, b: &glm::Vec3) -> bool {
+        (a - b).norm() < 1e-4
+    }
+
+    fn white() -> glm::Vec3 {
+        glm::vec3(1.0, 1.0, 1.0)
+    }
+
+    fn surface() -> BlinnPhong {
+        BlinnPhong {
+            diffuse: glm::vec3(0.5, 0.25, 1.0),
+            specular: glm::vec3(0.2, 0.2, 0.2),
+            shininess: 32.0,
+        }
+    }
+
+    fn lights(sources: &[LightSource]) -> LightList {
+        let mut lights = LightList::new();
+        lights.ambient = glm::vec3(0.0, 0.0, 0.0);
+        lights.eye = glm::vec3(0.0, 10.0, 0.0);
+        for source in sources {
+            lights.push(*source);
+        }
+        lights
+    }
+
+    fn source(light: Light, position: glm::Vec3, direction: glm::Vec3) -> LightSource {
+        LightSource { light, position, direction }
+    }
+
+    #[test]
+    fn attenuation_falls_off_with_distance() {
+        let attenuation = Attenuation::for_range(10.0);
+        assert_eq!(attenuation.factor(0.0), 1.0);
+        assert!(attenuation.factor(5.0) > attenuation.factor(10.0));
+        assert!(attenuation.factor(10.0) < 0.02);
+        assert_eq!(Attenuation::none().factor(100.0), 1.0);
+
+        assert!((attenuation.factor(attenuation.range(0.05)) - 0.05).abs() < 1e-5);
+        assert_eq!(Attenuation { constant: 1.0, linear: 1.0, quadratic: 0.0 }.range(0.25), 3.0);
+        assert_eq!(Attenuation::none().range(0.5), f32::INFINITY);
+    }
+
+    #[test]
+    fn head_on_directional_light_adds_diffuse_and_full_specular() {
+        let sun = source(Light::directional(white(), 2.0), glm::vec3(0.0, 0.0, 0.0), glm::vec3(0.0, -1.0, 0.0));
+        let mut lights = lights(&[sun]);
+        lights.ambient = glm::vec3(0.1, 0.1, 0.1);
+
+        let color = shade(&lights, &surface(), &glm::vec3(0.0, 0.0, 0.0), &glm::vec3(0.0, 1.0, 0.0));
+        // ambient * diffuse + 2 * (diffuse + specular), the eye is straight above
+        assert!(close(&color, &glm::vec3(0.05 + 1.4, 0.025 + 0.9, 0.1 + 2.4)), "{:?}", color);
+    }
+
+    #[test]
+    fn surfaces_facing_away_only_get_ambient() {
+        let sun = source(Light::directional(white(), 1.0), glm::vec3(0.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0));
+        let color = shade(&lights(&[sun]), &surface(), &glm::vec3(0.0, 0.0, 0.0), &glm::vec3(0.0, 1.0, 0.0));
+        assert!(close(&color, &glm::vec3(0.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn point_lights_are_attenuated() {
+        let attenuation = Attenuation { constant: 1.0, linear: 0.0, quadratic: 1.0 };
+        let lamp = source(Light::point(white(), 1.0, attenuation), glm::vec3(0.0, 2.0, 0.0), glm::vec3(0.0, 0.0, 0.0));
+        let surface = BlinnPhong { specular: glm::vec3(0.0, 0.0, 0.0), ..surface() };
+
+        let color = shade(&lights(&[lamp]), &surface, &glm::vec3(0.0, 0.0, 0.0), &glm::vec3(0.0, 1.0, 0.0));
+        assert!(close(&color, &(surface.diffuse / 5.0)), "{:?}", color);
+    }
+
+    #[test]
+    fn spot_lights_fade_between_their_cones() {
+        let spot = Light::spot(white(), 1.0, Attenuation::none(), 0.2, 0.4);
+        let above = |x: f32| source(spot, glm::vec3(x, 1.0, 0.0), glm::vec3(0.0, -1.0, 0.0));
+        let origin = glm::vec3(0.0, 0.0, 0.0);
+
+        assert_eq!(above(0.0).incoming(&origin).1, 1.0);
+        assert_eq!(above(0.5).incoming(&origin).1, 0.0);       // atan 0.5 is past the outer cone
+        let (_, edge) = above(0.3f32.tan()).incoming(&origin);
+        assert!(edge > 0.0 && edge < 1.0, "{}", edge);
+    }
+
+    #[test]
+    fn lists_are_laid_out_for_std140() {
+        let mut lights = LightList::new();
+        lights.eye = glm::vec3(1.0, 2.0, 3.0);
+        let spot = Light::spot(glm::vec3(1.0, 0.5, 0.0), 3.0, Attenuation::none(), 0.0, glm::half_pi());
+        assert!(lights.push(source(spot, glm::vec3(4.0, 5.0, 6.0), glm::vec3(0.0, -1.0, 0.0))));
+
+        let data = lights.std140();
+        assert_eq!(data.len(), 8 + 16 * MAX_LIGHTS);
+        assert_eq!(&data[3..7], &[1.0, 1.0, 2.0, 3.0]);
+        assert_eq!(&data[8..20], &[4.0, 5.0, 6.0, 2.0, 0.0, -1.0, 0.0, 1.0, 1.0, 0.5, 0.0, 3.0]);
+        assert_eq!(&data[20..23], &[1.0, 0.0, 0.0]);
+        assert!(data[23].abs() < 1e-6);
+
+        for _ in 1..MAX_LIGHTS {
+            assert!(lights.push(lights.sources()[0]));
+        }
+        assert!(!lights.push(lights.sources()[0]));
+    }
+}
@@ -0,0 +1,478 @@
+/*!
+ * Materials, a program with the parameters and textures it is drawn with.
+ *
+ * Materials are described in toml resources:
+ *
+ * ```toml
+ * program = "shaders/lit"      # vert and frag shaders, see Program::from_res
+ * blend = "opaque"             # opaque, alpha, premultiplied, additive or multiply
+ *
+ * [params]                     # uniforms: integers, floats or arrays of 2 to 4 floats
+ * roughness = 0.5
+ * tint = [1.0, 0.9, 0.8]
+ *
+ * [textures]                   # sampler uniform = texture added to the `Materials`
+ * albedo = "bricks"
+ * ```
+ *
+ * Every `Materials` has the 1x1 textures `white`, `black` and `flat_normal` for maps a
+ * material does without.
+ *
+ * An instance names a `base` instead of a program. It starts out with the parameters,
+ * textures and blend mode of its base and overrides what its own file sets.
+ */
+use gl;
+use std::collections::{ BTreeMap, HashMap };
+use std::rc::Rc;
+
+use crate::resources::Resources;
+use crate::resources::Error as ResourcesError;
+use super::ibl::bind_samplers;
+use super::light::{ LIGHTS_BINDING, LIGHTS_BLOCK };
+use super::shadow::{ bind_shadow_map, SHADOWS_BINDING, SHADOWS_BLOCK };
+use super::render_state::BlendMode;
+use super::shader::{ Error as ProgramError, Program };
+use super::texture::{ Texture, TextureFormat };
+use super::uniform::{ UniformValue, Uniforms };
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load resource {}", name)]
+    UnableToLoadResource { name: String, #[cause] error: ResourcesError },
+
+    #[fail(display = "Failed to parse material {}", name)]
+    Parse { name: String, #[cause] error: toml::de::Error },
+
+    #[fail(display = "Failed to load the program of material {}", name)]
+    Program { name: String, #[cause] error: ProgramError },
+
+    #[fail(display = "Invalid material {}: {}", name, message)]
+    Invalid { name: String, message: String },
+}
+
+/**
+ * A texture bound to `unit` for the sampler uniform `sampler`
+ */
+#[derive(Clone)]
+pub struct TextureSlot {
+    pub sampler: String,
+    pub unit: u32,
+    pub texture: Rc<Texture>,
+}
+
+pub struct Material {
+    name: String,
+    program: Rc<Program>,
+    params: Uniforms,
+    textures: Vec<TextureSlot>,
+    blend: BlendMode,
+    base: Option<Rc<Material>>,
+}
+
+impl Material {
+    pub fn new(name: &str, program: Rc<Program>) -> Material {
+        Material {
+            name: String::from(name),
+            program,
+            params: Uniforms::new(),
+            textures: Vec::new(),
+            blend: BlendMode::Opaque,
+            base: None,
+        }
+    }
+
+    /**
+     * A material with the program, parameters, textures and blend mode of `base`, to be
+     * overridden. Texture units stay the same so instances can share samplers.
+     */
+    pub fn instance(name: &str, base: &Rc<Material>) -> Material {
+        Material {
+            name: String::from(name),
+            program: base.program.clone(),
+            params: base.params.clone(),
+            textures: base.textures.clone(),
+            blend: base.blend,
+            base: Some(base.clone()),
+        }
+    }
+
+    pub fn with_param(mut self, name: &str, value: UniformValue) -> Material {
+        self.set_param(name, value);
+        self
+    }
+
+    pub fn with_texture(mut self, sampler: &str, texture: Rc<Texture>) -> Material {
+        self.set_texture(sampler, texture);
+        self
+    }
+
+    pub fn with_blend(mut self, blend: BlendMode) -> Material {
+        self.blend = blend;
+        self
+    }
+
+    pub fn set_param(&mut self, name: &str, value: UniformValue) {
+        self.params.set(name, value);
+    }
+
+    /**
+     * Replaces the texture of an existing slot or adds a slot on the next free unit
+     */
+    pub fn set_texture(&mut self, sampler: &str, texture: Rc<Texture>) {
+        match self.textures.iter_mut().find(|slot| slot.sampler == sampler) {
+            Some(slot) => slot.texture = texture,
+            None => {
+                let unit = self.textures.iter().map(|slot| slot.unit + 1).max().unwrap_or(0);
+                self.textures.push(TextureSlot { sampler: String::from(sampler), unit, texture });
+            },
+        }
+    }
+
+    pub fn set_blend(&mut self, blend: BlendMode) {
+        self.blend = blend;
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn program(&self) -> &Rc<Program> {
+        &self.program
+    }
+
+    pub fn param(&self, name: &str) -> Option<UniformValue> {
+        self.params.get(name)
+    }
+
+    pub fn params(&self) -> &Uniforms {
+        &self.params
+    }
+
+    pub fn textures(&self) -> &[TextureSlot] {
+        &self.textures
+    }
+
+    pub fn blend(&self) -> BlendMode {
+        self.blend
+    }
+
+    pub fn base(&self) -> Option<&Rc<Material>> {
+        self.base.as_ref()
+    }
+
+    /**
+     * Uses the program and sets the parameters and textures. `previous` is the material
+     * applied last, when it shares the program only the values that differ are uploaded.
+     */
+    pub fn apply(&self, gl: &gl::Gl, previous: Option<&Material>) {
+        let previous = previous.filter(|previous| previous.program.id() == self.program.id());
+        if previous.is_some_and(|previous| std::ptr::eq(previous, self)) {
+            return;
+        }
+        self.program.set_used();
+
+        for (name, value) in self.params.iter() {
+            if previous.and_then(|previous| previous.param(name)) != Some(*value) {
+                if let Some(location) = self.program.uniform_location(name) {
+                    value.upload(gl, location);
+                }
+            }
+        }
+
+        for slot in &self.textures {
+            let previous_unit = previous
+                .and_then(|previous| previous.textures.iter().find(|other| other.sampler == slot.sampler))
+                .map(|other| other.unit);
+            if previous_unit != Some(slot.unit) {
+                if let Some(location) = self.program.uniform_location(&slot.sampler) {
+                    UniformValue::Int(slot.unit as i32).upload(gl, location);
+                }
+            }
+            slot.texture.bind_to_unit(slot.unit);    // the gl state cache skips bound textures
+        }
+    }
+}
+
+/**
+ * A material file before its program, base and textures are looked up
+ */
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+struct MaterialFile {
+    base: Option<String>,
+    program: Option<String>,
+    blend: Option<String>,
+    params: BTreeMap<String, toml::Value>,
+    textures: BTreeMap<String, String>,
+}
+
+impl MaterialFile {
+    fn parse(name: &str, source: &str) -> Result<MaterialFile, Error> {
+        let file: MaterialFile = toml::from_str(source)
+            .map_err(|error| Error::Parse { name: String::from(name), error })?;
+
+        match (&file.base, &file.program) {
+            (Some(_), Some(_)) => Err(invalid(name, "instances use the program of their base")),
+            (None, None) => Err(invalid(name, "needs a program or a base")),
+            _ => Ok(file),
+        }
+    }
+
+    fn blend(&self, name: &str) -> Result<Option<BlendMode>, Error> {
+        self.blend.as_ref().map(|blend| match blend.as_str() {
+            "opaque" => Ok(BlendMode::Opaque),
+            "alpha" => Ok(BlendMode::Alpha),
+            "premultiplied" => Ok(BlendMode::PremultipliedAlpha),
+            "additive" => Ok(BlendMode::Additive),
+            "multiply" => Ok(BlendMode::Multiply),
+            other => Err(invalid(name, &format!("unknown blend mode {}", other))),
+        }).transpose()
+    }
+
+    fn params(&self, name: &str) -> Result<Vec<(&str, UniformValue)>, Error> {
+        self.params.iter()
+            .map(|(param, value)| match param_value(value) {
+                Some(value) => Ok((param.as_str(), value)),
+                None => Err(invalid(name, &format!("unsupported value for {}", param))),
+            })
+            .collect()
+    }
+}
+
+fn invalid(name: &str, message: &str) -> Error {
+    Error::Invalid { name: String::from(name), message: String::from(message) }
+}
+
+pub(super) fn param_value(value: &toml::Value) -> Option<UniformValue> {
+    let float = |value: &toml::Value| match value {
+        toml::Value::Float(value) => Some(*value as f32),
+        toml::Value::Integer(value) => Some(*value as f32),
+        _ => None,
+    };
+
+    match value {
+        toml::Value::Integer(value) => Some(UniformValue::Int(*value as i32)),
+        toml::Value::Float(value) => Some(UniformValue::Float(*value as f32)),
+        toml::Value::Array(values) => {
+            let values = values.iter().map(float).collect::<Option<Vec<f32>>>()?;
+            match values[..] {
+                [x, y] => Some(UniformValue::Vec2(glm::vec2(x, y))),
+                [x, y, z] => Some(UniformValue::Vec3(glm::vec3(x, y, z))),
+                [x, y, z, w] => Some(UniformValue::Vec4(glm::vec4(x, y, z, w))),
+                _ => None,
+            }
+        },
+        _ => None,
+    }
+}
+
+/**
+ * Loads material resources and keeps them, with their programs, so materials and draws
+ * that use the same file share one `Rc`. Textures named by materials are added up front.
+ */
+pub struct Materials {
+    gl: gl::Gl,
+    res: Resources,
+    programs: HashMap<String, Rc<Program>>,
+    textures: HashMap<String, Rc<Texture>>,
+    materials: HashMap<String, Rc<Material>>,
+}
+
+impl Materials {
+    pub fn new(gl: &gl::Gl, res: Resources) -> Materials {
+        let mut materials = Materials {
+            gl: gl.clone(),
+            res,
+            programs: HashMap::new(),
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+        };
+        for (name, pixel) in &[("white", [255u8, 255, 255, 255]), ("black", [0, 0, 0, 255]), ("flat_normal", [128, 128, 255, 255])] {
+            materials.add_texture(name, Rc::new(Texture::from_data(gl, 1, 1, TextureFormat::Rgba8, pixel)));
+        }
+        materials
+    }
+
+    pub fn add_texture(&mut self, name: &str, texture: Rc<Texture>) {
+        self.textures.insert(String::from(name), texture);
+    }
+
+    pub fn texture(&self, name: &str) -> Option<&Rc<Texture>> {
+        self.textures.get(name)
+    }
+
+    /**
+     * The program with the shaders `name`.vert and `name`.frag, loaded once. Its `Lights` and
+     * `Shadows` blocks, environment samplers and shadow map, if any, read from the light
+     * buffer, `ShadowMaps` and `Environment`.
+     */
+    pub fn program(&mut self, name: &str) -> Result<Rc<Program>, ProgramError> {
+        if let Some(program) = self.programs.get(name) {
+            return Ok(program.clone());
+        }
+        let program = Rc::new(Program::from_res(&self.gl, self.res.clone(), name)?);
+        program.bind_uniform_block(LIGHTS_BLOCK, LIGHTS_BINDING);
+        program.bind_uniform_block(SHADOWS_BLOCK, SHADOWS_BINDING);
+        bind_samplers(&self.gl, &program);
+        bind_shadow_map(&self.gl, &program);
+        self.programs.insert(String::from(name), program.clone());
+        Ok(program)
+    }
+
+    /**
+     * The material in the resource `name`.toml, loaded once
+     */
+    pub fn load(&mut self, name: &str) -> Result<Rc<Material>, Error> {
+        self.load_with_stack(name, &mut Vec::new())
+    }
+
+    fn load_with_stack(&mut self, name: &str, loading: &mut Vec<String>) -> Result<Rc<Material>, Error> {
+        if let Some(material) = self.materials.get(name) {
+            return Ok(material.clone());
+        }
+        if loading.iter().any(|other| other == name) {
+            return Err(invalid(name, "is its own base"));
+        }
+        loading.push(String::from(name));
+
+        let resource = format!("{}.toml", name);
+        let source = self.res.load_string(&resource)
+            .map_err(|error| Error::UnableToLoadResource { name: resource, error })?;
+        let file = MaterialFile::parse(name, &source)?;
+
+        let mut material = match (&file.base, &file.program) {
+            (Some(base), _) => Material::instance(name, &self.load_with_stack(base, loading)?),
+            (None, Some(program)) => {
+                let program = self.program(program)
+                    .map_err(|error| Error::Program { name: String::from(name), error })?;
+                Material::new(name, program)
+            },
+            (None, None) => unreachable!("rejected by MaterialFile::parse"),
+        };
+
+        if let Some(blend) = file.blend(name)? {
+            material.set_blend(blend);
+        }
+        for (param, value) in file.params(name)? {
+            material.set_param(param, value);
+        }
+        for (sampler, texture) in &file.textures {
+            let texture = self.textures.get(texture)
+                .ok_or_else(|| invalid(name, &format!("unknown texture {}", texture)))?
+                .clone();
+            material.set_texture(sampler, texture);
+        }
+
+        loading.pop();
+        let material = Rc::new(material);
+        self.materials.insert(String::from(name), material.clone());
+        Ok(material)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gl::recording::Recorder;
+    use super::super::texture::TextureFormat;
+
+    fn program(gl: &gl::Gl, recorder: &Recorder, id: i64) -> Rc<Program> {
+        recorder.set_return("CreateProgram", id);
+        Rc::new(Program::from_shaders(gl, &[]).unwrap())
+    }
+
+    #[test]
+    fn files_parse_params_and_blend_modes() {
+        let file = MaterialFile::parse("stone", r#"
+            program = "shaders/lit"
+            blend = "additive"
+
+            [params]
+            layers = 2
+            roughness = 0.5
+            tint = [1.0, 0, 0.5]
+
+            [textures]
+            albedo = "stone"
+        "#).unwrap();
+
+        assert_eq!(file.blend("stone").unwrap(), Some(BlendMode::Additive));
+        assert_eq!(file.params("stone").unwrap(), vec![
+            ("layers", UniformValue::Int(2)),
+            ("roughness", UniformValue::Float(0.5)),
+            ("tint", UniformValue::Vec3(glm::vec3(1.0, 0.0, 0.5))),
+        ]);
+        assert_eq!(file.textures.get("albedo").map(String::as_str), Some("stone"));
+    }
+
+    #[test]
+    fn invalid_files_are_rejected() {
+        assert!(MaterialFile::parse("none", "blend = \"alpha\"").is_err());
+        assert!(MaterialFile::parse("both", "base = \"a\"\nprogram = \"b\"").is_err());
+
+        let file = MaterialFile::parse("odd", "program = \"p\"\nblend = \"screen\"\n[params]\nv = [1.0]").unwrap();
+        assert!(file.blend("odd").is_err());
+        assert!(file.params("odd").is_err());
+    }
+
+    #[test]
+    fn instances_override_their_base() {
+        let (gl, recorder) = Recorder::new();
+        let texture = Rc::new(Texture::new_2d(&gl, 4, 4, TextureFormat::Rgba8));
+        let base = Rc::new(Material::new("stone", program(&gl, &recorder, 1))
+            .with_param("roughness", UniformValue::Float(0.8))
+            .with_param("tint", UniformValue::Vec3(glm::vec3(1.0, 1.0, 1.0)))
+            .with_texture("albedo", texture.clone())
+            .with_texture("normals", texture.clone()));
+
+        let mossy = Material::instance("mossy", &base)
+            .with_param("tint", UniformValue::Vec3(glm::vec3(0.5, 1.0, 0.5)))
+            .with_texture("normals", texture);
+
+        assert_eq!(mossy.param("roughness"), Some(UniformValue::Float(0.8)));
+        assert_eq!(mossy.param("tint"), Some(UniformValue::Vec3(glm::vec3(0.5, 1.0, 0.5))));
+        assert_eq!(base.param("tint"), Some(UniformValue::Vec3(glm::vec3(1.0, 1.0, 1.0))));
+        assert_eq!(mossy.textures().iter().map(|slot| slot.unit).collect::<Vec<_>>(), vec![0, 1]);
+        assert!(mossy.base().is_some_and(|material| Rc::ptr_eq(material, &base)));
+        assert_eq!(mossy.program().id(), base.program().id());
+    }
+
+    #[test]
+    fn applying_after_a_sibling_only_uploads_differences() {
+        let (gl, recorder) = Recorder::new();
+        let texture = Rc::new(Texture::new_2d(&gl, 4, 4, TextureFormat::Rgba8));
+        let base = Rc::new(Material::new("stone", program(&gl, &recorder, 1))
+            .with_param("roughness", UniformValue::Float(0.8))
+            .with_param("tint", UniformValue::Vec3(glm::vec3(1.0, 1.0, 1.0)))
+            .with_texture("albedo", texture));
+        let red = Material::instance("red", &base)
+            .with_param("tint", UniformValue::Vec3(glm::vec3(1.0, 0.0, 0.0)));
+        let other = Material::new("other", program(&gl, &recorder, 2))
+            .with_param("roughness", UniformValue::Float(0.8));
+        recorder.take_calls();
+
+        base.apply(&gl, None);
+        assert_eq!(recorder.count("UseProgram"), 1);
+        assert_eq!(recorder.count("Uniform1f"), 1);
+        assert_eq!(recorder.count("Uniform3fv"), 1);
+        assert_eq!(recorder.count("Uniform1i"), 1);     // the sampler's unit
+        assert_eq!(recorder.count("BindTexture"), 1);
+        recorder.take_calls();
+
+        base.apply(&gl, Some(&base));
+        assert!(recorder.take_calls().is_empty());
+
+        red.apply(&gl, Some(&base));
+        assert_eq!(recorder.count("UseProgram"), 0);
+        assert_eq!(recorder.count("Uniform3fv"), 1);
+        assert_eq!(recorder.count("Uniform1f"), 0);
+        assert_eq!(recorder.count("Uniform1i"), 0);
+        assert_eq!(recorder.count("BindTexture"), 0);
+        recorder.take_calls();
+
+        // a different program has none of the values
+        other.apply(&gl, Some(&red));
+        assert_eq!(recorder.count("UseProgram"), 1);
+        assert_eq!(recorder.count("Uniform1f"), 1);
+    }
+}
@@ -0,0 +1,74 @@
+use gl;
+
+use super::buffer::{ ArrayBuffer, VertexArray };
+
+/**
+ * Vertex data on the gpu with the attribute setup to draw it
+ */
+pub struct Mesh {
+    vao: VertexArray,
+    _vbo: ArrayBuffer,  // kept alive for the vao
+    mode: gl::types::GLenum,
+    count: gl::types::GLsizei,
+}
+
+impl Mesh {
+    /**
+     * `vao` has its attribute pointers set up for `vbo`
+     */
+    pub fn new(vao: VertexArray, vbo: ArrayBuffer, mode: gl::types::GLenum, count: gl::types::GLsizei)
+    -> Mesh
+    {
+        Mesh { vao, _vbo: vbo, mode, count }
+    }
+
+    /**
+     * The vertex array name, which identifies the mesh when sorting draws
+     */
+    pub fn id(&self) -> gl::types::GLuint {
+        self.vao.id()
+    }
+
+    pub fn bind(&self) {
+        self.vao.bind();
+    }
+
+    /**
+     * Draws with whatever vertex array is bound, for draws that share one
+     */
+    pub fn draw_bound(&self, gl: &gl::Gl) {
+        unsafe {
+            gl.DrawArrays(self.mode, 0, self.count);
+        }
+    }
+
+    pub fn draw(&self, gl: &gl::Gl) {
+        self.bind();
+        self.draw_bound(gl);
+    }
+}
+
+/**
+ * A triangle that covers the whole viewport, for passes that shade every pixel. It has no
+ * vertex data, the vertex shader places the corners from gl_VertexID.
+ */
+pub struct FullscreenTriangle {
+    gl: gl::Gl,
+    vao: VertexArray,   // empty, core profiles do not draw without one bound
+}
+
+impl FullscreenTriangle {
+    pub fn new(gl: &gl::Gl) -> FullscreenTriangle {
+        FullscreenTriangle { gl: gl.clone(), vao: VertexArray::new(gl) }
+    }
+
+    /**
+     * Draws with the bound program, leaves the empty vertex array bound
+     */
+    pub fn draw(&self) {
+        self.vao.bind();
+        unsafe {
+            self.gl.DrawArrays(gl::TRIANGLES, 0, 3);
+        }
+    }
+}
@@ -4,7 +4,47 @@ pub mod buffer;
 mod shader;
 mod viewport;
 mod color_buffer;
+mod vertex_layout;
+mod render_state;
+mod texture;
+mod framebuffer;
+mod capture;
+mod debug_output;
+mod command_list;
+mod query;
+mod mesh;
+mod uniform;
+mod material;
+mod light;
+mod cubemap;
+mod ibl;
+mod pbr;
+mod shadow;
+mod post;
+mod hdr;
+mod skybox;
+mod render_queue;
 
 pub use self::shader::{Error, Program, Shader};
 pub use self::viewport::Viewport;
 pub use self::color_buffer::ColorBuffer;
+pub use self::vertex_layout::{ActiveAttribute, AttribKind, AttributeMismatch, AttributeMismatches, VertexAttribute, VertexLayout};
+pub use self::render_state::{BlendMode, ClearMask, ClearValues, CompareFunc, CullFace, FrontFace, RenderState, RenderStateCache, Scissor, StencilOp, StencilState};
+pub use self::texture::{Texture, TextureFilter, TextureFormat, TextureWrap};
+pub use self::framebuffer::{Attachment, AttachmentDesc, AttachmentKind, Error as FramebufferError, Framebuffer, FramebufferDesc, FramebufferSize, Renderbuffer};
+pub use self::capture::{Error as CaptureError, FrameCapture, Image};
+pub use self::debug_output::{label_object, DebugMessage, DebugOutput, DebugOutputConfig, DebugSeverity, DebugSource, DebugType, Error as DebugOutputError};
+pub use self::command_list::{buffer_address, command_lists_supported, has_extension, mesh_tokens, CommandList, CommandSequence, StateObject, Token, TokenHeaders, TokenStream};
+pub use self::query::{Query, QueryKind};
+pub use self::mesh::{FullscreenTriangle, Mesh};
+pub use self::uniform::{UniformValue, Uniforms};
+pub use self::light::{shade, Attenuation, BlinnPhong, Light, LightBuffer, LightKind, LightList, LightSource, LIGHTS_BINDING, LIGHTS_BLOCK, MAX_LIGHTS};
+pub use self::cubemap::{cube_face_projection, cube_face_views, mip_levels, Cubemap, CUBE_FACES};
+pub use self::ibl::{bind_samplers, prefilter_roughness, Environment, Error as IblError, IblSettings, PREFILTERED_LEVELS};
+pub use self::pbr::{distribution_ggx, fresnel_schlick, gamma_correct, geometry_smith, shade as shade_pbr, PbrSurface, ToneMapping, DIELECTRIC_F0, GAMMA};
+pub use self::shadow::{bind_shadow_map, directional_matrix, frustum_corners, spot_matrix, tile_matrix, tile_rect, Error as ShadowError, ShadowList, ShadowMaps, ShadowSettings, ShadowView, MAX_CASCADES, MAX_SHADOWS, SHADOWS_BINDING, SHADOWS_BLOCK, SHADOW_MAP_UNIT};
+pub use self::post::{identity_lut, parse_cube_lut, Bloom, Effect, EffectKind, Error as PostError, PostStack};
+pub use self::hdr::{rgbe_to_rgb, Error as HdrError, HdrImage};
+pub use self::skybox::{load_equirectangular, load_faces, sky_view_projection, Error as SkyboxError, Skybox, SKYBOX_PROGRAM};
+pub use self::material::{Error as MaterialError, Material, Materials, TextureSlot};
+pub use self::render_queue::{quantize_depth, DrawCommand, QueueStats, RenderQueue, SortKey};
@@ -0,0 +1,239 @@
+/*!
+ * Metallic-roughness shading, the cpu side of `assets/shaders/pbr.frag`.
+ *
+ * Direct light uses the Cook-Torrance BRDF with the GGX distribution, Smith-Schlick geometry
+ * and Schlick fresnel, as in the glTF spec. The shader adds image based light from an
+ * `Environment`, then tone maps and gamma corrects. `shade` leaves the environment out so
+ * the direct light can be tested on its own.
+ */
+use std::f32::consts::PI;
+
+use super::light::LightList;
+
+/**
+ * Reflectance of dielectrics at normal incidence
+ */
+pub const DIELECTRIC_F0: f32 = 0.04;
+
+/**
+ * The material inputs at one point, after the maps have been sampled
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PbrSurface {
+    pub base_color: glm::Vec3,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub occlusion: f32,
+    pub emissive: glm::Vec3,
+}
+
+impl Default for PbrSurface {
+    fn default() -> PbrSurface {
+        PbrSurface {
+            base_color: glm::vec3(1.0, 1.0, 1.0),
+            metallic: 0.0,
+            roughness: 1.0,
+            occlusion: 1.0,
+            emissive: glm::vec3(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+impl PbrSurface {
+    /**
+     * Reflectance at normal incidence, metals tint their reflections
+     */
+    pub fn f0(&self) -> glm::Vec3 {
+        glm::lerp(&glm::vec3(DIELECTRIC_F0, DIELECTRIC_F0, DIELECTRIC_F0), &self.base_color, self.metallic)
+    }
+}
+
+pub fn fresnel_schlick(cos_theta: f32, f0: &glm::Vec3) -> glm::Vec3 {
+    let factor = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+    f0 + (glm::vec3(1.0, 1.0, 1.0) - f0) * factor
+}
+
+/**
+ * GGX normal distribution with alpha = roughness^2
+ */
+pub fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let alpha = roughness * roughness;
+    let alpha2 = alpha * alpha;
+    let denominator = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    alpha2 / (PI * denominator * denominator).max(f32::EPSILON)
+}
+
+/**
+ * Smith shadowing and masking with the Schlick-GGX approximation for direct light
+ */
+pub fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let schlick = |n_dot_x: f32| n_dot_x / (n_dot_x * (1.0 - k) + k);
+    schlick(n_dot_v) * schlick(n_dot_l)
+}
+
+/**
+ * Outgoing radiance towards the eye from direct light, plus the flat ambient term and emission.
+ * The shader replaces the flat ambient term with the environment when there is one.
+ */
+pub fn shade(lights: &LightList, surface: &PbrSurface, point: &glm::Vec3, normal: &glm::Vec3) -> glm::Vec3 {
+    let normal = glm::normalize(normal);
+    let to_eye = glm::normalize(&(lights.eye - point));
+    let n_dot_v = glm::dot(&normal, &to_eye).max(1e-4);
+    let roughness = surface.roughness.clamp(0.04, 1.0);
+    let f0 = surface.f0();
+
+    let mut color = lights.ambient.component_mul(&surface.base_color) * surface.occlusion + surface.emissive;
+    for source in lights.sources() {
+        let (to_light, amount) = source.incoming(point);
+        let n_dot_l = glm::dot(&normal, &to_light);
+        if n_dot_l <= 0.0 {
+            continue;
+        }
+        let halfway = glm::normalize(&(to_light + to_eye));
+        let fresnel = fresnel_schlick(glm::dot(&halfway, &to_eye).max(0.0), &f0);
+        let specular = fresnel
+            * (distribution_ggx(glm::dot(&normal, &halfway).max(0.0), roughness) * geometry_smith(n_dot_v, n_dot_l, roughness)
+                / (4.0 * n_dot_v * n_dot_l));
+        // what is not reflected is refracted and scattered, except by metals which absorb it
+        let diffuse = (glm::vec3(1.0, 1.0, 1.0) - fresnel) * (1.0 - surface.metallic);
+        let brdf = diffuse.component_mul(&surface.base_color) / PI + specular;
+
+        let radiance = source.light.color * (source.light.intensity * amount);
+        color += brdf.component_mul(&radiance) * n_dot_l;
+    }
+    color
+}
+
+/**
+ * How high dynamic range colors are brought into 0..1, the `tone_mapping` parameter of the
+ * shaders is the index
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ToneMapping {
+    None,
+    Reinhard,
+    Aces,   // Narkowicz's fit of the ACES filmic curve
+}
+
+impl ToneMapping {
+    pub fn index(&self) -> i32 {
+        match self {
+            ToneMapping::None => 0,
+            ToneMapping::Reinhard => 1,
+            ToneMapping::Aces => 2,
+        }
+    }
+
+    pub fn apply(&self, color: &glm::Vec3, exposure: f32) -> glm::Vec3 {
+        let color = color * exposure;
+        match self {
+            ToneMapping::None => color,
+            ToneMapping::Reinhard => color.map(|c| c / (1.0 + c)),
+            ToneMapping::Aces => color.map(|c| ((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)).clamp(0.0, 1.0)),
+        }
+    }
+}
+
+pub const GAMMA: f32 = 2.2;
+
+pub fn gamma_correct(color: &glm::Vec3) -> glm::Vec3 {
+    color.map(|c| c.max(0.0).powf(1.0 / GAMMA))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::light::{ Light, LightSource };
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    fn sun_above() -> LightList {
+        let mut lights = LightList::new();
+        lights.ambient = glm::vec3(0.0, 0.0, 0.0);
+        lights.eye = glm::vec3(0.0, 5.0, 0.0);
+        lights.push(LightSource {
+            light: Light::directional(glm::vec3(1.0, 1.0, 1.0), 1.0),
+            position: glm::vec3(0.0, 0.0, 0.0),
+            direction: glm::vec3(0.0, -1.0, 0.0),
+        });
+        lights
+    }
+
+    #[test]
+    fn fresnel_goes_from_f0_to_white_at_grazing_angles() {
+        let f0 = glm::vec3(0.04, 0.04, 0.04);
+        assert_eq!(fresnel_schlick(1.0, &f0), f0);
+        assert_eq!(fresnel_schlick(0.0, &f0), glm::vec3(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn ggx_is_a_normalized_distribution() {
+        // the projected distribution integrates to 1 over the hemisphere
+        for roughness in &[0.3f32, 0.6, 1.0] {
+            let steps = 20_000;
+            let integral: f32 = (0..steps).map(|i| {
+                let theta = (i as f32 + 0.5) / steps as f32 * PI / 2.0;
+                distribution_ggx(theta.cos(), *roughness) * theta.cos() * theta.sin() * 2.0 * PI * (PI / 2.0 / steps as f32)
+            }).sum();
+            assert!((integral - 1.0).abs() < 0.01, "{} {}", roughness, integral);
+        }
+        assert!(distribution_ggx(1.0, 0.2) > distribution_ggx(1.0, 0.8));
+    }
+
+    #[test]
+    fn geometry_term_is_one_head_on_and_zero_at_grazing_angles() {
+        assert!(close(geometry_smith(1.0, 1.0, 0.5), 1.0));
+        assert_eq!(geometry_smith(0.0, 1.0, 0.5), 0.0);
+    }
+
+    #[test]
+    fn rough_dielectrics_are_mostly_diffuse() {
+        let surface = PbrSurface { base_color: glm::vec3(0.5, 0.5, 0.5), ..PbrSurface::default() };
+        let color = shade(&sun_above(), &surface, &glm::vec3(0.0, 0.0, 0.0), &glm::vec3(0.0, 1.0, 0.0));
+
+        // (1 - F) * albedo / pi plus a small specular lobe
+        let diffuse = 0.96 * 0.5 / PI;
+        assert!(color.x > diffuse && color.x < diffuse + 0.05, "{:?}", color);
+    }
+
+    #[test]
+    fn metals_have_no_diffuse_and_tinted_reflections() {
+        let gold = PbrSurface { base_color: glm::vec3(1.0, 0.8, 0.3), metallic: 1.0, roughness: 0.5, ..PbrSurface::default() };
+        let lights = sun_above();
+
+        let lit = shade(&lights, &gold, &glm::vec3(0.0, 0.0, 0.0), &glm::vec3(0.0, 1.0, 0.0));
+        assert!(lit.x > lit.y && lit.y > lit.z, "{:?}", lit);
+
+        // seen from the side the highlight moves away and only the dark diffuse would remain
+        let mut side = lights.clone();
+        side.eye = glm::vec3(5.0, 0.1, 0.0);
+        let off = shade(&side, &gold, &glm::vec3(0.0, 0.0, 0.0), &glm::vec3(0.0, 1.0, 0.0));
+        assert!(off.x < lit.x * 0.1, "{:?} {:?}", off, lit);
+    }
+
+    #[test]
+    fn ambient_is_occluded_and_emission_added() {
+        let mut lights = LightList::new();
+        lights.ambient = glm::vec3(1.0, 1.0, 1.0);
+        let surface = PbrSurface { occlusion: 0.25, emissive: glm::vec3(0.0, 0.0, 2.0), ..PbrSurface::default() };
+
+        let color = shade(&lights, &surface, &glm::vec3(0.0, 0.0, 0.0), &glm::vec3(0.0, 1.0, 0.0));
+        assert_eq!(color, glm::vec3(0.25, 0.25, 2.25));
+    }
+
+    #[test]
+    fn tone_mapping_brings_colors_into_range() {
+        let hdr = glm::vec3(0.5, 4.0, 100.0);
+        for tone_mapping in &[ToneMapping::Reinhard, ToneMapping::Aces] {
+            let ldr = tone_mapping.apply(&hdr, 1.0);
+            assert!(ldr.iter().all(|c| (0.0..=1.0).contains(c)), "{:?}", ldr);
+            assert!(ldr.x < ldr.y && ldr.y <= ldr.z);
+        }
+        assert_eq!(ToneMapping::Reinhard.apply(&glm::vec3(1.0, 1.0, 1.0), 1.0), glm::vec3(0.5, 0.5, 0.5));
+        assert_eq!(ToneMapping::None.apply(&hdr, 2.0), hdr * 2.0);
+        assert!(close(gamma_correct(&glm::vec3(0.5, 0.0, 1.0)).x, 0.5f32.powf(1.0 / 2.2)));
+    }
+}
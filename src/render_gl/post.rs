@@ -0,0 +1,608 @@
+/*!
+ * Post-processing, full screen passes over the rendered scene.
+ *
+ * The scene is rendered into an offscreen target. `PostStack::run` draws every enabled effect
+ * in order with the output of the one before as its input, ping-ponging between two targets,
+ * and the last one into the window. The effects are described in a toml resource:
+ *
+ * ```toml
+ * [[effects]]
+ * name = "grading"
+ * shader = "shaders/post/color_grading"  # drawn with shaders/post/fullscreen.vert
+ * kind = "pass"                           # or bloom, which blurs the bright parts first
+ * enabled = true
+ * toggle = "toggle_color_grading"         # input action that turns it on and off
+ * lut = "luts/warm.cube"                  # 3D lookup table for the `lut` sampler
+ *
+ * [effects.params]                        # uniforms, as in materials
+ * strength = 1.0
+ * ```
+ *
+ * Every pass gets its input in the `source` sampler and the size of one of its texels in uv
+ * in `texel_size`. Bloom effects also get the blurred bright parts in `bloom`, and effects
+ * whose shader has a `lut` sampler get their lookup table in it with its size in `lut_size`,
+ * one that leaves colors as they are when the file names none.
+ */
+use gl;
+use std::collections::BTreeMap;
+
+use crate::resources::Resources;
+use crate::resources::Error as ResourcesError;
+use super::framebuffer::{ AttachmentDesc, Error as FramebufferError, Framebuffer, FramebufferDesc, FramebufferSize };
+use super::material::param_value;
+use super::mesh::FullscreenTriangle;
+use super::render_state::{ RenderState, RenderStateCache };
+use super::shader::{ Error as ShaderError, Program, Shader };
+use super::texture::{ Texture, TextureFormat };
+use super::uniform::{ UniformValue, Uniforms };
+use super::viewport::Viewport;
+
+const FULLSCREEN_SHADER: &str = "shaders/post/fullscreen.vert";
+const BLOOM_BRIGHT_SHADER: &str = "shaders/post/bloom_bright";
+const BLUR_SHADER: &str = "shaders/post/blur";
+
+const SOURCE_UNIT: u32 = 0;
+const BLOOM_UNIT: u32 = 1;
+const LUT_UNIT: u32 = 2;
+const DEFAULT_BLUR_PASSES: i32 = 4;
+const IDENTITY_LUT_SIZE: i32 = 16;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load resource {}", name)]
+    UnableToLoadResource { name: String, #[cause] error: ResourcesError },
+
+    #[fail(display = "Failed to parse post-processing effects {}", name)]
+    Parse { name: String, #[cause] error: toml::de::Error },
+
+    #[fail(display = "Invalid post-processing effect {}: {}", name, message)]
+    Invalid { name: String, message: String },
+
+    #[fail(display = "Failed to load shader {}", name)]
+    Shader { name: String, #[cause] error: ShaderError },
+
+    #[fail(display = "Failed to link {}: {}", name, message)]
+    Link { name: String, message: String },
+
+    #[fail(display = "Invalid lookup table {}: {}", name, message)]
+    Lut { name: String, message: String },
+
+    #[fail(display = "Failed to create post-processing targets")]
+    Framebuffer { #[cause] error: FramebufferError },
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EffectKind {
+    #[default]
+    Pass,
+    Bloom,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+struct EffectFile {
+    name: String,
+    shader: String,
+    kind: EffectKind,
+    enabled: bool,
+    toggle: Option<String>,
+    lut: Option<String>,
+    params: BTreeMap<String, toml::Value>,
+}
+
+impl Default for EffectFile {
+    fn default() -> EffectFile {
+        EffectFile {
+            name: String::new(),
+            shader: String::new(),
+            kind: EffectKind::Pass,
+            enabled: true,
+            toggle: None,
+            lut: None,
+            params: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+struct PostFile {
+    effects: Vec<EffectFile>,
+}
+
+impl PostFile {
+    fn parse(name: &str, source: &str) -> Result<PostFile, Error> {
+        let file: PostFile = toml::from_str(source)
+            .map_err(|error| Error::Parse { name: String::from(name), error })?;
+
+        for effect in &file.effects {
+            if effect.name.is_empty() || effect.shader.is_empty() {
+                return Err(Error::Invalid { name: effect.name.clone(), message: String::from("needs a name and a shader") });
+            }
+        }
+        Ok(file)
+    }
+}
+
+impl EffectFile {
+    fn params(&self) -> Result<Uniforms, Error> {
+        self.params.iter().try_fold(Uniforms::new(), |uniforms, (param, value)| match param_value(value) {
+            Some(value) => Ok(uniforms.with(param, value)),
+            None => Err(Error::Invalid { name: self.name.clone(), message: format!("unsupported value for {}", param) }),
+        })
+    }
+}
+
+/**
+ * Parses an Adobe .cube 3D lookup table into its size and RGBA texels, red changing fastest
+ */
+pub fn parse_cube_lut(name: &str, source: &str) -> Result<(i32, Vec<f32>), Error> {
+    let lut_error = |message: &str| Error::Lut { name: String::from(name), message: String::from(message) };
+
+    let mut size = None;
+    let mut texels = Vec::new();
+    for line in source.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[0] {
+            "LUT_3D_SIZE" => size = Some(words.get(1).and_then(|size| size.parse::<i32>().ok())
+                .filter(|size| *size >= 2)
+                .ok_or_else(|| lut_error("LUT_3D_SIZE needs a size of at least 2"))?),
+            "LUT_1D_SIZE" => return Err(lut_error("1D tables are not supported")),
+            "TITLE" | "DOMAIN_MIN" | "DOMAIN_MAX" => {},
+            _ => {
+                let rgb = words.iter().map(|word| word.parse::<f32>().ok()).collect::<Option<Vec<f32>>>()
+                    .filter(|rgb| rgb.len() == 3)
+                    .ok_or_else(|| lut_error(&format!("expected red, green and blue in \"{}\"", line)))?;
+                texels.extend_from_slice(&[rgb[0], rgb[1], rgb[2], 1.0]);
+            },
+        }
+    }
+
+    let size = size.ok_or_else(|| lut_error("missing LUT_3D_SIZE"))?;
+    let expected = (size * size * size) as usize;
+    if texels.len() != expected * 4 {
+        return Err(lut_error(&format!("expected {} entries, found {}", expected, texels.len() / 4)));
+    }
+    Ok((size, texels))
+}
+
+/**
+ * A lookup table that leaves colors as they are
+ */
+pub fn identity_lut(size: i32) -> Vec<f32> {
+    let step = 1.0 / (size - 1) as f32;
+    let mut texels = Vec::with_capacity((size * size * size * 4) as usize);
+    for blue in 0..size {
+        for green in 0..size {
+            for red in 0..size {
+                texels.extend_from_slice(&[red as f32 * step, green as f32 * step, blue as f32 * step, 1.0]);
+            }
+        }
+    }
+    texels
+}
+
+fn lut_texture(gl: &gl::Gl, size: i32, texels: &[f32]) -> Texture {
+    Texture::from_data_3d(gl, size, size, size, TextureFormat::Rgba32F, texels)
+}
+
+/**
+ * The fullscreen vertex shader linked with `name`.frag
+ */
+fn load_program(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Program, Error> {
+    let vertex_shader = Shader::from_res(gl, res, FULLSCREEN_SHADER)
+        .map_err(|error| Error::Shader { name: String::from(FULLSCREEN_SHADER), error })?;
+    let fragment = format!("{}.frag", name);
+    let fragment_shader = Shader::from_res(gl, res, &fragment)
+        .map_err(|error| Error::Shader { name: fragment, error })?;
+    let program = Program::from_shaders(gl, &[vertex_shader, fragment_shader])
+        .map_err(|message| Error::Link { name: String::from(name), message })?;
+    program.set_label(name);
+    Ok(program)
+}
+
+fn target(gl: &gl::Gl, scale: f32, viewport: &Viewport) -> Result<Framebuffer, Error> {
+    let desc = FramebufferDesc {
+        size: FramebufferSize::Viewport { scale },
+        samples: 1,
+        color: vec![AttachmentDesc::texture(TextureFormat::Rgba16F)],
+        depth_stencil: None,
+    };
+    Framebuffer::new(gl, desc, viewport).map_err(|error| Error::Framebuffer { error })
+}
+
+fn color(framebuffer: &Framebuffer) -> &Texture {
+    framebuffer.color_texture(0).expect("post-processing targets have a color texture")
+}
+
+/**
+ * Draws `source` through `program` into the bound target
+ */
+fn draw_fullscreen(gl: &gl::Gl, triangle: &FullscreenTriangle, program: &Program, params: &Uniforms, source: &Texture) {
+    source.bind_to_unit(SOURCE_UNIT);
+    program.set_used();
+    params.apply(gl, program);
+    Uniforms::new()
+        .with("source", UniformValue::Int(SOURCE_UNIT as i32))
+        .with("texel_size", UniformValue::Vec2(glm::vec2(1.0 / source.width() as f32, 1.0 / source.height() as f32)))
+        .apply(gl, program);
+    triangle.draw();
+}
+
+/**
+ * The bright parts of the input, blurred at half resolution. Reads `threshold` and
+ * `blur_passes` from the parameters of its effect.
+ */
+pub struct Bloom {
+    bright: Program,
+    blur: Program,
+    targets: [Framebuffer; 2],
+}
+
+impl Bloom {
+    pub fn new(gl: &gl::Gl, res: &Resources, viewport: &Viewport) -> Result<Bloom, Error> {
+        Ok(Bloom {
+            bright: load_program(gl, res, BLOOM_BRIGHT_SHADER)?,
+            blur: load_program(gl, res, BLUR_SHADER)?,
+            targets: [target(gl, 0.5, viewport)?, target(gl, 0.5, viewport)?],
+        })
+    }
+
+    fn render(&self, gl: &gl::Gl, triangle: &FullscreenTriangle, source: &Texture, params: &Uniforms) -> &Texture {
+        let [first, second] = &self.targets;
+        first.bind();
+        first.set_viewport();
+        draw_fullscreen(gl, triangle, &self.bright, params, source);
+
+        let passes = match params.get("blur_passes") {
+            Some(UniformValue::Int(passes)) => passes.max(0),
+            _ => DEFAULT_BLUR_PASSES,
+        };
+        for _ in 0..passes {
+            for (from, to, direction) in &[(first, second, glm::vec2(1.0, 0.0)), (second, first, glm::vec2(0.0, 1.0))] {
+                to.bind();
+                let direction = Uniforms::new().with("direction", UniformValue::Vec2(*direction));
+                draw_fullscreen(gl, triangle, &self.blur, &direction, color(from));
+            }
+        }
+        color(first)
+    }
+
+    fn resize_to_viewport(&mut self, viewport: &Viewport) -> Result<(), Error> {
+        for target in self.targets.iter_mut() {
+            target.resize_to_viewport(viewport).map_err(|error| Error::Framebuffer { error })?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Effect {
+    name: String,
+    program: Program,
+    params: Uniforms,
+    enabled: bool,
+    toggle: Option<String>,
+    lut: Option<(i32, Texture)>,
+    bloom: Option<Bloom>,
+}
+
+impl Effect {
+    pub fn new(name: &str, program: Program) -> Effect {
+        Effect {
+            name: String::from(name),
+            program,
+            params: Uniforms::new(),
+            enabled: true,
+            toggle: None,
+            lut: None,
+            bloom: None,
+        }
+    }
+
+    pub fn with_param(mut self, name: &str, value: UniformValue) -> Effect {
+        self.params.set(name, value);
+        self
+    }
+
+    pub fn with_toggle(mut self, action: &str) -> Effect {
+        self.toggle = Some(String::from(action));
+        self
+    }
+
+    pub fn with_lut(mut self, size: i32, lut: Texture) -> Effect {
+        self.lut = Some((size, lut));
+        self
+    }
+
+    pub fn with_bloom(mut self, bloom: Bloom) -> Effect {
+        self.bloom = Some(bloom);
+        self
+    }
+
+    fn load(gl: &gl::Gl, res: &Resources, file: &EffectFile, viewport: &Viewport) -> Result<Effect, Error> {
+        let mut effect = Effect::new(&file.name, load_program(gl, res, &file.shader)?);
+        effect.params = file.params()?;
+        effect.enabled = file.enabled;
+        effect.toggle = file.toggle.clone();
+
+        if let Some(lut) = &file.lut {
+            let source = res.load_string(lut)
+                .map_err(|error| Error::UnableToLoadResource { name: lut.clone(), error })?;
+            let (size, texels) = parse_cube_lut(lut, &source)?;
+            effect = effect.with_lut(size, lut_texture(gl, size, &texels));
+        } else if effect.program.uniform_location("lut").is_some() {
+            effect = effect.with_lut(IDENTITY_LUT_SIZE, lut_texture(gl, IDENTITY_LUT_SIZE, &identity_lut(IDENTITY_LUT_SIZE)));
+        }
+        if file.kind == EffectKind::Bloom {
+            effect = effect.with_bloom(Bloom::new(gl, res, viewport)?);
+        }
+        Ok(effect)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /**
+     * The input action that turns the effect on and off, if it has one
+     */
+    pub fn toggle_action(&self) -> Option<&str> {
+        self.toggle.as_deref()
+    }
+
+    pub fn param(&self, name: &str) -> Option<UniformValue> {
+        self.params.get(name)
+    }
+
+    pub fn set_param(&mut self, name: &str, value: UniformValue) {
+        self.params.set(name, value);
+    }
+
+    fn render(&self, gl: &gl::Gl, triangle: &FullscreenTriangle, source: &Texture, target: Option<&Framebuffer>, viewport: &Viewport) {
+        let mut params = self.params.clone();
+        if let Some(bloom) = &self.bloom {
+            bloom.render(gl, triangle, source, &self.params).bind_to_unit(BLOOM_UNIT);
+            params.set("bloom", UniformValue::Int(BLOOM_UNIT as i32));
+        }
+        if let Some((size, lut)) = &self.lut {
+            lut.bind_to_unit(LUT_UNIT);
+            params.set("lut", UniformValue::Int(LUT_UNIT as i32));
+            params.set("lut_size", UniformValue::Float(*size as f32));
+        }
+
+        match target {
+            Some(target) => {
+                target.bind();
+                target.set_viewport();
+            },
+            None => {
+                unsafe {
+                    gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+                }
+                viewport.set_used(gl);
+            },
+        }
+        draw_fullscreen(gl, triangle, &self.program, &params, source);
+    }
+}
+
+/**
+ * The effects and the targets they ping-pong between
+ */
+pub struct PostStack {
+    gl: gl::Gl,
+    effects: Vec<Effect>,
+    targets: [Framebuffer; 2],
+    triangle: FullscreenTriangle,
+}
+
+impl PostStack {
+    pub fn new(gl: &gl::Gl, effects: Vec<Effect>, viewport: &Viewport) -> Result<PostStack, Error> {
+        Ok(PostStack {
+            gl: gl.clone(),
+            effects,
+            targets: [target(gl, 1.0, viewport)?, target(gl, 1.0, viewport)?],
+            triangle: FullscreenTriangle::new(gl),
+        })
+    }
+
+    /**
+     * The effects of the toml resource `name`, in its order
+     */
+    pub fn from_res(gl: &gl::Gl, res: &Resources, name: &str, viewport: &Viewport) -> Result<PostStack, Error> {
+        let source = res.load_string(name)
+            .map_err(|error| Error::UnableToLoadResource { name: String::from(name), error })?;
+        let effects = PostFile::parse(name, &source)?.effects.iter()
+            .map(|file| Effect::load(gl, res, file, viewport))
+            .collect::<Result<Vec<Effect>, Error>>()?;
+        PostStack::new(gl, effects, viewport)
+    }
+
+    pub fn effects(&self) -> &[Effect] {
+        &self.effects
+    }
+
+    pub fn effects_mut(&mut self) -> &mut [Effect] {
+        &mut self.effects
+    }
+
+    pub fn effect_mut(&mut self, name: &str) -> Option<&mut Effect> {
+        self.effects.iter_mut().find(|effect| effect.name == name)
+    }
+
+    /**
+     * Turns the effects whose toggle action is in `pressed` on or off
+     */
+    pub fn toggle_pressed<F>(&mut self, pressed: F)
+        where F: Fn(&str) -> bool
+    {
+        for effect in self.effects.iter_mut() {
+            if effect.toggle_action().is_some_and(&pressed) {
+                effect.enabled = !effect.enabled;
+                info!("{} {}", effect.name, if effect.enabled { "on" } else { "off" });
+            }
+        }
+    }
+
+    /**
+     * Recreates the targets at the size `Viewport::update_size` last gave `viewport`, if it changed
+     */
+    fn follow_viewport(&mut self, viewport: &Viewport) -> Result<(), Error> {
+        for target in self.targets.iter_mut() {
+            target.resize_to_viewport(viewport).map_err(|error| Error::Framebuffer { error })?;
+        }
+        for bloom in self.effects.iter_mut().filter_map(|effect| effect.bloom.as_mut()) {
+            bloom.resize_to_viewport(viewport)?;
+        }
+        Ok(())
+    }
+
+    /**
+     * Runs the enabled effects over the color of `scene`, the last one draws into the window.
+     * Without any the scene is copied to the window as it is.
+     */
+    pub fn run(&mut self, render_state: &mut RenderStateCache, scene: &Framebuffer, viewport: &Viewport)
+    -> Result<(), Error>
+    {
+        self.follow_viewport(viewport)?;
+        let enabled: Vec<&Effect> = self.effects.iter().filter(|effect| effect.enabled).collect();
+        let scene_color = match scene.color_texture(0) {
+            Some(texture) if !enabled.is_empty() => texture,
+            _ => {
+                scene.blit_to_default(viewport);
+                return Ok(());
+            },
+        };
+
+        render_state.apply(&RenderState::default());
+        let mut source = scene_color;
+        for (i, effect) in enabled.iter().enumerate() {
+            let target = if i + 1 == enabled.len() { None } else { Some(&self.targets[i % 2]) };
+            effect.render(&self.gl, &self.triangle, source, target, viewport);
+            if let Some(target) = target {
+                source = color(target);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gl::recording::Recorder;
+
+    #[test]
+    fn effect_files_keep_their_order_and_defaults() {
+        let source = r#"
+            [[effects]]
+            name = "bloom"
+            shader = "shaders/post/bloom"
+            kind = "bloom"
+            toggle = "toggle_bloom"
+            params = { intensity = 0.5, blur_passes = 3 }
+
+            [[effects]]
+            name = "vignette"
+            shader = "shaders/post/vignette"
+            enabled = false
+        "#;
+        let file = PostFile::parse("post", source).unwrap();
+        let names: Vec<&str> = file.effects.iter().map(|effect| effect.name.as_str()).collect();
+        assert_eq!(names, vec!["bloom", "vignette"]);
+        assert_eq!(file.effects[0].kind, EffectKind::Bloom);
+        assert!(file.effects[0].enabled);
+        assert!(!file.effects[1].enabled);
+        assert_eq!(file.effects[1].kind, EffectKind::Pass);
+
+        let params = file.effects[0].params().unwrap();
+        assert_eq!(params.get("blur_passes"), Some(UniformValue::Int(3)));
+        assert_eq!(params.get("intensity"), Some(UniformValue::Float(0.5)));
+
+        assert!(PostFile::parse("post", "[[effects]]\nname = \"nameless shader\"").is_err());
+    }
+
+    #[test]
+    fn cube_luts_are_read_red_first() {
+        let source = "TITLE \"swap\"\n# comment\nLUT_3D_SIZE 2\n\
+            0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1 1 1\n";
+        let (size, texels) = parse_cube_lut("swap", source).unwrap();
+        assert_eq!(size, 2);
+        assert_eq!(texels, identity_lut(2));
+        assert_eq!(&texels[4..8], &[1.0, 0.0, 0.0, 1.0]);
+
+        assert!(parse_cube_lut("short", "LUT_3D_SIZE 2\n0 0 0\n").is_err());
+        assert!(parse_cube_lut("sizeless", "0 0 0\n").is_err());
+        assert!(parse_cube_lut("1d", "LUT_1D_SIZE 2\n").is_err());
+    }
+
+    #[test]
+    fn enabled_effects_ping_pong_into_the_window() {
+        let (gl, recorder) = Recorder::new();
+        recorder.set_return("CheckFramebufferStatus", gl::FRAMEBUFFER_COMPLETE as i64);
+        recorder.set_integer(gl::MAX_COLOR_ATTACHMENTS, 8);
+        let viewport = Viewport::for_window(64, 32);
+        let effect = |name| Effect::new(name, Program::from_shaders(&gl, &[]).unwrap());
+        let mut post = PostStack::new(&gl, vec![effect("a"), effect("b").with_toggle("toggle_b"), effect("c")], &viewport).unwrap();
+        let scene = Framebuffer::new(&gl, FramebufferDesc::viewport_hdr_depth(), &viewport).unwrap();
+        let mut render_state = RenderStateCache::new(&gl);
+
+        recorder.take_calls();
+        post.run(&mut render_state, &scene, &viewport).unwrap();
+        assert_eq!(recorder.count("DrawArrays"), 3);
+        assert_eq!(recorder.last_call_to("BindFramebuffer").unwrap().int(1), 0);
+
+        post.toggle_pressed(|action| action == "toggle_b");
+        assert!(!post.effects()[1].is_enabled());
+        post.effects_mut().iter_mut().for_each(|effect| effect.set_enabled(false));
+        recorder.take_calls();
+        post.run(&mut render_state, &scene, &viewport).unwrap();
+        assert_eq!(recorder.count("DrawArrays"), 0);
+        assert_eq!(recorder.count("BlitFramebuffer"), 1);
+    }
+
+    #[test]
+    fn bloom_and_lookup_table_use_their_own_units() {
+        let (gl, recorder) = Recorder::new();
+        recorder.set_integer(gl::MAX_COLOR_ATTACHMENTS, 8);
+        let viewport = Viewport::for_window(64, 32);
+        let program = || Program::from_shaders(&gl, &[]).unwrap();
+        let bloom = Bloom {
+            bright: program(),
+            blur: program(),
+            targets: [target(&gl, 0.5, &viewport).unwrap(), target(&gl, 0.5, &viewport).unwrap()],
+        };
+        let lut = lut_texture(&gl, 2, &identity_lut(2));
+        let effect = Effect::new("graded bloom", program()).with_bloom(bloom).with_lut(2, lut);
+        let source = Texture::new_2d(&gl, 64, 32, TextureFormat::Rgba16F);
+
+        recorder.take_calls();
+        effect.render(&gl, &FullscreenTriangle::new(&gl), &source, None, &viewport);
+
+        // the unit each texture target was last bound on
+        let mut unit = 0;
+        let mut bound = Vec::new();
+        for call in recorder.calls() {
+            match call.name {
+                "ActiveTexture" => unit = call.int(0) - gl::TEXTURE0 as i64,
+                "BindTexture" if call.int(1) != 0 => bound.push((call.int(0), unit)),
+                _ => {},
+            }
+        }
+        assert!(bound.contains(&(gl::TEXTURE_2D as i64, BLOOM_UNIT as i64)));
+        assert!(bound.contains(&(gl::TEXTURE_3D as i64, LUT_UNIT as i64)));
+        assert_eq!(bound.last(), Some(&(gl::TEXTURE_2D as i64, SOURCE_UNIT as i64)));
+    }
+}
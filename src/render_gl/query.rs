@@ -0,0 +1,159 @@
+use gl;
+
+/**
+ * What a query measures. Timer results are in nanoseconds, occlusion results count samples
+ * (or are 0 / 1 for the `Any*` kinds).
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum QueryKind {
+    TimeElapsed,
+    Timestamp,                      // written with `record_timestamp` instead of begin / end
+    SamplesPassed,
+    AnySamplesPassed,
+    AnySamplesPassedConservative,
+}
+
+impl QueryKind {
+    pub fn gl_enum(&self) -> gl::types::GLenum {
+        match self {
+            QueryKind::TimeElapsed => gl::TIME_ELAPSED,
+            QueryKind::Timestamp => gl::TIMESTAMP,
+            QueryKind::SamplesPassed => gl::SAMPLES_PASSED,
+            QueryKind::AnySamplesPassed => gl::ANY_SAMPLES_PASSED,
+            QueryKind::AnySamplesPassedConservative => gl::ANY_SAMPLES_PASSED_CONSERVATIVE,
+        }
+    }
+}
+
+/**
+ * A query object. Results arrive a few frames late, poll them with `result`, which never
+ * stalls the pipeline, instead of `wait_result`.
+ * Only one query of each kind other than timestamps can be active at a time.
+ */
+pub struct Query {
+    gl: gl::Gl,
+    id: gl::types::GLuint,
+    kind: QueryKind,
+}
+
+impl Query {
+    pub fn new(gl: &gl::Gl, kind: QueryKind)
+    -> Query
+    {
+        let mut id: gl::types::GLuint = 0;
+        unsafe {
+            gl.GenQueries(1, &mut id);
+        }
+
+        Query {
+            gl: gl.clone(),
+            id,
+            kind,
+        }
+    }
+
+    pub fn begin(&self)
+    {
+        debug_assert!(self.kind != QueryKind::Timestamp, "timestamp queries are recorded, not begun");
+        unsafe {
+            self.gl.BeginQuery(self.kind.gl_enum(), self.id);
+        }
+    }
+
+    pub fn end(&self)
+    {
+        unsafe {
+            self.gl.EndQuery(self.kind.gl_enum());
+        }
+    }
+
+    /**
+     * Stores the gpu time once every command issued before it has completed
+     */
+    pub fn record_timestamp(&self)
+    {
+        debug_assert!(self.kind == QueryKind::Timestamp, "only timestamp queries record timestamps");
+        unsafe {
+            self.gl.QueryCounter(self.id, gl::TIMESTAMP);
+        }
+    }
+
+    pub fn is_available(&self) -> bool {
+        let mut available: gl::types::GLint = 0;
+        unsafe {
+            self.gl.GetQueryObjectiv(self.id, gl::QUERY_RESULT_AVAILABLE, &mut available);
+        }
+        available != 0
+    }
+
+    /**
+     * The result if the gpu got to it, without waiting
+     */
+    pub fn result(&self) -> Option<u64> {
+        if self.is_available() {
+            Some(self.wait_result())
+        } else {
+            None
+        }
+    }
+
+    /**
+     * The result, blocking until the gpu has finished the measured commands
+     */
+    pub fn wait_result(&self) -> u64 {
+        let mut result: gl::types::GLuint64 = 0;
+        unsafe {
+            self.gl.GetQueryObjectui64v(self.id, gl::QUERY_RESULT, &mut result);
+        }
+        result
+    }
+
+    pub fn kind(&self) -> QueryKind {
+        self.kind
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+}
+
+impl Drop for Query {
+    fn drop(&mut self)
+    {
+        unsafe {
+            self.gl.DeleteQueries(1, &self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gl::recording::Recorder;
+
+    #[test]
+    fn result_does_not_wait_for_the_gpu() {
+        let (gl, recorder) = Recorder::new();
+        let query = Query::new(&gl, QueryKind::SamplesPassed);
+        query.begin();
+        query.end();
+
+        assert_eq!(query.result(), None);
+        assert_eq!(recorder.count("GetQueryObjectui64v"), 0);
+
+        recorder.set_integer(gl::QUERY_RESULT_AVAILABLE, 1);
+        assert_eq!(query.result(), Some(0));
+        assert_eq!(recorder.count("GetQueryObjectui64v"), 1);
+    }
+
+    #[test]
+    fn timestamps_use_query_counter() {
+        let (gl, recorder) = Recorder::new();
+        let query = Query::new(&gl, QueryKind::Timestamp);
+        query.record_timestamp();
+
+        let call = recorder.last_call_to("QueryCounter").unwrap();
+        assert_eq!(call.int(0), query.id() as i64);
+        assert_eq!(call.int(1), gl::TIMESTAMP as i64);
+    }
+}
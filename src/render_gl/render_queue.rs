@@ -0,0 +1,492 @@
+/*!
+ * Render queue that sorts and batches draws before submitting them.
+ *
+ * Draws are collected for a frame, then sorted by a 64 bit `SortKey`:
+ *
+ * ```text
+ * opaque       0 | program 16 | material 16 | mesh 16 | depth 15      front to back
+ * translucent  1 | far depth 16 | program 16 | material 16 | mesh 15  back to front
+ * ```
+ *
+ * Opaque draws are grouped by state so consecutive draws share a program, material and
+ * vertex array; the depth bits only order draws within a group, front to back so early
+ * depth testing rejects hidden fragments. Translucent draws come last, furthest first, as
+ * blending needs. Consecutive draws with the same state are merged into one batch that
+ * sets the state once and only uploads each draw's matrix.
+ *
+ * Program, material and mesh ids in keys are handed out per frame in order of first use.
+ */
+use gl;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::camera::Camera;
+use crate::mvp_matrix::normal_matrix;
+use super::material::Material;
+use super::mesh::Mesh;
+use super::render_state::{ BlendMode, RenderState, RenderStateCache };
+use super::uniform::UniformValue;
+
+const MVP_UNIFORM: &str = "MVP";
+const MODEL_UNIFORM: &str = "Model";
+const NORMAL_MATRIX_UNIFORM: &str = "NormalMatrix";
+
+const TRANSLUCENT_BIT: u64 = 1 << 63;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SortKey(pub u64);
+
+impl SortKey {
+    pub fn opaque(program: u16, material: u16, mesh: u16, depth: u16) -> SortKey {
+        SortKey(
+            (program as u64) << 47
+            | (material as u64) << 31
+            | (mesh as u64) << 15
+            | (depth >> 1) as u64
+        )
+    }
+
+    /**
+     * Only the low 15 bits of `mesh` fit, draws are told apart by their ids when batching
+     */
+    pub fn translucent(program: u16, material: u16, mesh: u16, depth: u16) -> SortKey {
+        SortKey(
+            TRANSLUCENT_BIT
+            | (!depth as u64) << 47
+            | (program as u64) << 31
+            | (material as u64) << 15
+            | (mesh & 0x7fff) as u64
+        )
+    }
+
+    pub fn is_translucent(&self) -> bool {
+        self.0 & TRANSLUCENT_BIT != 0
+    }
+}
+
+/**
+ * Maps the distance from the camera to 0..u16::MAX between the clipping planes
+ */
+pub fn quantize_depth(distance: f32, near: f32, far: f32) -> u16 {
+    let range = (far - near).max(f32::EPSILON);
+    (((distance - near) / range).clamp(0.0, 1.0) * u16::MAX as f32).round() as u16
+}
+
+#[derive(Clone)]
+pub struct DrawCommand {
+    pub mesh: Rc<Mesh>,
+    pub material: Rc<Material>,
+    pub model: glm::Mat4,
+}
+
+impl DrawCommand {
+    pub fn is_translucent(&self) -> bool {
+        self.material.blend() != BlendMode::Opaque
+    }
+
+    /**
+     * World space position of the model's origin
+     */
+    pub fn position(&self) -> glm::Vec3 {
+        glm::vec3(self.model[(0, 3)], self.model[(1, 3)], self.model[(2, 3)])
+    }
+}
+
+/**
+ * Per frame ids of the state a draw needs, equal ids can share a batch
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct DrawState {
+    program: u16,
+    material: u16,
+    mesh: u16,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct QueueItem {
+    key: SortKey,
+    state: DrawState,
+    command: usize,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    pub draws: usize,
+    pub batches: usize,
+}
+
+pub struct RenderQueue {
+    commands: Vec<DrawCommand>,
+    items: Vec<QueueItem>,
+    sorted: bool,
+    programs: HashMap<gl::types::GLuint, u16>,
+    materials: HashMap<*const Material, u16>,
+    meshes: HashMap<gl::types::GLuint, u16>,
+    eye: glm::Vec3,
+    near: f32,
+    far: f32,
+}
+
+impl Default for RenderQueue {
+    fn default() -> RenderQueue {
+        RenderQueue::new()
+    }
+}
+
+impl RenderQueue {
+    pub fn new() -> RenderQueue {
+        RenderQueue {
+            commands: Vec::new(),
+            items: Vec::new(),
+            sorted: true,
+            programs: HashMap::new(),
+            materials: HashMap::new(),
+            meshes: HashMap::new(),
+            eye: glm::vec3(0.0, 0.0, 0.0),
+            near: 0.1,
+            far: 100.0,
+        }
+    }
+
+    /**
+     * Clears the queue for a frame seen from `eye`, depth is measured between `near` and `far`
+     */
+    pub fn begin(&mut self, eye: glm::Vec3, near: f32, far: f32) {
+        self.commands.clear();
+        self.items.clear();
+        self.programs.clear();
+        self.materials.clear();
+        self.meshes.clear();
+        self.sorted = true;
+        self.eye = eye;
+        self.near = near;
+        self.far = far;
+    }
+
+    pub fn begin_for(&mut self, camera: &Camera) {
+        self.begin(camera.position(), camera.near_clipping_plane(), camera.far_clipping_plane());
+    }
+
+    pub fn push(&mut self, command: DrawCommand) {
+        let state = DrawState {
+            program: dense_id(&mut self.programs, command.material.program().id()),
+            material: dense_id(&mut self.materials, Rc::as_ptr(&command.material)),
+            mesh: dense_id(&mut self.meshes, command.mesh.id()),
+        };
+        let depth = quantize_depth(glm::distance(&self.eye, &command.position()), self.near, self.far);
+        let key = if command.is_translucent() {
+            SortKey::translucent(state.program, state.material, state.mesh, depth)
+        } else {
+            SortKey::opaque(state.program, state.material, state.mesh, depth)
+        };
+
+        self.items.push(QueueItem { key, state, command: self.commands.len() });
+        self.commands.push(command);
+        self.sorted = false;
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /**
+     * Sorts by key, draws with equal keys keep the order they were pushed in
+     */
+    pub fn sort(&mut self) {
+        if !self.sorted {
+            self.items.sort_by_key(|item| item.key);
+            self.sorted = true;
+        }
+    }
+
+    /**
+     * Draws in submission order, sorts first
+     */
+    pub fn sorted_commands(&mut self) -> impl Iterator<Item = &DrawCommand> {
+        self.sort();
+        let commands = &self.commands;
+        self.items.iter().map(move |item| &commands[item.command])
+    }
+
+    /**
+     * Ranges of sorted draws that share their state, sorts first
+     */
+    pub fn batches(&mut self) -> Vec<Range<usize>> {
+        self.sort();
+
+        let mut batches = Vec::new();
+        let mut start = 0;
+        for i in 1..=self.items.len() {
+            if i == self.items.len() || self.items[i].state != self.items[start].state {
+                batches.push(start..i);
+                start = i;
+            }
+        }
+        batches
+    }
+
+    /**
+     * Draws everything through `camera`. Each draw's matrices go to the `MVP`, `Model` and
+     * `NormalMatrix` uniforms of programs that have them.
+     */
+    pub fn submit(&mut self, gl: &gl::Gl, render_state: &mut RenderStateCache, camera: &mut Camera)
+    -> QueueStats
+    {
+        self.submit_with(gl, render_state, camera, |_, _| {})
+    }
+
+    /**
+     * Like `submit`, with `after_opaque` called between the opaque and translucent draws, eg.
+     * to draw a background only where nothing opaque is
+     */
+    pub fn submit_with<F>(&mut self, gl: &gl::Gl, render_state: &mut RenderStateCache, camera: &mut Camera, after_opaque: F)
+    -> QueueStats
+        where F: FnOnce(&mut RenderStateCache, &Camera)
+    {
+        let mut after_opaque = Some(after_opaque);
+        let projection_view = camera.get_projection_view_matrix();
+        let batches = self.batches();
+        let mut previous: Option<&Material> = None;
+
+        for batch in &batches {
+            let first = &self.commands[self.items[batch.start].command];
+            if first.is_translucent() {
+                if let Some(after_opaque) = after_opaque.take() {
+                    after_opaque(render_state, camera);
+                    previous = None;    // it may have used other programs and textures
+                }
+            }
+            let state = if first.is_translucent() { RenderState::transparent_3d() } else { RenderState::opaque_3d() };
+            render_state.apply(&RenderState { blend: first.material.blend(), ..state });
+
+            first.material.apply(gl, previous);
+            first.mesh.bind();
+            let program = first.material.program();
+            let mvp_location = program.uniform_location(MVP_UNIFORM);
+            let model_location = program.uniform_location(MODEL_UNIFORM);
+            let normal_matrix_location = program.uniform_location(NORMAL_MATRIX_UNIFORM);
+            previous = Some(&first.material);
+
+            for item in &self.items[batch.clone()] {
+                let command = &self.commands[item.command];
+                if let Some(location) = mvp_location {
+                    UniformValue::Mat4(projection_view * command.model).upload(gl, location);
+                }
+                if let Some(location) = model_location {
+                    UniformValue::Mat4(command.model).upload(gl, location);
+                }
+                if let Some(location) = normal_matrix_location {
+                    UniformValue::Mat3(normal_matrix(&command.model)).upload(gl, location);
+                }
+                command.mesh.draw_bound(gl);
+            }
+        }
+        if let Some(after_opaque) = after_opaque {
+            after_opaque(render_state, camera);
+        }
+
+        QueueStats {
+            draws: self.items.len(),
+            batches: batches.len(),
+        }
+    }
+}
+
+/**
+ * Panics on the 65537th distinct key of a frame rather than wrapping, equal ids would batch
+ * draws that need different state
+ */
+fn dense_id<K: std::hash::Hash + Eq>(ids: &mut HashMap<K, u16>, key: K) -> u16 {
+    let next = ids.len();
+    *ids.entry(key).or_insert_with(|| {
+        assert!(next <= u16::MAX as usize, "more than 65536 programs, materials or meshes in one frame");
+        next as u16
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gl::recording::Recorder;
+    use super::super::buffer::{ ArrayBuffer, VertexArray };
+    use super::super::shader::Program;
+
+    fn mesh(gl: &gl::Gl) -> Rc<Mesh> {
+        Rc::new(Mesh::new(VertexArray::new(gl), ArrayBuffer::new(gl), gl::TRIANGLES, 3))
+    }
+
+    /**
+     * A program named `id`, the queue tells programs apart by name
+     */
+    fn program(gl: &gl::Gl, recorder: &Recorder, id: i64) -> Rc<Program> {
+        recorder.set_return("CreateProgram", id);
+        Rc::new(Program::from_shaders(gl, &[]).unwrap())
+    }
+
+    fn material(program: &Rc<Program>, blend: BlendMode) -> Rc<Material> {
+        Rc::new(Material::new("test", program.clone()).with_blend(blend))
+    }
+
+    fn camera() -> Camera {
+        Camera::new(1.0, 0.75, 0.1, 100.0,
+            glm::vec3(0.0, 0.0, 0.0), glm::vec3(0.0, 0.0, 1.0), glm::vec3(0.0, 1.0, 0.0))
+    }
+
+    fn draw(mesh: &Rc<Mesh>, material: &Rc<Material>, z: f32) -> DrawCommand {
+        DrawCommand {
+            mesh: mesh.clone(),
+            material: material.clone(),
+            model: glm::translation(&glm::vec3(0.0, 0.0, z)),
+        }
+    }
+
+    #[test]
+    fn opaque_keys_group_by_state_before_depth() {
+        let near = SortKey::opaque(1, 0, 0, 0);
+        let far = SortKey::opaque(1, 0, 0, u16::MAX);
+        let other_program = SortKey::opaque(2, 0, 0, 0);
+
+        assert!(near < far);
+        assert!(far < other_program);
+        assert!(SortKey::opaque(1, 0, 1, 0) > far);
+        assert!(!far.is_translucent());
+    }
+
+    #[test]
+    fn translucent_keys_sort_after_opaque_and_back_to_front() {
+        let far = SortKey::translucent(0, 0, 0, u16::MAX);
+        let near = SortKey::translucent(0, 0, 0, 0);
+
+        assert!(far.is_translucent());
+        assert!(far < near);
+        assert!(SortKey::opaque(u16::MAX, u16::MAX, u16::MAX, u16::MAX) < far);
+        // depth beats state for translucent draws
+        assert!(SortKey::translucent(9, 9, 9, 100) < SortKey::translucent(0, 0, 0, 50));
+    }
+
+    #[test]
+    fn depth_is_clamped_to_the_clipping_planes() {
+        assert_eq!(quantize_depth(0.0, 1.0, 11.0), 0);
+        assert_eq!(quantize_depth(6.0, 1.0, 11.0), 32768);
+        assert_eq!(quantize_depth(50.0, 1.0, 11.0), u16::MAX);
+    }
+
+    #[test]
+    fn ids_are_dense_in_order_of_first_use() {
+        let mut ids = HashMap::new();
+        assert_eq!(dense_id(&mut ids, 'b'), 0);
+        assert_eq!(dense_id(&mut ids, 'a'), 1);
+        assert_eq!(dense_id(&mut ids, 'b'), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "more than 65536")]
+    fn ids_do_not_wrap_around() {
+        let mut ids = HashMap::new();
+        for key in 0..=u16::MAX as u32 {
+            dense_id(&mut ids, key);
+        }
+        assert_eq!(dense_id(&mut ids, 0), 0);
+        dense_id(&mut ids, u16::MAX as u32 + 1);
+    }
+
+    #[test]
+    fn draws_are_sorted_and_merged_into_batches() {
+        let (gl, recorder) = Recorder::new();
+        let (rock, tree) = (mesh(&gl), mesh(&gl));
+        let lit = material(&program(&gl, &recorder, 1), BlendMode::Opaque);
+        let glass = material(&program(&gl, &recorder, 2), BlendMode::Alpha);
+
+        let mut queue = RenderQueue::new();
+        queue.begin(glm::vec3(0.0, 0.0, 0.0), 0.1, 100.0);
+        queue.push(draw(&tree, &lit, 8.0));
+        queue.push(draw(&rock, &glass, 2.0));
+        queue.push(draw(&rock, &lit, 5.0));
+        queue.push(draw(&tree, &lit, 3.0));
+        queue.push(draw(&rock, &glass, 9.0));
+
+        let order: Vec<(u32, f32)> = queue.sorted_commands()
+            .map(|command| (command.mesh.id(), command.position().z))
+            .collect();
+        assert_eq!(order, vec![
+            (tree.id(), 3.0), (tree.id(), 8.0),     // front to back within a batch
+            (rock.id(), 5.0),
+            (rock.id(), 9.0), (rock.id(), 2.0),     // translucent, back to front
+        ]);
+        assert_eq!(queue.batches(), vec![0..2, 2..3, 3..5]);
+    }
+
+    #[test]
+    fn translucent_draws_between_others_split_batches() {
+        let (gl, recorder) = Recorder::new();
+        let mesh = mesh(&gl);
+        let program = program(&gl, &recorder, 1);
+        let red = material(&program, BlendMode::Alpha);
+        let blue = material(&program, BlendMode::Alpha);
+
+        let mut queue = RenderQueue::new();
+        queue.begin(glm::vec3(0.0, 0.0, 0.0), 0.1, 100.0);
+        queue.push(draw(&mesh, &red, 9.0));
+        queue.push(draw(&mesh, &blue, 5.0));
+        queue.push(draw(&mesh, &red, 1.0));
+
+        // depth order wins over grouping, so red is set up twice
+        assert_eq!(queue.batches(), vec![0..1, 1..2, 2..3]);
+    }
+
+    #[test]
+    fn submit_sets_state_once_per_batch() {
+        let (gl, recorder) = Recorder::new();
+        let mesh = mesh(&gl);
+        let material = material(&program(&gl, &recorder, 1), BlendMode::Opaque);
+        let mut render_state = RenderStateCache::new(&gl);
+        let mut camera = camera();
+
+        let mut queue = RenderQueue::new();
+        queue.begin_for(&camera);
+        for z in 1..4 {
+            queue.push(draw(&mesh, &material, z as f32));
+        }
+        recorder.take_calls();
+
+        let stats = queue.submit(&gl, &mut render_state, &mut camera);
+        assert_eq!(stats, QueueStats { draws: 3, batches: 1 });
+        assert_eq!(recorder.count("DrawArrays"), 3);
+        assert_eq!(recorder.count("UniformMatrix4fv"), 6);     // MVP and Model
+        assert_eq!(recorder.count("UniformMatrix3fv"), 3);
+        assert_eq!(recorder.count("UseProgram"), 1);
+        assert_eq!(recorder.count("BindVertexArray"), 1);
+    }
+
+    #[test]
+    fn after_opaque_runs_before_translucent_draws() {
+        let (gl, recorder) = Recorder::new();
+        let mesh = mesh(&gl);
+        let lit = material(&program(&gl, &recorder, 1), BlendMode::Opaque);
+        let glass = material(&program(&gl, &recorder, 2), BlendMode::Alpha);
+        let mut render_state = RenderStateCache::new(&gl);
+        let mut camera = camera();
+
+        let mut queue = RenderQueue::new();
+        queue.begin_for(&camera);
+        queue.push(draw(&mesh, &glass, 2.0));
+        queue.push(draw(&mesh, &lit, 4.0));
+        recorder.take_calls();
+
+        let mut draws_before = None;
+        queue.submit_with(&gl, &mut render_state, &mut camera, |_, _| draws_before = Some(recorder.count("DrawArrays")));
+        assert_eq!(draws_before, Some(1));
+        assert_eq!(recorder.count("DrawArrays"), 2);
+
+        let mut called = false;
+        let mut queue = RenderQueue::new();
+        queue.begin_for(&camera);
+        queue.submit_with(&gl, &mut render_state, &mut camera, |_, _| called = true);
+        assert!(called, "called even without translucent draws");
+    }
+}
@@ -0,0 +1,530 @@
+use nalgebra as na;
+use gl;
+use std::ops::BitOr;
+
+/**
+ * Comparison used by both the depth test and the stencil test
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CompareFunc {
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+impl CompareFunc {
+    pub fn gl_enum(&self) -> gl::types::GLenum {
+        match self {
+            CompareFunc::Never => gl::NEVER,
+            CompareFunc::Less => gl::LESS,
+            CompareFunc::Equal => gl::EQUAL,
+            CompareFunc::LessEqual => gl::LEQUAL,
+            CompareFunc::Greater => gl::GREATER,
+            CompareFunc::NotEqual => gl::NOTEQUAL,
+            CompareFunc::GreaterEqual => gl::GEQUAL,
+            CompareFunc::Always => gl::ALWAYS,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StencilOp {
+    Keep,
+    Zero,
+    Replace,
+    Increment,
+    IncrementWrap,
+    Decrement,
+    DecrementWrap,
+    Invert,
+}
+
+impl StencilOp {
+    pub fn gl_enum(&self) -> gl::types::GLenum {
+        match self {
+            StencilOp::Keep => gl::KEEP,
+            StencilOp::Zero => gl::ZERO,
+            StencilOp::Replace => gl::REPLACE,
+            StencilOp::Increment => gl::INCR,
+            StencilOp::IncrementWrap => gl::INCR_WRAP,
+            StencilOp::Decrement => gl::DECR,
+            StencilOp::DecrementWrap => gl::DECR_WRAP,
+            StencilOp::Invert => gl::INVERT,
+        }
+    }
+}
+
+/**
+ * Stencil test and the operations applied when it (or the depth test after it) fails or passes.
+ * The same settings are used for front and back faces.
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StencilState {
+    pub func: CompareFunc,
+    pub reference: gl::types::GLint,
+    pub read_mask: gl::types::GLuint,
+    pub write_mask: gl::types::GLuint,
+    pub stencil_fail: StencilOp,
+    pub depth_fail: StencilOp,
+    pub pass: StencilOp,
+}
+
+impl Default for StencilState {
+    fn default() -> StencilState {
+        StencilState {
+            func: CompareFunc::Always,
+            reference: 0,
+            read_mask: !0,
+            write_mask: !0,
+            stencil_fail: StencilOp::Keep,
+            depth_fail: StencilOp::Keep,
+            pass: StencilOp::Keep,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CullFace {
+    Front,
+    Back,
+    FrontAndBack,
+}
+
+impl CullFace {
+    pub fn gl_enum(&self) -> gl::types::GLenum {
+        match self {
+            CullFace::Front => gl::FRONT,
+            CullFace::Back => gl::BACK,
+            CullFace::FrontAndBack => gl::FRONT_AND_BACK,
+        }
+    }
+}
+
+/**
+ * Winding order of triangles which are considered to face the camera
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FrontFace {
+    CounterClockwise,
+    Clockwise,
+}
+
+impl FrontFace {
+    pub fn gl_enum(&self) -> gl::types::GLenum {
+        match self {
+            FrontFace::CounterClockwise => gl::CCW,
+            FrontFace::Clockwise => gl::CW,
+        }
+    }
+}
+
+/**
+ * Common blend equations. `Custom` takes the source and destination factors
+ * that would be passed to glBlendFunc, eg. gl::ONE and gl::ONE_MINUS_SRC_COLOR
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    Opaque,
+    Alpha,                  // src * a + dst * (1 - a)
+    PremultipliedAlpha,     // src + dst * (1 - a)
+    Additive,               // src + dst
+    Multiply,               // src * dst
+    Custom { src: gl::types::GLenum, dst: gl::types::GLenum },
+}
+
+impl BlendMode {
+    /**
+     * The (source, destination) factors, None when blending is disabled
+     */
+    pub fn factors(&self) -> Option<(gl::types::GLenum, gl::types::GLenum)> {
+        match *self {
+            BlendMode::Opaque => None,
+            BlendMode::Alpha => Some((gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA)),
+            BlendMode::PremultipliedAlpha => Some((gl::ONE, gl::ONE_MINUS_SRC_ALPHA)),
+            BlendMode::Additive => Some((gl::ONE, gl::ONE)),
+            BlendMode::Multiply => Some((gl::DST_COLOR, gl::ZERO)),
+            BlendMode::Custom { src, dst } => Some((src, dst)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Scissor {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+}
+
+/**
+ * Everything a draw call needs from the fixed function part of the pipeline.
+ * `None` disables the corresponding test.
+ *
+ * The default matches a fresh gl context: no depth test, depth writes on,
+ * no stencil test, no culling, counter clockwise front faces, no blending and no scissor.
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RenderState {
+    pub depth_test: Option<CompareFunc>,
+    pub depth_write: bool,
+    pub stencil: Option<StencilState>,
+    pub cull_face: Option<CullFace>,
+    pub front_face: FrontFace,
+    pub blend: BlendMode,
+    pub scissor: Option<Scissor>,
+}
+
+impl Default for RenderState {
+    fn default() -> RenderState {
+        RenderState {
+            depth_test: None,
+            depth_write: true,
+            stencil: None,
+            cull_face: None,
+            front_face: FrontFace::CounterClockwise,
+            blend: BlendMode::Opaque,
+            scissor: None,
+        }
+    }
+}
+
+impl RenderState {
+    /**
+     * Depth tested, depth writing and not blended, what most solid geometry wants
+     */
+    pub fn opaque_3d() -> RenderState {
+        RenderState {
+            depth_test: Some(CompareFunc::Less),
+            ..RenderState::default()
+        }
+    }
+
+    /**
+     * Depth tested against opaque geometry but not written, so transparent
+     * surfaces drawn back to front do not hide each other
+     */
+    pub fn transparent_3d() -> RenderState {
+        RenderState {
+            depth_test: Some(CompareFunc::Less),
+            depth_write: false,
+            blend: BlendMode::Alpha,
+            ..RenderState::default()
+        }
+    }
+}
+
+/**
+ * Which buffers glClear should touch, combine with `|`
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ClearMask(pub gl::types::GLbitfield);
+
+impl ClearMask {
+    pub const COLOR: ClearMask = ClearMask(gl::COLOR_BUFFER_BIT);
+    pub const DEPTH: ClearMask = ClearMask(gl::DEPTH_BUFFER_BIT);
+    pub const STENCIL: ClearMask = ClearMask(gl::STENCIL_BUFFER_BIT);
+    pub const ALL: ClearMask = ClearMask(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+
+    pub fn contains(&self, other: ClearMask) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for ClearMask {
+    type Output = ClearMask;
+    fn bitor(self, other: ClearMask) -> ClearMask {
+        ClearMask(self.0 | other.0)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ClearValues {
+    pub color: na::Vector4<f32>,
+    pub depth: f64,
+    pub stencil: gl::types::GLint,
+}
+
+impl ClearValues {
+    pub fn with_color(color: na::Vector4<f32>) -> ClearValues {
+        ClearValues {
+            color,
+            ..ClearValues::default()
+        }
+    }
+}
+
+impl Default for ClearValues {
+    fn default() -> ClearValues {
+        ClearValues {
+            color: na::Vector4::new(0.0, 0.0, 0.0, 1.0),
+            depth: 1.0,
+            stencil: 0,
+        }
+    }
+}
+
+/**
+ * Remembers the render state and clear values last sent to gl so that
+ * switching to an identical state costs nothing.
+ *
+ * Everything has to go through the same cache for this to work; if some other code
+ * changes the state directly, call `invalidate` so the next `apply` sets everything again.
+ */
+pub struct RenderStateCache {
+    gl: gl::Gl,
+    current: Option<RenderState>,
+    clear_color: Option<na::Vector4<f32>>,
+    clear_depth: Option<f64>,
+    clear_stencil: Option<gl::types::GLint>,
+}
+
+impl RenderStateCache {
+    pub fn new(gl: &gl::Gl)
+    -> RenderStateCache
+    {
+        RenderStateCache {
+            gl: gl.clone(),
+            current: None,
+            clear_color: None,
+            clear_depth: None,
+            clear_stencil: None,
+        }
+    }
+
+    pub fn current(&self) -> Option<&RenderState> {
+        self.current.as_ref()
+    }
+
+    pub fn invalidate(&mut self)
+    {
+        self.current = None;
+        self.clear_color = None;
+        self.clear_depth = None;
+        self.clear_stencil = None;
+    }
+
+    pub fn apply(&mut self, state: &RenderState)
+    {
+        // with nothing cached every piece of state is sent
+        let unknown = self.current.is_none();
+        let previous = self.current.unwrap_or(*state);
+
+        unsafe {
+            if unknown || previous.depth_test != state.depth_test {
+                match state.depth_test {
+                    Some(func) => {
+                        self.gl.set_capability(gl::DEPTH_TEST, true);
+                        self.gl.DepthFunc(func.gl_enum());
+                    }
+                    None => self.gl.set_capability(gl::DEPTH_TEST, false),
+                }
+            }
+
+            if unknown || previous.depth_write != state.depth_write {
+                self.gl.DepthMask(if state.depth_write { gl::TRUE } else { gl::FALSE });
+            }
+
+            if unknown || previous.stencil != state.stencil {
+                match state.stencil {
+                    Some(stencil) => {
+                        self.gl.set_capability(gl::STENCIL_TEST, true);
+                        self.gl.StencilFunc(stencil.func.gl_enum(), stencil.reference, stencil.read_mask);
+                        self.gl.StencilMask(stencil.write_mask);
+                        self.gl.StencilOp(
+                            stencil.stencil_fail.gl_enum(),
+                            stencil.depth_fail.gl_enum(),
+                            stencil.pass.gl_enum(),
+                        );
+                    }
+                    None => {
+                        self.gl.set_capability(gl::STENCIL_TEST, false);
+                        self.gl.StencilMask(!0); // so clears still reach the stencil buffer
+                    }
+                }
+            }
+
+            if unknown || previous.cull_face != state.cull_face {
+                match state.cull_face {
+                    Some(face) => {
+                        self.gl.set_capability(gl::CULL_FACE, true);
+                        self.gl.CullFace(face.gl_enum());
+                    }
+                    None => self.gl.set_capability(gl::CULL_FACE, false),
+                }
+            }
+
+            if unknown || previous.front_face != state.front_face {
+                self.gl.FrontFace(state.front_face.gl_enum());
+            }
+
+            if unknown || previous.blend != state.blend {
+                match state.blend.factors() {
+                    Some((src, dst)) => {
+                        self.gl.set_capability(gl::BLEND, true);
+                        self.gl.BlendFunc(src, dst);
+                    }
+                    None => self.gl.set_capability(gl::BLEND, false),
+                }
+            }
+
+            if unknown || previous.scissor != state.scissor {
+                match state.scissor {
+                    Some(scissor) => {
+                        self.gl.set_capability(gl::SCISSOR_TEST, true);
+                        self.gl.Scissor(scissor.x, scissor.y, scissor.w, scissor.h);
+                    }
+                    None => self.gl.set_capability(gl::SCISSOR_TEST, false),
+                }
+            }
+        }
+
+        self.current = Some(*state);
+    }
+
+    /**
+     * Clears the requested buffers of the bound framebuffer.
+     * Depth and stencil writes are switched on for the clear if the current state has them off,
+     * since glClear respects the write masks. The scissor rectangle still applies.
+     */
+    pub fn clear(&mut self, mask: ClearMask, values: &ClearValues)
+    {
+        unsafe {
+            if mask.contains(ClearMask::COLOR) && self.clear_color != Some(values.color) {
+                self.gl.ClearColor(values.color.x, values.color.y, values.color.z, values.color.w);
+                self.clear_color = Some(values.color);
+            }
+            if mask.contains(ClearMask::DEPTH) && self.clear_depth != Some(values.depth) {
+                self.gl.ClearDepth(values.depth);
+                self.clear_depth = Some(values.depth);
+            }
+            if mask.contains(ClearMask::STENCIL) && self.clear_stencil != Some(values.stencil) {
+                self.gl.ClearStencil(values.stencil);
+                self.clear_stencil = Some(values.stencil);
+            }
+        }
+
+        self.with_writes_enabled(mask, |gl| unsafe {
+            gl.Clear(mask.0);
+        });
+    }
+
+    /**
+     * Clears a single color attachment of the bound framebuffer (draw buffer `index`),
+     * leaving the other attachments untouched. Used with multiple render targets.
+     */
+    pub fn clear_color_attachment(&mut self, index: u32, color: na::Vector4<f32>)
+    {
+        unsafe {
+            self.gl.ClearBufferfv(gl::COLOR, index as gl::types::GLint, color.as_slice().as_ptr());
+        }
+    }
+
+    /**
+     * Clears depth and stencil of the bound framebuffer without touching any color attachment
+     */
+    pub fn clear_depth_stencil(&mut self, depth: f32, stencil: gl::types::GLint)
+    {
+        self.with_writes_enabled(ClearMask::DEPTH | ClearMask::STENCIL, |gl| unsafe {
+            gl.ClearBufferfi(gl::DEPTH_STENCIL, 0, depth, stencil);
+        });
+    }
+
+    fn with_writes_enabled<F>(&self, mask: ClearMask, clear: F)
+        where F: FnOnce(&gl::Gl)
+    {
+        let depth_masked = mask.contains(ClearMask::DEPTH)
+            && self.current.is_some_and(|s| !s.depth_write);
+        let stencil_masked = mask.contains(ClearMask::STENCIL)
+            && self.current.and_then(|s| s.stencil).is_some_and(|s| s.write_mask != !0);
+
+        unsafe {
+            if depth_masked { self.gl.DepthMask(gl::TRUE); }
+            if stencil_masked { self.gl.StencilMask(!0); }
+        }
+
+        clear(&self.gl);
+
+        unsafe {
+            if depth_masked { self.gl.DepthMask(gl::FALSE); }
+            if let (true, Some(stencil)) = (stencil_masked, self.current.and_then(|s| s.stencil)) {
+                self.gl.StencilMask(stencil.write_mask);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gl::recording::Recorder;
+
+    fn names(recorder: &Recorder) -> Vec<&'static str> {
+        recorder.take_calls().iter().map(|call| call.name).collect()
+    }
+
+    #[test]
+    fn applying_the_same_state_again_costs_nothing() {
+        let (gl, recorder) = Recorder::new();
+        let mut cache = RenderStateCache::new(&gl);
+
+        cache.apply(&RenderState::opaque_3d());
+        assert!(recorder.count("DepthFunc") == 1 && recorder.count("FrontFace") == 1);
+        recorder.take_calls();
+
+        cache.apply(&RenderState::opaque_3d());
+        assert!(names(&recorder).is_empty());
+
+        // only what differs is sent
+        cache.apply(&RenderState::transparent_3d());
+        assert_eq!(names(&recorder), vec!["DepthMask", "Enable", "BlendFunc"]);
+
+        cache.invalidate();
+        cache.apply(&RenderState::transparent_3d());
+        assert!(recorder.count("DepthFunc") == 1 && recorder.count("FrontFace") == 1);
+    }
+
+    #[test]
+    fn clear_values_are_only_sent_when_they_change() {
+        let (gl, recorder) = Recorder::new();
+        let mut cache = RenderStateCache::new(&gl);
+
+        cache.clear(ClearMask::COLOR | ClearMask::DEPTH, &ClearValues::default());
+        assert_eq!(names(&recorder), vec!["ClearColor", "ClearDepth", "Clear"]);
+        cache.clear(ClearMask::COLOR | ClearMask::DEPTH, &ClearValues::default());
+        assert_eq!(names(&recorder), vec!["Clear"]);
+    }
+
+    #[test]
+    fn clear_writes_through_masked_depth_and_stencil() {
+        let (gl, recorder) = Recorder::new();
+        let mut cache = RenderStateCache::new(&gl);
+        let stencil = StencilState { write_mask: 0x0f, ..StencilState::default() };
+        cache.apply(&RenderState { depth_write: false, stencil: Some(stencil), ..RenderState::default() });
+        recorder.take_calls();
+
+        cache.clear(ClearMask::DEPTH | ClearMask::STENCIL, &ClearValues::default());
+        let calls = recorder.take_calls();
+        let masks: Vec<(&str, i64)> = calls.iter()
+            .filter(|call| call.name != "ClearDepth" && call.name != "ClearStencil")
+            .map(|call| (call.name, if call.name == "Clear" { 0 } else { call.int(0) }))
+            .collect();
+        assert_eq!(masks, vec![
+            ("DepthMask", gl::TRUE as i64),
+            ("StencilMask", 0xffff_ffff),
+            ("Clear", 0),
+            ("DepthMask", gl::FALSE as i64),
+            ("StencilMask", 0x0f),
+        ]);
+
+        // nothing to switch on when the state writes anyway
+        cache.apply(&RenderState::default());
+        recorder.take_calls();
+        cache.clear(ClearMask::DEPTH | ClearMask::STENCIL, &ClearValues::default());
+        assert_eq!(names(&recorder), vec!["Clear"]);
+    }
+}
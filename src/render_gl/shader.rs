@@ -1,9 +1,13 @@
 
 
 use gl;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ffi::{ CString, CStr };
 use crate::resources::Resources;
 use crate::resources::Error as ResourcesError;
+use super::vertex_layout::{ ActiveAttribute, AttributeMismatches, VertexLayout };
+use super::debug_output::label_object;
 
 pub struct Shader {
     id: gl::types::GLuint,
@@ -13,6 +17,7 @@ pub struct Shader {
 pub struct Program {
     id: gl::types::GLuint,
     gl: gl::Gl,
+    uniform_locations: RefCell<HashMap<String, gl::types::GLint>>,
 }
 
 #[derive(Debug, Fail)]
@@ -28,6 +33,9 @@ pub enum Error {
 
     #[fail(display = "Failed to link program {}: {}", name, message)]
     LinkError { name: String, message: String },
+
+    #[fail(display = "Vertex layout does not match the attributes of program {}:\n{}", program_id, mismatches)]
+    VertexLayoutMismatch { program_id: gl::types::GLuint, mismatches: AttributeMismatches },
 }
 
 impl Shader {
@@ -53,7 +61,9 @@ impl Shader {
         let source: CString = res.load_cstring(name)
             .map_err(|error| Error::UnableToLoadResource{ name: String::from(name), error })?;
 
-        Shader::from_source(gl, &source, shader_kind)
+        let shader = Shader::from_source(gl, &source, shader_kind)?;
+        shader.set_label(name);
+        Ok(shader)
     }
 
     pub fn from_source(
@@ -77,6 +87,13 @@ impl Shader {
     pub fn id(&self) -> gl::types::GLuint {
         self.id
     }
+
+    /**
+     * Names the shader in driver debug messages and graphics debuggers
+     */
+    pub fn set_label(&self, label: &str) {
+        label_object(&self.gl, gl::SHADER, self.id, label);
+    }
 }
 
 impl Drop for Shader {
@@ -106,10 +123,12 @@ impl Program {
             })
             .collect::<Result<Vec<Shader>, Error>>()?;
 
-        Program::from_shaders(gl, &shaders[..]).map_err(|message| Error::LinkError {
+        let program = Program::from_shaders(gl, &shaders[..]).map_err(|message| Error::LinkError {
             name: name.into(),
             message
-        })
+        })?;
+        program.set_label(name);
+        Ok(program)
     }
 
     pub fn from_shaders(gl: &gl::Gl, shaders: &[Shader]) -> Result<Program, String> {
@@ -150,7 +169,7 @@ impl Program {
             unsafe { gl.DetachShader(program_id, shader.id()); } // Delete Shader will fail if the shader is still attached to a program when the shader is dropped
         }
 
-        Ok(Program{ id: program_id, gl: gl.clone() })
+        Ok(Program{ id: program_id, gl: gl.clone(), uniform_locations: RefCell::new(HashMap::new()) })
     }
 
     pub fn id(&self) -> gl::types::GLuint {
@@ -158,14 +177,106 @@ impl Program {
     }
 
     pub fn set_used(&self) {
+        self.gl.use_program(self.id);
+    }
+
+    /**
+     * Location of a uniform, None when the program has no active uniform by that name.
+     * Lookups are cached.
+     */
+    pub fn uniform_location(&self, name: &str) -> Option<gl::types::GLint> {
+        let mut locations = self.uniform_locations.borrow_mut();
+        let location = *locations.entry(String::from(name)).or_insert_with(|| {
+            let name = CString::new(name).expect("uniform names can not contain 0");
+            unsafe { self.gl.GetUniformLocation(self.id, name.as_ptr()) }
+        });
+        if location < 0 { None } else { Some(location) }
+    }
+
+    /**
+     * Makes the uniform block `name` read from the buffer bound to `binding`, false when
+     * the program has no such block
+     */
+    pub fn bind_uniform_block(&self, name: &str, binding: u32) -> bool {
+        let name = CString::new(name).expect("uniform block names can not contain 0");
+        let index = unsafe { self.gl.GetUniformBlockIndex(self.id, name.as_ptr()) };
+        if index == gl::INVALID_INDEX {
+            return false;
+        }
+        unsafe {
+            self.gl.UniformBlockBinding(self.id, index, binding);
+        }
+        true
+    }
+
+    /**
+     * Names the program in driver debug messages and graphics debuggers
+     */
+    pub fn set_label(&self, label: &str) {
+        label_object(&self.gl, gl::PROGRAM, self.id, label);
+    }
+
+    /**
+     * Lists the vertex inputs the linker kept, with the location each was assigned
+     */
+    pub fn active_attributes(&self) -> Vec<ActiveAttribute> {
+        let mut count: gl::types::GLint = 0;
+        let mut max_name_len: gl::types::GLint = 0;
+
         unsafe {
-            self.gl.UseProgram(self.id);
+            self.gl.GetProgramiv(self.id, gl::ACTIVE_ATTRIBUTES, &mut count);
+            self.gl.GetProgramiv(self.id, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH, &mut max_name_len);
+        }
+
+        (0..count as gl::types::GLuint)
+            .map(|index| {
+                let name = create_whitespace_cstring_with_len(max_name_len as usize);
+                let mut name_len: gl::types::GLsizei = 0;
+                let mut size: gl::types::GLint = 0;
+                let mut gl_type: gl::types::GLenum = 0;
+
+                let location = unsafe {
+                    self.gl.GetActiveAttrib(
+                        self.id,
+                        index,
+                        max_name_len,
+                        &mut name_len,
+                        &mut size,
+                        &mut gl_type,
+                        name.as_ptr() as *mut gl::types::GLchar,
+                    );
+                    self.gl.GetAttribLocation(self.id, name.as_ptr())
+                };
+
+                let name = name.to_string_lossy()[..name_len as usize].to_owned();
+
+                ActiveAttribute { name, location, gl_type, size }
+            })
+            .collect()
+    }
+
+    /**
+     * Checks that every input of the vertex shader is fed by an attribute of the
+     * given layout with the same location, kind and component count.
+     * Call this once after loading so mismatches fail loudly instead of rendering garbage.
+     */
+    pub fn validate_vertex_layout(&self, layout: &VertexLayout) -> Result<(), Error> {
+        let mismatches = layout.mismatches(&self.active_attributes());
+
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::VertexLayoutMismatch {
+                program_id: self.id,
+                mismatches: AttributeMismatches(mismatches),
+            })
         }
     }
 }
 
 impl Drop for Program {
     fn drop (&mut self) {
+        self.gl.forget_program(self.id);
         unsafe {
             self.gl.DeleteProgram(self.id);
         }
@@ -218,3 +329,57 @@ fn shader_from_source(
     }
     Ok(id)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gl::recording::Recorder;
+
+    #[test]
+    fn program_links_shaders_and_deletes_everything_on_drop() {
+        let (gl, recorder) = Recorder::new();
+
+        let vert = Shader::from_vert_source(&gl, &CString::new("void main() {}").unwrap()).unwrap();
+        let frag = Shader::from_frag_source(&gl, &CString::new("void main() {}").unwrap()).unwrap();
+        let program = Program::from_shaders(&gl, &[vert, frag]).unwrap();
+        let (vert_id, frag_id) = (1, 2); // fake names are handed out in creation order
+        let program_id = program.id();
+
+        let attached: Vec<_> = recorder.calls_to("AttachShader").iter().map(|c| c.int(1)).collect();
+        assert_eq!(attached, vec![vert_id, frag_id]);
+        assert_eq!(recorder.last_call_to("LinkProgram").unwrap().int(0), program_id as i64);
+
+        drop(program);
+
+        let deleted_shaders: Vec<_> = recorder.calls_to("DeleteShader").iter().map(|c| c.int(0)).collect();
+        assert_eq!(deleted_shaders, vec![vert_id, frag_id]);
+        assert_eq!(recorder.last_call_to("DeleteProgram").unwrap().int(0), program_id as i64);
+    }
+
+    #[test]
+    fn labels_name_the_object() {
+        let (gl, recorder) = Recorder::new();
+        let shader = Shader::from_vert_source(&gl, &CString::new("void main() {}").unwrap()).unwrap();
+
+        shader.set_label("shaders/triangle.vert");
+
+        let label = recorder.last_call_to("ObjectLabel").unwrap();
+        assert_eq!(label.int(0), gl::SHADER as i64);
+        assert_eq!(label.int(1), shader.id() as i64);
+        assert_eq!(label.int(2), "shaders/triangle.vert".len() as i64);
+    }
+
+    #[test]
+    fn compile_error_reports_the_info_log() {
+        let (gl, recorder) = Recorder::new();
+        recorder.set_integer(gl::COMPILE_STATUS, gl::FALSE as gl::types::GLint);
+
+        let result = Shader::from_vert_source(&gl, &CString::new("not glsl").unwrap());
+
+        match result {
+            Err(Error::CompileError { .. }) => {},
+            _ => panic!("expected a compile error"),
+        }
+        assert_eq!(recorder.count("GetShaderInfoLog"), 1);
+    }
+}
@@ -0,0 +1,581 @@
+/*!
+ * Shadow maps for directional and spot lights.
+ *
+ * Every frame `ShadowList::update` picks the lights that cast shadows and works out the
+ * light space matrix of each shadow view: one per spot light, and one per cascade of a
+ * directional light, each cascade fitting a slice of the camera frustum. `ShadowMaps` renders
+ * the depth of every view into its tile of one depth atlas and uploads the views to the
+ * `Shadows` uniform block, which the lit shaders declare as:
+ *
+ * ```glsl
+ * struct Shadow {
+ *   mat4 matrix;           // world to atlas uv and depth
+ *   vec4 rect;             // the tile in the atlas, min uv in xy and max uv in zw
+ * };
+ *
+ * layout (std140) uniform Shadows {
+ *   vec4 shadow_params;    // number of views, pcf radius in texels, depth bias, normal bias
+ *   vec4 view_forward;     // camera forward in xyz, number of cascades in w
+ *   vec4 cascade_splits;   // view distance each cascade ends at
+ *   vec4 first_shadow[MAX_LIGHTS / 4];   // first view of light i at [i / 4][i % 4], -1 for none
+ *   Shadow shadows[MAX_SHADOWS];
+ * };
+ *
+ * uniform sampler2DShadow shadow_map;
+ * ```
+ *
+ * Lookups average the comparisons of the texels within `pcf_radius` (percentage closer filtering).
+ */
+use gl;
+use std::rc::Rc;
+
+use crate::camera::Camera;
+use crate::resources::Resources;
+use super::buffer::UniformBuffer;
+use super::framebuffer::{ AttachmentDesc, Error as FramebufferError, Framebuffer, FramebufferDesc, FramebufferSize };
+use super::light::{ LightKind, LightList, LightSource, MAX_LIGHTS };
+use super::mesh::Mesh;
+use super::render_state::{ ClearMask, ClearValues, CompareFunc, RenderState, RenderStateCache };
+use super::shader::{ Error as ShaderError, Program };
+use super::texture::{ Texture, TextureFormat };
+use super::uniform::{ UniformValue, Uniforms };
+use super::viewport::Viewport;
+
+pub const SHADOWS_BLOCK: &str = "Shadows";
+pub const SHADOWS_BINDING: u32 = 1;
+pub const SHADOW_MAP_SAMPLER: &str = "shadow_map";
+pub const SHADOW_MAP_UNIT: u32 = 12;
+
+/**
+ * Shadow views that fit in the atlas, `MAX_SHADOWS` in the shaders has to match
+ */
+pub const MAX_SHADOWS: usize = 8;
+pub const MAX_CASCADES: usize = 4;
+
+const ATLAS_COLUMNS: usize = 4;
+const ATLAS_ROWS: usize = MAX_SHADOWS / ATLAS_COLUMNS;
+const HEADER_FLOATS: usize = 12 + MAX_LIGHTS;
+const SHADOW_FLOATS: usize = 20;
+
+const DEPTH_PROGRAM: &str = "shaders/shadow";
+const SPOT_NEAR: f32 = 0.05;
+const SPOT_CUTOFF: f32 = 1.0 / 256.0;   // spot shadows reach as far as the light gets this dim
+const CASTER_REACH: f32 = 2.0;          // radii of a cascade towards the light that still cast into it
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load shadow program {}", name)]
+    Program { name: String, #[cause] error: ShaderError },
+
+    #[fail(display = "Failed to create the shadow atlas")]
+    Framebuffer { #[cause] error: FramebufferError },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    pub resolution: i32,            // texels along a side of each shadow view
+    pub depth_bias: f32,            // subtracted from the depth compared against the map
+    pub normal_bias: f32,           // world units lookups move out along the surface normal
+    pub pcf_radius: i32,            // 0 takes one hardware filtered sample
+    pub cascades: usize,            // per directional light, at most MAX_CASCADES
+    pub split_lambda: f32,          // cascade splits from even (0) to logarithmic (1)
+    pub cascade_splits: Vec<f32>,   // view distances the cascades end at, replaces cascades and split_lambda
+    pub max_distance: f32,          // directional shadows end here or at the far plane
+}
+
+impl Default for ShadowSettings {
+    fn default() -> ShadowSettings {
+        ShadowSettings {
+            enabled: true,
+            resolution: 1024,
+            depth_bias: 0.0015,
+            normal_bias: 0.02,
+            pcf_radius: 1,
+            cascades: 3,
+            split_lambda: 0.75,
+            cascade_splits: Vec::new(),
+            max_distance: 40.0,
+        }
+    }
+}
+
+impl ShadowSettings {
+    /**
+     * The view distance each cascade ends at, for a camera seeing from `near` to `far`
+     */
+    pub fn cascade_distances(&self, near: f32, far: f32) -> Vec<f32> {
+        let far = far.min(self.max_distance);
+        if !self.cascade_splits.is_empty() {
+            return self.cascade_splits.iter().take(MAX_CASCADES).map(|split| split.min(far)).collect();
+        }
+
+        let count = self.cascades.clamp(1, MAX_CASCADES);
+        (1..=count)
+            .map(|i| {
+                let fraction = i as f32 / count as f32;
+                let logarithmic = near * (far / near).powf(fraction);
+                let even = near + (far - near) * fraction;
+                self.split_lambda * logarithmic + (1.0 - self.split_lambda) * even
+            })
+            .collect()
+    }
+}
+
+/**
+ * The world space corners of the clip volume of `projection_view`
+ */
+pub fn frustum_corners(projection_view: &glm::Mat4) -> [glm::Vec3; 8] {
+    let clip_to_world = projection_view.try_inverse().unwrap_or_else(glm::Mat4::identity);
+    let mut corners = [glm::vec3(0.0, 0.0, 0.0); 8];
+    for (i, corner) in corners.iter_mut().enumerate() {
+        let clip = glm::vec4(
+            if i & 1 == 0 { -1.0 } else { 1.0 },
+            if i & 2 == 0 { -1.0 } else { 1.0 },
+            if i & 4 == 0 { -1.0 } else { 1.0 },
+            1.0,
+        );
+        let world = clip_to_world * clip;
+        *corner = glm::vec3(world.x, world.y, world.z) / world.w;
+    }
+    corners
+}
+
+fn up_for(direction: &glm::Vec3) -> glm::Vec3 {
+    if direction.y.abs() > 0.99 { glm::vec3(0.0, 0.0, 1.0) } else { glm::vec3(0.0, 1.0, 0.0) }
+}
+
+/**
+ * An orthographic view along `direction` around the bounding sphere of `corners`. The sphere
+ * keeps the size steady as the camera turns and the view moves in whole texels, so static
+ * shadow edges do not swim.
+ */
+pub fn directional_matrix(direction: &glm::Vec3, corners: &[glm::Vec3; 8], resolution: i32) -> glm::Mat4 {
+    let center = corners.iter().fold(glm::vec3(0.0, 0.0, 0.0), |sum, corner| sum + corner) / 8.0;
+    let radius = corners.iter().map(|corner| glm::distance(corner, &center)).fold(0.0, f32::max);
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    let direction = glm::normalize(direction);
+    let eye = center - direction * (radius * CASTER_REACH);
+    let view = glm::look_at(&eye, &center, &up_for(&direction));
+    let projection = glm::ortho(-radius, radius, -radius, radius, 0.0, radius * (CASTER_REACH + 1.0));
+    let mut matrix = projection * view;
+
+    let half_resolution = resolution as f32 / 2.0;
+    let origin = matrix * glm::vec4(0.0, 0.0, 0.0, 1.0);
+    matrix[(0, 3)] += ((origin.x * half_resolution).round() - origin.x * half_resolution) / half_resolution;
+    matrix[(1, 3)] += ((origin.y * half_resolution).round() - origin.y * half_resolution) / half_resolution;
+    matrix
+}
+
+/**
+ * A perspective view from the light down its cone, out to `range`
+ */
+pub fn spot_matrix(source: &LightSource, outer_cone: f32, range: f32) -> glm::Mat4 {
+    let direction = glm::normalize(&source.direction);
+    let view = glm::look_at(&source.position, &(source.position + direction), &up_for(&direction));
+    let fov = (2.0 * outer_cone).clamp(0.01, glm::pi::<f32>() - 0.01);
+    glm::perspective(1.0, fov, SPOT_NEAR, range.max(SPOT_NEAR * 2.0)) * view
+}
+
+/**
+ * Where `tile` is in the atlas, min uv in xy and max uv in zw
+ */
+pub fn tile_rect(tile: usize) -> glm::Vec4 {
+    let (column, row) = ((tile % ATLAS_COLUMNS) as f32, (tile / ATLAS_COLUMNS) as f32);
+    let (columns, rows) = (ATLAS_COLUMNS as f32, ATLAS_ROWS as f32);
+    glm::vec4(column / columns, row / rows, (column + 1.0) / columns, (row + 1.0) / rows)
+}
+
+/**
+ * Clip space of a view to the uv of its tile and depth in 0..1
+ */
+pub fn tile_matrix(tile: usize) -> glm::Mat4 {
+    let rect = tile_rect(tile);
+    let half_size = glm::vec3((rect.z - rect.x) / 2.0, (rect.w - rect.y) / 2.0, 0.5);
+    glm::translation(&glm::vec3(rect.x + half_size.x, rect.y + half_size.y, 0.5)) * glm::scaling(&half_size)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ShadowView {
+    pub light: usize,                   // index into the `LightList`
+    pub cascade: Option<usize>,         // of a directional light
+    pub view_projection: glm::Mat4,
+}
+
+/**
+ * The shadow views of a frame, view i is rendered into tile i of the atlas
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShadowList {
+    views: Vec<ShadowView>,
+    splits: Vec<f32>,
+    forward: glm::Vec3,
+}
+
+impl Default for ShadowList {
+    fn default() -> ShadowList {
+        ShadowList::new()
+    }
+}
+
+impl ShadowList {
+    pub fn new() -> ShadowList {
+        ShadowList {
+            views: Vec::new(),
+            splits: Vec::new(),
+            forward: glm::vec3(0.0, 0.0, -1.0),
+        }
+    }
+
+    /**
+     * Views for the lights of `lights` that cast shadows, seen through `camera`. Lights that
+     * no longer fit in the atlas and point lights are left unshadowed.
+     */
+    pub fn update(&mut self, settings: &ShadowSettings, camera: &Camera, lights: &LightList) {
+        self.views.clear();
+        self.splits.clear();
+        self.forward = camera.forward();
+        if !settings.enabled {
+            return;
+        }
+
+        let near = camera.near_clipping_plane();
+        let distances = settings.cascade_distances(near, camera.far_clipping_plane());
+        for (index, source) in lights.sources().iter().enumerate() {
+            if !source.light.casts_shadows {
+                continue;
+            }
+            match source.light.kind {
+                LightKind::Directional => {
+                    if self.views.len() + distances.len() > MAX_SHADOWS {
+                        continue;
+                    }
+                    let mut start = near;
+                    for (cascade, end) in distances.iter().enumerate() {
+                        let corners = frustum_corners(&camera.slice_projection_view(start, *end));
+                        self.views.push(ShadowView {
+                            light: index,
+                            cascade: Some(cascade),
+                            view_projection: directional_matrix(&source.direction, &corners, settings.resolution),
+                        });
+                        start = *end;
+                    }
+                    self.splits = distances.clone();
+                },
+                LightKind::Spot { attenuation, outer_cone, .. } => {
+                    if self.views.len() >= MAX_SHADOWS {
+                        continue;
+                    }
+                    let range = attenuation.range(SPOT_CUTOFF).min(settings.max_distance);
+                    self.views.push(ShadowView {
+                        light: index,
+                        cascade: None,
+                        view_projection: spot_matrix(source, outer_cone, range),
+                    });
+                },
+                LightKind::Point { .. } => {},  // would need a cube map
+            }
+        }
+    }
+
+    pub fn views(&self) -> &[ShadowView] {
+        &self.views
+    }
+
+    pub fn splits(&self) -> &[f32] {
+        &self.splits
+    }
+
+    /**
+     * The list laid out as the std140 `Shadows` block
+     */
+    pub fn std140(&self, settings: &ShadowSettings) -> Vec<f32> {
+        let mut data = vec![0.0; HEADER_FLOATS + MAX_SHADOWS * SHADOW_FLOATS];
+        data[0..4].copy_from_slice(&[self.views.len() as f32, settings.pcf_radius as f32, settings.depth_bias, settings.normal_bias]);
+        data[4..8].copy_from_slice(&[self.forward.x, self.forward.y, self.forward.z, self.splits.len() as f32]);
+        data[8..8 + self.splits.len()].copy_from_slice(&self.splits);
+
+        let first_shadow = &mut data[12..HEADER_FLOATS];
+        first_shadow.iter_mut().for_each(|first| *first = -1.0);
+        for (tile, view) in self.views.iter().enumerate().rev() {
+            first_shadow[view.light] = tile as f32;
+        }
+
+        for (tile, (view, shadow)) in self.views.iter().zip(data[HEADER_FLOATS..].chunks_mut(SHADOW_FLOATS)).enumerate() {
+            shadow[0..16].copy_from_slice((tile_matrix(tile) * view.view_projection).as_slice());
+            shadow[16..20].copy_from_slice(tile_rect(tile).as_slice());
+        }
+        data
+    }
+}
+
+/**
+ * Points the shadow map sampler of `program` at its unit, the program is left in use
+ */
+pub fn bind_shadow_map(gl: &gl::Gl, program: &Program) {
+    program.set_used();
+    Uniforms::new()
+        .with(SHADOW_MAP_SAMPLER, UniformValue::Int(SHADOW_MAP_UNIT as i32))
+        .apply(gl, program);
+}
+
+/**
+ * The depth atlas, the program rendering into it and the buffer of the `Shadows` block
+ */
+pub struct ShadowMaps {
+    gl: gl::Gl,
+    framebuffer: Framebuffer,
+    buffer: UniformBuffer,
+    program: Program,
+    tile_size: i32,
+}
+
+impl ShadowMaps {
+    /**
+     * With shadows disabled the atlas is a single texel per tile, the shaders still sample it
+     */
+    pub fn new(gl: &gl::Gl, res: &Resources, settings: &ShadowSettings)
+    -> Result<ShadowMaps, Error>
+    {
+        let tile_size = tile_size(settings);
+        let (width, height) = (tile_size * ATLAS_COLUMNS as i32, tile_size * ATLAS_ROWS as i32);
+        let framebuffer = Framebuffer::new(
+            gl,
+            FramebufferDesc {
+                size: FramebufferSize::Fixed { width, height },
+                samples: 1,
+                color: Vec::new(),
+                depth_stencil: Some(AttachmentDesc::texture(TextureFormat::Depth32F)),
+            },
+            &Viewport::for_window(width, height),
+        ).map_err(|error| Error::Framebuffer { error })?;
+
+        if let Some(depth) = framebuffer.depth_stencil_texture() {
+            depth.bind();
+            depth.set_depth_compare(Some(CompareFunc::LessEqual));
+            depth.unbind();
+        }
+
+        let program = Program::from_res(gl, res.clone(), DEPTH_PROGRAM)
+            .map_err(|error| Error::Program { name: String::from(DEPTH_PROGRAM), error })?;
+        program.set_label(DEPTH_PROGRAM);
+
+        let buffer = UniformBuffer::new(gl);
+        buffer.set_label("shadows");
+        buffer.bind_base(SHADOWS_BINDING);
+        buffer.dynamic_draw_data(&ShadowList::new().std140(settings));
+
+        Ok(ShadowMaps { gl: gl.clone(), framebuffer, buffer, program, tile_size })
+    }
+
+    /**
+     * False when `settings` need an atlas of another size, the maps have to be created again
+     */
+    pub fn fits(&self, settings: &ShadowSettings) -> bool {
+        self.tile_size == tile_size(settings)
+    }
+
+    /**
+     * Uploads `shadows`, renders the depth of `casters` into the tile of every view and binds
+     * the atlas for the lit shaders. Leaves the window framebuffer bound, the viewport has to
+     * be set again afterwards.
+     */
+    pub fn render(
+        &self,
+        render_state: &mut RenderStateCache,
+        shadows: &ShadowList,
+        settings: &ShadowSettings,
+        casters: &[(Rc<Mesh>, glm::Mat4)]
+    )
+    {
+        self.buffer.bind_base(SHADOWS_BINDING);
+        self.buffer.dynamic_draw_data(&shadows.std140(settings));
+
+        // the atlas can not be read from while rendering into it
+        self.gl.bind_texture_to_unit(SHADOW_MAP_UNIT, gl::TEXTURE_2D, 0);
+        self.framebuffer.bind();
+        render_state.apply(&RenderState::opaque_3d());
+        render_state.clear(ClearMask::DEPTH, &ClearValues::default());
+
+        if !shadows.views().is_empty() {
+            self.program.set_used();
+            let mvp_location = self.program.uniform_location("MVP");
+            for (tile, view) in shadows.views().iter().enumerate() {
+                let (column, row) = ((tile % ATLAS_COLUMNS) as i32, (tile / ATLAS_COLUMNS) as i32);
+                unsafe {
+                    self.gl.Viewport(column * self.tile_size, row * self.tile_size, self.tile_size, self.tile_size);
+                }
+                for (mesh, model) in casters {
+                    if let Some(location) = mvp_location {
+                        UniformValue::Mat4(view.view_projection * model).upload(&self.gl, location);
+                    }
+                    mesh.draw(&self.gl);
+                }
+            }
+        }
+
+        self.framebuffer.unbind();
+        if let Some(depth) = self.texture() {
+            depth.bind_to_unit(SHADOW_MAP_UNIT);
+        }
+    }
+
+    pub fn texture(&self) -> Option<&Texture> {
+        self.framebuffer.depth_stencil_texture()
+    }
+
+    pub fn tile_size(&self) -> i32 {
+        self.tile_size
+    }
+}
+
+fn tile_size(settings: &ShadowSettings) -> i32 {
+    if settings.enabled { settings.resolution.max(1) } else { 1 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::light::{ Attenuation, Light };
+
+    fn camera() -> Camera {
+        Camera::new(
+            glm::half_pi(),
+            1.0,
+            0.1,
+            100.0,
+            glm::vec3(0.0, 0.0, 5.0),
+            glm::vec3(0.0, 0.0, 0.0),
+            glm::vec3(0.0, 1.0, 0.0)
+        )
+    }
+
+    fn sun(casts_shadows: bool) -> LightSource {
+        let light = Light::directional(glm::vec3(1.0, 1.0, 1.0), 1.0);
+        LightSource {
+            light: if casts_shadows { light.with_shadows() } else { light },
+            position: glm::vec3(0.0, 0.0, 0.0),
+            direction: glm::vec3(-1.0, -2.0, -1.0),
+        }
+    }
+
+    fn project(matrix: &glm::Mat4, point: &glm::Vec3) -> glm::Vec3 {
+        let clip = matrix * glm::vec4(point.x, point.y, point.z, 1.0);
+        glm::vec3(clip.x, clip.y, clip.z) / clip.w
+    }
+
+    #[test]
+    fn cascade_splits_blend_even_and_logarithmic() {
+        let even = ShadowSettings { split_lambda: 0.0, cascades: 4, ..ShadowSettings::default() };
+        assert_eq!(even.cascade_distances(4.0, 100.0), vec![13.0, 22.0, 31.0, 40.0]);
+
+        let logarithmic = ShadowSettings { split_lambda: 1.0, cascades: 2, max_distance: 100.0, ..ShadowSettings::default() };
+        let distances = logarithmic.cascade_distances(1.0, 100.0);
+        assert!((distances[0] - 10.0).abs() < 1e-3 && (distances[1] - 100.0).abs() < 1e-3, "{:?}", distances);
+
+        let explicit = ShadowSettings { cascade_splits: vec![5.0, 15.0, 80.0], ..ShadowSettings::default() };
+        assert_eq!(explicit.cascade_distances(0.1, 100.0), vec![5.0, 15.0, 40.0]);
+    }
+
+    #[test]
+    fn cascades_contain_their_frustum_slice() {
+        let camera = camera();
+        let mut lights = LightList::new();
+        lights.push(sun(true));
+        lights.push(sun(false));
+        let settings = ShadowSettings::default();
+
+        let mut shadows = ShadowList::new();
+        shadows.update(&settings, &camera, &lights);
+        assert_eq!(shadows.views().len(), 3);
+        assert_eq!(shadows.splits().len(), 3);
+
+        let mut start = camera.near_clipping_plane();
+        for (view, end) in shadows.views().iter().zip(shadows.splits()) {
+            assert_eq!(view.light, 0);
+            for corner in frustum_corners(&camera.slice_projection_view(start, *end)).iter() {
+                let projected = project(&view.view_projection, corner);
+                assert!(projected.iter().all(|c| c.abs() <= 1.0), "{:?}", projected);
+            }
+            start = *end;
+        }
+    }
+
+    #[test]
+    fn directional_views_move_in_whole_texels() {
+        let corners = frustum_corners(&camera().slice_projection_view(0.1, 10.0));
+        let matrix = directional_matrix(&glm::vec3(-1.0, -2.0, -1.0), &corners, 1024);
+        let origin = project(&matrix, &glm::vec3(0.0, 0.0, 0.0)) * 512.0;
+        assert!((origin.x - origin.x.round()).abs() < 1e-2 && (origin.y - origin.y.round()).abs() < 1e-2, "{:?}", origin);
+    }
+
+    #[test]
+    fn spot_views_look_down_the_cone_to_the_range_of_the_light() {
+        let spot = Light::spot(glm::vec3(1.0, 1.0, 1.0), 1.0, Attenuation::for_range(10.0), 0.3, 0.5).with_shadows();
+        let source = LightSource { light: spot, position: glm::vec3(0.0, 4.0, 0.0), direction: glm::vec3(0.0, -1.0, 0.0) };
+        let mut lights = LightList::new();
+        lights.push(source);
+
+        let mut shadows = ShadowList::new();
+        shadows.update(&ShadowSettings::default(), &camera(), &lights);
+        assert_eq!(shadows.views().len(), 1);
+        assert!(shadows.splits().is_empty());
+
+        let below = project(&shadows.views()[0].view_projection, &glm::vec3(0.0, 0.0, 0.0));
+        assert!(below.x.abs() < 1e-5 && below.y.abs() < 1e-5 && below.z.abs() < 1.0, "{:?}", below);
+        let beside = project(&shadows.views()[0].view_projection, &glm::vec3(4.0, 0.0, 0.0));
+        assert!(beside.x.abs() > 1.0 || beside.y.abs() > 1.0, "{:?}", beside);
+    }
+
+    #[test]
+    fn lights_that_do_not_fit_stay_unshadowed() {
+        let mut lights = LightList::new();
+        for _ in 0..3 {
+            lights.push(sun(true));
+        }
+        let mut shadows = ShadowList::new();
+        shadows.update(&ShadowSettings::default(), &camera(), &lights);
+        assert_eq!(shadows.views().len(), 6);
+
+        shadows.update(&ShadowSettings { enabled: false, ..ShadowSettings::default() }, &camera(), &lights);
+        assert!(shadows.views().is_empty());
+    }
+
+    #[test]
+    fn lists_are_laid_out_for_std140() {
+        let mut lights = LightList::new();
+        lights.push(sun(false));
+        lights.push(sun(true));
+        let settings = ShadowSettings { cascades: 2, pcf_radius: 2, ..ShadowSettings::default() };
+        let mut shadows = ShadowList::new();
+        shadows.update(&settings, &camera(), &lights);
+
+        let data = shadows.std140(&settings);
+        assert_eq!(data.len(), 28 + 20 * MAX_SHADOWS);
+        assert_eq!(&data[0..2], &[2.0, 2.0]);
+        assert_eq!(&data[4..8], &[0.0, 0.0, -1.0, 2.0]);
+        assert_eq!(&data[8..10], shadows.splits());
+        assert_eq!(&data[12..15], &[-1.0, 0.0, -1.0]);
+
+        // the second view lands in the second tile
+        let point = glm::vec3(0.0, 0.0, -3.0);
+        let matrix = glm::Mat4::from_column_slice(&data[48..64]);
+        let uv = project(&matrix, &point);
+        let rect = tile_rect(1);
+        assert_eq!(&data[64..68], rect.as_slice());
+        assert!(uv.x >= rect.x && uv.x <= rect.z && uv.y >= rect.y && uv.y <= rect.w, "{:?}", uv);
+        assert!(uv.z >= 0.0 && uv.z <= 1.0);
+    }
+
+    #[test]
+    fn only_resolution_and_enabling_resize_the_atlas() {
+        let settings = ShadowSettings::default();
+        let biased = ShadowSettings { depth_bias: 0.01, pcf_radius: 2, cascades: 2, ..settings.clone() };
+        assert_eq!(tile_size(&biased), tile_size(&settings));
+
+        assert_eq!(tile_size(&ShadowSettings { resolution: 2048, ..settings.clone() }), 2048);
+        assert_eq!(tile_size(&ShadowSettings { enabled: false, ..settings }), 1);
+    }
+}
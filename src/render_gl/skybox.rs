@@ -0,0 +1,179 @@
+/*!
+ * Sky backgrounds from cube maps.
+ *
+ * A cube map is loaded from six face images or converted from an equirectangular hdr
+ * panorama on load. The same cube map can light the scene through `Environment::generate`
+ * before it is handed to a `Skybox`, which draws it behind everything:
+ *
+ * ```ignore
+ * let sky = load_equirectangular(&gl, &res, &mut render_state, "skies/dusk.hdr", 512)?;
+ * let environment = Environment::generate(&gl, &res, &mut render_state, &sky, &IblSettings::default())?;
+ * let skybox = Skybox::new(&gl, &res, sky)?;
+ * ```
+ */
+use gl;
+
+use crate::camera::Camera;
+use crate::resources::Resources;
+use super::capture::{ Error as CaptureError, Image };
+use super::cubemap::Cubemap;
+use super::hdr::{ Error as HdrError, HdrImage };
+use super::ibl::{ Environment, Error as IblError };
+use super::mesh::FullscreenTriangle;
+use super::render_state::{ CompareFunc, RenderState, RenderStateCache };
+use super::shader::{ Error as ShaderError, Program };
+use super::texture::{ Texture, TextureFormat };
+use super::uniform::{ UniformValue, Uniforms };
+
+pub const SKYBOX_PROGRAM: &str = "shaders/skybox";
+const SKY_UNIT: u32 = 0;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load program {}", name)]
+    Program { name: String, #[cause] error: ShaderError },
+
+    #[fail(display = "Failed to load cube map face {}", name)]
+    Face { name: String, #[cause] error: CaptureError },
+
+    #[fail(display = "Cube map face {} is {}x{}, expected {}x{}", name, width, height, size, size)]
+    FaceSize { name: String, width: u32, height: u32, size: u32 },
+
+    #[fail(display = "Failed to load panorama")]
+    Hdr { #[cause] error: HdrError },
+
+    #[fail(display = "Failed to convert panorama {} to a cube map", name)]
+    Convert { name: String, #[cause] error: IblError },
+}
+
+/**
+ * A cube map from six square 8 bit RGBA pngs, named in the order of `CUBE_FACES`:
+ * +x, -x, +y, -y, +z, -z
+ */
+pub fn load_faces(gl: &gl::Gl, res: &Resources, names: [&str; 6]) -> Result<Cubemap, Error> {
+    let mut faces = Vec::with_capacity(6);
+    for name in names.iter() {
+        let face = Image::load_png(&res.path(name))
+            .map_err(|error| Error::Face { name: String::from(*name), error })?;
+        let size = faces.first().map_or(face.width, |first: &Image| first.width);
+        if face.width != size || face.height != size {
+            return Err(Error::FaceSize { name: String::from(*name), width: face.width, height: face.height, size });
+        }
+        faces.push(face);
+    }
+
+    let pixels: Vec<&[u8]> = faces.iter().map(|face| &face.pixels[..]).collect();
+    Ok(Cubemap::from_faces(
+        gl,
+        faces[0].width as i32,
+        TextureFormat::Srgb8Alpha8,
+        [pixels[0], pixels[1], pixels[2], pixels[3], pixels[4], pixels[5]],
+    ))
+}
+
+/**
+ * A cube map with faces of `size` from a Radiance .hdr panorama. Leaves the window
+ * framebuffer bound, the viewport has to be set again afterwards.
+ */
+pub fn load_equirectangular(gl: &gl::Gl, res: &Resources, render_state: &mut RenderStateCache, name: &str, size: i32)
+-> Result<Cubemap, Error>
+{
+    let image = HdrImage::from_res(res, name).map_err(|error| Error::Hdr { error })?;
+    let panorama = Texture::from_data(gl, image.width as i32, image.height as i32, TextureFormat::Rgba32F, &image.pixels);
+    Environment::equirectangular(gl, res, render_state, &panorama, size)
+        .map_err(|error| Error::Convert { name: String::from(name), error })
+}
+
+/**
+ * `camera`'s projection * view without the translation, so the sky stays put as the camera
+ * moves and only turns with it
+ */
+pub fn sky_view_projection(camera: &Camera) -> glm::Mat4 {
+    let mut view = camera.view_matrix();
+    view[(0, 3)] = 0.0;
+    view[(1, 3)] = 0.0;
+    view[(2, 3)] = 0.0;
+    camera.projection_matrix() * view
+}
+
+/**
+ * Draws a cube map behind everything, a fullscreen triangle at the far plane that passes
+ * the depth test only where nothing has been drawn
+ */
+pub struct Skybox {
+    gl: gl::Gl,
+    program: Program,
+    triangle: FullscreenTriangle,
+    cubemap: Cubemap,
+}
+
+impl Skybox {
+    pub fn new(gl: &gl::Gl, res: &Resources, cubemap: Cubemap) -> Result<Skybox, Error> {
+        let program = Program::from_res(gl, res.clone(), SKYBOX_PROGRAM)
+            .map_err(|error| Error::Program { name: String::from(SKYBOX_PROGRAM), error })?;
+        program.set_label(SKYBOX_PROGRAM);
+        Ok(Skybox { gl: gl.clone(), program, triangle: FullscreenTriangle::new(gl), cubemap })
+    }
+
+    pub fn cubemap(&self) -> &Cubemap {
+        &self.cubemap
+    }
+
+    pub fn render(&self, render_state: &mut RenderStateCache, camera: &Camera) {
+        render_state.apply(&RenderState {
+            depth_test: Some(CompareFunc::LessEqual),
+            depth_write: false,
+            ..RenderState::default()
+        });
+        Cubemap::enable_seamless(&self.gl);
+        self.cubemap.bind_to_unit(SKY_UNIT);
+        self.program.set_used();
+
+        let clip_to_world = sky_view_projection(camera).try_inverse().unwrap_or_else(glm::Mat4::identity);
+        Uniforms::new()
+            .with("sky", UniformValue::Int(SKY_UNIT as i32))
+            .with("ClipToWorld", UniformValue::Mat4(clip_to_world))
+            .apply(&self.gl, &self.program);
+
+        self.triangle.draw();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gl::recording::Recorder;
+
+    fn camera_at(position: glm::Vec3) -> Camera {
+        Camera::new(1.0, 0.75, 0.1, 100.0, position, position + glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0))
+    }
+
+    #[test]
+    fn the_sky_ignores_where_the_camera_is() {
+        let sky = sky_view_projection(&camera_at(glm::vec3(0.0, 0.0, 0.0)));
+        let moved = sky_view_projection(&camera_at(glm::vec3(50.0, -3.0, 12.0)));
+        assert!((sky - moved).norm() < 1e-5);
+
+        // looking along +x, which lands in the middle of the screen
+        let clip = sky * glm::vec4(1.0, 0.0, 0.0, 0.0);
+        assert!((clip.x / clip.w).abs() < 1e-5 && (clip.y / clip.w).abs() < 1e-5);
+    }
+
+    #[test]
+    fn skybox_is_drawn_at_max_depth_without_writing_it() {
+        let (gl, recorder) = Recorder::new();
+        let skybox = Skybox {
+            gl: gl.clone(),
+            program: Program::from_shaders(&gl, &[]).unwrap(),
+            triangle: FullscreenTriangle::new(&gl),
+            cubemap: Cubemap::new(&gl, 4, TextureFormat::Rgba16F, 1),
+        };
+        let mut render_state = RenderStateCache::new(&gl);
+        recorder.take_calls();
+
+        skybox.render(&mut render_state, &camera_at(glm::vec3(0.0, 0.0, 0.0)));
+        assert_eq!(recorder.last_call_to("DepthFunc").unwrap().int(0), gl::LEQUAL as i64);
+        assert_eq!(recorder.last_call_to("DepthMask").unwrap().int(0), gl::FALSE as i64);
+        assert_eq!(recorder.count("DrawArrays"), 1);
+    }
+}
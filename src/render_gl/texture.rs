@@ -0,0 +1,390 @@
+use gl;
+
+use super::render_state::CompareFunc;
+
+/**
+ * Internal formats we allocate textures and renderbuffers with.
+ * Each knows the matching pixel format / type for uploads and read backs,
+ * and which framebuffer attachment point it belongs on.
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextureFormat {
+    R8,
+    Rg8,
+    Rgb8,
+    Rgba8,
+    Srgb8Alpha8,
+    R16F,
+    Rg16F,
+    Rgb16F,
+    Rgba16F,
+    R32F,
+    Rgba32F,
+    Depth16,
+    Depth24,
+    Depth32F,
+    Depth24Stencil8,
+    Depth32FStencil8,
+    Stencil8,
+}
+
+impl TextureFormat {
+    pub fn internal_format(&self) -> gl::types::GLenum {
+        match self {
+            TextureFormat::R8 => gl::R8,
+            TextureFormat::Rg8 => gl::RG8,
+            TextureFormat::Rgb8 => gl::RGB8,
+            TextureFormat::Rgba8 => gl::RGBA8,
+            TextureFormat::Srgb8Alpha8 => gl::SRGB8_ALPHA8,
+            TextureFormat::R16F => gl::R16F,
+            TextureFormat::Rg16F => gl::RG16F,
+            TextureFormat::Rgb16F => gl::RGB16F,
+            TextureFormat::Rgba16F => gl::RGBA16F,
+            TextureFormat::R32F => gl::R32F,
+            TextureFormat::Rgba32F => gl::RGBA32F,
+            TextureFormat::Depth16 => gl::DEPTH_COMPONENT16,
+            TextureFormat::Depth24 => gl::DEPTH_COMPONENT24,
+            TextureFormat::Depth32F => gl::DEPTH_COMPONENT32F,
+            TextureFormat::Depth24Stencil8 => gl::DEPTH24_STENCIL8,
+            TextureFormat::Depth32FStencil8 => gl::DEPTH32F_STENCIL8,
+            TextureFormat::Stencil8 => gl::STENCIL_INDEX8,
+        }
+    }
+
+    /**
+     * The `format` argument of TexImage2D / ReadPixels for this internal format
+     */
+    pub fn pixel_format(&self) -> gl::types::GLenum {
+        match self {
+            TextureFormat::R8 | TextureFormat::R16F | TextureFormat::R32F => gl::RED,
+            TextureFormat::Rg8 | TextureFormat::Rg16F => gl::RG,
+            TextureFormat::Rgb8 | TextureFormat::Rgb16F => gl::RGB,
+            TextureFormat::Rgba8 | TextureFormat::Srgb8Alpha8
+                | TextureFormat::Rgba16F | TextureFormat::Rgba32F => gl::RGBA,
+            TextureFormat::Depth16 | TextureFormat::Depth24 | TextureFormat::Depth32F => gl::DEPTH_COMPONENT,
+            TextureFormat::Depth24Stencil8 | TextureFormat::Depth32FStencil8 => gl::DEPTH_STENCIL,
+            TextureFormat::Stencil8 => gl::STENCIL_INDEX,
+        }
+    }
+
+    /**
+     * The `type` argument of TexImage2D / ReadPixels for this internal format
+     */
+    pub fn pixel_type(&self) -> gl::types::GLenum {
+        match self {
+            TextureFormat::R8 | TextureFormat::Rg8 | TextureFormat::Rgb8
+                | TextureFormat::Rgba8 | TextureFormat::Srgb8Alpha8
+                | TextureFormat::Stencil8 => gl::UNSIGNED_BYTE,
+            TextureFormat::R16F | TextureFormat::Rg16F | TextureFormat::Rgb16F
+                | TextureFormat::Rgba16F => gl::HALF_FLOAT,
+            TextureFormat::R32F | TextureFormat::Rgba32F | TextureFormat::Depth32F => gl::FLOAT,
+            TextureFormat::Depth16 => gl::UNSIGNED_SHORT,
+            TextureFormat::Depth24 => gl::UNSIGNED_INT,
+            TextureFormat::Depth24Stencil8 => gl::UNSIGNED_INT_24_8,
+            TextureFormat::Depth32FStencil8 => gl::FLOAT_32_UNSIGNED_INT_24_8_REV,
+        }
+    }
+
+    pub fn has_depth(&self) -> bool {
+        matches!(
+            self,
+            TextureFormat::Depth16 | TextureFormat::Depth24 | TextureFormat::Depth32F
+                | TextureFormat::Depth24Stencil8 | TextureFormat::Depth32FStencil8
+        )
+    }
+
+    pub fn has_stencil(&self) -> bool {
+        matches!(
+            self,
+            TextureFormat::Depth24Stencil8 | TextureFormat::Depth32FStencil8 | TextureFormat::Stencil8
+        )
+    }
+
+    pub fn is_color(&self) -> bool {
+        !self.has_depth() && !self.has_stencil()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextureFilter {
+    Nearest,
+    Linear,
+    LinearMipmapLinear, // only valid as a minification filter
+}
+
+impl TextureFilter {
+    pub fn gl_enum(&self) -> gl::types::GLenum {
+        match self {
+            TextureFilter::Nearest => gl::NEAREST,
+            TextureFilter::Linear => gl::LINEAR,
+            TextureFilter::LinearMipmapLinear => gl::LINEAR_MIPMAP_LINEAR,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextureWrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+    ClampToBorder,
+}
+
+impl TextureWrap {
+    pub fn gl_enum(&self) -> gl::types::GLenum {
+        match self {
+            TextureWrap::Repeat => gl::REPEAT,
+            TextureWrap::MirroredRepeat => gl::MIRRORED_REPEAT,
+            TextureWrap::ClampToEdge => gl::CLAMP_TO_EDGE,
+            TextureWrap::ClampToBorder => gl::CLAMP_TO_BORDER,
+        }
+    }
+}
+
+/**
+ * A 2D texture, or a 2D multisample texture when created with more than one sample.
+ * Freshly created textures use linear filtering without mipmaps and clamp to the edge,
+ * which is what render targets want.
+ */
+pub struct Texture {
+    gl: gl::Gl,
+    id: gl::types::GLuint,
+    target: gl::types::GLenum,
+    format: TextureFormat,
+    width: i32,
+    height: i32,
+    samples: i32,
+}
+
+impl Texture {
+    pub fn new_2d(gl: &gl::Gl, width: i32, height: i32, format: TextureFormat)
+    -> Texture
+    {
+        let texture = Texture::generate(gl, gl::TEXTURE_2D, format, width, height, 1);
+
+        texture.bind();
+        unsafe {
+            gl.TexImage2D(
+                gl::TEXTURE_2D,
+                0,                                      // mipmap level
+                format.internal_format() as gl::types::GLint,
+                width,
+                height,
+                0,                                      // border, must be 0
+                format.pixel_format(),
+                format.pixel_type(),
+                ::std::ptr::null(),                     // no data, only allocate
+            );
+        }
+        texture.set_filter(TextureFilter::Linear, TextureFilter::Linear);
+        texture.set_wrap(TextureWrap::ClampToEdge);
+        texture.unbind();
+
+        texture
+    }
+
+    /**
+     * A texture with pixels, `data` is in the pixel format and type of `format`. Mipmaps
+     * are generated and it is sampled trilinearly with repeat wrapping, like material maps want.
+     */
+    pub fn from_data<T>(gl: &gl::Gl, width: i32, height: i32, format: TextureFormat, data: &[T])
+    -> Texture
+    {
+        assert!(!data.is_empty(), "texture data can not be empty");
+        let texture = Texture::generate(gl, gl::TEXTURE_2D, format, width, height, 1);
+
+        texture.bind();
+        unsafe {
+            gl.PixelStorei(gl::UNPACK_ALIGNMENT, 1);   // rows of odd sized pixels are not padded
+            gl.TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                format.internal_format() as gl::types::GLint,
+                width,
+                height,
+                0,
+                format.pixel_format(),
+                format.pixel_type(),
+                data.as_ptr() as *const gl::types::GLvoid,
+            );
+            gl.GenerateMipmap(gl::TEXTURE_2D);
+        }
+        texture.set_filter(TextureFilter::LinearMipmapLinear, TextureFilter::Linear);
+        texture.set_wrap(TextureWrap::Repeat);
+        texture.unbind();
+
+        texture
+    }
+
+    /**
+     * A 3D texture with pixels, eg. a color lookup table. It is sampled linearly and clamped
+     * to the edge on every axis. Only width and height are kept.
+     */
+    pub fn from_data_3d<T>(gl: &gl::Gl, width: i32, height: i32, depth: i32, format: TextureFormat, data: &[T])
+    -> Texture
+    {
+        assert!(!data.is_empty(), "texture data can not be empty");
+        let texture = Texture::generate(gl, gl::TEXTURE_3D, format, width, height, 1);
+
+        texture.bind();
+        unsafe {
+            gl.PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl.TexImage3D(
+                gl::TEXTURE_3D,
+                0,
+                format.internal_format() as gl::types::GLint,
+                width,
+                height,
+                depth,
+                0,
+                format.pixel_format(),
+                format.pixel_type(),
+                data.as_ptr() as *const gl::types::GLvoid,
+            );
+        }
+        texture.set_filter(TextureFilter::Linear, TextureFilter::Linear);
+        texture.set_wrap(TextureWrap::ClampToEdge);
+        unsafe {
+            gl.TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as gl::types::GLint);
+        }
+        texture.unbind();
+
+        texture
+    }
+
+    /**
+     * Multisampled textures can not be sampled with filtering or have mipmaps, they
+     * are resolved into a regular texture with a framebuffer blit or read with texelFetch
+     */
+    pub fn new_2d_multisample(gl: &gl::Gl, width: i32, height: i32, format: TextureFormat, samples: i32)
+    -> Texture
+    {
+        let texture = Texture::generate(gl, gl::TEXTURE_2D_MULTISAMPLE, format, width, height, samples);
+
+        texture.bind();
+        unsafe {
+            gl.TexImage2DMultisample(
+                gl::TEXTURE_2D_MULTISAMPLE,
+                samples,
+                format.internal_format(),
+                width,
+                height,
+                gl::TRUE,                               // same sample locations for every texel
+            );
+        }
+        texture.unbind();
+
+        texture
+    }
+
+    fn generate(
+        gl: &gl::Gl,
+        target: gl::types::GLenum,
+        format: TextureFormat,
+        width: i32,
+        height: i32,
+        samples: i32
+    )
+    -> Texture
+    {
+        let mut id: gl::types::GLuint = 0;
+        unsafe {
+            gl.GenTextures(1, &mut id);
+        }
+
+        Texture {
+            gl: gl.clone(),
+            id,
+            target,
+            format,
+            width,
+            height,
+            samples,
+        }
+    }
+
+    pub fn bind(&self)
+    {
+        self.gl.bind_texture(self.target, self.id);
+    }
+
+    /**
+     * Binds the texture to texture unit `unit` so a sampler uniform set to `unit` reads from it
+     */
+    pub fn bind_to_unit(&self, unit: u32)
+    {
+        self.gl.bind_texture_to_unit(unit, self.target, self.id);
+    }
+
+    pub fn unbind(&self)
+    {
+        self.gl.bind_texture(self.target, 0);
+    }
+
+    pub fn set_filter(&self, min: TextureFilter, mag: TextureFilter)
+    {
+        unsafe {
+            self.gl.TexParameteri(self.target, gl::TEXTURE_MIN_FILTER, min.gl_enum() as gl::types::GLint);
+            self.gl.TexParameteri(self.target, gl::TEXTURE_MAG_FILTER, mag.gl_enum() as gl::types::GLint);
+        }
+    }
+
+    pub fn set_wrap(&self, wrap: TextureWrap)
+    {
+        unsafe {
+            self.gl.TexParameteri(self.target, gl::TEXTURE_WRAP_S, wrap.gl_enum() as gl::types::GLint);
+            self.gl.TexParameteri(self.target, gl::TEXTURE_WRAP_T, wrap.gl_enum() as gl::types::GLint);
+        }
+    }
+
+    /**
+     * Depth textures compare against the reference of `sampler2DShadow` lookups with `compare`,
+     * `None` samples the stored depth instead
+     */
+    pub fn set_depth_compare(&self, compare: Option<CompareFunc>)
+    {
+        unsafe {
+            match compare {
+                Some(compare) => {
+                    self.gl.TexParameteri(self.target, gl::TEXTURE_COMPARE_MODE, gl::COMPARE_REF_TO_TEXTURE as gl::types::GLint);
+                    self.gl.TexParameteri(self.target, gl::TEXTURE_COMPARE_FUNC, compare.gl_enum() as gl::types::GLint);
+                },
+                None => self.gl.TexParameteri(self.target, gl::TEXTURE_COMPARE_MODE, gl::NONE as gl::types::GLint),
+            }
+        }
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+
+    pub fn target(&self) -> gl::types::GLenum {
+        self.target
+    }
+
+    pub fn format(&self) -> TextureFormat {
+        self.format
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn samples(&self) -> i32 {
+        self.samples
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self)
+    {
+        self.gl.forget_texture(self.id);
+        unsafe {
+            self.gl.DeleteTextures(1, &self.id);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use gl;
+
+use super::shader::Program;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum UniformValue {
+    Int(i32),
+    Float(f32),
+    Vec2(glm::Vec2),
+    Vec3(glm::Vec3),
+    Vec4(glm::Vec4),
+    Mat3(glm::Mat3),
+    Mat4(glm::Mat4),
+}
+
+impl UniformValue {
+    /**
+     * Sets the uniform at `location` of the program in use
+     */
+    pub fn upload(&self, gl: &gl::Gl, location: gl::types::GLint) {
+        unsafe {
+            match self {
+                UniformValue::Int(value) => gl.Uniform1i(location, *value),
+                UniformValue::Float(value) => gl.Uniform1f(location, *value),
+                UniformValue::Vec2(value) => gl.Uniform2fv(location, 1, glm::value_ptr(value).as_ptr()),
+                UniformValue::Vec3(value) => gl.Uniform3fv(location, 1, glm::value_ptr(value).as_ptr()),
+                UniformValue::Vec4(value) => gl.Uniform4fv(location, 1, glm::value_ptr(value).as_ptr()),
+                UniformValue::Mat3(value) => gl.UniformMatrix3fv(location, 1, gl::FALSE, glm::value_ptr(value).as_ptr()),
+                UniformValue::Mat4(value) => gl.UniformMatrix4fv(location, 1, gl::FALSE, glm::value_ptr(value).as_ptr()),
+            }
+        }
+    }
+}
+
+/**
+ * Named uniform values, set on a program in one go
+ */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Uniforms {
+    values: Vec<(String, UniformValue)>,
+}
+
+impl Uniforms {
+    pub fn new() -> Uniforms {
+        Uniforms::default()
+    }
+
+    pub fn with(mut self, name: &str, value: UniformValue) -> Uniforms {
+        self.set(name, value);
+        self
+    }
+
+    pub fn set(&mut self, name: &str, value: UniformValue) {
+        match self.values.iter_mut().find(|(existing, _)| existing == name) {
+            Some((_, existing)) => *existing = value,
+            None => self.values.push((String::from(name), value)),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<UniformValue> {
+        self.values.iter().find(|(existing, _)| existing == name).map(|(_, value)| *value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &UniformValue)> {
+        self.values.iter().map(|(name, value)| (name.as_str(), value))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /**
+     * Uploads every value the program uses, `program` has to be in use
+     */
+    pub fn apply(&self, gl: &gl::Gl, program: &Program) {
+        for (name, value) in &self.values {
+            if let Some(location) = program.uniform_location(name) {
+                value.upload(gl, location);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn setting_a_name_again_replaces_its_value() {
+        let mut uniforms = Uniforms::new()
+            .with("color", UniformValue::Vec3(glm::vec3(1.0, 0.0, 0.0)))
+            .with("strength", UniformValue::Float(0.5));
+        uniforms.set("strength", UniformValue::Float(1.0));
+
+        assert_eq!(uniforms.get("strength"), Some(UniformValue::Float(1.0)));
+        assert_eq!(uniforms.get("missing"), None);
+        assert_eq!(uniforms.iter().map(|(name, _)| name).collect::<Vec<_>>(), vec!["color", "strength"]);
+    }
+}
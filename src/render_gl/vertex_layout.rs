@@ -0,0 +1,317 @@
+use gl;
+use std::fmt;
+
+use super::data::VertexAttribPointer;
+
+/**
+ * Which family of shader input an attribute can feed.
+ * Float attributes go through VertexAttribPointer and may be integers converted to floats,
+ * Integer attributes go through VertexAttribIPointer and Double through VertexAttribLPointer.
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AttribKind {
+    Float { normalized: bool },
+    Integer,
+    Double,
+}
+
+impl AttribKind {
+    fn is_compatible_with(&self, other: &AttribKind) -> bool {
+        matches!(
+            (self, other),
+            (AttribKind::Float { .. }, AttribKind::Float { .. })
+                | (AttribKind::Integer, AttribKind::Integer)
+                | (AttribKind::Double, AttribKind::Double)
+        )
+    }
+}
+
+impl fmt::Display for AttribKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AttribKind::Float { normalized: true } => write!(f, "normalized float"),
+            AttribKind::Float { normalized: false } => write!(f, "float"),
+            AttribKind::Integer => write!(f, "integer"),
+            AttribKind::Double => write!(f, "double"),
+        }
+    }
+}
+
+/**
+ * A single field of a vertex struct as it is handed to gl
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VertexAttribute {
+    pub location: usize,
+    pub slots: usize,
+    pub components: i32,
+    pub data_type: gl::types::GLenum,
+    pub kind: AttribKind,
+    pub offset: usize,
+}
+
+impl VertexAttribute {
+    pub fn of<T: VertexAttribPointer>(location: usize, offset: usize)
+    -> VertexAttribute
+    {
+        VertexAttribute {
+            location,
+            slots: T::SLOTS,
+            components: T::COMPONENTS,
+            data_type: T::DATA_TYPE,
+            kind: T::KIND,
+            offset,
+        }
+    }
+}
+
+/**
+ * Runtime description of a vertex struct, generated by `#[derive(VertexAttribPointers)]`
+ * as `Vertex::vertex_layout()`. Used to check a vertex struct against the inputs of a shader.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct VertexLayout {
+    pub stride: usize,
+    pub attributes: Vec<VertexAttribute>,
+}
+
+impl VertexLayout {
+    /**
+     * Compares the layout with the active attributes of a linked program.
+     * Attributes in the layout that the shader does not use are not an error,
+     * since the driver is free to optimise unused inputs away, and neither are
+     * component counts that differ where gl fills in or drops the difference.
+     */
+    pub fn mismatches(&self, active_attributes: &[ActiveAttribute])
+    -> Vec<AttributeMismatch>
+    {
+        let mut mismatches = Vec::new();
+
+        for active in active_attributes.iter().filter(|a| a.location >= 0) {
+            let input = match ShaderInput::from_gl_type(active.gl_type) {
+                Some(input) => input,
+                None => {
+                    mismatches.push(AttributeMismatch::UnsupportedType {
+                        name: active.name.clone(),
+                        location: active.location as usize,
+                        gl_type: active.gl_type,
+                    });
+                    continue;
+                }
+            };
+
+            // matrices and arrays take one location (or two for large doubles) per column / element
+            let columns = input.columns * active.size.max(1) as usize;
+            for column in 0..columns {
+                let location = active.location as usize + column * input.slots_per_column;
+                mismatches.extend(self.check_location(&active.name, location, &input));
+            }
+        }
+
+        mismatches
+    }
+
+    fn check_location(&self, name: &str, location: usize, input: &ShaderInput)
+    -> Option<AttributeMismatch>
+    {
+        let attribute = match self.attributes.iter().find(|a| a.location == location) {
+            Some(attribute) => attribute,
+            None => return Some(AttributeMismatch::Missing {
+                name: name.into(),
+                location,
+            }),
+        };
+
+        if !attribute.kind.is_compatible_with(&input.kind) {
+            return Some(AttributeMismatch::KindMismatch {
+                name: name.into(),
+                location,
+                shader: input.kind,
+                vertex: attribute.kind,
+            });
+        }
+
+        // float and integer inputs fill missing components from (0, 0, 0, 1) and ignore extra
+        // ones, but doubles that are not supplied are undefined
+        if attribute.kind == AttribKind::Double && attribute.components < input.components {
+            return Some(AttributeMismatch::ComponentMismatch {
+                name: name.into(),
+                location,
+                shader: input.components,
+                vertex: attribute.components,
+            });
+        }
+
+        None
+    }
+}
+
+/**
+ * An input of a linked program as reported by glGetActiveAttrib
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActiveAttribute {
+    pub name: String,
+    pub location: gl::types::GLint, // -1 for built in inputs such as gl_VertexID
+    pub gl_type: gl::types::GLenum,
+    pub size: gl::types::GLint,     // array length, 1 for non arrays
+}
+
+struct ShaderInput {
+    kind: AttribKind,
+    components: i32,
+    columns: usize,
+    slots_per_column: usize,
+}
+
+impl ShaderInput {
+    fn from_gl_type(gl_type: gl::types::GLenum)
+    -> Option<ShaderInput>
+    {
+        let float = AttribKind::Float { normalized: false };
+        let (kind, components, columns) = match gl_type {
+            gl::FLOAT => (float, 1, 1),
+            gl::FLOAT_VEC2 => (float, 2, 1),
+            gl::FLOAT_VEC3 => (float, 3, 1),
+            gl::FLOAT_VEC4 => (float, 4, 1),
+            gl::FLOAT_MAT2 => (float, 2, 2),
+            gl::FLOAT_MAT2x3 => (float, 3, 2),
+            gl::FLOAT_MAT2x4 => (float, 4, 2),
+            gl::FLOAT_MAT3 => (float, 3, 3),
+            gl::FLOAT_MAT3x2 => (float, 2, 3),
+            gl::FLOAT_MAT3x4 => (float, 4, 3),
+            gl::FLOAT_MAT4 => (float, 4, 4),
+            gl::FLOAT_MAT4x2 => (float, 2, 4),
+            gl::FLOAT_MAT4x3 => (float, 3, 4),
+            gl::INT | gl::UNSIGNED_INT => (AttribKind::Integer, 1, 1),
+            gl::INT_VEC2 | gl::UNSIGNED_INT_VEC2 => (AttribKind::Integer, 2, 1),
+            gl::INT_VEC3 | gl::UNSIGNED_INT_VEC3 => (AttribKind::Integer, 3, 1),
+            gl::INT_VEC4 | gl::UNSIGNED_INT_VEC4 => (AttribKind::Integer, 4, 1),
+            gl::DOUBLE => (AttribKind::Double, 1, 1),
+            gl::DOUBLE_VEC2 => (AttribKind::Double, 2, 1),
+            gl::DOUBLE_VEC3 => (AttribKind::Double, 3, 1),
+            gl::DOUBLE_VEC4 => (AttribKind::Double, 4, 1),
+            gl::DOUBLE_MAT2 => (AttribKind::Double, 2, 2),
+            gl::DOUBLE_MAT2x3 => (AttribKind::Double, 3, 2),
+            gl::DOUBLE_MAT2x4 => (AttribKind::Double, 4, 2),
+            gl::DOUBLE_MAT3 => (AttribKind::Double, 3, 3),
+            gl::DOUBLE_MAT3x2 => (AttribKind::Double, 2, 3),
+            gl::DOUBLE_MAT3x4 => (AttribKind::Double, 4, 3),
+            gl::DOUBLE_MAT4 => (AttribKind::Double, 4, 4),
+            gl::DOUBLE_MAT4x2 => (AttribKind::Double, 2, 4),
+            gl::DOUBLE_MAT4x3 => (AttribKind::Double, 3, 4),
+            _ => return None,
+        };
+
+        // dvec3 and dvec4 sized columns need two locations each
+        let slots_per_column = if kind == AttribKind::Double && components > 2 { 2 } else { 1 };
+
+        Some(ShaderInput { kind, components, columns, slots_per_column })
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum AttributeMismatch {
+    #[fail(display = "shader input {} at location {} has no matching vertex attribute", name, location)]
+    Missing { name: String, location: usize },
+
+    #[fail(display = "shader input {} at location {} expects {} data but the vertex attribute is {}", name, location, shader, vertex)]
+    KindMismatch { name: String, location: usize, shader: AttribKind, vertex: AttribKind },
+
+    #[fail(display = "shader input {} at location {} has {} double components but the vertex attribute only has {}", name, location, shader, vertex)]
+    ComponentMismatch { name: String, location: usize, shader: i32, vertex: i32 },
+
+    #[fail(display = "shader input {} at location {} has unsupported type 0x{:x}", name, location, gl_type)]
+    UnsupportedType { name: String, location: usize, gl_type: gl::types::GLenum },
+}
+
+/**
+ * Every mismatch found between a vertex layout and a program, printed one per line
+ */
+#[derive(Debug)]
+pub struct AttributeMismatches(pub Vec<AttributeMismatch>);
+
+impl fmt::Display for AttributeMismatches {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for mismatch in &self.0 {
+            writeln!(f, "  {}", mismatch)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::data;
+
+    fn input(name: &str, location: i32, gl_type: gl::types::GLenum, size: i32) -> ActiveAttribute {
+        ActiveAttribute { name: String::from(name), location, gl_type, size }
+    }
+
+    fn layout(attributes: Vec<VertexAttribute>) -> VertexLayout {
+        VertexLayout { stride: 0, attributes }
+    }
+
+    #[test]
+    fn component_counts_may_differ_where_gl_fills_them_in() {
+        let vertex = layout(vec![
+            VertexAttribute::of::<data::f32_f32_f32>(0, 0),
+            VertexAttribute::of::<data::i32_i32>(1, 12),
+            VertexAttribute::of::<data::f32_f32_f32>(2, 20),
+        ]);
+        let inputs = [
+            input("Position", 0, gl::FLOAT_VEC4, 1),    // w defaults to 1
+            input("Index", 1, gl::INT, 1),              // extra components are dropped
+            input("Normal", 2, gl::FLOAT_VEC3, 1),
+            input("gl_VertexID", -1, gl::INT, 1),
+        ];
+        assert!(vertex.mismatches(&inputs).is_empty());
+    }
+
+    #[test]
+    fn missing_and_mismatched_inputs_are_reported() {
+        let vertex = layout(vec![
+            VertexAttribute::of::<data::f32_f32_f32>(0, 0),
+            VertexAttribute::of::<data::f64_f64>(1, 12),
+        ]);
+        let mismatches = vertex.mismatches(&[
+            input("Position", 0, gl::INT_VEC3, 1),
+            input("Weights", 1, gl::DOUBLE_VEC3, 1),
+            input("Uv", 3, gl::FLOAT_VEC2, 1),
+            input("Sampler", 4, gl::SAMPLER_2D, 1),
+        ]);
+        assert!(matches!(mismatches[0], AttributeMismatch::KindMismatch { location: 0, shader: AttribKind::Integer, .. }));
+        assert!(matches!(mismatches[1], AttributeMismatch::ComponentMismatch { location: 1, shader: 3, vertex: 2, .. }));
+        assert!(matches!(mismatches[2], AttributeMismatch::Missing { location: 3, .. }));
+        assert!(matches!(mismatches[3], AttributeMismatch::UnsupportedType { location: 4, .. }));
+        assert_eq!(mismatches.len(), 4);
+    }
+
+    #[test]
+    fn multi_slot_inputs_need_every_location() {
+        // a dvec3 takes locations 0 and 1, so the next input starts at 2
+        let vertex = layout(vec![
+            VertexAttribute::of::<data::f64_f64_f64>(0, 0),
+            VertexAttribute::of::<data::f32_f32_f32_f32>(2, 24),
+            VertexAttribute::of::<data::f32_f32_f32_f32>(3, 40),
+            VertexAttribute::of::<data::f32_f32_f32_f32>(4, 56),
+        ]);
+        assert!(vertex.mismatches(&[input("Position", 0, gl::DOUBLE_VEC3, 1)]).is_empty());
+
+        // a mat4 takes a location per column, the fourth one is missing
+        let mismatches = vertex.mismatches(&[input("Model", 2, gl::FLOAT_MAT4, 1)]);
+        assert_eq!(mismatches.len(), 1);
+        assert!(matches!(mismatches[0], AttributeMismatch::Missing { location: 5, .. }));
+
+        // each element of an array does too, a dmat2x3 column takes two
+        let missing: Vec<usize> = vertex.mismatches(&[input("Colors", 3, gl::FLOAT_VEC4, 3), input("Frame", 6, gl::DOUBLE_MAT2x3, 1)])
+            .iter()
+            .map(|mismatch| match mismatch {
+                AttributeMismatch::Missing { location, .. } => *location,
+                other => panic!("unexpected {}", other),
+            })
+            .collect();
+        assert_eq!(missing, vec![5, 6, 8]);
+    }
+}
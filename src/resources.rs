@@ -22,6 +22,7 @@ impl From<io::Error> for Error {
     }
 }
 
+#[derive(Clone)]
 pub struct Resources {
     root_path: PathBuf,
 }
@@ -39,6 +40,12 @@ impl Resources {
         })
     }
 
+    pub fn from_path(root_path: &Path) -> Resources {
+        Resources {
+            root_path: root_path.into()
+        }
+    }
+
     pub fn load_cstring(&self, resource_name: &str) -> Result<ffi::CString, Error> {
         let mut file = fs::File::open(
             resource_name_to_path(&self.root_path, resource_name)
@@ -58,6 +65,21 @@ impl Resources {
 
         Ok( unsafe { ffi::CString::from_vec_unchecked(buffer) })
     }
+
+    pub fn load_string(&self, resource_name: &str) -> Result<String, Error> {
+        Ok(fs::read_to_string(resource_name_to_path(&self.root_path, resource_name))?)
+    }
+
+    pub fn load_bytes(&self, resource_name: &str) -> Result<Vec<u8>, Error> {
+        Ok(fs::read(resource_name_to_path(&self.root_path, resource_name))?)
+    }
+
+    /**
+     * Where the resource is on disk, for loaders that open files themselves
+     */
+    pub fn path(&self, resource_name: &str) -> PathBuf {
+        resource_name_to_path(&self.root_path, resource_name)
+    }
 }
 
 fn resource_name_to_path(root_dir: &Path, location: &str) -> PathBuf {
@@ -0,0 +1,396 @@
+/*!
+ * Scene stack for menus, gameplay and pause screens.
+ *
+ * Only the scene on top gets events, input and updates. Scenes below keep their state and
+ * are rendered too while everything above them is an overlay, which is how a pause screen
+ * shows the paused game. Scenes change the stack by returning a `Transition` from their hooks.
+ *
+ * ```ignore
+ * let mut scenes = SceneStack::new();
+ * scenes.push(Box::new(MainMenu::new()), &mut ctx)?;
+ * while !scenes.should_quit() {
+ *     scenes.handle_input(&mut ctx)?;
+ *     scenes.update(&mut ctx, step)?;
+ *     scenes.render(&mut ctx, alpha)?;
+ * }
+ * ```
+ */
+use sdl2::event::Event;
+use std::fmt;
+use std::time::Duration;
+
+use crate::input::Input;
+use crate::render_gl::{ RenderStateCache, Viewport };
+use crate::resources::Resources;
+use crate::settings::Settings;
+
+/**
+ * What the scenes of the game share
+ */
+pub struct Context {
+    pub gl: gl::Gl,
+    pub res: Resources,
+    pub input: Input,
+    pub viewport: Viewport,
+    pub render_state: RenderStateCache,
+    pub settings: Settings,
+}
+
+pub enum Transition<C = Context> {
+    None,
+    Push(Box<dyn Scene<C>>),    // on top of the current scene, which is paused
+    Pop,                        // back to the scene below, which is resumed
+    Replace(Box<dyn Scene<C>>), // the current scene
+    Reset(Box<dyn Scene<C>>),   // the whole stack
+    Quit,
+}
+
+impl<C> fmt::Debug for Transition<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Transition::None => write!(f, "None"),
+            Transition::Push(scene) => write!(f, "Push({})", scene.name()),
+            Transition::Pop => write!(f, "Pop"),
+            Transition::Replace(scene) => write!(f, "Replace({})", scene.name()),
+            Transition::Reset(scene) => write!(f, "Reset({})", scene.name()),
+            Transition::Quit => write!(f, "Quit"),
+        }
+    }
+}
+
+/**
+ * A state of the game. Scenes own their resources and camera, everything shared is in `C`.
+ */
+pub trait Scene<C = Context> {
+    fn name(&self) -> &str;
+
+    /**
+     * Called when the scene is added to the stack
+     */
+    fn on_enter(&mut self, _ctx: &mut C) -> Result<(), failure::Error> {
+        Ok(())
+    }
+
+    /**
+     * Called when the scene is removed from the stack
+     */
+    fn on_exit(&mut self, _ctx: &mut C) {}
+
+    /**
+     * Called when another scene is pushed on top
+     */
+    fn on_pause(&mut self, _ctx: &mut C) {}
+
+    /**
+     * Called when the scene is on top again
+     */
+    fn on_resume(&mut self, _ctx: &mut C) {}
+
+    /**
+     * Sdl events the input state did not consume, such as window focus changes
+     */
+    fn handle_event(&mut self, _ctx: &mut C, _event: &Event) -> Result<Transition<C>, failure::Error> {
+        Ok(Transition::None)
+    }
+
+    /**
+     * Called once a frame after the events, the place to check input actions
+     */
+    fn handle_input(&mut self, _ctx: &mut C) -> Result<Transition<C>, failure::Error> {
+        Ok(Transition::None)
+    }
+
+    /**
+     * Called for every fixed step of the frame, which may be none
+     */
+    fn update(&mut self, _ctx: &mut C, _step: Duration) -> Result<Transition<C>, failure::Error> {
+        Ok(Transition::None)
+    }
+
+    /**
+     * `alpha` is how far between the last two updates the frame is
+     */
+    fn render(&mut self, ctx: &mut C, alpha: f32) -> Result<(), failure::Error>;
+
+    /**
+     * Called on every scene in the stack when the viewport changes size
+     */
+    fn resize(&mut self, _ctx: &mut C) {}
+
+    /**
+     * Overlays are drawn on top of the scene below instead of hiding it
+     */
+    fn is_overlay(&self) -> bool {
+        false
+    }
+}
+
+pub struct SceneStack<C = Context> {
+    scenes: Vec<Box<dyn Scene<C>>>,
+    quit: bool,
+}
+
+impl<C> Default for SceneStack<C> {
+    fn default() -> SceneStack<C> {
+        SceneStack::new()
+    }
+}
+
+impl<C> SceneStack<C> {
+    pub fn new() -> SceneStack<C> {
+        SceneStack {
+            scenes: Vec::new(),
+            quit: false,
+        }
+    }
+
+    pub fn push(&mut self, mut scene: Box<dyn Scene<C>>, ctx: &mut C) -> Result<(), failure::Error> {
+        if let Some(top) = self.scenes.last_mut() {
+            top.on_pause(ctx);
+        }
+        info!("entering scene {}", scene.name());
+        scene.on_enter(ctx)?;
+        self.scenes.push(scene);
+        Ok(())
+    }
+
+    pub fn pop(&mut self, ctx: &mut C) {
+        if let Some(mut scene) = self.scenes.pop() {
+            scene.on_exit(ctx);
+        }
+        if let Some(top) = self.scenes.last_mut() {
+            top.on_resume(ctx);
+        }
+    }
+
+    pub fn replace(&mut self, scene: Box<dyn Scene<C>>, ctx: &mut C) -> Result<(), failure::Error> {
+        if let Some(mut top) = self.scenes.pop() {
+            top.on_exit(ctx);
+        }
+        self.push_without_pause(scene, ctx)
+    }
+
+    /**
+     * Removes every scene, top first, and starts over with `scene`
+     */
+    pub fn reset(&mut self, scene: Box<dyn Scene<C>>, ctx: &mut C) -> Result<(), failure::Error> {
+        while let Some(mut top) = self.scenes.pop() {
+            top.on_exit(ctx);
+        }
+        self.push_without_pause(scene, ctx)
+    }
+
+    pub fn apply(&mut self, transition: Transition<C>, ctx: &mut C) -> Result<(), failure::Error> {
+        match transition {
+            Transition::None => {},
+            Transition::Push(scene) => self.push(scene, ctx)?,
+            Transition::Pop => self.pop(ctx),
+            Transition::Replace(scene) => self.replace(scene, ctx)?,
+            Transition::Reset(scene) => self.reset(scene, ctx)?,
+            Transition::Quit => self.quit = true,
+        }
+        Ok(())
+    }
+
+    pub fn handle_event(&mut self, ctx: &mut C, event: &Event) -> Result<(), failure::Error> {
+        let transition = match self.scenes.last_mut() {
+            Some(top) => top.handle_event(ctx, event)?,
+            None => return Ok(()),
+        };
+        self.apply(transition, ctx)
+    }
+
+    pub fn handle_input(&mut self, ctx: &mut C) -> Result<(), failure::Error> {
+        let transition = match self.scenes.last_mut() {
+            Some(top) => top.handle_input(ctx)?,
+            None => return Ok(()),
+        };
+        self.apply(transition, ctx)
+    }
+
+    pub fn update(&mut self, ctx: &mut C, step: Duration) -> Result<(), failure::Error> {
+        let transition = match self.scenes.last_mut() {
+            Some(top) => top.update(ctx, step)?,
+            None => return Ok(()),
+        };
+        self.apply(transition, ctx)
+    }
+
+    /**
+     * Renders the top scene and, while it is an overlay, the ones below it, bottom first
+     */
+    pub fn render(&mut self, ctx: &mut C, alpha: f32) -> Result<(), failure::Error> {
+        let first_visible = self.scenes.iter()
+            .rposition(|scene| !scene.is_overlay())
+            .unwrap_or(0);
+
+        for scene in &mut self.scenes[first_visible..] {
+            scene.render(ctx, alpha)?;
+        }
+        Ok(())
+    }
+
+    pub fn resize(&mut self, ctx: &mut C) {
+        for scene in &mut self.scenes {
+            scene.resize(ctx);
+        }
+    }
+
+    /**
+     * True after a scene asked to quit or the last scene was popped
+     */
+    pub fn should_quit(&self) -> bool {
+        self.quit || self.scenes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scenes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scenes.is_empty()
+    }
+
+    /**
+     * Scene names, bottom first
+     */
+    pub fn names(&self) -> Vec<&str> {
+        self.scenes.iter().map(|scene| scene.name()).collect()
+    }
+
+    fn push_without_pause(&mut self, mut scene: Box<dyn Scene<C>>, ctx: &mut C) -> Result<(), failure::Error> {
+        info!("entering scene {}", scene.name());
+        scene.on_enter(ctx)?;
+        self.scenes.push(scene);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Log = Vec<String>;
+
+    /**
+     * Records every hook call as "name:hook", and returns a scripted transition from its
+     * next `handle_input`
+     */
+    struct TestScene {
+        name: &'static str,
+        overlay: bool,
+        next: Option<Transition<Log>>,
+    }
+
+    fn scene(name: &'static str) -> Box<TestScene> {
+        Box::new(TestScene { name, overlay: false, next: None })
+    }
+
+    fn overlay(name: &'static str) -> Box<TestScene> {
+        Box::new(TestScene { name, overlay: true, next: None })
+    }
+
+    fn then(mut scene: Box<TestScene>, transition: Transition<Log>) -> Box<TestScene> {
+        scene.next = Some(transition);
+        scene
+    }
+
+    impl Scene<Log> for TestScene {
+        fn name(&self) -> &str { self.name }
+        fn on_enter(&mut self, log: &mut Log) -> Result<(), failure::Error> {
+            log.push(format!("{}:enter", self.name));
+            Ok(())
+        }
+        fn on_exit(&mut self, log: &mut Log) { log.push(format!("{}:exit", self.name)); }
+        fn on_pause(&mut self, log: &mut Log) { log.push(format!("{}:pause", self.name)); }
+        fn on_resume(&mut self, log: &mut Log) { log.push(format!("{}:resume", self.name)); }
+        fn handle_input(&mut self, _log: &mut Log) -> Result<Transition<Log>, failure::Error> {
+            Ok(self.next.take().unwrap_or(Transition::None))
+        }
+        fn update(&mut self, log: &mut Log, _step: Duration) -> Result<Transition<Log>, failure::Error> {
+            log.push(format!("{}:update", self.name));
+            Ok(Transition::None)
+        }
+        fn render(&mut self, log: &mut Log, _alpha: f32) -> Result<(), failure::Error> {
+            log.push(format!("{}:render", self.name));
+            Ok(())
+        }
+        fn resize(&mut self, log: &mut Log) { log.push(format!("{}:resize", self.name)); }
+        fn is_overlay(&self) -> bool { self.overlay }
+    }
+
+    #[test]
+    fn push_and_pop_pause_and_resume_the_scene_below() {
+        let mut log = Log::new();
+        let mut scenes = SceneStack::new();
+        scenes.push(then(scene("game"), Transition::Push(then(overlay("pause"), Transition::Pop))), &mut log).unwrap();
+
+        scenes.handle_input(&mut log).unwrap();
+        assert_eq!(scenes.names(), vec!["game", "pause"]);
+        scenes.handle_input(&mut log).unwrap();
+        assert_eq!(scenes.names(), vec!["game"]);
+
+        assert_eq!(log, vec!["game:enter", "game:pause", "pause:enter", "pause:exit", "game:resume"]);
+    }
+
+    #[test]
+    fn replace_and_reset_exit_the_removed_scenes() {
+        let mut log = Log::new();
+        let mut scenes = SceneStack::new();
+        scenes.push(then(scene("menu"), Transition::Replace(scene("game"))), &mut log).unwrap();
+        scenes.handle_input(&mut log).unwrap();
+        scenes.push(overlay("pause"), &mut log).unwrap();
+        log.clear();
+
+        scenes.reset(scene("menu"), &mut log).unwrap();
+        assert_eq!(scenes.names(), vec!["menu"]);
+        assert_eq!(log, vec!["pause:exit", "game:exit", "menu:enter"]);
+    }
+
+    #[test]
+    fn only_the_top_scene_updates() {
+        let mut log = Log::new();
+        let mut scenes = SceneStack::new();
+        scenes.push(scene("game"), &mut log).unwrap();
+        scenes.push(scene("menu"), &mut log).unwrap();
+        log.clear();
+
+        scenes.update(&mut log, Duration::from_millis(16)).unwrap();
+        assert_eq!(log, vec!["menu:update"]);
+    }
+
+    #[test]
+    fn overlays_render_on_top_of_the_scene_below() {
+        let mut log = Log::new();
+        let mut scenes = SceneStack::new();
+        scenes.push(scene("menu"), &mut log).unwrap();
+        scenes.push(scene("game"), &mut log).unwrap();
+        scenes.push(overlay("pause"), &mut log).unwrap();
+        scenes.push(overlay("options"), &mut log).unwrap();
+        log.clear();
+
+        scenes.render(&mut log, 0.5).unwrap();
+        assert_eq!(log, vec!["game:render", "pause:render", "options:render"]);
+
+        log.clear();
+        scenes.resize(&mut log);
+        assert_eq!(log.len(), 4);
+    }
+
+    #[test]
+    fn quitting_and_popping_the_last_scene_end_the_game() {
+        let mut log = Log::new();
+        let mut scenes = SceneStack::new();
+        assert!(scenes.should_quit());
+
+        scenes.push(then(scene("menu"), Transition::Quit), &mut log).unwrap();
+        assert!(!scenes.should_quit());
+        scenes.handle_input(&mut log).unwrap();
+        assert!(scenes.should_quit());
+
+        let mut scenes = SceneStack::new();
+        scenes.push(then(scene("menu"), Transition::Pop), &mut log).unwrap();
+        scenes.handle_input(&mut log).unwrap();
+        assert!(scenes.should_quit());
+    }
+}
@@ -0,0 +1,85 @@
+use crate::scene::{ Context, Scene, Transition };
+
+/**
+ * Overlay for rebinding actions. `next` and `previous` pick an action, `confirm` binds the
+ * next button pressed to it and saves the bindings for the user, `back` closes it.
+ */
+pub struct Controls {
+    actions: Vec<String>,
+    selected: usize,
+    rebinding: bool,    // waiting for the input to finish the rebind
+}
+
+impl Controls {
+    pub fn new() -> Controls {
+        Controls {
+            actions: Vec::new(),
+            selected: 0,
+            rebinding: false,
+        }
+    }
+
+    fn log_selected(&self, ctx: &Context) {
+        if let Some(action) = self.actions.get(self.selected) {
+            let bound: Vec<String> = ctx.input.bindings().actions[action].iter()
+                .map(|button| button.to_string())
+                .collect();
+            info!("{}: {}", action, bound.join(", "));
+        }
+    }
+}
+
+impl Default for Controls {
+    fn default() -> Controls {
+        Controls::new()
+    }
+}
+
+impl Scene for Controls {
+    fn name(&self) -> &str {
+        "controls"
+    }
+
+    fn on_enter(&mut self, ctx: &mut Context) -> Result<(), failure::Error> {
+        self.actions = ctx.input.bindings().actions.keys().cloned().collect();
+        info!("controls: next and previous to pick an action, confirm to rebind it, back to return");
+        self.log_selected(ctx);
+        Ok(())
+    }
+
+    fn handle_input(&mut self, ctx: &mut Context) -> Result<Transition, failure::Error> {
+        if self.rebinding {
+            if !ctx.input.is_rebinding() {
+                self.rebinding = false;
+                ctx.input.bindings().save_user()?;
+                self.log_selected(ctx);
+            }
+            return Ok(Transition::None);
+        }
+
+        if self.actions.is_empty() || ctx.input.is_pressed("back") {
+            return Ok(Transition::Pop);
+        }
+
+        if ctx.input.is_pressed("next") {
+            self.selected = (self.selected + 1) % self.actions.len();
+            self.log_selected(ctx);
+        } else if ctx.input.is_pressed("previous") {
+            self.selected = (self.selected + self.actions.len() - 1) % self.actions.len();
+            self.log_selected(ctx);
+        } else if ctx.input.is_pressed("confirm") {
+            info!("press the new input for {}", self.actions[self.selected]);
+            ctx.input.rebind_next_press(&self.actions[self.selected]);
+            self.rebinding = true;
+        }
+        Ok(Transition::None)
+    }
+
+    fn render(&mut self, _ctx: &mut Context, _alpha: f32) -> Result<(), failure::Error> {
+        Ok(())
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,184 @@
+use sdl2::event::{ Event, WindowEvent };
+use std::time::Duration;
+
+use crate::camera::Camera;
+use crate::cube;
+use crate::ecs::{ Light, Material, RenderSystem, SceneTarget, Schedule, Spin, SpinSystem, Time, Transform, World };
+use crate::render_gl::{ self, Attenuation, ClearMask, ClearValues, ColorBuffer, Environment, Framebuffer, FramebufferDesc, IblSettings, Materials, PostStack, RenderState, Skybox };
+use crate::scene::{ Context, Scene, Transition };
+use crate::triangle;
+
+use super::pause::Pause;
+
+/**
+ * The game itself, pauses on `pause` and when the window loses focus. The world is drawn in
+ * hdr, with `msaa_samples` of the graphics settings, and post-processed into the window.
+ */
+pub struct Gameplay {
+    world: World,
+    update: Schedule<Context>,  // every fixed step
+    render: Schedule<Context>,  // every frame
+    clear_values: ClearValues,
+    post: PostStack,
+    resolved: Option<Framebuffer>,  // single sampled copy of a multisampled scene for the effects
+}
+
+impl Gameplay {
+    pub fn new(ctx: &mut Context)
+    -> Result<Gameplay, failure::Error>
+    {
+        let mut world = World::new();
+        world.insert_resource(Time::default());
+        world.insert_resource(ctx.settings.graphics.shadows.clone());
+        let samples = (ctx.settings.graphics.msaa_samples as i32).max(1);
+        let scene = FramebufferDesc { samples, ..FramebufferDesc::viewport_hdr_depth() };
+        world.insert_resource(SceneTarget(Framebuffer::new(&ctx.gl, scene, &ctx.viewport)?));
+        let resolved = if samples > 1 {
+            let color = FramebufferDesc { depth_stencil: None, ..FramebufferDesc::viewport_hdr_depth() };
+            Some(Framebuffer::new(&ctx.gl, color, &ctx.viewport)?)
+        } else {
+            None
+        };
+        let post = PostStack::from_res(&ctx.gl, &ctx.res, "config/post.toml", &ctx.viewport)?;
+
+        world.spawn()
+            .with(Camera::new(
+                45.0 * glm::pi::<f32>() / 180.0,
+                ctx.viewport.h as f32 / ctx.viewport.w as f32,
+                0.1,
+                100.0,
+                glm::vec3(4.0, 3.0, 3.0),
+                glm::vec3(0.0, 0.0, 0.0),
+                glm::vec3(0.0, 1.0, 0.0)
+            ))
+            .build();
+
+        let mut materials = Materials::new(&ctx.gl, ctx.res.clone());
+        world.spawn()
+            .with(Transform::default())
+            .with(Spin { axis: glm::Vec3::y_axis(), radians_per_second: 0.5 })
+            .with(triangle::triangle_mesh(&ctx.gl))
+            .with(Material(materials.load("materials/triangle")?))
+            .build();
+
+        for (x, material) in [(-1.5, "materials/lit"), (1.5, "materials/lit_red")] {
+            world.spawn()
+                .with(Transform::at(glm::vec3(x, 0.0, 0.0)))
+                .with(Spin { axis: glm::Vec3::x_axis(), radians_per_second: 0.3 })
+                .with(cube::cube_mesh(&ctx.gl))
+                .with(Material(materials.load(material)?))
+                .build();
+        }
+
+        world.spawn()
+            .with(Transform::at(glm::vec3(0.0, 0.0, -1.5)))
+            .with(Spin { axis: glm::Vec3::z_axis(), radians_per_second: 0.2 })
+            .with(cube::cube_mesh(&ctx.gl))
+            .with(Material(materials.load("materials/pbr_gold")?))
+            .build();
+
+        world.spawn()
+            .with(Transform {
+                position: glm::vec3(0.0, -1.1, 0.0),
+                scale: glm::vec3(10.0, 0.2, 10.0),
+                ..Transform::default()
+            })
+            .with(cube::cube_mesh(&ctx.gl))
+            .with(Material(materials.load("materials/lit")?))
+            .build();
+
+        let sky = render_gl::load_equirectangular(&ctx.gl, &ctx.res, &mut ctx.render_state, "skies/dusk.hdr", 256)?;
+        let environment = Environment::generate(&ctx.gl, &ctx.res, &mut ctx.render_state, &sky, &IblSettings::default())?;
+        ctx.viewport.set_used(&ctx.gl);
+        world.insert_resource(environment);
+        world.insert_resource(Skybox::new(&ctx.gl, &ctx.res, sky)?);
+
+        let sun = render_gl::Light::directional(glm::vec3(1.0, 0.95, 0.9), 0.8).with_shadows();
+        world.spawn()
+            .with(Transform {
+                rotation: na::UnitQuaternion::rotation_between(&glm::vec3(0.0, 0.0, -1.0), &glm::vec3(-1.0, -2.0, -1.0))
+                    .unwrap_or_else(na::UnitQuaternion::identity),
+                ..Transform::default()
+            })
+            .with(Light(sun))
+            .build();
+
+        let lamp = render_gl::Light::point(glm::vec3(0.3, 0.5, 1.0), 2.0, Attenuation::for_range(8.0));
+        world.spawn()
+            .with(Transform::at(glm::vec3(0.0, 1.5, 2.0)))
+            .with(Light(lamp))
+            .build();
+
+        Ok(Gameplay {
+            world,
+            update: Schedule::new().with(SpinSystem),
+            render: Schedule::new().with(RenderSystem::new()),
+            clear_values: ClearValues::with_color(ColorBuffer::from_color(na::Vector3::new(0.3, 0.3, 0.5)).color),
+            post,
+            resolved,
+        })
+    }
+}
+
+impl Scene for Gameplay {
+    fn name(&self) -> &str {
+        "gameplay"
+    }
+
+    fn handle_event(&mut self, _ctx: &mut Context, event: &Event) -> Result<Transition, failure::Error> {
+        Ok(match event {
+            Event::Window { win_event: WindowEvent::FocusLost, .. } => Transition::Push(Box::new(Pause::new())),
+            _ => Transition::None,
+        })
+    }
+
+    fn handle_input(&mut self, ctx: &mut Context) -> Result<Transition, failure::Error> {
+        self.post.toggle_pressed(|action| ctx.input.is_pressed(action));
+        Ok(if ctx.input.is_pressed("pause") {
+            Transition::Push(Box::new(Pause::new()))
+        } else {
+            Transition::None
+        })
+    }
+
+    fn update(&mut self, ctx: &mut Context, step: Duration) -> Result<Transition, failure::Error> {
+        if let Some(mut time) = self.world.resource_mut::<Time>() {
+            time.step = step;
+            time.elapsed += step;
+        }
+        self.update.run(&mut self.world, ctx)?;
+        Ok(Transition::None)
+    }
+
+    fn render(&mut self, ctx: &mut Context, alpha: f32) -> Result<(), failure::Error> {
+        if let Some(mut time) = self.world.resource_mut::<Time>() {
+            time.alpha = alpha;
+        }
+        if let Some(mut target) = self.world.resource_mut::<SceneTarget>() {
+            target.0.bind_for(&ctx.viewport)?;
+        }
+        ctx.render_state.apply(&RenderState::opaque_3d());
+        ctx.render_state.clear(ClearMask::COLOR | ClearMask::DEPTH, &self.clear_values);
+        self.render.run(&mut self.world, ctx)?;
+
+        if let Some(target) = self.world.resource::<SceneTarget>() {
+            let scene = match &mut self.resolved {
+                Some(resolved) => {
+                    resolved.resize_to_viewport(&ctx.viewport)?;
+                    target.0.blit_to(resolved, gl::COLOR_BUFFER_BIT);
+                    &*resolved
+                },
+                None => &target.0,
+            };
+            self.post.run(&mut ctx.render_state, scene, &ctx.viewport)?;
+        }
+        Ok(())
+    }
+
+    fn resize(&mut self, ctx: &mut Context) {
+        self.world.register::<Camera>();
+        for (_, camera) in self.world.write::<Camera>().iter_mut() {
+            camera.set_aspect_ratio(ctx.viewport.h as f32 / ctx.viewport.w as f32);
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use crate::render_gl::{ ClearMask, ClearValues, RenderState };
+use crate::scene::{ Context, Scene, Transition };
+
+use super::gameplay::Gameplay;
+
+/**
+ * Shows one frame, then loads the gameplay scene and replaces itself with it.
+ * Loading blocks, so without the frame the previous screen would freeze instead.
+ */
+pub struct Loading {
+    clear_values: ClearValues,
+    shown: bool,
+}
+
+impl Loading {
+    pub fn new() -> Loading {
+        Loading {
+            clear_values: ClearValues::with_color(na::Vector4::new(0.0, 0.0, 0.0, 1.0)),
+            shown: false,
+        }
+    }
+}
+
+impl Default for Loading {
+    fn default() -> Loading {
+        Loading::new()
+    }
+}
+
+impl Scene for Loading {
+    fn name(&self) -> &str {
+        "loading"
+    }
+
+    fn update(&mut self, ctx: &mut Context, _step: Duration) -> Result<Transition, failure::Error> {
+        if !self.shown {
+            return Ok(Transition::None);
+        }
+        Ok(Transition::Replace(Box::new(Gameplay::new(ctx)?)))
+    }
+
+    fn render(&mut self, ctx: &mut Context, _alpha: f32) -> Result<(), failure::Error> {
+        ctx.render_state.apply(&RenderState::default());
+        ctx.render_state.clear(ClearMask::COLOR | ClearMask::DEPTH, &self.clear_values);
+        self.shown = true;
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+use crate::render_gl::{ ClearMask, ClearValues, RenderState };
+use crate::scene::{ Context, Scene, Transition };
+
+use super::loading::Loading;
+
+/**
+ * Title screen, `confirm` starts the game and `back` quits
+ */
+pub struct MainMenu {
+    clear_values: ClearValues,
+}
+
+impl MainMenu {
+    pub fn new() -> MainMenu {
+        MainMenu {
+            clear_values: ClearValues::with_color(na::Vector4::new(0.1, 0.1, 0.15, 1.0)),
+        }
+    }
+}
+
+impl Default for MainMenu {
+    fn default() -> MainMenu {
+        MainMenu::new()
+    }
+}
+
+impl Scene for MainMenu {
+    fn name(&self) -> &str {
+        "main menu"
+    }
+
+    fn on_enter(&mut self, _ctx: &mut Context) -> Result<(), failure::Error> {
+        info!("main menu: confirm to start, back to quit");
+        Ok(())
+    }
+
+    fn handle_input(&mut self, ctx: &mut Context) -> Result<Transition, failure::Error> {
+        Ok(if ctx.input.is_pressed("confirm") {
+            Transition::Replace(Box::new(Loading::new()))
+        } else if ctx.input.is_pressed("back") {
+            Transition::Quit
+        } else {
+            Transition::None
+        })
+    }
+
+    fn render(&mut self, ctx: &mut Context, _alpha: f32) -> Result<(), failure::Error> {
+        ctx.render_state.apply(&RenderState::default());
+        ctx.render_state.clear(ClearMask::COLOR | ClearMask::DEPTH, &self.clear_values);
+        Ok(())
+    }
+}
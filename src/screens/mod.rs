@@ -0,0 +1,11 @@
+mod controls;
+mod gameplay;
+mod loading;
+mod menu;
+mod pause;
+
+pub use self::controls::Controls;
+pub use self::gameplay::Gameplay;
+pub use self::loading::Loading;
+pub use self::menu::MainMenu;
+pub use self::pause::Pause;
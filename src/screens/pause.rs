@@ -0,0 +1,54 @@
+use crate::scene::{ Context, Scene, Transition };
+
+use super::controls::Controls;
+use super::menu::MainMenu;
+
+/**
+ * Overlay that stops the game below it from updating. `pause` or `back` resumes,
+ * `controls` opens the rebinding screen and `quit_to_menu` drops the game and goes
+ * back to the main menu.
+ */
+pub struct Pause;
+
+impl Pause {
+    pub fn new() -> Pause {
+        Pause
+    }
+}
+
+impl Default for Pause {
+    fn default() -> Pause {
+        Pause::new()
+    }
+}
+
+impl Scene for Pause {
+    fn name(&self) -> &str {
+        "pause"
+    }
+
+    fn on_enter(&mut self, _ctx: &mut Context) -> Result<(), failure::Error> {
+        info!("paused: pause or back to resume, controls to rebind, quit_to_menu for the main menu");
+        Ok(())
+    }
+
+    fn handle_input(&mut self, ctx: &mut Context) -> Result<Transition, failure::Error> {
+        Ok(if ctx.input.is_pressed("pause") || ctx.input.is_pressed("back") {
+            Transition::Pop
+        } else if ctx.input.is_pressed("controls") {
+            Transition::Push(Box::new(Controls::new()))
+        } else if ctx.input.is_pressed("quit_to_menu") {
+            Transition::Reset(Box::new(MainMenu::new()))
+        } else {
+            Transition::None
+        })
+    }
+
+    fn render(&mut self, _ctx: &mut Context, _alpha: f32) -> Result<(), failure::Error> {
+        Ok(())
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
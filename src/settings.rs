@@ -0,0 +1,446 @@
+/*!
+ * Window and graphics settings.
+ *
+ * Defaults ship in the `config/settings.toml` resource. Settings changed at runtime are saved
+ * to `settings.toml` in the user config directory, which is layered over the defaults when
+ * loading, so keys added to the defaults later still take effect for existing users.
+ *
+ * Window mode, size, display, title and vsync apply to a running window. The gl version,
+ * MSAA and HiDPI are fixed when the window is created, see `Settings::requires_restart`.
+ * Shadow settings take effect in scenes loaded after the change.
+ */
+use sdl2::video::{ FullscreenType, Window, WindowPos };
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{ Path, PathBuf };
+
+use crate::game_loop::VsyncMode;
+use crate::render_gl::ShadowSettings;
+use crate::resources::Resources;
+use crate::resources::Error as ResourcesError;
+
+const APP_DIRECTORY: &str = "game";
+const USER_SETTINGS_FILE: &str = "settings.toml";
+
+// SDL_WINDOWPOS_CENTERED_DISPLAY
+const WINDOWPOS_CENTERED_MASK: i32 = 0x2FFF_0000;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load resource {}", name)]
+    UnableToLoadResource { name: String, #[cause] error: ResourcesError },
+
+    #[fail(display = "Failed to parse settings {}", name)]
+    Parse { name: String, #[cause] error: toml::de::Error },
+
+    #[fail(display = "Failed to serialize settings")]
+    Serialize { #[cause] error: toml::ser::Error },
+
+    #[fail(display = "I/O error on {}", path)]
+    Io { path: String, #[cause] error: io::Error },
+
+    #[fail(display = "Failed to apply {}: {}", setting, message)]
+    Sdl { setting: String, message: String },
+}
+
+fn sdl_error<E: ToString>(setting: &str) -> impl FnOnce(E) -> Error + '_ {
+    move |e| Error::Sdl { setting: String::from(setting), message: e.to_string() }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowMode {
+    Windowed,
+    Borderless,     // fullscreen at the desktop resolution
+    Fullscreen,     // exclusive, changes the display mode to the window size
+}
+
+impl WindowMode {
+    pub fn fullscreen_type(&self) -> FullscreenType {
+        match self {
+            WindowMode::Windowed => FullscreenType::Off,
+            WindowMode::Borderless => FullscreenType::Desktop,
+            WindowMode::Fullscreen => FullscreenType::True,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowSettings {
+    pub title: String,
+    pub width: u32,
+    pub height: u32,
+    pub mode: WindowMode,
+    pub display: i32,       // index of the display the window opens centered on
+    pub resizable: bool,
+    pub high_dpi: bool,     // full resolution drawable on retina style displays
+}
+
+impl Default for WindowSettings {
+    fn default() -> WindowSettings {
+        WindowSettings {
+            title: String::from("Game"),
+            width: 900,
+            height: 700,
+            mode: WindowMode::Windowed,
+            display: 0,
+            resizable: true,
+            high_dpi: false,
+        }
+    }
+}
+
+impl WindowSettings {
+    /**
+     * Position that centers the window on its display
+     */
+    pub fn position(&self) -> i32 {
+        WINDOWPOS_CENTERED_MASK | self.display.max(0)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GraphicsSettings {
+    pub gl_major: u8,
+    pub gl_minor: u8,
+    pub vsync: VsyncMode,
+    pub frame_limit: Option<u32>,   // frames per second, on top of vsync
+    pub msaa_samples: u8,           // 0 disables multisampling
+    pub shadows: ShadowSettings,
+}
+
+impl Default for GraphicsSettings {
+    fn default() -> GraphicsSettings {
+        GraphicsSettings {
+            gl_major: 4,
+            gl_minor: 5,
+            vsync: VsyncMode::On,
+            frame_limit: None,
+            msaa_samples: 0,
+            shadows: ShadowSettings::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub window: WindowSettings,
+    pub graphics: GraphicsSettings,
+}
+
+impl Settings {
+    /**
+     * Loads the defaults from `name` with the user's saved settings on top
+     */
+    pub fn load(res: &Resources, name: &str)
+    -> Result<Settings, Error>
+    {
+        let defaults = res.load_string(name)
+            .map_err(|error| Error::UnableToLoadResource { name: String::from(name), error })?;
+
+        let user = match user_settings_path() {
+            Some(path) => match fs::read_to_string(&path) {
+                Ok(source) => Some((path.display().to_string(), source)),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(error) => return Err(Error::Io { path: path.display().to_string(), error }),
+            },
+            None => None,
+        };
+
+        Settings::layered(name, &defaults, user.as_ref().map(|(name, source)| (name.as_str(), source.as_str())))
+    }
+
+    /**
+     * Loads the defaults only, for runs that should not depend on the user's settings
+     */
+    pub fn from_res(res: &Resources, name: &str)
+    -> Result<Settings, Error>
+    {
+        let source = res.load_string(name)
+            .map_err(|error| Error::UnableToLoadResource { name: String::from(name), error })?;
+        Settings::layered(name, &source, None)
+    }
+
+    /**
+     * Parses `defaults` with the keys of `overrides`, a `(name, source)` pair, replacing
+     * theirs. Names are only used in errors.
+     */
+    pub fn layered(name: &str, defaults: &str, overrides: Option<(&str, &str)>)
+    -> Result<Settings, Error>
+    {
+        let parse = |name: &str, source: &str| toml::from_str::<toml::Value>(source)
+            .map_err(|error| Error::Parse { name: String::from(name), error });
+
+        let mut value = parse(name, defaults)?;
+        if let Some((override_name, source)) = overrides {
+            merge(&mut value, parse(override_name, source)?);
+        }
+
+        value.try_into().map_err(|error| Error::Parse { name: String::from(name), error })
+    }
+
+    pub fn to_toml(&self) -> Result<String, Error> {
+        toml::to_string_pretty(self).map_err(|error| Error::Serialize { error })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let io_error = |error| Error::Io { path: path.display().to_string(), error };
+
+        let source = self.to_toml()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(io_error)?;
+        }
+        fs::write(path, source).map_err(io_error)
+    }
+
+    /**
+     * Saves to the user config directory, where `load` picks the settings up next time
+     */
+    pub fn save_user(&self) -> Result<(), Error> {
+        match user_settings_path() {
+            Some(path) => self.save(&path),
+            None => {
+                warn!("no user config directory, settings are not saved");
+                Ok(())
+            },
+        }
+    }
+
+    /**
+     * True when going from `self` to `other` needs a new window and gl context
+     */
+    pub fn requires_restart(&self, other: &Settings) -> bool {
+        self.graphics.gl_major != other.graphics.gl_major
+            || self.graphics.gl_minor != other.graphics.gl_minor
+            || self.graphics.msaa_samples != other.graphics.msaa_samples
+            || self.window.high_dpi != other.window.high_dpi
+            || self.window.resizable != other.window.resizable
+    }
+
+    /**
+     * Sets the gl context attributes, call before creating the window
+     */
+    pub fn set_gl_attributes(&self, video: &sdl2::VideoSubsystem, debug: bool) {
+        let gl_attr = video.gl_attr();
+        gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
+        gl_attr.set_context_version(self.graphics.gl_major, self.graphics.gl_minor);
+        gl_attr.set_multisample_buffers(if self.graphics.msaa_samples > 0 { 1 } else { 0 });
+        gl_attr.set_multisample_samples(self.graphics.msaa_samples);
+        if debug {
+            gl_attr.set_context_flags().debug().set();
+        }
+    }
+
+    pub fn build_window(&self, video: &sdl2::VideoSubsystem)
+    -> Result<Window, Error>
+    {
+        let display = self.valid_display(video);
+        let window = &self.window;
+
+        let mut builder = video.window(&window.title, window.width, window.height);
+        builder.opengl().position(display.position(), display.position());
+        if window.resizable {
+            builder.resizable();
+        }
+        if window.high_dpi {
+            builder.allow_highdpi();
+        }
+        match window.mode {
+            WindowMode::Windowed => {},
+            WindowMode::Borderless => { builder.fullscreen_desktop(); },
+            WindowMode::Fullscreen => { builder.fullscreen(); },
+        }
+
+        builder.build().map_err(sdl_error("window"))
+    }
+
+    /**
+     * Applies the settings that can change on a running window, `previous` being the ones
+     * it was last set up with. Returns the vsync mode in effect.
+     */
+    pub fn apply(&self, previous: &Settings, window: &mut Window, video: &sdl2::VideoSubsystem)
+    -> Result<VsyncMode, Error>
+    {
+        if self.requires_restart(previous) {
+            warn!("gl version, msaa, hidpi and resizable changes take effect after a restart");
+        }
+
+        let (new, old) = (&self.window, &previous.window);
+        if new.title != old.title {
+            window.set_title(&new.title).map_err(sdl_error("window title"))?;
+        }
+
+        let moved = new.display != old.display;
+        if new.mode != old.mode || moved || (new.width, new.height) != (old.width, old.height) {
+            // leave fullscreen first, a fullscreen window can not be moved or resized
+            window.set_fullscreen(FullscreenType::Off).map_err(sdl_error("window mode"))?;
+            window.set_size(new.width, new.height).map_err(sdl_error("window size"))?;
+            if moved || new.mode == WindowMode::Windowed {
+                let position = self.valid_display(video).position();
+                window.set_position(WindowPos::Positioned(position), WindowPos::Positioned(position));
+            }
+            window.set_fullscreen(new.mode.fullscreen_type()).map_err(sdl_error("window mode"))?;
+        }
+
+        self.graphics.vsync.apply(video).map_err(sdl_error("vsync"))
+    }
+
+    /**
+     * The window settings with the display clamped to the connected ones
+     */
+    fn valid_display(&self, video: &sdl2::VideoSubsystem) -> WindowSettings {
+        let displays = video.num_video_displays().unwrap_or(1);
+        let mut window = self.window.clone();
+        if window.display < 0 || window.display >= displays {
+            warn!("display {} is not connected, using display 0", window.display);
+            window.display = 0;
+        }
+        window
+    }
+}
+
+/**
+ * Tables are merged key by key, anything else in `overrides` replaces the value in `base`
+ */
+pub(crate) fn merge(base: &mut toml::Value, overrides: toml::Value) {
+    match (base, overrides) {
+        (toml::Value::Table(base), toml::Value::Table(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => { base.insert(key, value); },
+                }
+            }
+        },
+        (base, overrides) => *base = overrides,
+    }
+}
+
+/**
+ * The per user config directory of the game, following the platform's conventions
+ */
+pub fn user_config_dir() -> Option<PathBuf> {
+    let home = || env::var_os("HOME").map(PathBuf::from);
+
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| home().map(|home| home.join(".config")))
+    };
+
+    base.map(|base| base.join(APP_DIRECTORY))
+}
+
+pub fn user_settings_path() -> Option<PathBuf> {
+    user_config_dir().map(|dir| dir.join(USER_SETTINGS_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFAULTS: &str = r#"
+        [window]
+        title = "Test"
+        width = 800
+        height = 600
+
+        [graphics]
+        vsync = "on"
+        msaa_samples = 4
+    "#;
+
+    #[test]
+    fn missing_keys_use_defaults() {
+        let settings = Settings::layered("defaults", DEFAULTS, None).unwrap();
+        assert_eq!(settings.window.title, "Test");
+        assert_eq!((settings.window.width, settings.window.height), (800, 600));
+        assert_eq!(settings.window.mode, WindowMode::Windowed);
+        assert_eq!(settings.graphics.msaa_samples, 4);
+        assert_eq!((settings.graphics.gl_major, settings.graphics.gl_minor), (4, 5));
+
+        assert_eq!(Settings::layered("empty", "", None).unwrap(), Settings::default());
+    }
+
+    #[test]
+    fn user_settings_override_key_by_key() {
+        let user = r#"
+            [window]
+            mode = "borderless"
+            display = 1
+
+            [graphics]
+            vsync = "adaptive"
+
+            [graphics.shadows]
+            resolution = 2048
+        "#;
+        let settings = Settings::layered("defaults", DEFAULTS, Some(("user", user))).unwrap();
+        assert_eq!(settings.window.mode, WindowMode::Borderless);
+        assert_eq!(settings.window.display, 1);
+        assert_eq!(settings.window.width, 800);
+        assert_eq!(settings.graphics.vsync, VsyncMode::Adaptive);
+        assert_eq!(settings.graphics.msaa_samples, 4);
+        assert_eq!(settings.graphics.shadows.resolution, 2048);
+        assert_eq!(settings.graphics.shadows.cascades, ShadowSettings::default().cascades);
+    }
+
+    #[test]
+    fn saved_settings_load_back() {
+        let mut settings = Settings::default();
+        settings.window.mode = WindowMode::Fullscreen;
+        settings.graphics.frame_limit = Some(144);
+
+        let saved = settings.to_toml().unwrap();
+        assert_eq!(Settings::layered("defaults", DEFAULTS, Some(("user", &saved))).unwrap(), settings);
+
+        let path = env::temp_dir().join(format!("settings-test-{}", std::process::id())).join(USER_SETTINGS_FILE);
+        settings.save(&path).unwrap();
+        let loaded = fs::read_to_string(&path).unwrap();
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(loaded, saved);
+    }
+
+    #[test]
+    fn errors_name_the_broken_file() {
+        match Settings::layered("defaults", DEFAULTS, Some(("user", "[window]\nmode = \"tiny\""))) {
+            Err(Error::Parse { name, .. }) => assert_eq!(name, "defaults"),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        match Settings::layered("defaults", DEFAULTS, Some(("user", "[window"))) {
+            Err(Error::Parse { name, .. }) => assert_eq!(name, "user"),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn restart_only_for_context_settings() {
+        let settings = Settings::default();
+
+        let mut live = settings.clone();
+        live.window.mode = WindowMode::Borderless;
+        live.window.display = 2;
+        live.graphics.vsync = VsyncMode::Off;
+        assert!(!settings.requires_restart(&live));
+
+        let mut msaa = settings.clone();
+        msaa.graphics.msaa_samples = 8;
+        assert!(settings.requires_restart(&msaa));
+    }
+
+    #[test]
+    fn windows_are_centered_on_their_display() {
+        let mut window = WindowSettings::default();
+        assert_eq!(window.position(), 0x2FFF_0000);
+        window.display = 2;
+        assert_eq!(window.position(), 0x2FFF_0002);
+    }
+}
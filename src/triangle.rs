@@ -1,58 +1,90 @@
 use gl;
-use failure;
+#[cfg(feature = "headless")] use failure;
+use std::rc::Rc;
 
-use crate::render_gl::{ self, data, buffer, Viewport };
-use crate::resources::Resources;
-use crate::mvp_matrix::ModelViewProjectionMatrix;
-use crate::camera::Camera;
+use crate::render_gl::{ self, data, buffer };
+#[cfg(feature = "headless")] use crate::resources::Resources;
+#[cfg(feature = "headless")] use crate::mvp_matrix::ModelViewProjectionMatrix;
+#[cfg(feature = "headless")] use crate::camera::Camera;
+use crate::ecs;
 
 #[derive(VertexAttribPointers)]
 #[derive(Copy, Clone, Debug)]
 #[repr(C, packed)]
 struct Vertex {
-    #[location = "0"]
+    #[location = 0]
     pos: data::f32_f32_f32,
-    #[location = "1"]
+    #[location = 1]
     clr: data::u2_u10_u10_u10_rev_float,
 }
 
+/**
+ * The standalone triangle of the headless smoke test (`--headless`). The game itself draws
+ * `triangle_mesh` through the ecs and the render queue.
+ */
+#[cfg(feature = "headless")]
 pub struct Triangle {
     program: render_gl::Program,
     _vbo: buffer::ArrayBuffer,
     vao: buffer::VertexArray,
-    mvp_matrix: ModelViewProjectionMatrix
+    mvp_matrix: ModelViewProjectionMatrix,
 }
 
-impl Triangle {
-    pub fn new(res: Resources, gl: &gl::Gl)
-    -> Result<Triangle, failure::Error>
-    {
+#[cfg(feature = "headless")]
+fn triangle_program(res: Resources, gl: &gl::Gl)
+-> Result<render_gl::Program, failure::Error>
+{
+    let program = render_gl::Program::from_res(gl, res, "shaders/triangle")?;
+    program.validate_vertex_layout(&Vertex::vertex_layout())?;
+    Ok(program)
+}
 
-        // set up shader program
+fn triangle_buffers(gl: &gl::Gl)
+-> (buffer::ArrayBuffer, buffer::VertexArray)
+{
+    let vertices: Vec<Vertex> = vec![
+        Vertex{ pos: (0.5, -0.5, 0.0).into(), clr: (1.0, 0.0, 0.0, 1.0).into() }, // bottom right
+        Vertex{ pos: (-0.5, -0.5, 0.0).into(), clr: (0.0, 1.0, 0.0, 1.0).into() }, // bottom left
+        Vertex{ pos: (0.0, 0.5, 0.0).into(), clr: (0.0, 0.0, 1.0, 1.0).into() } // top
+    ];
 
-        let program = render_gl::Program::from_res(gl, res, "shaders/triangle")?;
+    let vbo = render_gl::buffer::ArrayBuffer::new(gl);
+    vbo.set_label("triangle vertices");
+    vbo.bind();
+    vbo.static_draw_data(&vertices);
+    vbo.unbind();
 
-        let vertices: Vec<Vertex> = vec![
-            Vertex{ pos: (0.5, -0.5, 0.0).into(), clr: (1.0, 0.0, 0.0, 1.0).into() }, // bottom right
-            Vertex{ pos: (-0.5, -0.5, 0.0).into(), clr: (0.0, 1.0, 0.0, 1.0).into() }, // bottom left
-            Vertex{ pos: (0.0, 0.5, 0.0).into(), clr: (0.0, 0.0, 1.0, 1.0).into() } // top
-        ];
+    let vao = render_gl::buffer::VertexArray::new(gl);
+    vao.set_label("triangle");
 
-        let vbo = render_gl::buffer::ArrayBuffer::new(&gl);
-        vbo.bind();
-        vbo.static_draw_data(&vertices);
-        vbo.unbind();
+    vao.bind();
+    vbo.bind();
 
+    Vertex::vertex_attrib_pointers(gl);
 
-        let vao = render_gl::buffer::VertexArray::new(&gl);
+    vbo.unbind();
+    vao.unbind();
 
-        vao.bind();
-        vbo.bind();
+    (vbo, vao)
+}
 
-        Vertex::vertex_attrib_pointers(&gl);
+/**
+ * The triangle's vertices as an ecs component, drawn with the `materials/triangle` material
+ */
+pub fn triangle_mesh(gl: &gl::Gl) -> ecs::Mesh {
+    let (vbo, vao) = triangle_buffers(gl);
+    ecs::Mesh(Rc::new(render_gl::Mesh::new(vao, vbo, gl::TRIANGLES, 3)))
+}
 
-        vbo.unbind();
-        vao.unbind();
+#[cfg(feature = "headless")]
+impl Triangle {
+    pub fn new(res: Resources, gl: &gl::Gl)
+    -> Result<Triangle, failure::Error>
+    {
+        // set up shader program
+
+        let program = triangle_program(res, gl)?;
+        let (vbo, vao) = triangle_buffers(gl);
 
         // let matrix_id = unsafe {
         //     gl.GetUniformLocation(
@@ -101,7 +133,6 @@ impl Triangle {
 
         self.mvp_matrix.calculate_and_update_mvp(camera);
 
-
         unsafe {
             gl.DrawArrays(
                 gl::TRIANGLES, // mode